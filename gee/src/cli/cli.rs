@@ -1,15 +1,183 @@
+use std::{net::SocketAddr, path::PathBuf};
+
 use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
     #[clap(subcommand)]
-    command: Option<Commands>,
+    pub command: Option<Commands>,
 }
 
 #[derive(Subcommand)]
-enum Commands {
-    Init,
-    Serve,
-    Validate,
+pub enum Commands {
+    /// Create a template configuration file in the current directory.
+    Init {
+        /// Format of the configuration file: toml, json, or yaml.
+        #[clap(long, default_value = "toml")]
+        format: String,
+    },
+
+    /// Start Gee serving requests.
+    Serve {
+        /// Configuration file to use instead of gee.toml, gee.json, or gee.yaml.
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+    },
+
+    /// Manage the configuration file.
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Verify that the configuration is valid.
+    Validate {
+        /// Configuration file to use instead of gee.toml, gee.json, or gee.yaml.
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+
+        /// Also run the checks the server runs at startup: import the
+        /// applications, build the routes, and bind the address.
+        #[clap(long)]
+        startup: bool,
+
+        /// Address a running server listens at, which need not be free.
+        #[clap(long, hide = true)]
+        listening: Option<SocketAddr>,
+    },
+
+    /// Print the routes served, including those derived by convention.
+    Routes {
+        /// Configuration file to use instead of gee.toml, gee.json, or gee.yaml.
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+    },
+
+    /// Control the running Gee server through its control socket.
+    Ctl {
+        /// Configuration file to use instead of gee.toml, gee.json, or gee.yaml.
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+
+        #[clap(subcommand)]
+        command: CtlCommands,
+    },
+
+    /// Start Gee and check its responses to a suite of requests.
+    E2e {
+        /// Suite of requests and expected responses, as TOML, JSON, or YAML.
+        suite: PathBuf,
+
+        /// Configuration file to use instead of gee.toml, gee.json, or gee.yaml.
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+
+        /// Write the results as JUnit XML to this file.
+        #[clap(long)]
+        junit: Option<PathBuf>,
+    },
+
+    /// Print the version of Gee and how it was built.
+    Version {
+        /// Print the version information as JSON.
+        #[clap(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Rewrite a configuration file from an older release to the current format.
+    Migrate {
+        /// Configuration file to migrate instead of gee.toml, gee.json, or gee.yaml.
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+
+        /// Print the changes without writing them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CtlCommands {
+    /// Disable, enable, or list disabled routes.
+    Route {
+        #[clap(subcommand)]
+        command: RouteCommands,
+    },
+
+    /// Enable, disable, or list the faults injected into routes.
+    Faults {
+        #[clap(subcommand)]
+        command: FaultCommands,
+    },
+
+    /// Print the routes and hosts which sent the most bytes.
+    Top {
+        /// Number of routes and of hosts to print.
+        #[clap(long, default_value = "10")]
+        count: usize,
+    },
+
+    /// Import the Python applications afresh without restarting the server.
+    RestartApp,
+
+    /// Check a configuration file against the running server without applying it.
+    CheckConfig {
+        /// Configuration file to check.
+        path: PathBuf,
+    },
+
+    /// Print the recent log records kept by the server.
+    Logs {
+        /// Number of records to print.
+        #[clap(long, default_value = "200")]
+        tail: usize,
+
+        /// Least severe level to print, e.g. warn. Defaults to info.
+        #[clap(long)]
+        level: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RouteCommands {
+    /// Answer every request under a route with an error status.
+    Disable {
+        /// Route to disable, e.g. /beta.
+        route: String,
+
+        /// Status to answer with. Defaults to 503.
+        #[clap(long)]
+        status: Option<u16>,
+    },
+
+    /// Resume handling the requests under a disabled route.
+    Enable {
+        /// Route to enable.
+        route: String,
+    },
+
+    /// List the disabled routes.
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum FaultCommands {
+    /// Start injecting the faults configured for a route.
+    Enable {
+        /// Route whose faults to enable, e.g. /api.
+        route: String,
+    },
+
+    /// Stop injecting the faults configured for a route.
+    Disable {
+        /// Route whose faults to disable.
+        route: String,
+    },
+
+    /// List the routes with faults configured and whether they are enabled.
+    List,
 }
@@ -0,0 +1,87 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use super::{load_config, CtlCommands, FaultCommands, RouteCommands};
+use crate::server::CONTROL_ERROR_PREFIX;
+
+/// `ctl` sends a command to the running server through the control socket
+/// named by the configuration and prints its reply.
+pub fn ctl(config_path: Option<PathBuf>, command: CtlCommands) -> Result<(), Box<dyn Error>> {
+    let config = load_config(config_path)?;
+    let path = config
+        .control_socket_path()
+        .ok_or("No control_socket is configured.")?;
+
+    let mut stream = UnixStream::connect(&path).map_err(|e| {
+        format!(
+            "Cannot connect to the control socket at {}: {}. Is the server running?",
+            path.display(),
+            e
+        )
+    })?;
+    writeln!(stream, "{}", words(&command)?.join(" "))?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+
+    match reply.strip_prefix(CONTROL_ERROR_PREFIX) {
+        Some(error) => Err(error.trim_end().into()),
+        None => {
+            print!("{}", reply);
+            Ok(())
+        }
+    }
+}
+
+/// `words` spells out a command as it is sent over the control socket. Paths
+/// are made absolute, as the server may run in another directory.
+fn words(command: &CtlCommands) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(match command {
+        CtlCommands::Route { command } => {
+            let mut words = vec!["route".to_owned()];
+            match command {
+                RouteCommands::Disable { route, status } => {
+                    words.extend(["disable".to_owned(), route.clone()]);
+                    words.extend(status.map(|status| status.to_string()));
+                }
+                RouteCommands::Enable { route } => {
+                    words.extend(["enable".to_owned(), route.clone()])
+                }
+                RouteCommands::List => words.push("list".to_owned()),
+            }
+            words
+        }
+        CtlCommands::Faults { command } => {
+            let mut words = vec!["faults".to_owned()];
+            match command {
+                FaultCommands::Enable { route } => {
+                    words.extend(["enable".to_owned(), route.clone()])
+                }
+                FaultCommands::Disable { route } => {
+                    words.extend(["disable".to_owned(), route.clone()])
+                }
+                FaultCommands::List => words.push("list".to_owned()),
+            }
+            words
+        }
+        CtlCommands::Top { count } => vec!["top".to_owned(), count.to_string()],
+        CtlCommands::RestartApp => vec!["restart-app".to_owned()],
+        CtlCommands::Logs { tail, level } => {
+            let mut words = vec!["logs".to_owned(), tail.to_string()];
+            words.extend(level.clone());
+            words
+        }
+        CtlCommands::CheckConfig { path } => {
+            let path = std::fs::canonicalize(path)
+                .map_err(|e| format!("Cannot find {}: {}", path.display(), e))?;
+            vec![
+                "check-config".to_owned(),
+                path.to_string_lossy().into_owned(),
+            ]
+        }
+    })
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Write as _,
+    fs::read_to_string,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use hyper::{client::HttpConnector, header::HeaderMap, Body, Client, Method, Request};
+use regex::Regex;
+use serde::Deserialize;
+
+use super::load_config;
+use crate::server::Server;
+
+/// `READY_TIMEOUT` is how long the server is given to start accepting
+/// connections before the suite is abandoned.
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `Suite` is a list of requests to send to the server and what their
+/// responses are expected to be.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+struct Suite {
+    /// `tests` are run in order.
+    tests: Vec<TestCase>,
+}
+
+/// `TestCase` is a request and the response it is expected to get.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+struct TestCase {
+    /// `name` identifies the test in the results.
+    name: String,
+
+    /// `method` is the request method. Defaults to GET.
+    method: Option<String>,
+
+    /// `path` is the path and query the request is sent to.
+    path: String,
+
+    /// `headers` are sent with the request.
+    headers: Option<HashMap<String, String>>,
+
+    /// `body` is sent as the request body.
+    body: Option<String>,
+
+    /// `expect` describes the expected response.
+    expect: Expectation,
+}
+
+/// `Expectation` describes a response. Header values and the body are
+/// regular expressions which must match somewhere in the response.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+struct Expectation {
+    /// `status` is the expected status code.
+    status: Option<u16>,
+
+    /// `headers` are patterns the values of response headers must match.
+    headers: Option<HashMap<String, String>>,
+
+    /// `body` is a pattern the response body must match.
+    body: Option<String>,
+}
+
+/// `TestResult` is the outcome of a test case: a failure message if it
+/// failed, and how long it took.
+struct TestResult {
+    /// `name` is the name of the test case.
+    name: String,
+
+    /// `failure` describes how the response differed from the expectation.
+    failure: Option<String>,
+
+    /// `duration` is how long the request took.
+    duration: Duration,
+}
+
+impl Suite {
+    /// `from_file` reads a suite from a TOML, JSON, or YAML file.
+    fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = read_to_string(path)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&content).map_err(|e| e.into()),
+            Some("json") => serde_json::from_str(&content).map_err(|e| e.into()),
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(|e| e.into()),
+            _ => Err(format!(
+                "Unsupported suite file {}. File must be a TOML, JSON, or YAML file.",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+/// `e2e` starts the server with the configuration, sends it the requests of
+/// the suite, and reports which responses were not as expected, writing the
+/// results as JUnit XML to `junit` if it is given. It fails if any test did.
+pub async fn e2e(
+    config_path: Option<PathBuf>,
+    suite_path: PathBuf,
+    junit: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let config = load_config(config_path)?;
+    let suite = Suite::from_file(&suite_path)?;
+    let mut address = config.socket_address();
+    if address.ip().is_unspecified() {
+        address.set_ip([127, 0, 0, 1].into());
+    }
+
+    let server = Server::new(config);
+    let results = tokio::select! {
+        result = server.start() => {
+            return Err(match result {
+                Ok(()) => "The server stopped before the suite finished.".into(),
+                Err(e) => format!("The server could not be started: {}", e).into(),
+            });
+        }
+        results = run_suite(&suite, address) => results?,
+    };
+
+    for result in &results {
+        match &result.failure {
+            None => println!("ok      {}", result.name),
+            Some(failure) => println!("FAILED  {}: {}", result.name, failure),
+        }
+    }
+    if let Some(junit) = junit {
+        std::fs::write(&junit, junit_xml(&suite_path, &results))?;
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.failure.is_some())
+        .count();
+    println!("\n{} passed, {} failed", results.len() - failed, failed);
+    if failed > 0 {
+        return Err(format!("{} of {} tests failed.", failed, results.len()).into());
+    }
+
+    Ok(())
+}
+
+/// `run_suite` waits for the server at `address` to accept connections and
+/// runs each test case against it in turn.
+async fn run_suite(suite: &Suite, address: SocketAddr) -> Result<Vec<TestResult>, String> {
+    let started = Instant::now();
+    while tokio::net::TcpStream::connect(address).await.is_err() {
+        if started.elapsed() > READY_TIMEOUT {
+            return Err(format!(
+                "The server did not start listening on {}.",
+                address
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let client = Client::new();
+    let mut results = Vec::new();
+    for case in &suite.tests {
+        let started = Instant::now();
+        let failure = run_case(&client, address, case).await.err();
+        results.push(TestResult {
+            name: case.name.clone(),
+            failure,
+            duration: started.elapsed(),
+        });
+    }
+
+    Ok(results)
+}
+
+/// `run_case` sends the request of a test case and checks its response.
+async fn run_case(
+    client: &Client<HttpConnector>,
+    address: SocketAddr,
+    case: &TestCase,
+) -> Result<(), String> {
+    let method = case
+        .method
+        .as_deref()
+        .unwrap_or("GET")
+        .parse::<Method>()
+        .map_err(|e| format!("invalid method: {}", e))?;
+
+    let mut req = Request::builder()
+        .method(method)
+        .uri(format!("http://{}{}", address, case.path));
+    for (name, value) in case.headers.iter().flatten() {
+        req = req.header(name, value);
+    }
+    let req = req
+        .body(Body::from(case.body.clone().unwrap_or_default()))
+        .map_err(|e| format!("invalid request: {}", e))?;
+
+    let response = client
+        .request(req)
+        .await
+        .map_err(|e| format!("request failed: {}", e))?;
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| format!("cannot read response body: {}", e))?;
+
+    check(
+        &case.expect,
+        status,
+        &headers,
+        &String::from_utf8_lossy(&body),
+    )
+}
+
+/// `check` compares a response to what was expected, describing the first
+/// difference found.
+fn check(expect: &Expectation, status: u16, headers: &HeaderMap, body: &str) -> Result<(), String> {
+    if let Some(expected) = expect.status {
+        if status != expected {
+            return Err(format!("expected status {}, got {}", expected, status));
+        }
+    }
+
+    for (name, pattern) in expect.headers.iter().flatten() {
+        let value = headers
+            .get(name.as_str())
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| format!("expected a {} header", name))?;
+        if !matches(pattern, value)? {
+            return Err(format!(
+                "expected {} to match {}, got {}",
+                name, pattern, value
+            ));
+        }
+    }
+
+    if let Some(pattern) = &expect.body {
+        if !matches(pattern, body)? {
+            return Err(format!("expected the body to match {}", pattern));
+        }
+    }
+
+    Ok(())
+}
+
+/// `matches` returns whether the pattern matches somewhere in the text.
+fn matches(pattern: &str, text: &str) -> Result<bool, String> {
+    Regex::new(pattern)
+        .map(|pattern| pattern.is_match(text))
+        .map_err(|e| format!("invalid pattern {}: {}", pattern, e))
+}
+
+/// `junit_xml` formats the results as a JUnit XML report with one test suite
+/// named after the suite file.
+fn junit_xml(suite_path: &Path, results: &[TestResult]) -> String {
+    let failures = results
+        .iter()
+        .filter(|result| result.failure.is_some())
+        .count();
+    let time: f64 = results
+        .iter()
+        .map(|result| result.duration.as_secs_f64())
+        .sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        xml,
+        "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+        escape(&suite_path.display().to_string()),
+        results.len(),
+        failures,
+        time
+    )
+    .unwrap();
+    for result in results {
+        let name = escape(&result.name);
+        let time = result.duration.as_secs_f64();
+        match &result.failure {
+            None => writeln!(xml, "  <testcase name=\"{}\" time=\"{:.3}\"/>", name, time),
+            Some(failure) => writeln!(
+                xml,
+                "  <testcase name=\"{}\" time=\"{:.3}\">\n    <failure message=\"{}\"/>\n  </testcase>",
+                name,
+                time,
+                escape(failure)
+            ),
+        }
+        .unwrap();
+    }
+    xml.push_str("</testsuite>\n");
+
+    xml
+}
+
+/// `escape` escapes text for use in an XML attribute.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_suite_from_toml() {
+        let suite: Suite = toml::from_str(
+            r#"
+            [[tests]]
+            name = "home page"
+            path = "/"
+            expect = { status = 200, headers = { content-type = "^text/html" } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(suite.tests[0].path, "/");
+        assert_eq!(suite.tests[0].expect.status, Some(200));
+    }
+
+    #[test]
+    fn test_check() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/html; charset=utf-8".parse().unwrap());
+        let expect = Expectation {
+            status: Some(200),
+            headers: Some(HashMap::from([(
+                "Content-Type".to_owned(),
+                "^text/html".to_owned(),
+            )])),
+            body: Some("<h1>Hello</h1>".to_owned()),
+        };
+
+        assert_eq!(check(&expect, 200, &headers, "<h1>Hello</h1>"), Ok(()));
+        assert!(check(&expect, 404, &headers, "<h1>Hello</h1>").is_err());
+        assert!(check(&expect, 200, &HeaderMap::new(), "<h1>Hello</h1>").is_err());
+        assert!(check(&expect, 200, &headers, "<h1>Bye</h1>").is_err());
+    }
+
+    #[test]
+    fn test_junit_xml() {
+        let results = vec![
+            TestResult {
+                name: "home page".to_owned(),
+                failure: None,
+                duration: Duration::from_millis(5),
+            },
+            TestResult {
+                name: "api".to_owned(),
+                failure: Some("expected status 200, got 404 <missing>".to_owned()),
+                duration: Duration::from_millis(10),
+            },
+        ];
+
+        assert_eq!(
+            junit_xml(Path::new("suite.toml"), &results),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <testsuite name=\"suite.toml\" tests=\"2\" failures=\"1\" time=\"0.015\">\n  \
+             <testcase name=\"home page\" time=\"0.005\"/>\n  \
+             <testcase name=\"api\" time=\"0.010\">\n    \
+             <failure message=\"expected status 200, got 404 &lt;missing&gt;\"/>\n  \
+             </testcase>\n\
+             </testsuite>\n"
+        );
+    }
+}
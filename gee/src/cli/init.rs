@@ -0,0 +1,31 @@
+use std::{error::Error, fs, path::Path};
+
+use crate::config::Config;
+
+/// `init` writes a template configuration file in the given format to the
+/// current directory. An existing configuration file is never overwritten.
+pub fn init(format: &str) -> Result<(), Box<dyn Error>> {
+    let config = Config::new_default();
+
+    let (file_name, content) = match format.to_lowercase().as_str() {
+        "toml" => ("gee.toml", config.to_toml()?),
+        "json" => ("gee.json", config.to_json()?),
+        "yaml" => ("gee.yaml", config.to_yaml()?),
+        _ => {
+            return Err(format!(
+                "Unsupported file format: {}. Format must be TOML, JSON, or YAML.",
+                format
+            )
+            .into())
+        }
+    };
+
+    if Path::new(file_name).exists() {
+        return Err(format!("{} already exists.", file_name).into());
+    }
+
+    fs::write(file_name, content)?;
+    println!("Created {}", file_name);
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+use similar::TextDiff;
+
+use crate::config::Config;
+
+/// `Migration` rewrites a configuration document from an older schema, returning
+/// a description of each change it made.
+type Migration = fn(&mut Map<String, Value>) -> Result<Vec<String>, String>;
+
+/// `Migrated` is a configuration document rewritten to the current schema.
+struct Migrated {
+    /// `content` is the rewritten document.
+    content: String,
+
+    /// `changes` describe each change made to the document.
+    changes: Vec<String>,
+}
+
+/// `MIGRATIONS` are applied to a configuration document in order.
+const MIGRATIONS: [Migration; 1] = [migrate_application];
+
+/// `migrate` rewrites the configuration file to the current schema. The changes
+/// are printed as a diff and, unless this is a dry run, the original file is
+/// backed up before it is overwritten.
+pub fn migrate(config_path: Option<PathBuf>, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let path = match config_path {
+        Some(path) => path,
+        None => Config::working_dir_file()
+            .ok_or("No configuration file found. Pass one with --config.")?
+            .to_path_buf(),
+    };
+
+    let original = fs::read_to_string(&path)?;
+    let migrated = match migrate_content(&original, extension(&path)?)? {
+        Some(migrated) => migrated,
+        None => {
+            println!("{} is already up to date.", path.display());
+            return Ok(());
+        }
+    };
+
+    for change in migrated.changes.iter() {
+        println!("- {}", change);
+    }
+    println!();
+    print!(
+        "{}",
+        TextDiff::from_lines(&original, &migrated.content)
+            .unified_diff()
+            .header(&path.display().to_string(), &path.display().to_string())
+    );
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let backup = backup_path(&path);
+    fs::copy(&path, &backup)?;
+    fs::write(&path, migrated.content)?;
+    println!(
+        "\nMigrated {}. The original was backed up to {}.",
+        path.display(),
+        backup.display()
+    );
+
+    Ok(())
+}
+
+/// `migrate_content` applies the migrations to the content of a configuration
+/// file in the format given by its `extension`, or returns `None` if nothing
+/// changed.
+fn migrate_content(content: &str, extension: &str) -> Result<Option<Migrated>, Box<dyn Error>> {
+    let mut document: Value = match extension {
+        "toml" => toml::from_str(content)?,
+        "json" => serde_json::from_str(content)?,
+        _ => serde_yaml::from_str(content)?,
+    };
+    let table = document
+        .as_object_mut()
+        .ok_or("The configuration file must contain a table of settings.")?;
+
+    let mut changes = Vec::new();
+    for migration in MIGRATIONS.iter() {
+        changes.extend(migration(table)?);
+    }
+
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    // The migrated document must still be a valid configuration.
+    serde_json::from_value::<Config>(document.clone())
+        .map_err(|e| format!("The migrated configuration is invalid: {}", e))?;
+
+    let content = match extension {
+        "toml" => toml::to_string(&toml::Value::try_from(&document)?)?,
+        "json" => serde_json::to_string_pretty(&document)? + "\n",
+        _ => serde_yaml::to_string(&document)?,
+    };
+
+    Ok(Some(Migrated { content, changes }))
+}
+
+/// `migrate_application` moves the `application` and `application_name`
+/// settings into an entry of `applications` mounted at `/`.
+fn migrate_application(table: &mut Map<String, Value>) -> Result<Vec<String>, String> {
+    let (path, name) = match (
+        table.remove("application"),
+        table.remove("application_name"),
+    ) {
+        (None, None) => return Ok(vec![]),
+        (Some(path), Some(name)) => (path, name),
+        _ => {
+            return Err(
+                "`application` and `application_name` must be set together to be migrated."
+                    .to_owned(),
+            )
+        }
+    };
+
+    let mut application = Map::new();
+    application.insert("path".to_owned(), path);
+    application.insert("name".to_owned(), name);
+
+    let applications = table
+        .entry("applications")
+        .or_insert_with(|| Value::Array(vec![]))
+        .as_array_mut()
+        .ok_or("`applications` must be a list.")?;
+    applications.push(Value::Object(application));
+
+    Ok(vec![
+        "Moved `application` and `application_name` into `[[applications]]`.".to_owned(),
+    ])
+}
+
+/// `extension` returns the extension of a configuration file, which decides
+/// its format.
+fn extension(path: &Path) -> Result<&str, Box<dyn Error>> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension @ ("toml" | "json" | "yaml" | "yml")) => Ok(extension),
+        _ => Err(format!(
+            "Unsupported file format: {}. File must be a TOML, JSON, or YAML file.",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+/// `backup_path` returns a path next to the configuration file that does not
+/// exist yet, e.g. `gee.toml.bak` or `gee.toml.bak.1`.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = PathBuf::from(format!("{}.bak", path.display()));
+    let mut n = 1;
+    while backup.exists() {
+        backup = PathBuf::from(format!("{}.bak.{}", path.display(), n));
+        n += 1;
+    }
+
+    backup
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_migrate_content_toml() {
+        let content = r#"address = "127.0.0.1"
+port = 8080
+root_dir = "."
+application = "app/app.py"
+application_name = "simple_app"
+
+[static_routes]
+"/static" = "./static/"
+"#;
+
+        let migrated = migrate_content(content, "toml").unwrap().unwrap();
+        let config: Config = toml::from_str(&migrated.content).unwrap();
+
+        assert_eq!(migrated.changes.len(), 1);
+        assert_eq!(config.application, None);
+        assert_eq!(config.application_name, None);
+        let applications = config.applications.unwrap();
+        assert_eq!(applications.len(), 1);
+        assert_eq!(applications[0].path, "app/app.py");
+        assert_eq!(applications[0].name, "simple_app");
+        assert_eq!(
+            config.static_routes.unwrap().get("/static").unwrap(),
+            "./static/"
+        );
+    }
+
+    #[test]
+    fn test_migrate_content_appends_to_applications() {
+        let content = r#"{
+  "address": "127.0.0.1",
+  "port": 8080,
+  "root_dir": ".",
+  "application": "app.py",
+  "application_name": "application",
+  "applications": [{ "path": "api.py", "name": "application", "route": "/api" }]
+}"#;
+
+        let migrated = migrate_content(content, "json").unwrap().unwrap();
+        let config: Config = serde_json::from_str(&migrated.content).unwrap();
+
+        let applications = config.applications.unwrap();
+        assert_eq!(applications.len(), 2);
+        assert_eq!(applications[0].route(), "/api");
+        assert_eq!(applications[1].path, "app.py");
+    }
+
+    #[test]
+    fn test_migrate_content_up_to_date() {
+        let content = std::fs::read_to_string("./src/fixtures/test_config_valid_03.yaml").unwrap();
+
+        assert!(migrate_content(&content, "yaml").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_migrate_content_incomplete_application() {
+        let content =
+            "address = \"127.0.0.1\"\nport = 8080\nroot_dir = \".\"\napplication = \"app.py\"\n";
+
+        assert!(migrate_content(content, "toml").is_err());
+    }
+}
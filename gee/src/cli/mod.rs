@@ -1,6 +1,42 @@
+#[allow(clippy::module_inception)]
 mod cli;
+mod ctl;
+mod e2e;
 mod init;
+mod migrate;
+mod routes;
 mod serve;
 mod verify;
+mod version;
 
-pub use cli::Cli;
+use std::{error::Error, path::PathBuf};
+
+use crate::config::Config;
+
+pub use cli::{Cli, Commands, ConfigCommands, CtlCommands, FaultCommands, RouteCommands};
+pub use ctl::ctl;
+pub use e2e::e2e;
+pub use init::init;
+pub use migrate::migrate;
+pub use routes::routes;
+pub use serve::serve;
+pub use verify::verify;
+pub use version::version;
+
+/// `load_config` reads the configuration from the given file or, if no file is
+/// given, from the configuration file in the current directory, adding the
+/// routes derived by convention.
+fn load_config(path: Option<PathBuf>) -> Result<Config, Box<dyn Error>> {
+    let mut config = read_config(path)?;
+    config.apply_conventions();
+
+    Ok(config)
+}
+
+/// `read_config` reads the configuration as it is written.
+fn read_config(path: Option<PathBuf>) -> Result<Config, Box<dyn Error>> {
+    match path {
+        Some(path) => Config::from_file(&path),
+        None => Config::from_working_dir(),
+    }
+}
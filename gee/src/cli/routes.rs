@@ -0,0 +1,79 @@
+use std::{error::Error, fmt::Write, path::PathBuf};
+
+use super::read_config;
+use crate::config::Config;
+
+/// `routes` prints the routes the configuration serves, marking those derived
+/// by convention from the layout of the `root_dir`.
+pub fn routes(config_path: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let mut config = read_config(config_path)?;
+    let derived = config.apply_conventions();
+
+    print!("{}", route_table(&config, &derived));
+
+    Ok(())
+}
+
+/// `route_table` lists the static routes and applications of a configuration
+/// as a table, ordered by route.
+fn route_table(config: &Config, derived: &[String]) -> String {
+    let mut rows: Vec<(String, &str, String)> = config
+        .static_routes
+        .iter()
+        .flatten()
+        .map(|(route, dir)| (route.clone(), "static", dir.clone()))
+        .chain(config.all_applications().iter().map(|application| {
+            (
+                application.route().to_owned(),
+                "application",
+                format!("{}:{}", application.path, application.name),
+            )
+        }))
+        .collect();
+    rows.sort();
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{:<24} {:<12} {:<32} SOURCE",
+        "ROUTE", "KIND", "TARGET"
+    )
+    .unwrap();
+    for (route, kind, target) in rows {
+        let source = if derived.contains(&route) {
+            "convention"
+        } else {
+            "config"
+        };
+        writeln!(out, "{:<24} {:<12} {:<32} {}", route, kind, target, source).unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ApplicationConfig;
+    use crate::hashmap;
+
+    #[test]
+    fn test_route_table() {
+        let mut config = Config::new_default();
+        config.static_routes = Some(hashmap!["/".to_owned() => "public".to_owned()]);
+        config.applications = Some(vec![ApplicationConfig {
+            path: "apps/blog/app.py".to_owned(),
+            name: "app".to_owned(),
+            route: Some("/blog".to_owned()),
+            ..Default::default()
+        }]);
+
+        let table = route_table(&config, &["/blog".to_owned()]);
+        let lines: Vec<_> = table.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("ROUTE "));
+        assert!(lines[1].starts_with("/ ") && lines[1].ends_with(" config"));
+        assert!(lines[2].contains("apps/blog/app.py:app") && lines[2].ends_with(" convention"));
+    }
+}
@@ -0,0 +1,11 @@
+use std::{error::Error, path::PathBuf};
+
+use super::load_config;
+use crate::server::Server;
+
+/// `serve` loads the configuration and runs the Gee server until it stops.
+pub async fn serve(config_path: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let config = load_config(config_path)?;
+
+    Server::new(config).start().await
+}
@@ -0,0 +1,22 @@
+use std::{error::Error, net::SocketAddr, path::PathBuf};
+
+use super::load_config;
+use crate::server::check_startup;
+
+/// `verify` loads the configuration and prints it if it is valid. With
+/// `startup`, the checks the server runs at startup are run too, except that
+/// the address a running server is `listening` at need not be free.
+pub fn verify(
+    config_path: Option<PathBuf>,
+    startup: bool,
+    listening: Option<SocketAddr>,
+) -> Result<(), Box<dyn Error>> {
+    let config = load_config(config_path)?;
+    if startup {
+        check_startup(&config, listening)?;
+    }
+
+    println!("Configuration is valid.\n\n{}", config);
+
+    Ok(())
+}
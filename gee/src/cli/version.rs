@@ -0,0 +1,17 @@
+use std::error::Error;
+
+use crate::version::VersionInfo;
+
+/// `version` prints the version of Gee, its build, and the Python it is
+/// linked against, as JSON if `json` is set.
+pub fn version(json: bool) -> Result<(), Box<dyn Error>> {
+    let info = VersionInfo::current(true);
+
+    if json {
+        println!("{}", info.to_json());
+    } else {
+        println!("{}", info);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use hyper::{header::HeaderMap, Body, Request};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::SystemTime,
+};
+
+/// `X_FORWARDED_FOR` lists the addresses a request was forwarded from.
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// `ClientInfo` holds what Gee knows about the client a request was received
+/// from. It is part of the `RequestContext` attached to each request.
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    /// `remote_addr` is the address of the peer the request was received
+    /// from, if known.
+    pub remote_addr: Option<SocketAddr>,
+
+    /// `client_ip` is the address of the client after trusted proxies have
+    /// been resolved.
+    pub client_ip: Option<IpAddr>,
+
+    /// `received` is the time the request was received.
+    pub received: SystemTime,
+
+    /// `country` is the ISO country code of the client, if known.
+    pub country: Option<String>,
+
+    /// `asn` is the autonomous system number of the client, if known.
+    pub asn: Option<u32>,
+}
+
+/// `TrustedProxies` are the addresses whose `X-Forwarded-For` headers are
+/// believed.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    /// `ranges` are the trusted networks, as an address and prefix length.
+    ranges: Vec<(IpAddr, u8)>,
+}
+
+impl ClientInfo {
+    /// `from_request` describes the client a request was received from
+    /// through `remote_addr`.
+    pub fn from_request(
+        req: &Request<Body>,
+        remote_addr: Option<SocketAddr>,
+        trusted_proxies: &TrustedProxies,
+    ) -> Self {
+        Self {
+            remote_addr,
+            client_ip: remote_addr.map(|addr| trusted_proxies.resolve(addr.ip(), req.headers())),
+            received: SystemTime::now(),
+            country: None,
+            asn: None,
+        }
+    }
+}
+
+impl TrustedProxies {
+    /// `new` parses the addresses and CIDR ranges of the trusted proxies.
+    pub fn new(proxies: &[String]) -> Result<Self, String> {
+        let ranges = proxies
+            .iter()
+            .map(|proxy| {
+                parse_range(proxy).ok_or_else(|| format!("Invalid trusted proxy `{}`", proxy))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { ranges })
+    }
+
+    /// `contains` returns whether an address is a trusted proxy.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges
+            .iter()
+            .any(|(network, prefix)| in_range(ip, *network, *prefix))
+    }
+
+    /// `resolve` finds the address of the client behind the proxies. Starting
+    /// from the peer, the `X-Forwarded-For` chain is walked from the right
+    /// while the address is a trusted proxy, so that entries a client wrote
+    /// itself are never believed.
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer;
+        if !self.contains(client) {
+            return client;
+        }
+
+        let forwarded = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+
+        for entry in forwarded.into_iter().rev() {
+            match entry.parse::<IpAddr>() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+            if !self.contains(client) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+/// `parse_range` parses an address or a CIDR range.
+fn parse_range(range: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix) = match range.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+        None => (range, None),
+    };
+
+    let address = address.parse::<IpAddr>().ok()?;
+    let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max_prefix);
+
+    (prefix <= max_prefix).then_some((address, prefix))
+}
+
+/// `in_range` returns whether `ip` belongs to the network of `prefix` bits.
+fn in_range(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            (u32::from(ip) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+
+    let mask =
+        u128::MAX.checked_shl(u32::from(bits - prefix)).unwrap_or(0) & (u128::MAX >> (128 - bits));
+
+    ip & mask == network & mask
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_range() {
+        assert!(TrustedProxies::new(&["10.0.0.0/8".to_owned(), "::1".to_owned()]).is_ok());
+        assert!(TrustedProxies::new(&["10.0.0.0/33".to_owned()]).is_err());
+        assert!(TrustedProxies::new(&["proxy.local".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let proxies = TrustedProxies::new(&[
+            "10.0.0.0/8".to_owned(),
+            "192.168.1.1".to_owned(),
+            "fd00::/8".to_owned(),
+        ])
+        .unwrap();
+
+        assert!(proxies.contains("10.20.30.40".parse().unwrap()));
+        assert!(proxies.contains("192.168.1.1".parse().unwrap()));
+        assert!(!proxies.contains("192.168.1.2".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(!proxies.contains("fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8".to_owned()]).unwrap();
+        let headers = forwarded_for("1.1.1.1, 2.2.2.2, 10.0.0.2");
+
+        assert_eq!(
+            proxies.resolve("10.0.0.1".parse().unwrap(), &headers),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            proxies.resolve("3.3.3.3".parse().unwrap(), &headers),
+            "3.3.3.3".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            proxies.resolve("10.0.0.1".parse().unwrap(), &HeaderMap::new()),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -1,7 +1,4 @@
 use serde::{Deserialize, Serialize};
-use serde_json;
-use serde_yaml;
-use toml;
 
 use std::{
     collections::HashMap,
@@ -9,11 +6,33 @@ use std::{
     fmt::{self, Display},
     fs::read_to_string,
     net::{IpAddr, SocketAddr},
-    path::Path,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
 use crate::hashmap;
 
+/// `CONFIG_FILE_NAMES` are the file names Gee looks for when no configuration
+/// file is given, in order of precedence.
+const CONFIG_FILE_NAMES: [&str; 4] = ["gee.toml", "gee.json", "gee.yaml", "gee.yml"];
+
+/// `CONVENTION_STATIC_DIR` is the directory of the `root_dir` served at `/`
+/// by convention.
+const CONVENTION_STATIC_DIR: &str = "public";
+
+/// `CONVENTION_APPS_DIR` is the directory of the `root_dir` holding a
+/// directory for each application mounted by convention.
+const CONVENTION_APPS_DIR: &str = "apps";
+
+/// `CONVENTION_APP_FILE` is the file of an application mounted by convention,
+/// and `CONVENTION_APP_NAME` the name of its callable.
+const CONVENTION_APP_FILE: &str = "app.py";
+const CONVENTION_APP_NAME: &str = "app";
+
+/// `DEFAULT_INSPECT_LIMIT` is the number of bytes of a request body observed
+/// by the inspectors when no `inspect_limit` is configured.
+const DEFAULT_INSPECT_LIMIT: u64 = 64 * 1024;
+
 /// `Config` is the global, immutable configuration used to construct and run
 /// the Gee server.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -36,12 +55,877 @@ pub struct Config {
     pub ignored_files: Option<Vec<String>>,
 
     /// `application` is the relative path to a Python callable that will be
-    /// invoked to handle requests.
+    /// invoked to handle requests. Prefer `applications`, which this is
+    /// equivalent to an entry of mounted at `/`.
     pub application: Option<String>,
 
     /// `application_name` is the name of the callable that will be invoked to
     /// handle requests.
     pub application_name: Option<String>,
+
+    /// `applications` are the Python applications requests are passed to,
+    /// each mounted at its own route.
+    pub applications: Option<Vec<ApplicationConfig>>,
+
+    /// `allowed_hosts` are the host names the Gee server may be addressed by.
+    /// Requests whose `Host` does not match an entry are rejected before they
+    /// are routed. An entry beginning with `.` matches the domain and all of
+    /// its subdomains and `*` matches any host. If unset, any well-formed host
+    /// is accepted.
+    pub allowed_hosts: Option<Vec<String>>,
+
+    /// `application_etags` computes an `ETag` from the body of successful
+    /// application responses that do not set one, so that conditional
+    /// requests for them can be answered with a 304.
+    pub application_etags: Option<bool>,
+
+    /// `require_preconditions` are the request methods (e.g. `PUT`) that must
+    /// carry an `If-Match` or `If-Unmodified-Since` header. Requests using
+    /// these methods without one are rejected with a 428 before they are
+    /// routed, so clients cannot accidentally overwrite each other's changes.
+    pub require_preconditions: Option<Vec<String>>,
+
+    /// `workers` is the number of requests the Python applications may handle
+    /// at once, at least 1. Defaults to 8.
+    pub workers: Option<usize>,
+
+    /// `queue` configures the admission queue requests wait in for a worker.
+    pub queue: Option<QueueConfig>,
+
+    /// `handler_timeout` is the number of milliseconds the Python
+    /// applications have to answer a request, counted from when it is
+    /// received. Requests not answered in time get a 504. The deadline is
+    /// passed to the application so that it can bound its own calls.
+    /// Unlimited if unset.
+    pub handler_timeout: Option<u64>,
+
+    /// `metrics_path` is the path on the server at which the Gee server's own
+    /// metrics are served in the Prometheus text format. Metrics are not
+    /// served if unset.
+    pub metrics_path: Option<String>,
+
+    /// `traffic_accounting` counts the requests and the bytes received and
+    /// sent under each route and for each virtual host, exposing them at the
+    /// `metrics_path` and through `gee ctl top`.
+    pub traffic_accounting: Option<bool>,
+
+    /// `readiness_path` is the path on the server answered with a 200 while
+    /// the directories of every `critical` static route can be read, and a
+    /// 503 while one is missing. Readiness is not served if unset.
+    pub readiness_path: Option<String>,
+
+    /// `static_check_interval` is the number of seconds between checks that
+    /// the directories of the static routes can still be read, so that
+    /// missing ones are noticed and returning ones recovered. Must be at
+    /// least 1. Defaults to 5.
+    pub static_check_interval: Option<u64>,
+
+    /// `conventions` derives routes from the layout of the `root_dir`: the
+    /// `public` directory is served at `/` and the `app` callable of each
+    /// `apps/<name>/app.py` is mounted at `/<name>`. Configured routes take
+    /// precedence. `gee routes` prints the derived routes.
+    pub conventions: Option<bool>,
+
+    /// `capabilities_path` is the path on the server at which the routes,
+    /// methods, and features of the Gee server are served as JSON. They are
+    /// not served if unset.
+    pub capabilities_path: Option<String>,
+
+    /// `error_pages` map error statuses to the files, relative to the
+    /// `root_dir`, served in place of the empty bodies of error responses.
+    /// Their `{{path}}`, `{{status}}`, `{{request_id}}`, and `{{host}}`
+    /// placeholders are filled in for each response.
+    pub error_pages: Option<HashMap<String, String>>,
+
+    /// `diagnostics_dir` is the directory, relative to the `root_dir`, a
+    /// diagnostics bundle describing the server is written to when it
+    /// panics. No bundle is written if unset.
+    pub diagnostics_dir: Option<String>,
+
+    /// `log_buffer` is the number of recent log records kept in memory for
+    /// `gee ctl logs`. Defaults to 1000; 0 keeps none.
+    pub log_buffer: Option<usize>,
+
+    /// `max_body_size` is the number of bytes a request body may contain.
+    /// Larger requests are rejected with a 413. Unlimited if unset.
+    pub max_body_size: Option<u64>,
+
+    /// `max_header_size` is the number of bytes the names and values of a
+    /// request's headers may add up to. Larger requests are rejected with a
+    /// 431. Unlimited if unset.
+    pub max_header_size: Option<usize>,
+
+    /// `max_headers` is the number of headers a request may have. Requests
+    /// with more are rejected with a 431. Unlimited if unset.
+    pub max_headers: Option<usize>,
+
+    /// `routes` override settings for requests under a path on the server.
+    /// The entry with the longest `route` matching a request's path applies.
+    pub routes: Option<Vec<RouteConfig>>,
+
+    /// `access_log` configures the line logged for each request. Requests are
+    /// not logged if unset.
+    pub access_log: Option<AccessLogConfig>,
+
+    /// `well_known` declares the `robots.txt` and `security.txt` files Gee
+    /// serves when there are no such files on disk.
+    pub well_known: Option<WellKnownConfig>,
+
+    /// `trusted_proxies` are the addresses or CIDR ranges (e.g. `10.0.0.0/8`)
+    /// of proxies in front of the Gee server. The client address of requests
+    /// received from them is taken from `X-Forwarded-For`.
+    pub trusted_proxies: Option<Vec<String>>,
+
+    /// `decorations` are values Gee computes about each request and passes to
+    /// the applications.
+    pub decorations: Option<DecorationsConfig>,
+
+    /// `geoip` configures the databases clients are located with and the
+    /// countries and networks requests are accepted from.
+    pub geoip: Option<GeoipConfig>,
+
+    /// `verify_checksums` rejects requests with a 400 if their body does not
+    /// match a checksum sent in `Content-MD5`, `Digest`, or `Content-Digest`.
+    /// The body is hashed as it streams to its handler.
+    pub verify_checksums: Option<bool>,
+
+    /// `static_digests` adds `Digest` and `Content-Digest` headers with the
+    /// SHA-256 of the file to static responses.
+    pub static_digests: Option<bool>,
+
+    /// `debug_dump` logs the headers and the beginning of the body of each
+    /// request, up to the `inspect_limit`, for debugging clients.
+    pub debug_dump: Option<bool>,
+
+    /// `inspect_limit` is the number of bytes of a request body the debug
+    /// dump and other inspectors observe while it streams to its handler.
+    /// Defaults to 64 KiB.
+    pub inspect_limit: Option<u64>,
+
+    /// `waf` filters out requests for sensitive paths and requests carrying
+    /// attack signatures before they are routed. No requests are filtered if
+    /// unset.
+    pub waf: Option<WafConfig>,
+
+    /// `control_socket` is the path of the Unix socket `gee ctl` commands
+    /// the running server through. The server is not controllable if unset.
+    pub control_socket: Option<String>,
+
+    /// `timezone` is the IANA time zone (e.g. `Europe/Berlin`) schedules are
+    /// evaluated in. Defaults to the system time zone.
+    pub timezone: Option<String>,
+
+    /// `schedules` serve static content under a route during a window of
+    /// time, taking precedence over the route's usual handler.
+    pub schedules: Option<Vec<ScheduleConfig>>,
+
+    /// `file_cache` keeps frequently requested static files open so that
+    /// serving them skips opening and inspecting them. Files are opened for
+    /// every request if unset.
+    pub file_cache: Option<FileCacheConfig>,
+
+    /// `precompress` serves gzip and Brotli variants of static files to
+    /// clients that accept them, generating the missing variants at startup.
+    /// Files are only served as they are on disk if unset.
+    pub precompress: Option<PrecompressConfig>,
+}
+
+/// `ApplicationConfig` configures a Python application and the route it is
+/// mounted at.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ApplicationConfig {
+    /// `path` is the path, relative to the `root_dir`, of the Python file
+    /// containing the application.
+    pub path: String,
+
+    /// `name` is the name of the callable within the file that will be
+    /// invoked to handle requests.
+    pub name: String,
+
+    /// `route` is the path on the server under which requests are passed to
+    /// the application. Defaults to `/`.
+    pub route: Option<String>,
+
+    /// `strip_prefix` is removed from the beginning of the request path before
+    /// it is passed to the application and becomes its `SCRIPT_NAME`, so that
+    /// e.g. a request to `/api/v1/users` reaches the application as `/users`.
+    pub strip_prefix: Option<String>,
+
+    /// `rewrite` rules are applied to the path the application receives after
+    /// the prefix is stripped. Only the first rule whose pattern matches is
+    /// applied.
+    pub rewrite: Option<Vec<RewriteConfig>>,
+
+    /// `framework` is the web framework the application is built with: Flask,
+    /// Django, or Bottle. It is detected from the application if unset.
+    pub framework: Option<String>,
+
+    /// `django_settings_module` is set as `DJANGO_SETTINGS_MODULE` while a
+    /// Django application is imported. Django is set up once per process, so
+    /// applications naming different settings modules cannot be mounted
+    /// together.
+    pub django_settings_module: Option<String>,
+
+    /// `env` are environment variables set in `os.environ` while the
+    /// application is imported and passed in the WSGI environ of each of its
+    /// requests, so that applications needing different settings can be
+    /// mounted side by side.
+    pub env: Option<HashMap<String, String>>,
+
+    /// `coalesce` lets only the first of a set of identical, concurrent GET
+    /// requests without credentials reach the application, answering the
+    /// others with its response if it is shareable. Responses which set a
+    /// cookie or are marked `private` or `no-store` are not shared.
+    pub coalesce: Option<bool>,
+
+    /// `suspend_after` is the number of seconds without requests after which
+    /// the application is unloaded to free its memory. It is imported again
+    /// when the next request for it arrives. Never suspended if unset.
+    pub suspend_after: Option<u64>,
+
+    /// `cold_start_budget` is the number of milliseconds a request waits for
+    /// a suspended application to be imported again before it is answered
+    /// with a 503. The import carries on regardless. Defaults to 10000.
+    pub cold_start_budget: Option<u64>,
+}
+
+/// `RewriteConfig` replaces the part of a path matching a regular expression.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RewriteConfig {
+    /// `pattern` is the regular expression matched against the path.
+    pub pattern: String,
+
+    /// `replacement` replaces the match and may refer to capture groups of
+    /// the `pattern` as `$1`, `$name`, etc.
+    pub replacement: String,
+}
+
+/// `QueueConfig` configures the admission queue in front of the Python
+/// workers. Requests that would make the queue deeper than `max_depth` or that
+/// wait longer than `max_wait` are answered with a 503. The queue is unbounded
+/// if neither is set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// `max_depth` is the number of requests that may wait for a worker.
+    pub max_depth: Option<usize>,
+
+    /// `max_wait` is the number of milliseconds a request may wait for a
+    /// worker.
+    pub max_wait: Option<u64>,
+
+    /// `retry_after` is the number of seconds shed requests are told to wait
+    /// before retrying. Defaults to 1.
+    pub retry_after: Option<u64>,
+}
+
+/// `RouteConfig` overrides settings for requests under a path on the server,
+/// whether they are served from a static route or an application.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct RouteConfig {
+    /// `route` is the path on the server the settings apply under.
+    pub route: String,
+
+    /// `max_body_size` overrides the global `max_body_size`.
+    pub max_body_size: Option<u64>,
+
+    /// `max_header_size` overrides the global `max_header_size`.
+    pub max_header_size: Option<usize>,
+
+    /// `max_headers` overrides the global `max_headers`.
+    pub max_headers: Option<usize>,
+
+    /// `allow_countries` overrides the global `geoip.allow_countries`.
+    pub allow_countries: Option<Vec<String>>,
+
+    /// `deny_countries` overrides the global `geoip.deny_countries`.
+    pub deny_countries: Option<Vec<String>>,
+
+    /// `handler_timeout` overrides the global `handler_timeout`.
+    pub handler_timeout: Option<u64>,
+
+    /// `static_digests` overrides the global `static_digests`.
+    pub static_digests: Option<bool>,
+
+    /// `debug_dump` overrides the global `debug_dump`.
+    pub debug_dump: Option<bool>,
+
+    /// `inspect_limit` overrides the global `inspect_limit`.
+    pub inspect_limit: Option<u64>,
+
+    /// `mirror` sends a copy of the requests under the route to a shadow
+    /// target.
+    pub mirror: Option<MirrorConfig>,
+
+    /// `critical` marks a static route whose directory must be readable for
+    /// the server to report itself ready at the `readiness_path`.
+    pub critical: Option<bool>,
+
+    /// `unavailable_status` is the status requests under a static route are
+    /// answered with while its directory is missing. Defaults to 503.
+    pub unavailable_status: Option<u16>,
+
+    /// `unavailable_page` is a file, relative to the `root_dir`, served in
+    /// place of the files of a static route while its directory is missing.
+    /// It is read when the server starts, so that it outlives the directory.
+    pub unavailable_page: Option<String>,
+
+    /// `methods` are the request methods accepted under the route, e.g.
+    /// `["POST"]` for webhooks. Requests using other methods are answered
+    /// with a 405 listing these in `Allow` before they are handled. `HEAD` is
+    /// accepted wherever `GET` is. Any method is accepted if unset.
+    pub methods: Option<Vec<String>>,
+
+    /// `enabled` can be set to false to answer every request under the route
+    /// with its `disabled_status` instead of handling it. Routes can also be
+    /// disabled and enabled at runtime with `gee ctl route`.
+    pub enabled: Option<bool>,
+
+    /// `disabled_status` is the status requests under the route are answered
+    /// with while it is disabled. Defaults to 503.
+    pub disabled_status: Option<u16>,
+
+    /// `transforms` rewrite the bodies of HTML responses under the route, e.g.
+    /// to inject an analytics snippet or an environment banner.
+    pub transforms: Option<Vec<TransformConfig>>,
+
+    /// `max_transform_size` is the size of the largest HTML response that is
+    /// transformed. Larger responses are passed through unchanged. Defaults
+    /// to 1 MiB.
+    pub max_transform_size: Option<u64>,
+
+    /// `signing_secret` requires static files under the route to be requested
+    /// with an unexpired `expires` and `signature` issued with this secret,
+    /// e.g. by an application handing out time-limited download links.
+    pub signing_secret: Option<String>,
+
+    /// `max_bandwidth` is the number of bytes per second the responses under
+    /// the route are sent at, shared between all of them. Unlimited if unset.
+    pub max_bandwidth: Option<u64>,
+
+    /// `bandwidth_burst` is the number of bytes that may be sent at once
+    /// after the route has been idle. Defaults to `max_bandwidth`.
+    pub bandwidth_burst: Option<u64>,
+
+    /// `asset_manifest` is the path on the server a manifest of the files of
+    /// the static route at `route` is served at. The manifest maps each file
+    /// to a fingerprinted path it is also served at, cached for good, along
+    /// with its integrity hash. It is generated when the server starts.
+    pub asset_manifest: Option<String>,
+
+    /// `negotiation` serves language and type variants of the static files
+    /// under the route, chosen by the `Accept-Language` and `Accept` headers.
+    pub negotiation: Option<NegotiationConfig>,
+
+    /// `faults` injects latency, errors, and dropped connections into the
+    /// requests under the route, to test how clients cope with them.
+    pub faults: Option<FaultConfig>,
+
+    /// `digest_auth` requires the requests under the route to authenticate
+    /// with HTTP Digest authentication, so that passwords are never sent in
+    /// the clear.
+    pub digest_auth: Option<DigestAuthConfig>,
+
+    /// `openapi` is an OpenAPI 3 document, in JSON or YAML and relative to
+    /// the `root_dir`, which the requests under the route are validated
+    /// against before they reach the application. Requests to undocumented
+    /// paths are answered with a 404, with undocumented methods with a 405,
+    /// and lacking required parameters or sending the wrong content type with
+    /// a 400.
+    pub openapi: Option<String>,
+
+    /// `cache` keeps the successful responses to GET requests under the
+    /// route, answering identical requests from it until they expire.
+    pub cache: Option<ResponseCacheConfig>,
+}
+
+/// `ResponseCacheConfig` configures the cache of a route's responses. Only
+/// `200` responses to GET requests without credentials are cached, and
+/// responses setting cookies, marked `private` or `no-store`, or varying on
+/// headers other than `Host` and the `Accept` headers never are.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ResponseCacheConfig {
+    /// `storage` is where the responses are kept. Defaults to `memory`.
+    pub storage: Option<CacheStorageKind>,
+
+    /// `dir` is the directory the `disk` storage keeps the responses in,
+    /// relative to the `root_dir`. It is created if it does not exist.
+    pub dir: Option<String>,
+
+    /// `ttl` is the number of seconds a response is served from the cache.
+    /// Defaults to 60.
+    pub ttl: Option<u64>,
+
+    /// `max_entries` is the number of responses kept. Defaults to 1000.
+    pub max_entries: Option<usize>,
+
+    /// `max_bytes` is the total size in bytes of the responses the `disk`
+    /// storage keeps. Defaults to 256 MiB.
+    pub max_bytes: Option<u64>,
+
+    /// `max_body_size` is the size in bytes of the largest body cached.
+    /// Defaults to 1 MiB.
+    pub max_body_size: Option<usize>,
+}
+
+/// `CacheStorageKind` is where a response cache keeps its responses.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheStorageKind {
+    /// `Memory` keeps them in the process, losing them when it restarts.
+    #[default]
+    Memory,
+
+    /// `Disk` keeps them in files, so that they survive restarts and are not
+    /// limited by the memory of the process.
+    Disk,
+}
+
+/// `DigestAuthConfig` configures HTTP Digest authentication (RFC 7616) with
+/// the SHA-256 algorithm. Passwords are not stored: each user is given the
+/// hash of `user:realm:password`, e.g. from
+/// `printf 'alice:internal:s3cret' | sha256sum`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DigestAuthConfig {
+    /// `realm` is shown to users when they are asked for their credentials
+    /// and is part of the hash of their passwords.
+    pub realm: String,
+
+    /// `users` maps user names to the hex SHA-256 hash of
+    /// `user:realm:password`.
+    pub users: HashMap<String, String>,
+
+    /// `nonce_lifetime` is the number of seconds a nonce issued to a client
+    /// may be used for before it must fetch a new one. Defaults to 300.
+    pub nonce_lifetime: Option<u64>,
+}
+
+/// `FaultConfig` configures the faults injected into a share of the requests
+/// under a route. Faults can be switched on and off at runtime with
+/// `gee ctl faults`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct FaultConfig {
+    /// `enabled` can be set to false to configure the faults without
+    /// injecting them until they are enabled at runtime. Defaults to true.
+    pub enabled: Option<bool>,
+
+    /// `latency` is the number of milliseconds the affected requests are
+    /// delayed by before they are handled.
+    pub latency: Option<u64>,
+
+    /// `latency_percent` is the percentage of requests delayed. Defaults to
+    /// 100.
+    pub latency_percent: Option<f64>,
+
+    /// `error_percent` is the percentage of requests answered with the
+    /// `error_status` instead of being handled.
+    pub error_percent: Option<f64>,
+
+    /// `error_status` is the status injected errors are answered with.
+    /// Defaults to 500.
+    pub error_status: Option<u16>,
+
+    /// `drop_percent` is the percentage of requests whose connection is
+    /// closed without an answer.
+    pub drop_percent: Option<f64>,
+}
+
+/// `NegotiationConfig` lists the variants a static file may have on disk.
+/// Language variants carry the language as an extra extension, e.g.
+/// `index.html.de`, and type variants are siblings with a different
+/// extension, e.g. `report.json` and `report.html` for `/report`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct NegotiationConfig {
+    /// `languages` are the language tags of the language variants, e.g.
+    /// `["en", "de"]`.
+    pub languages: Option<Vec<String>>,
+
+    /// `default_language` is served when none of the languages is
+    /// acceptable. Defaults to the first of the `languages`.
+    pub default_language: Option<String>,
+
+    /// `types` are the extensions of the type variants, e.g.
+    /// `["html", "json"]`.
+    pub types: Option<Vec<String>>,
+
+    /// `default_type` is served when none of the types is acceptable.
+    /// Defaults to the first of the `types`.
+    pub default_type: Option<String>,
+}
+
+/// `TransformConfig` replaces either a regular expression or a literal token
+/// in HTML responses. Replacements are applied one line at a time as the
+/// response streams, so a match cannot span lines.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TransformConfig {
+    /// `pattern` is the regular expression matched against the response.
+    pub pattern: Option<String>,
+
+    /// `token` is the literal text matched against the response, e.g.
+    /// `</head>` or `{{ENVIRONMENT}}`.
+    pub token: Option<String>,
+
+    /// `replacement` replaces every match and may refer to capture groups of
+    /// the `pattern` as `$1`, `$name`, etc.
+    pub replacement: String,
+}
+
+/// `MirrorConfig` configures the shadow target copies of requests are sent
+/// to. The client is always answered by the primary handler, and the
+/// response of the shadow target is discarded.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct MirrorConfig {
+    /// `mount` is the route of the application the copies are passed to.
+    pub mount: Option<String>,
+
+    /// `upstream` is the `http://` URL the copies are sent to, with the
+    /// request path appended.
+    pub upstream: Option<String>,
+
+    /// `sample_percent` is the percentage of requests that are copied.
+    /// Defaults to 100.
+    pub sample_percent: Option<f64>,
+
+    /// `max_body_size` is the size of the largest body that is copied.
+    /// Requests with larger bodies are not mirrored.
+    pub max_body_size: Option<u64>,
+
+    /// `forwarded` is how the `Forwarded` and `X-Forwarded-*` headers sent to
+    /// an `upstream` describe the client. Defaults to `append`.
+    pub forwarded: Option<ForwardedMode>,
+}
+
+/// `WafConfig` configures the rules requests are filtered with. A request
+/// matching a rule is refused in `enforce` mode and only logged in `log`
+/// mode, so that rules can be tried against real traffic first.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct WafConfig {
+    /// `mode` is `enforce` or `log`. Defaults to `enforce`.
+    pub mode: Option<WafMode>,
+
+    /// `default_rules` can be set to false to leave out the built-in rules,
+    /// which block version control and environment files, path traversal,
+    /// and common SQL injection signatures. Defaults to true.
+    pub default_rules: Option<bool>,
+
+    /// `blocked_paths` are regular expressions matched against the decoded
+    /// path of each request.
+    pub blocked_paths: Option<Vec<String>>,
+
+    /// `blocked_queries` are regular expressions matched against the decoded
+    /// query string of each request.
+    pub blocked_queries: Option<Vec<String>>,
+
+    /// `max_headers` is the number of headers a request may have before it
+    /// is considered hostile. Unlike the global `max_headers`, it follows the
+    /// WAF's `mode`, so a limit can be tried out in `log` mode first.
+    /// Unlimited if unset.
+    pub max_headers: Option<usize>,
+
+    /// `status` is the status refused requests are answered with. Defaults
+    /// to 403.
+    pub status: Option<u16>,
+}
+
+/// `WafMode` is what happens to a request matching a WAF rule.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WafMode {
+    /// `Enforce` refuses the request.
+    #[default]
+    Enforce,
+
+    /// `Log` logs the match and handles the request as usual.
+    Log,
+}
+
+/// `ForwardedMode` is how Gee describes the client of a request it forwards
+/// to another server.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardedMode {
+    /// `Append` adds Gee's peer to the `Forwarded` and `X-Forwarded-For`
+    /// chains the request arrived with.
+    #[default]
+    Append,
+
+    /// `Replace` discards the chains the request arrived with and sends only
+    /// the client resolved through `trusted_proxies`.
+    Replace,
+}
+
+/// `AccessLogConfig` configures the access log. Only one in every
+/// `sample_every` successful requests is logged, while every request answered
+/// with an error status is, so that high-traffic deployments can keep log
+/// volume down without losing sight of errors.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccessLogConfig {
+    /// `format` is the line logged for each request, with fields written as
+    /// `{field}` (e.g. `{method} {path} {status}`). Takes precedence over
+    /// `fields`.
+    pub format: Option<String>,
+
+    /// `fields` are logged separated by spaces, in the order given.
+    pub fields: Option<Vec<String>>,
+
+    /// `sample_every` is the number of successful requests logged as one.
+    /// Defaults to 1, logging every request.
+    pub sample_every: Option<u64>,
+
+    /// `path` is the file, relative to the `root_dir`, lines are appended to.
+    /// Lines are written to standard output if unset.
+    pub path: Option<String>,
+
+    /// `destinations` split the requests for some hosts or routes off into
+    /// their own logs. A request is logged to the first destination matching
+    /// it, or to this log if none does.
+    pub destinations: Option<Vec<AccessLogDestinationConfig>>,
+}
+
+/// `AccessLogDestinationConfig` configures a log for the requests to a host,
+/// under a route, or both. Its `format`, `fields`, and `sample_every` default
+/// to those of the main access log.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccessLogDestinationConfig {
+    /// `host` is the virtual host whose requests are logged here, without a
+    /// port. Requests to any host are if unset.
+    pub host: Option<String>,
+
+    /// `route` is the path under which requests are logged here. Requests
+    /// under any path are if unset.
+    pub route: Option<String>,
+
+    /// `path` is the file, relative to the `root_dir`, lines are appended to.
+    /// Lines are written to standard output if unset.
+    pub path: Option<String>,
+
+    /// `format` overrides the `format` of the main access log.
+    pub format: Option<String>,
+
+    /// `fields` overrides the `fields` of the main access log.
+    pub fields: Option<Vec<String>>,
+
+    /// `sample_every` overrides the `sample_every` of the main access log.
+    pub sample_every: Option<u64>,
+}
+
+/// `WellKnownConfig` declares the contents of the files Gee synthesizes and
+/// serves at their standard paths.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct WellKnownConfig {
+    /// `robots` are the rules served as `/robots.txt`.
+    pub robots: Option<Vec<RobotsConfig>>,
+
+    /// `sitemaps` are the sitemap URLs listed in `/robots.txt`.
+    pub sitemaps: Option<Vec<String>>,
+
+    /// `security` is served as `/.well-known/security.txt`.
+    pub security: Option<SecurityConfig>,
+}
+
+/// `RobotsConfig` is a group of `robots.txt` rules for a crawler.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RobotsConfig {
+    /// `user_agent` is the crawler the rules apply to, or `*` for all.
+    pub user_agent: String,
+
+    /// `allow` are the paths the crawler may visit.
+    pub allow: Option<Vec<String>>,
+
+    /// `disallow` are the paths the crawler may not visit.
+    pub disallow: Option<Vec<String>>,
+
+    /// `crawl_delay` is the number of seconds the crawler should wait between
+    /// requests.
+    pub crawl_delay: Option<u64>,
+}
+
+/// `SecurityConfig` holds the fields of a `security.txt` file as described by
+/// RFC 9116.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SecurityConfig {
+    /// `contact` are the URIs security issues may be reported to.
+    pub contact: Vec<String>,
+
+    /// `expires` is the date and time after which the file should be
+    /// considered stale, e.g. `2026-12-31T23:59:59Z`.
+    pub expires: String,
+
+    /// `encryption` are the URIs of keys reports may be encrypted with.
+    pub encryption: Option<Vec<String>>,
+
+    /// `acknowledgments` are the URIs of pages thanking the reporters of past
+    /// issues.
+    pub acknowledgments: Option<Vec<String>>,
+
+    /// `preferred_languages` is a comma-separated list of the languages
+    /// reports may be written in, e.g. `en, de`.
+    pub preferred_languages: Option<String>,
+
+    /// `canonical` are the URIs the file is published at.
+    pub canonical: Option<Vec<String>>,
+
+    /// `policy` are the URIs of the policies for reporting issues.
+    pub policy: Option<Vec<String>>,
+
+    /// `hiring` are the URIs of security-related job openings.
+    pub hiring: Option<Vec<String>>,
+}
+
+/// `DecorationsConfig` selects the values computed about a request that are
+/// passed to the applications. Each is set in the environ as `gee.<value>`
+/// (e.g. `gee.client_ip`), and as an `X-Gee-<Value>` header if `headers` is
+/// set. Values that are unknown for a request are left out.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DecorationsConfig {
+    /// `values` are the values passed to the applications.
+    pub values: Vec<Decoration>,
+
+    /// `headers` also passes the values as request headers, replacing any the
+    /// client sent under the same names.
+    pub headers: Option<bool>,
+}
+
+/// `Decoration` is a value Gee may compute about a request.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decoration {
+    /// `RequestStart` is the time the request was received, in seconds since
+    /// the Unix epoch.
+    RequestStart,
+
+    /// `ClientIp` is the address of the client after `trusted_proxies` have
+    /// been resolved.
+    ClientIp,
+
+    /// `GeoCountry` is the ISO country code of the client.
+    GeoCountry,
+
+    /// `GeoAsn` is the autonomous system number of the client.
+    GeoAsn,
+}
+
+/// `ScheduleConfig` serves requests under a route from a static directory
+/// or file while its window is active, e.g. a "we're closed" page outside of
+/// business hours. Windows whose `end` is before their `start` run overnight.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ScheduleConfig {
+    /// `route` is the path on the server the schedule applies under.
+    pub route: String,
+
+    /// `start` is the time of day the window opens, as `HH:MM`.
+    pub start: String,
+
+    /// `end` is the time of day the window closes, as `HH:MM`.
+    pub end: String,
+
+    /// `days` are the days of the week (e.g. `mon`, `sat`) the window opens
+    /// on. Defaults to every day.
+    pub days: Option<Vec<String>>,
+
+    /// `static_dir` is the directory requests are served from during the
+    /// window, in the same way as a static route.
+    pub static_dir: Option<String>,
+
+    /// `file` is served in response to every request during the window.
+    pub file: Option<String>,
+}
+
+/// `FileCacheConfig` configures the cache of open static files. A cached file
+/// is checked against the file on disk at most once every `revalidate_after`
+/// and reopened if it has been modified or replaced.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FileCacheConfig {
+    /// `max_files` is the number of files kept open. The least recently used
+    /// file is closed to make room for another. Defaults to 1024.
+    pub max_files: Option<usize>,
+
+    /// `idle_ttl` is the number of seconds a file is kept open after it was
+    /// last served. Defaults to 60.
+    pub idle_ttl: Option<u64>,
+
+    /// `revalidate_after` is the number of milliseconds a file is served
+    /// without being checked against the file on disk. Defaults to 1000.
+    pub revalidate_after: Option<u64>,
+}
+
+/// `PrecompressConfig` configures the compressed variants of static files.
+/// A variant is served from beside the file, e.g. `app.js.br` for `app.js`,
+/// or else from the `cache_dir`, as long as it is newer than the file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PrecompressConfig {
+    /// `cache_dir` is the directory, relative to the `root_dir`, the
+    /// generated variants are written to.
+    pub cache_dir: String,
+
+    /// `generate` walks the static routes when the server starts and
+    /// compresses the files without an up-to-date variant into the
+    /// `cache_dir`. Set to false to only serve the variants produced by the
+    /// build. Defaults to true.
+    pub generate: Option<bool>,
+
+    /// `encodings` are the content codings served, in order of preference:
+    /// `br` and `gzip`. Defaults to both.
+    pub encodings: Option<Vec<String>>,
+
+    /// `extensions` are the extensions of the files compressed, e.g. `css`.
+    /// Defaults to common text formats.
+    pub extensions: Option<Vec<String>>,
+
+    /// `min_size` is the number of bytes a file must have to be compressed.
+    /// Defaults to 1024.
+    pub min_size: Option<u64>,
+
+    /// `max_size` is the number of bytes a file may have to be compressed.
+    /// Defaults to 16 MiB.
+    pub max_size: Option<u64>,
+}
+
+/// `GeoipConfig` configures the MaxMind-format databases requests are tagged
+/// from with the country and autonomous system of the client, and the rules
+/// deciding which clients are served. Requests from clients the rules exclude
+/// are answered with a 451.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GeoipConfig {
+    /// `country_database` is the path to a GeoIP2 or GeoLite2 Country or City
+    /// database.
+    pub country_database: Option<String>,
+
+    /// `asn_database` is the path to a GeoLite2 ASN database.
+    pub asn_database: Option<String>,
+
+    /// `allow_countries` are the ISO country codes requests are accepted
+    /// from. If set, requests from other or unknown countries are refused.
+    pub allow_countries: Option<Vec<String>>,
+
+    /// `deny_countries` are the ISO country codes requests are refused from.
+    pub deny_countries: Option<Vec<String>>,
+
+    /// `deny_asns` are the autonomous system numbers requests are refused
+    /// from.
+    pub deny_asns: Option<Vec<u32>>,
+}
+
+/// `GeoRules` decide which clients the requests to a path are accepted from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GeoRules<'a> {
+    pub allow_countries: Option<&'a [String]>,
+    pub deny_countries: Option<&'a [String]>,
+    pub deny_asns: Option<&'a [u32]>,
+}
+
+/// `Limits` are the size limits that apply to a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_body_size: Option<u64>,
+    pub max_header_size: Option<usize>,
+    pub max_headers: Option<usize>,
+}
+
+impl ApplicationConfig {
+    /// `route` returns the route the application is mounted at.
+    pub fn route(&self) -> &str {
+        self.route.as_deref().unwrap_or("/")
+    }
 }
 
 impl Config {
@@ -63,6 +947,41 @@ impl Config {
             ignored_files,
             application,
             application_name,
+            applications: None,
+            allowed_hosts: None,
+            application_etags: None,
+            require_preconditions: None,
+            workers: None,
+            queue: None,
+            handler_timeout: None,
+            metrics_path: None,
+            capabilities_path: None,
+            error_pages: None,
+            diagnostics_dir: None,
+            log_buffer: None,
+            max_body_size: None,
+            max_header_size: None,
+            max_headers: None,
+            routes: None,
+            access_log: None,
+            well_known: None,
+            trusted_proxies: None,
+            decorations: None,
+            geoip: None,
+            verify_checksums: None,
+            static_digests: None,
+            control_socket: None,
+            timezone: None,
+            schedules: None,
+            file_cache: None,
+            precompress: None,
+            debug_dump: None,
+            inspect_limit: None,
+            waf: None,
+            traffic_accounting: None,
+            readiness_path: None,
+            static_check_interval: None,
+            conventions: None,
         }
     }
 
@@ -101,9 +1020,97 @@ impl Config {
         }
     }
 
+    /// `from_working_dir` creates a new `Config` instance from the first of
+    /// `gee.toml`, `gee.json`, `gee.yaml`, or `gee.yml` found in the current
+    /// directory. If none of these exist, the default configuration is used.
+    pub fn from_working_dir() -> Result<Self, Box<dyn Error>> {
+        match Self::working_dir_file() {
+            Some(path) => Self::from_file(path),
+            None => Ok(Self::new_default()),
+        }
+    }
+
+    /// `working_dir_file` returns the first of `gee.toml`, `gee.json`,
+    /// `gee.yaml`, or `gee.yml` found in the current directory.
+    pub fn working_dir_file() -> Option<&'static Path> {
+        CONFIG_FILE_NAMES
+            .iter()
+            .map(Path::new)
+            .find(|path| path.is_file())
+    }
+
+    /// `apply_conventions` adds the routes derived from the layout of the
+    /// `root_dir` if `conventions` is set, skipping any route already
+    /// configured, and returns the routes it added.
+    pub fn apply_conventions(&mut self) -> Vec<String> {
+        if self.conventions != Some(true) {
+            return vec![];
+        }
+
+        let root_dir = Path::new(&self.root_dir);
+        let mut taken: Vec<String> = self
+            .static_routes
+            .iter()
+            .flatten()
+            .map(|(route, _)| route.clone())
+            .chain(
+                self.all_applications()
+                    .iter()
+                    .map(|application| application.route().to_owned()),
+            )
+            .collect();
+        let mut derived = Vec::new();
+
+        if root_dir.join(CONVENTION_STATIC_DIR).is_dir() && !taken.iter().any(|route| route == "/")
+        {
+            self.static_routes
+                .get_or_insert_with(HashMap::new)
+                .insert("/".to_owned(), format!("{}/", CONVENTION_STATIC_DIR));
+            taken.push("/".to_owned());
+            derived.push("/".to_owned());
+        }
+
+        let mut names: Vec<String> = std::fs::read_dir(root_dir.join(CONVENTION_APPS_DIR))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().join(CONVENTION_APP_FILE).is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        for name in names {
+            let route = format!("/{}", name);
+            if taken.contains(&route) {
+                continue;
+            }
+
+            self.applications
+                .get_or_insert_with(Vec::new)
+                .push(ApplicationConfig {
+                    path: format!("{}/{}/{}", CONVENTION_APPS_DIR, name, CONVENTION_APP_FILE),
+                    name: CONVENTION_APP_NAME.to_owned(),
+                    route: Some(route.clone()),
+                    ..Default::default()
+                });
+            taken.push(route.clone());
+            derived.push(route);
+        }
+
+        derived
+    }
+
+    /// `uses_legacy_application` returns whether the configuration uses the
+    /// `application` and `application_name` settings, which `gee config
+    /// migrate` rewrites as an entry of `applications`.
+    pub fn uses_legacy_application(&self) -> bool {
+        self.application.is_some() || self.application_name.is_some()
+    }
+
     // `to_toml` returns the TOML representation of the `Config` instance.
     pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
-        toml::to_string(self).map_err(|e| e.into())
+        // Converting to a `toml::Value` first orders tables after plain values,
+        // as TOML requires.
+        toml::to_string(&toml::Value::try_from(self)?).map_err(|e| e.into())
     }
 
     // `to_json` returns the JSON representation of the `Config` instance.
@@ -122,11 +1129,185 @@ impl Config {
         SocketAddr::new(self.address, self.port)
     }
 
-    /// `is_static_path` returns whether the given path is a static route.
-    /// This is used to determine if a request to this path should be handled
-    /// by the `StaticHandler`.
-    pub fn is_static_path(&self, path: &str) -> bool {
-        self.static_routes.is_some() && self.static_routes.as_ref().unwrap().contains_key(path)
+    /// `all_applications` returns the configured `applications` along with
+    /// the application configured by `application` and `application_name`,
+    /// if any.
+    pub fn all_applications(&self) -> Vec<ApplicationConfig> {
+        let mut applications = self.applications.clone().unwrap_or_default();
+
+        if let (Some(path), Some(name)) = (&self.application, &self.application_name) {
+            applications.push(ApplicationConfig {
+                path: path.clone(),
+                name: name.clone(),
+                ..Default::default()
+            });
+        }
+
+        applications
+    }
+
+    /// `route_config` returns the `routes` entry with the longest route the
+    /// given path falls under.
+    pub fn route_config(&self, path: &str) -> Option<&RouteConfig> {
+        self.route_config_with(path, |_| true)
+    }
+
+    /// `route_config_with` returns the `routes` entry with the longest route
+    /// the given path falls under among those matching `has`. Protections such
+    /// as authentication look their route up this way, so that they extend to
+    /// more specific routes configured under it for other reasons.
+    pub fn route_config_with(
+        &self,
+        path: &str,
+        has: impl Fn(&RouteConfig) -> bool,
+    ) -> Option<&RouteConfig> {
+        self.routes
+            .as_ref()?
+            .iter()
+            .filter(|route| is_under(path, &route.route) && has(route))
+            .max_by_key(|route| route.route.len())
+    }
+
+    /// `limits` returns the size limits for requests to the given path, taking
+    /// overrides from its `routes` entry over the global limits.
+    pub fn limits(&self, path: &str) -> Limits {
+        let route = self.route_config(path);
+
+        Limits {
+            max_body_size: route
+                .and_then(|route| route.max_body_size)
+                .or(self.max_body_size),
+            max_header_size: route
+                .and_then(|route| route.max_header_size)
+                .or(self.max_header_size),
+            max_headers: route
+                .and_then(|route| route.max_headers)
+                .or(self.max_headers),
+        }
+    }
+
+    /// `handler_timeout` returns how long the applications have to answer
+    /// requests to the given path, taking the override from its `routes`
+    /// entry over the global timeout.
+    pub fn handler_timeout(&self, path: &str) -> Option<Duration> {
+        self.route_config(path)
+            .and_then(|route| route.handler_timeout)
+            .or(self.handler_timeout)
+            .map(Duration::from_millis)
+    }
+
+    /// `geo_rules` returns the rules deciding which clients requests to the
+    /// given path are accepted from, taking overrides from its `routes` entry
+    /// over the global rules.
+    pub fn geo_rules(&self, path: &str) -> GeoRules<'_> {
+        let route = self.route_config(path);
+        let geoip = self.geoip.as_ref();
+
+        GeoRules {
+            allow_countries: route
+                .and_then(|route| route.allow_countries.as_deref())
+                .or_else(|| geoip.and_then(|geoip| geoip.allow_countries.as_deref())),
+            deny_countries: route
+                .and_then(|route| route.deny_countries.as_deref())
+                .or_else(|| geoip.and_then(|geoip| geoip.deny_countries.as_deref())),
+            deny_asns: geoip.and_then(|geoip| geoip.deny_asns.as_deref()),
+        }
+    }
+
+    /// `static_digests` returns whether static responses to the given path
+    /// carry digests of their body.
+    pub fn static_digests(&self, path: &str) -> bool {
+        self.route_config(path)
+            .and_then(|route| route.static_digests)
+            .or(self.static_digests)
+            == Some(true)
+    }
+
+    /// `debug_dump` returns whether requests to the given path are dumped to
+    /// the log.
+    pub fn debug_dump(&self, path: &str) -> bool {
+        self.route_config(path)
+            .and_then(|route| route.debug_dump)
+            .or(self.debug_dump)
+            == Some(true)
+    }
+
+    /// `inspect_limit` returns the number of bytes of the bodies of requests
+    /// to the given path that are inspected.
+    pub fn inspect_limit(&self, path: &str) -> u64 {
+        self.route_config(path)
+            .and_then(|route| route.inspect_limit)
+            .or(self.inspect_limit)
+            .unwrap_or(DEFAULT_INSPECT_LIMIT)
+    }
+
+    /// `signing_secret` returns the secret static files under the given path
+    /// must be requested with a signature made with, if they must be.
+    pub fn signing_secret(&self, path: &str) -> Option<&str> {
+        self.route_config_with(path, |route| route.signing_secret.is_some())?
+            .signing_secret
+            .as_deref()
+    }
+
+    /// `negotiation` returns the variants the static files under the given
+    /// path may have, if they are negotiated.
+    pub fn negotiation(&self, path: &str) -> Option<&NegotiationConfig> {
+        self.route_config(path)?.negotiation.as_ref()
+    }
+
+    /// `control_socket_path` returns the path of the control socket, resolved
+    /// against the `root_dir`, if one is configured.
+    pub fn control_socket_path(&self) -> Option<PathBuf> {
+        self.control_socket
+            .as_ref()
+            .map(|control_socket| Path::new(&self.root_dir).join(control_socket))
+    }
+
+    /// `diagnostics_dir_path` resolves the `diagnostics_dir` against the
+    /// `root_dir`.
+    pub fn diagnostics_dir_path(&self) -> Option<PathBuf> {
+        self.diagnostics_dir
+            .as_ref()
+            .map(|diagnostics_dir| Path::new(&self.root_dir).join(diagnostics_dir))
+    }
+
+    /// `precompress_cache_dir` resolves the `cache_dir` of the compressed
+    /// variants against the `root_dir`.
+    pub fn precompress_cache_dir(&self) -> Option<PathBuf> {
+        self.precompress
+            .as_ref()
+            .map(|precompress| Path::new(&self.root_dir).join(&precompress.cache_dir))
+    }
+
+    /// `requires_precondition` returns whether requests using the given method
+    /// must carry a precondition.
+    pub fn requires_precondition(&self, method: &str) -> bool {
+        self.require_preconditions.as_ref().is_some_and(|methods| {
+            methods
+                .iter()
+                .any(|required| required.eq_ignore_ascii_case(method))
+        })
+    }
+
+    /// `static_route` returns the longest static route the given path falls
+    /// under along with the directory it maps to. This is used to determine
+    /// if a request to this path should be handled by the `StaticHandler`.
+    pub fn static_route(&self, path: &str) -> Option<(&str, &str)> {
+        self.static_routes
+            .as_ref()?
+            .iter()
+            .filter(|(route, _)| is_under(path, route))
+            .max_by_key(|(route, _)| route.len())
+            .map(|(route, dir)| (route.as_str(), dir.as_str()))
+    }
+}
+
+/// `is_under` returns whether a path falls under a route: it is the route, or
+/// continues it past a `/`, so that `/adminx` is not under `/admin`.
+pub fn is_under(path: &str, route: &str) -> bool {
+    match path.strip_prefix(route) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || route.ends_with('/'),
+        None => false,
     }
 }
 
@@ -139,6 +1320,41 @@ impl PartialEq for Config {
             && self.ignored_files == other.ignored_files
             && self.application == other.application
             && self.application_name == other.application_name
+            && self.applications == other.applications
+            && self.allowed_hosts == other.allowed_hosts
+            && self.application_etags == other.application_etags
+            && self.require_preconditions == other.require_preconditions
+            && self.workers == other.workers
+            && self.handler_timeout == other.handler_timeout
+            && self.queue == other.queue
+            && self.metrics_path == other.metrics_path
+            && self.capabilities_path == other.capabilities_path
+            && self.error_pages == other.error_pages
+            && self.diagnostics_dir == other.diagnostics_dir
+            && self.log_buffer == other.log_buffer
+            && self.max_body_size == other.max_body_size
+            && self.max_header_size == other.max_header_size
+            && self.max_headers == other.max_headers
+            && self.routes == other.routes
+            && self.access_log == other.access_log
+            && self.well_known == other.well_known
+            && self.trusted_proxies == other.trusted_proxies
+            && self.decorations == other.decorations
+            && self.geoip == other.geoip
+            && self.verify_checksums == other.verify_checksums
+            && self.static_digests == other.static_digests
+            && self.control_socket == other.control_socket
+            && self.timezone == other.timezone
+            && self.schedules == other.schedules
+            && self.file_cache == other.file_cache
+            && self.precompress == other.precompress
+            && self.debug_dump == other.debug_dump
+            && self.inspect_limit == other.inspect_limit
+            && self.waf == other.waf
+            && self.traffic_accounting == other.traffic_accounting
+            && self.readiness_path == other.readiness_path
+            && self.static_check_interval == other.static_check_interval
+            && self.conventions == other.conventions
     }
 }
 
@@ -163,6 +1379,7 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
         let actual = Config::new(
@@ -188,6 +1405,7 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
         let actual = Config::new_default();
@@ -199,7 +1417,7 @@ mod test {
     fn test_from_file_with_nonexistent_file() {
         let path = Path::new("/tmp/gee_config.toml");
 
-        assert!(Config::from_file(&path).is_err());
+        assert!(Config::from_file(path).is_err());
     }
 
     #[test]
@@ -214,9 +1432,10 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
-        let actual = Config::from_file(&path).unwrap();
+        let actual = Config::from_file(path).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -233,9 +1452,10 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
-        let actual = Config::from_file(&path).unwrap();
+        let actual = Config::from_file(path).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -252,9 +1472,10 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
-        let actual = Config::from_file(&path).unwrap();
+        let actual = Config::from_file(path).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -271,9 +1492,10 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
-        let actual = Config::from_file(&path).unwrap();
+        let actual = Config::from_file(path).unwrap();
 
         assert_eq!(expected, actual);
     }
@@ -282,17 +1504,7 @@ mod test {
     fn test_from_file_with_config_invalid_00() {
         let path = Path::new("./src/fixtures/test_config_invalid_00.toml");
 
-        let expected = Config {
-            address: IpAddr::from([127, 0, 0, 1]),
-            port: 8080,
-            root_dir: ".".to_string(),
-            static_routes: Some(hashmap!("/".to_owned() => "./".to_owned())),
-            ignored_files: None,
-            application: None,
-            application_name: None,
-        };
-
-        let actual = Config::from_file(&path);
+        let actual = Config::from_file(path);
         assert!(actual.is_err());
     }
 
@@ -308,6 +1520,7 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
         let actual = config.socket_address();
@@ -315,20 +1528,244 @@ mod test {
     }
 
     #[test]
-    fn test_is_socket_path() {
+    fn test_static_route() {
         let config = Config {
             address: IpAddr::from([127, 0, 0, 1]),
             port: 8080,
             root_dir: ".".to_string(),
-            static_routes: Some(hashmap!["/static".to_owned() => "./static/".to_owned()]),
+            static_routes: Some(hashmap![
+                "/static".to_owned() => "./static/".to_owned(),
+                "/static/vendor".to_owned() => "./node_modules/".to_owned()
+            ]),
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
+        };
+
+        assert_eq!(
+            config.static_route("/static"),
+            Some(("/static", "./static/"))
+        );
+        assert_eq!(
+            config.static_route("/static/hello.txt"),
+            Some(("/static", "./static/"))
+        );
+        assert_eq!(
+            config.static_route("/static/vendor/react.js"),
+            Some(("/static/vendor", "./node_modules/"))
+        );
+        assert_eq!(config.static_route("/"), None);
+        assert_eq!(config.static_route("/foo"), None);
+        assert_eq!(config.static_route("/static_backup/x"), None);
+    }
+
+    #[test]
+    fn test_from_file_with_config_valid_04() {
+        let path = Path::new("./src/fixtures/test_config_valid_04.toml");
+
+        let expected = Config {
+            address: IpAddr::from([127, 0, 0, 1]),
+            port: 8080,
+            root_dir: ".".to_string(),
+            static_routes: None,
+            ignored_files: None,
+            application: None,
+            application_name: None,
+            applications: Some(vec![ApplicationConfig {
+                path: "app/app.py".to_string(),
+                name: "simple_app".to_string(),
+                route: Some("/api".to_string()),
+                strip_prefix: Some("/api/v1".to_string()),
+                rewrite: Some(vec![RewriteConfig {
+                    pattern: "^/people(/.*)?$".to_string(),
+                    replacement: "/users$1".to_string(),
+                }]),
+                ..Default::default()
+            }]),
+            ..Config::new_default()
+        };
+
+        let actual = Config::from_file(path).unwrap();
+
+        assert_eq!(expected, actual);
+        assert_eq!(
+            toml::from_str::<Config>(&actual.to_toml().unwrap()).unwrap(),
+            actual
+        );
+    }
+
+    #[test]
+    fn test_all_applications() {
+        let mut config = Config::new_default();
+        config.application = Some("app.py".to_string());
+        config.application_name = Some("application".to_string());
+        config.applications = Some(vec![ApplicationConfig {
+            path: "api.py".to_string(),
+            name: "application".to_string(),
+            route: Some("/api".to_string()),
+            ..Default::default()
+        }]);
+
+        let applications = config.all_applications();
+
+        assert_eq!(applications.len(), 2);
+        assert_eq!(applications[0].route(), "/api");
+        assert_eq!(applications[1].path, "app.py");
+        assert_eq!(applications[1].route(), "/");
+    }
+
+    #[test]
+    fn test_apply_conventions() {
+        let dir = std::env::temp_dir().join(format!("gee-conventions-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("public")).unwrap();
+        for name in ["blog", "api", "notes"] {
+            std::fs::create_dir_all(dir.join("apps").join(name)).unwrap();
+        }
+        std::fs::write(dir.join("apps/blog/app.py"), "").unwrap();
+        std::fs::write(dir.join("apps/api/app.py"), "").unwrap();
+
+        let mut config = Config::new_default();
+        config.root_dir = dir.to_string_lossy().into_owned();
+        config.static_routes = None;
+        assert!(config.apply_conventions().is_empty());
+
+        config.conventions = Some(true);
+        config.applications = Some(vec![ApplicationConfig {
+            path: "api.py".to_string(),
+            name: "application".to_string(),
+            route: Some("/api".to_string()),
+            ..Default::default()
+        }]);
+        assert_eq!(config.apply_conventions(), vec!["/", "/blog"]);
+        assert_eq!(
+            config.static_routes,
+            Some(hashmap!["/".to_owned() => "public/".to_owned()])
+        );
+
+        let applications = config.all_applications();
+        assert_eq!(applications.len(), 2);
+        assert_eq!(applications[0].path, "api.py");
+        assert_eq!(applications[1].path, "apps/blog/app.py");
+        assert_eq!(applications[1].name, "app");
+        assert_eq!(applications[1].route(), "/blog");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_route_config_with() {
+        let mut config = Config::new_default();
+        config.routes = Some(vec![
+            RouteConfig {
+                route: "/admin".to_owned(),
+                signing_secret: Some("secret".to_owned()),
+                ..RouteConfig::default()
+            },
+            RouteConfig {
+                route: "/admin/uploads".to_owned(),
+                max_body_size: Some(1024),
+                ..RouteConfig::default()
+            },
+        ]);
+
+        assert_eq!(
+            config.route_config("/admin/uploads/a.png").unwrap().route,
+            "/admin/uploads"
+        );
+        assert_eq!(
+            config.signing_secret("/admin/uploads/a.png"),
+            Some("secret")
+        );
+        assert_eq!(config.signing_secret("/admin"), Some("secret"));
+        assert!(config.route_config("/adminx").is_none());
+        assert!(config.signing_secret("/adminx/a.png").is_none());
+    }
+
+    #[test]
+    fn test_is_under() {
+        assert!(is_under("/admin", "/admin"));
+        assert!(is_under("/admin/users", "/admin"));
+        assert!(is_under("/static/app.js", "/static/"));
+        assert!(is_under("/anything", "/"));
+        assert!(!is_under("/adminx", "/admin"));
+        assert!(!is_under("/", "/admin"));
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = Config {
+            max_body_size: Some(1024),
+            max_headers: Some(50),
+            routes: Some(vec![
+                RouteConfig {
+                    route: "/upload".to_string(),
+                    max_body_size: Some(1_000_000),
+                    ..Default::default()
+                },
+                RouteConfig {
+                    route: "/upload/avatars".to_string(),
+                    max_body_size: Some(10_000),
+                    max_header_size: Some(4096),
+                    ..Default::default()
+                },
+            ]),
+            ..Config::new_default()
         };
 
-        assert!(config.is_static_path("/static"));
-        assert!(!config.is_static_path("/"));
-        assert!(!config.is_static_path("/foo"));
+        assert_eq!(
+            config.limits("/"),
+            Limits {
+                max_body_size: Some(1024),
+                max_header_size: None,
+                max_headers: Some(50),
+            }
+        );
+        assert_eq!(
+            config.limits("/upload/files"),
+            Limits {
+                max_body_size: Some(1_000_000),
+                max_header_size: None,
+                max_headers: Some(50),
+            }
+        );
+        assert_eq!(
+            config.limits("/upload/avatars/1"),
+            Limits {
+                max_body_size: Some(10_000),
+                max_header_size: Some(4096),
+                max_headers: Some(50),
+            }
+        );
+    }
+
+    #[test]
+    fn test_geo_rules() {
+        let config = Config {
+            geoip: Some(GeoipConfig {
+                deny_countries: Some(vec!["XX".to_string()]),
+                deny_asns: Some(vec![64512]),
+                ..Default::default()
+            }),
+            routes: Some(vec![RouteConfig {
+                route: "/eu".to_string(),
+                allow_countries: Some(vec!["DE".to_string(), "FR".to_string()]),
+                ..Default::default()
+            }]),
+            ..Config::new_default()
+        };
+
+        let rules = config.geo_rules("/");
+        assert_eq!(rules.allow_countries, None);
+        assert_eq!(rules.deny_countries, Some(&["XX".to_string()][..]));
+        assert_eq!(rules.deny_asns, Some(&[64512][..]));
+
+        let rules = config.geo_rules("/eu/shop");
+        assert_eq!(
+            rules.allow_countries,
+            Some(&["DE".to_string(), "FR".to_string()][..])
+        );
+        assert_eq!(rules.deny_countries, Some(&["XX".to_string()][..]));
     }
 
     #[test]
@@ -341,6 +1778,7 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
         let config2 = Config {
@@ -351,6 +1789,7 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
         assert_eq!(config1, config2);
@@ -366,6 +1805,7 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
         let config2 = Config {
@@ -376,6 +1816,7 @@ mod test {
             ignored_files: None,
             application: None,
             application_name: None,
+            ..Config::new_default()
         };
 
         assert_ne!(config1, config2);
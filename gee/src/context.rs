@@ -0,0 +1,183 @@
+use hyper::{
+    header::{HeaderMap, HOST},
+    Body, Request,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::client::ClientInfo;
+use crate::trace::trace_id;
+
+/// `X_REQUEST_ID` identifies a request, if the client or a proxy sent one.
+const X_REQUEST_ID: &str = "x-request-id";
+
+/// `RequestContext` holds what Gee works out about a request once, when it is
+/// received, so that the routing checks, the handlers, and the access log all
+/// describe the request the same way. It is attached to each request as an
+/// extension before the request is routed.
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    /// `request_id` is the `X-Request-Id` of the request, or the ID of its
+    /// trace if it has none.
+    pub request_id: String,
+
+    /// `client` describes the client the request was received from.
+    pub client: ClientInfo,
+
+    /// `host` is the virtual host the request was addressed to, without its
+    /// port, if it named one.
+    pub host: Option<String>,
+
+    /// `route` is the static route, application, or schedule the request
+    /// falls under, if any.
+    pub route: Option<String>,
+
+    /// `received` is when the request was received, from which the time
+    /// taken to answer it is measured.
+    pub received: Instant,
+
+    /// `deadline` is when the request must be answered by, if its route has
+    /// a `handler_timeout`.
+    pub deadline: Option<Instant>,
+}
+
+impl RequestContext {
+    /// `new` describes a request received from `client`, falling under
+    /// `route`. The request must already carry its trace context.
+    pub fn new(req: &Request<Body>, client: ClientInfo, route: Option<String>) -> Self {
+        Self {
+            request_id: request_id(req.headers()),
+            client,
+            host: host(req),
+            route,
+            received: Instant::now(),
+            deadline: None,
+        }
+    }
+
+    /// `with_timeout` sets the deadline of the request to `timeout` after it
+    /// was received.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            deadline: timeout.map(|timeout| self.received + timeout),
+            ..self
+        }
+    }
+
+    /// `remaining` returns the time left before the deadline, if there is
+    /// one.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// `deadline_time` returns the deadline as a time of day, to be passed to
+    /// other processes.
+    pub fn deadline_time(&self) -> Option<SystemTime> {
+        self.remaining()
+            .map(|remaining| SystemTime::now() + remaining)
+    }
+
+    /// `of` returns the context attached to a request, if it has one.
+    pub fn of(req: &Request<Body>) -> Option<&Self> {
+        req.extensions().get::<Arc<Self>>().map(Arc::as_ref)
+    }
+}
+
+/// `request_id` returns the `X-Request-Id` of a request, or the ID of its
+/// trace if it has none.
+fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .or_else(|| trace_id(headers))
+        .unwrap_or_default()
+}
+
+/// `host` returns the host a request was addressed to, lowercased and
+/// without its port, from its `Host` header or else its URI.
+fn host(req: &Request<Body>) -> Option<String> {
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| req.uri().host())?;
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+
+    Some(host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn client() -> ClientInfo {
+        ClientInfo {
+            remote_addr: None,
+            client_ip: None,
+            received: SystemTime::now(),
+            country: None,
+            asn: None,
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let req = Request::builder()
+            .uri("/static/app.css")
+            .header(HOST, "Example.com:8080")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(Body::empty())
+            .unwrap();
+        let context = RequestContext::new(&req, client(), Some("/static".to_owned()));
+
+        assert_eq!(context.request_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.host.as_deref(), Some("example.com"));
+        assert_eq!(context.route.as_deref(), Some("/static"));
+
+        let req = Request::builder()
+            .uri("http://[::1]:8080/")
+            .header(X_REQUEST_ID, "req-1")
+            .body(Body::empty())
+            .unwrap();
+        let context = RequestContext::new(&req, client(), None);
+
+        assert_eq!(context.request_id, "req-1");
+        assert_eq!(context.host.as_deref(), Some("[::1]"));
+    }
+
+    #[test]
+    fn test_of() {
+        let mut req = Request::new(Body::empty());
+        assert!(RequestContext::of(&req).is_none());
+
+        let context = RequestContext::new(&req, client(), None);
+        req.extensions_mut().insert(Arc::new(context));
+        assert!(RequestContext::of(&req).is_some());
+    }
+
+    #[test]
+    fn test_with_timeout() {
+        let req = Request::new(Body::empty());
+        let context = RequestContext::new(&req, client(), None);
+        assert_eq!(context.remaining(), None);
+        assert_eq!(context.deadline_time(), None);
+
+        let context = context.with_timeout(Some(Duration::from_secs(30)));
+        assert_eq!(
+            context.deadline,
+            Some(context.received + Duration::from_secs(30))
+        );
+        assert!(context.remaining().unwrap() <= Duration::from_secs(30));
+        assert!(context.deadline_time().unwrap() > SystemTime::now());
+    }
+}
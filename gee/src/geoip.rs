@@ -0,0 +1,56 @@
+use maxminddb::{geoip2, Reader};
+use std::{net::IpAddr, path::Path};
+
+use crate::config::GeoipConfig;
+
+/// `GeoIp` locates clients by their address using MaxMind-format databases.
+pub struct GeoIp {
+    /// `country` is the database countries are looked up in, if configured.
+    country: Option<Reader<Vec<u8>>>,
+
+    /// `asn` is the database autonomous systems are looked up in, if
+    /// configured.
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// `new` opens the databases named by the config, resolving relative paths
+    /// against the `root_dir`.
+    pub fn new(config: &GeoipConfig, root_dir: &str) -> Result<Self, String> {
+        let open = |database: &Option<String>| {
+            database
+                .as_ref()
+                .map(|database| {
+                    let path = Path::new(root_dir).join(database);
+                    Reader::open_readfile(&path).map_err(|e| {
+                        format!("Cannot open GeoIP database {}: {}", path.display(), e)
+                    })
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            country: open(&config.country_database)?,
+            asn: open(&config.asn_database)?,
+        })
+    }
+
+    /// `country` returns the ISO code of the country an address is located
+    /// in, if it is known.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let country: geoip2::Country = self.country.as_ref()?.lookup(ip).ok()?;
+
+        country
+            .country
+            .and_then(|country| country.iso_code)
+            .map(str::to_owned)
+    }
+
+    /// `asn` returns the number of the autonomous system an address belongs
+    /// to, if it is known.
+    pub fn asn(&self, ip: IpAddr) -> Option<u32> {
+        let asn: geoip2::Asn = self.asn.as_ref()?.lookup(ip).ok()?;
+
+        asn.autonomous_system_number
+    }
+}
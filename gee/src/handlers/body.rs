@@ -0,0 +1,68 @@
+use hyper::{body::HttpBody, Body};
+
+/// `BodyError` enumerates the reasons a request body could not be read.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body was larger than the limit.
+    TooLarge,
+
+    /// The body could not be received from the client.
+    Read(hyper::Error),
+}
+
+/// `read_body` receives the body of a request chunk by chunk, giving up as soon
+/// as it grows larger than `limit` bytes rather than buffering all of it.
+pub async fn read_body(mut body: Body, limit: Option<u64>) -> Result<Vec<u8>, BodyError> {
+    let mut content = Vec::new();
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(BodyError::Read)?;
+
+        if limit.is_some_and(|limit| (content.len() + chunk.len()) as u64 > limit) {
+            return Err(BodyError::TooLarge);
+        }
+        content.extend_from_slice(&chunk);
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn streamed(chunks: Vec<&'static str>) -> Body {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in chunks {
+                if sender.send_data(chunk.into()).await.is_err() {
+                    return;
+                }
+            }
+        });
+        body
+    }
+
+    #[tokio::test]
+    async fn test_read_body() {
+        let content = read_body(streamed(vec!["hello ", "world"]), Some(11))
+            .await
+            .unwrap();
+
+        assert_eq!(content, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_read_body_too_large() {
+        let result = read_body(streamed(vec!["hello ", "world"]), Some(10)).await;
+
+        assert!(matches!(result, Err(BodyError::TooLarge)));
+    }
+
+    #[tokio::test]
+    async fn test_read_body_unlimited() {
+        let content = read_body(Body::from("hello"), None).await.unwrap();
+
+        assert_eq!(content, b"hello");
+    }
+}
@@ -0,0 +1,232 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::header::{HeaderMap, HeaderName};
+use md5::Md5;
+use sha2::{digest::DynDigest, Digest, Sha256, Sha512};
+
+use super::conditional::header_str;
+use super::inspect::{Inspector, Outcome};
+
+/// `CONTENT_MD5` carries the base64 MD5 of a body, as described by RFC 1864.
+const CONTENT_MD5: &str = "content-md5";
+
+/// `DIGEST` carries `algorithm=base64` checksums of a body, as described by
+/// RFC 3230.
+pub const DIGEST: &str = "digest";
+
+/// `CONTENT_DIGEST` carries `algorithm=:base64:` checksums of a body, as
+/// described by RFC 9530.
+pub const CONTENT_DIGEST: &str = "content-digest";
+
+/// `ChecksumInspector` verifies a request body against every checksum its
+/// request carries, hashing the body chunk by chunk as it streams to its
+/// handler rather than buffering it.
+pub struct ChecksumInspector {
+    /// `checksums` are the checksums the body must match.
+    checksums: Vec<Checksum>,
+
+    /// `unreadable` names a checksum header which is not valid text, so that
+    /// no body can match it.
+    unreadable: Option<&'static str>,
+}
+
+/// `Checksum` is a checksum a body must match and the hasher computing it.
+struct Checksum {
+    /// `header` is the name of the header the checksum was sent in.
+    header: &'static str,
+
+    /// `expected` is the base64 checksum sent by the client.
+    expected: String,
+
+    /// `hasher` hashes the body as it is received.
+    hasher: Box<dyn DynDigest + Send>,
+}
+
+impl ChecksumInspector {
+    /// `new` returns the inspector verifying a request body against the
+    /// checksums sent in `Content-MD5`, `Digest`, or `Content-Digest` using an
+    /// algorithm Gee supports, or `None` if the request carries none.
+    /// Checksums using other algorithms are ignored.
+    pub fn new(req_headers: &HeaderMap) -> Option<Self> {
+        let mut checksums = Vec::new();
+        let mut unreadable = None;
+
+        if let Some(expected) = header_str(req_headers, HeaderName::from_static(CONTENT_MD5)) {
+            checksums.push(Checksum {
+                header: "Content-MD5",
+                expected: expected.trim().to_owned(),
+                hasher: hasher("md5").unwrap(),
+            });
+        }
+
+        for (name, header) in [(DIGEST, "Digest"), (CONTENT_DIGEST, "Content-Digest")] {
+            for value in req_headers.get_all(name) {
+                let value = match value.to_str() {
+                    Ok(value) => value,
+                    Err(_) => {
+                        unreadable = unreadable.or(Some(header));
+                        continue;
+                    }
+                };
+
+                for entry in value.split(',') {
+                    let (algorithm, expected) = match entry.split_once('=') {
+                        Some((algorithm, expected)) => (algorithm.trim(), expected.trim()),
+                        None => continue,
+                    };
+
+                    if let Some(hasher) = hasher(algorithm) {
+                        checksums.push(Checksum {
+                            header,
+                            expected: expected.trim_matches(':').to_owned(),
+                            hasher,
+                        });
+                    }
+                }
+            }
+        }
+
+        if checksums.is_empty() && unreadable.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            checksums,
+            unreadable,
+        })
+    }
+}
+
+impl Inspector for ChecksumInspector {
+    fn limit(&self) -> Option<u64> {
+        None
+    }
+
+    fn inspect(&mut self, chunk: &[u8]) {
+        for checksum in self.checksums.iter_mut() {
+            checksum.hasher.update(chunk);
+        }
+    }
+
+    fn verdict(&mut self) -> Result<(), String> {
+        let mismatch = self.unreadable.or_else(|| {
+            self.checksums.iter_mut().find_map(|checksum| {
+                let actual = STANDARD.encode(checksum.hasher.finalize_reset());
+                (actual != checksum.expected).then_some(checksum.header)
+            })
+        });
+
+        match mismatch {
+            Some(header) => Err(format!("{} does not match the body", header)),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(self: Box<Self>, _outcome: Outcome) {}
+}
+
+/// `digest_headers` returns the `Digest` and `Content-Digest` values
+/// describing a body with its SHA-256.
+pub fn digest_headers(body: &[u8]) -> [(&'static str, String); 2] {
+    let sha256 = checksum("sha-256", body).unwrap();
+
+    [
+        (DIGEST, format!("sha-256={}", sha256)),
+        (CONTENT_DIGEST, format!("sha-256=:{}:", sha256)),
+    ]
+}
+
+/// `checksum` computes the base64 checksum of a body with the named
+/// algorithm, if Gee supports it.
+fn checksum(algorithm: &str, body: &[u8]) -> Option<String> {
+    let mut hasher = hasher(algorithm)?;
+    hasher.update(body);
+
+    Some(STANDARD.encode(hasher.finalize()))
+}
+
+/// `hasher` returns a hasher for the named algorithm, if Gee supports it.
+fn hasher(algorithm: &str) -> Option<Box<dyn DynDigest + Send>> {
+    match algorithm.to_ascii_lowercase().as_str() {
+        "md5" => Some(Box::new(Md5::new())),
+        "sha-256" => Some(Box::new(Sha256::new())),
+        "sha-512" => Some(Box::new(Sha512::new())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BODY: &[u8] = b"hello world";
+    const MD5: &str = "XrY7u+Ae7tCTyyK7j1rNww==";
+    const SHA256: &str = "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=";
+
+    fn headers(name: &str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            value.parse().unwrap(),
+        );
+        headers
+    }
+
+    /// `verify` shows the body to the inspector of a request in two chunks.
+    fn verify(req_headers: &HeaderMap, body: &[u8]) -> Result<(), String> {
+        let mut inspector = ChecksumInspector::new(req_headers).unwrap();
+        let (first, second) = body.split_at(body.len() / 2);
+        inspector.inspect(first);
+        inspector.inspect(second);
+
+        inspector.verdict()
+    }
+
+    fn mismatch(header: &str) -> Result<(), String> {
+        Err(format!("{} does not match the body", header))
+    }
+
+    #[test]
+    fn test_checksum_inspector_content_md5() {
+        assert_eq!(verify(&headers("Content-MD5", MD5), BODY), Ok(()));
+        assert_eq!(
+            verify(&headers("Content-MD5", MD5), b"goodbye world"),
+            mismatch("Content-MD5")
+        );
+    }
+
+    #[test]
+    fn test_checksum_inspector_digest() {
+        let digest = format!("SHA-256={}, unixsum=30637", SHA256);
+        assert_eq!(verify(&headers("Digest", &digest), BODY), Ok(()));
+        assert_eq!(
+            verify(&headers("Digest", &format!("md5={}", SHA256)), BODY),
+            mismatch("Digest")
+        );
+    }
+
+    #[test]
+    fn test_checksum_inspector_content_digest() {
+        let digest = format!("sha-256=:{}:", SHA256);
+        assert_eq!(verify(&headers("Content-Digest", &digest), BODY), Ok(()));
+        assert_eq!(
+            verify(&headers("Content-Digest", &digest), b""),
+            mismatch("Content-Digest")
+        );
+    }
+
+    #[test]
+    fn test_checksum_inspector_without_checksum() {
+        assert!(ChecksumInspector::new(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_digest_headers() {
+        assert_eq!(
+            digest_headers(BODY),
+            [
+                (DIGEST, format!("sha-256={}", SHA256)),
+                (CONTENT_DIGEST, format!("sha-256=:{}:", SHA256)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::time::SystemTime;
+
+use hyper::{
+    header::{
+        HeaderMap, CONTENT_LENGTH, ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        IF_UNMODIFIED_SINCE, LAST_MODIFIED,
+    },
+    Body, Method, Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+
+/// `etag` returns a strong entity tag derived from the content of a body.
+pub fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    format!("\"{}\"", hex)
+}
+
+/// `is_not_modified` returns whether the validators of a GET or HEAD request
+/// show that the client's cached copy of a successful response is still
+/// current. `If-None-Match` is evaluated against the `ETag` of the response and
+/// takes precedence over `If-Modified-Since`, which is evaluated against its
+/// `Last-Modified`.
+pub fn is_not_modified(
+    method: &Method,
+    req_headers: &HeaderMap,
+    response: &Response<Body>,
+) -> bool {
+    if (method != Method::GET && method != Method::HEAD) || response.status() != StatusCode::OK {
+        return false;
+    }
+
+    let resp_headers = response.headers();
+
+    if let Some(if_none_match) = header_str(req_headers, IF_NONE_MATCH) {
+        return match header_str(resp_headers, ETAG) {
+            Some(etag) => etag_list_matches(if_none_match, etag, false),
+            None => false,
+        };
+    }
+
+    match (
+        header_date(req_headers, IF_MODIFIED_SINCE),
+        header_date(resp_headers, LAST_MODIFIED),
+    ) {
+        (Some(if_modified_since), Some(last_modified)) => last_modified <= if_modified_since,
+        _ => false,
+    }
+}
+
+/// `is_precondition_failed` returns whether the `If-Match` or
+/// `If-Unmodified-Since` validators of a request are not satisfied by the
+/// response. `If-Match` is evaluated against the `ETag` of the response using
+/// strong comparison and takes precedence over `If-Unmodified-Since`, which is
+/// evaluated against its `Last-Modified` and ignored if there is none.
+pub fn is_precondition_failed(req_headers: &HeaderMap, response: &Response<Body>) -> bool {
+    let resp_headers = response.headers();
+
+    if let Some(if_match) = header_str(req_headers, IF_MATCH) {
+        if !response.status().is_success() {
+            return true;
+        }
+
+        return match header_str(resp_headers, ETAG) {
+            Some(etag) => !etag_list_matches(if_match, etag, true),
+            None => if_match.trim() != "*",
+        };
+    }
+
+    match (
+        header_date(req_headers, IF_UNMODIFIED_SINCE),
+        header_date(resp_headers, LAST_MODIFIED),
+    ) {
+        (Some(if_unmodified_since), Some(last_modified)) => last_modified > if_unmodified_since,
+        _ => false,
+    }
+}
+
+/// `has_precondition` returns whether a request carries an `If-Match` or
+/// `If-Unmodified-Since` header.
+pub fn has_precondition(req_headers: &HeaderMap) -> bool {
+    req_headers.contains_key(IF_MATCH) || req_headers.contains_key(IF_UNMODIFIED_SINCE)
+}
+
+/// `evaluate_conditionals` evaluates the validators of a request against the
+/// response in the order given by RFC 9110, replacing the response with a 412
+/// Precondition Failed or a 304 Not Modified if they call for one.
+pub fn evaluate_conditionals(
+    method: &Method,
+    req_headers: &HeaderMap,
+    response: Response<Body>,
+) -> Response<Body> {
+    if is_precondition_failed(req_headers, &response) {
+        return precondition_failed(response);
+    }
+
+    if is_not_modified(method, req_headers, &response) {
+        return not_modified(response);
+    }
+
+    response
+}
+
+/// `precondition_failed` converts a response into an empty 412 Precondition
+/// Failed response that keeps its validators.
+pub fn precondition_failed(response: Response<Body>) -> Response<Body> {
+    let mut rsp = Response::builder().status(StatusCode::PRECONDITION_FAILED);
+    for name in [ETAG, LAST_MODIFIED] {
+        if let Some(value) = response.headers().get(&name) {
+            rsp = rsp.header(name, value);
+        }
+    }
+
+    rsp.body(Body::empty()).unwrap()
+}
+
+/// `not_modified` converts a response into a 304 Not Modified response that
+/// keeps its headers but drops its body.
+pub fn not_modified(response: Response<Body>) -> Response<Body> {
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Response::from_parts(parts, Body::empty())
+}
+
+/// `etag_list_matches` returns whether `etag` is matched by the list of entity
+/// tags in a conditional header. `*` matches any entity tag. Strong comparison
+/// requires both tags to be strong.
+pub fn etag_list_matches(list: &str, etag: &str, strong: bool) -> bool {
+    if list.trim() == "*" {
+        return true;
+    }
+
+    let (etag_is_weak, etag) = split_weak(etag.trim());
+
+    list.split(',').map(str::trim).any(|candidate| {
+        let (candidate_is_weak, candidate) = split_weak(candidate);
+        candidate == etag && (!strong || (!candidate_is_weak && !etag_is_weak))
+    })
+}
+
+/// `split_weak` separates the weakness indicator from an entity tag.
+fn split_weak(etag: &str) -> (bool, &str) {
+    match etag.strip_prefix("W/") {
+        Some(etag) => (true, etag),
+        None => (false, etag),
+    }
+}
+
+/// `header_str` returns the value of a header if it is present and valid.
+pub fn header_str(headers: &HeaderMap, name: hyper::header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// `header_date` returns the value of a header parsed as an HTTP date.
+pub fn header_date(headers: &HeaderMap, name: hyper::header::HeaderName) -> Option<SystemTime> {
+    header_str(headers, name).and_then(|value| httpdate::parse_http_date(value).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(headers: Vec<(&str, &str)>) -> Response<Body> {
+        let mut builder = Response::builder().status(StatusCode::OK);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(Body::from("hello")).unwrap()
+    }
+
+    fn request_headers(headers: Vec<(&'static str, &str)>) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(name, value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_etag() {
+        assert_eq!(etag(b"hello"), etag(b"hello"));
+        assert_ne!(etag(b"hello"), etag(b"world"));
+        assert!(etag(b"hello").starts_with('"') && etag(b"hello").ends_with('"'));
+    }
+
+    #[test]
+    fn test_is_not_modified_with_if_none_match() {
+        let response = response(vec![("ETag", "\"abc\"")]);
+
+        let matching = request_headers(vec![("If-None-Match", "\"xyz\", W/\"abc\"")]);
+        let different = request_headers(vec![("If-None-Match", "\"xyz\"")]);
+        let any = request_headers(vec![("If-None-Match", "*")]);
+
+        assert!(is_not_modified(&Method::GET, &matching, &response));
+        assert!(is_not_modified(&Method::HEAD, &any, &response));
+        assert!(!is_not_modified(&Method::GET, &different, &response));
+        assert!(!is_not_modified(&Method::POST, &matching, &response));
+    }
+
+    #[test]
+    fn test_is_not_modified_with_if_modified_since() {
+        let response = response(vec![("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT")]);
+
+        let later = request_headers(vec![("If-Modified-Since", "Wed, 21 Oct 2015 07:28:00 GMT")]);
+        let earlier = request_headers(vec![("If-Modified-Since", "Tue, 20 Oct 2015 07:28:00 GMT")]);
+        let precedence = request_headers(vec![
+            ("If-Modified-Since", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("If-None-Match", "\"abc\""),
+        ]);
+
+        assert!(is_not_modified(&Method::GET, &later, &response));
+        assert!(!is_not_modified(&Method::GET, &earlier, &response));
+        assert!(!is_not_modified(&Method::GET, &precedence, &response));
+    }
+
+    #[test]
+    fn test_is_precondition_failed_with_if_match() {
+        let response = response(vec![("ETag", "\"abc\"")]);
+
+        let matching = request_headers(vec![("If-Match", "\"xyz\", \"abc\"")]);
+        let weak = request_headers(vec![("If-Match", "W/\"abc\"")]);
+        let any = request_headers(vec![("If-Match", "*")]);
+
+        assert!(!is_precondition_failed(&matching, &response));
+        assert!(!is_precondition_failed(&any, &response));
+        assert!(is_precondition_failed(&weak, &response));
+
+        let missing = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+        assert!(is_precondition_failed(&any, &missing));
+    }
+
+    #[test]
+    fn test_is_precondition_failed_with_if_unmodified_since() {
+        let response = response(vec![("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT")]);
+
+        let later = request_headers(vec![(
+            "If-Unmodified-Since",
+            "Wed, 21 Oct 2015 07:28:00 GMT",
+        )]);
+        let earlier = request_headers(vec![(
+            "If-Unmodified-Since",
+            "Tue, 20 Oct 2015 07:28:00 GMT",
+        )]);
+
+        assert!(!is_precondition_failed(&later, &response));
+        assert!(is_precondition_failed(&earlier, &response));
+        assert!(!is_precondition_failed(&earlier, &self::response(vec![])));
+    }
+
+    #[test]
+    fn test_evaluate_conditionals() {
+        let headers = request_headers(vec![("If-Match", "\"xyz\""), ("If-None-Match", "\"abc\"")]);
+        let actual =
+            evaluate_conditionals(&Method::GET, &headers, response(vec![("ETag", "\"abc\"")]));
+        assert_eq!(actual.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(actual.headers().get(ETAG).unwrap(), "\"abc\"");
+
+        let headers = request_headers(vec![("If-Match", "\"abc\""), ("If-None-Match", "\"abc\"")]);
+        let actual =
+            evaluate_conditionals(&Method::GET, &headers, response(vec![("ETag", "\"abc\"")]));
+        assert_eq!(actual.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn test_not_modified() {
+        let response = not_modified(response(vec![("ETag", "\"abc\""), ("Content-Length", "5")]));
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(ETAG).unwrap(), "\"abc\"");
+        assert!(response.headers().get(CONTENT_LENGTH).is_none());
+    }
+}
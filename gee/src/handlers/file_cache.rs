@@ -0,0 +1,258 @@
+use hyper::body::Bytes;
+use log::debug;
+use std::{
+    collections::HashMap,
+    fs::{File, Metadata},
+    io,
+    os::unix::fs::{FileExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::FileCacheConfig;
+
+/// `FileCache` keeps static files open between requests, along with their
+/// metadata, so that serving a cached file skips opening and inspecting it.
+/// Files idle for longer than the `idle_ttl` are closed, as is the least
+/// recently used file once `max_files` are open.
+pub struct FileCache {
+    /// `max_files` is the number of files kept open.
+    max_files: usize,
+
+    /// `idle_ttl` is how long a file is kept open after it was last served.
+    idle_ttl: Duration,
+
+    /// `revalidate_after` is how long a file is served before it is checked
+    /// against the file on disk again.
+    revalidate_after: Duration,
+
+    /// `entries` are the open files, by path.
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+/// `Entry` is an open file in the cache.
+struct Entry {
+    /// `file` is the open file, read from at an offset so it can be shared.
+    file: Arc<File>,
+
+    /// `metadata` is the metadata of the file when it was opened.
+    metadata: Metadata,
+
+    /// `last_used` is when the file was last served.
+    last_used: Instant,
+
+    /// `validated` is when the file was last checked against the file on
+    /// disk.
+    validated: Instant,
+}
+
+impl FileCache {
+    /// `new` creates an empty cache.
+    pub fn new(config: &FileCacheConfig) -> Self {
+        Self {
+            max_files: config.max_files.unwrap_or(1024),
+            idle_ttl: Duration::from_secs(config.idle_ttl.unwrap_or(60)),
+            revalidate_after: Duration::from_millis(config.revalidate_after.unwrap_or(1000)),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `read` returns the content and metadata of the file at `path`, from
+    /// the cached open file if there is a valid one.
+    pub async fn read(self: Arc<Self>, path: PathBuf) -> io::Result<(Bytes, Metadata)> {
+        tokio::task::spawn_blocking(move || {
+            let (file, metadata) = self.open(&path, Instant::now())?;
+
+            let mut content = vec![0; metadata.len() as usize];
+            file.read_exact_at(&mut content, 0)?;
+
+            Ok((Bytes::from(content), metadata))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    /// `open` returns the open file at `path` and its metadata, opening it if
+    /// it is not cached or the cached file is no longer the file on disk.
+    fn open(&self, path: &Path, now: Instant) -> io::Result<(Arc<File>, Metadata)> {
+        if let Some(cached) = self.cached(path, now) {
+            return Ok(cached);
+        }
+
+        let file = Arc::new(File::open(path)?);
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a file"));
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| now.duration_since(entry.last_used) < self.idle_ttl);
+        if entries.len() >= self.max_files {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        if self.max_files > 0 {
+            entries.insert(
+                path.to_owned(),
+                Entry {
+                    file: file.clone(),
+                    metadata: metadata.clone(),
+                    last_used: now,
+                    validated: now,
+                },
+            );
+        }
+
+        Ok((file, metadata))
+    }
+
+    /// `cached` returns the cached open file at `path` if it has been used
+    /// within the `idle_ttl` and, once it is due to be checked again, is
+    /// still the file on disk.
+    fn cached(&self, path: &Path, now: Instant) -> Option<(Arc<File>, Metadata)> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(path)?;
+
+        let is_valid = now.duration_since(entry.last_used) < self.idle_ttl
+            && (now.duration_since(entry.validated) < self.revalidate_after
+                || std::fs::metadata(path)
+                    .is_ok_and(|metadata| is_same_file(&entry.metadata, &metadata)));
+        if !is_valid {
+            debug!("Closing cached file {}", path.display());
+            entries.remove(path);
+            return None;
+        }
+
+        if now.duration_since(entry.validated) >= self.revalidate_after {
+            entry.validated = now;
+        }
+        entry.last_used = now;
+
+        Some((entry.file.clone(), entry.metadata.clone()))
+    }
+}
+
+/// `is_same_file` returns whether two sets of metadata describe the same,
+/// unmodified file.
+fn is_same_file(cached: &Metadata, current: &Metadata) -> bool {
+    cached.dev() == current.dev()
+        && cached.ino() == current.ino()
+        && cached.len() == current.len()
+        && cached.modified().ok() == current.modified().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "gee-file-cache-{}-{}",
+                name,
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn cache(max_files: usize) -> FileCache {
+        FileCache::new(&FileCacheConfig {
+            max_files: Some(max_files),
+            idle_ttl: Some(60),
+            revalidate_after: Some(1000),
+        })
+    }
+
+    #[test]
+    fn test_open_reuses_file() {
+        let dir = TempDir::new("reuse");
+        let path = dir.0.join("index.html");
+        std::fs::write(&path, "hello").unwrap();
+        let cache = cache(8);
+        let now = Instant::now();
+
+        let (first, _) = cache.open(&path, now).unwrap();
+        let (second, _) = cache.open(&path, now + Duration::from_millis(10)).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let (expired, _) = cache.open(&path, now + Duration::from_secs(120)).unwrap();
+        assert!(!Arc::ptr_eq(&second, &expired));
+    }
+
+    #[test]
+    fn test_open_revalidates_replaced_file() {
+        let dir = TempDir::new("replace");
+        let path = dir.0.join("index.html");
+        std::fs::write(&path, "hello").unwrap();
+        let cache = cache(8);
+        let now = Instant::now();
+
+        let (first, _) = cache.open(&path, now).unwrap();
+        std::fs::write(dir.0.join("new.html"), "hello, world").unwrap();
+        std::fs::rename(dir.0.join("new.html"), &path).unwrap();
+
+        let (stale, _) = cache.open(&path, now + Duration::from_millis(10)).unwrap();
+        assert!(Arc::ptr_eq(&first, &stale));
+
+        let (current, metadata) = cache.open(&path, now + Duration::from_secs(2)).unwrap();
+        assert!(!Arc::ptr_eq(&first, &current));
+        assert_eq!(metadata.len(), 12);
+    }
+
+    #[test]
+    fn test_open_evicts_least_recently_used() {
+        let dir = TempDir::new("evict");
+        let paths: Vec<_> = (0..3).map(|i| dir.0.join(format!("{}.html", i))).collect();
+        for path in &paths {
+            std::fs::write(path, "hello").unwrap();
+        }
+        let cache = cache(2);
+        let now = Instant::now();
+
+        cache.open(&paths[0], now).unwrap();
+        cache
+            .open(&paths[1], now + Duration::from_millis(1))
+            .unwrap();
+        cache
+            .open(&paths[0], now + Duration::from_millis(2))
+            .unwrap();
+        cache
+            .open(&paths[2], now + Duration::from_millis(3))
+            .unwrap();
+
+        let entries = cache.entries.lock().unwrap();
+        assert!(entries.contains_key(&paths[0]));
+        assert!(!entries.contains_key(&paths[1]));
+        assert!(entries.contains_key(&paths[2]));
+    }
+
+    #[tokio::test]
+    async fn test_read() {
+        let dir = TempDir::new("read");
+        let path = dir.0.join("index.html");
+        std::fs::write(&path, "hello").unwrap();
+        let cache = Arc::new(cache(8));
+
+        let (content, metadata) = cache.clone().read(path.clone()).await.unwrap();
+        assert_eq!(&content[..], b"hello");
+        assert_eq!(metadata.len(), 5);
+
+        assert!(cache.read(dir.0.clone()).await.is_err());
+    }
+}
@@ -0,0 +1,234 @@
+use std::sync::{Arc, Mutex};
+
+use hyper::{body::HttpBody, Body, Request};
+use log::debug;
+
+/// `Inspector` observes a request body as it streams to its handler, e.g. to
+/// log it, to match it against rules, or to verify it. Inspectors keep
+/// whatever they need of the bytes they are shown, so that the body itself
+/// is never buffered on their behalf.
+pub trait Inspector: Send + 'static {
+    /// `limit` is the number of bytes at the beginning of the body the
+    /// inspector is shown, or `None` to be shown all of it.
+    fn limit(&self) -> Option<u64>;
+
+    /// `inspect` is shown each chunk of the body, cut short at the limit.
+    fn inspect(&mut self, chunk: &[u8]);
+
+    /// `verdict` is asked once the whole body has been received, before its
+    /// end is passed on to the handler. A body rejected by any inspector ends
+    /// with an error instead, so that its handler never acts on it, and the
+    /// reason is left in the request's `Rejection`.
+    fn verdict(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// `finish` is called once the body has been sent to the handler, or has
+    /// stopped early, with how much of it was inspected.
+    fn finish(self: Box<Self>, outcome: Outcome);
+}
+
+/// `Outcome` is how much of a body an inspector was shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// `Complete` bodies were shown in full.
+    Complete,
+
+    /// `Truncated` bodies were longer than the inspector's limit.
+    Truncated,
+
+    /// `Rejected` bodies were received in full, but an inspector rejected
+    /// them.
+    Rejected,
+
+    /// `Failed` bodies could not be received from the client, or the handler
+    /// stopped reading them.
+    Failed,
+}
+
+/// `Rejection` holds why an inspector rejected the body of a request. It is
+/// added to the extensions of every request whose body is inspected, so that
+/// a handler whose body ends with an error can tell a rejection apart from a
+/// client gone away.
+#[derive(Clone, Debug, Default)]
+pub struct Rejection(Arc<Mutex<Option<String>>>);
+
+impl Rejection {
+    /// `reason` returns why the body was rejected, if it was.
+    pub fn reason(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// `inspect_body` passes the body of a request through the `inspectors`,
+/// each of which is shown up to its own limit. The body is forwarded chunk by
+/// chunk as the handler reads it, so large uploads are not held in memory
+/// however many inspectors observe them.
+pub fn inspect_body(req: Request<Body>, mut inspectors: Vec<Box<dyn Inspector>>) -> Request<Body> {
+    if inspectors.is_empty() {
+        return req;
+    }
+
+    let (mut parts, mut body) = req.into_parts();
+    let rejection = Rejection::default();
+    parts.extensions.insert(rejection.clone());
+    let (mut sender, inspected) = Body::channel();
+    tokio::spawn(async move {
+        let mut seen: u64 = 0;
+
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    debug!("Stopped inspecting a request body: {}", e);
+                    sender.abort();
+                    finish(inspectors, seen, Outcome::Failed);
+                    return;
+                }
+            };
+
+            for inspector in inspectors.iter_mut() {
+                let shown = match inspector.limit() {
+                    Some(limit) => (limit.saturating_sub(seen) as usize).min(chunk.len()),
+                    None => chunk.len(),
+                };
+                if shown > 0 {
+                    inspector.inspect(&chunk[..shown]);
+                }
+            }
+            seen += chunk.len() as u64;
+
+            if sender.send_data(chunk).await.is_err() {
+                finish(inspectors, seen, Outcome::Failed);
+                return;
+            }
+        }
+
+        let reasons: Vec<String> = inspectors
+            .iter_mut()
+            .filter_map(|inspector| inspector.verdict().err())
+            .collect();
+        if !reasons.is_empty() {
+            *rejection.0.lock().unwrap() = Some(reasons.join(", "));
+            sender.abort();
+            finish(inspectors, seen, Outcome::Rejected);
+            return;
+        }
+
+        if let Ok(Some(trailers)) = body.trailers().await {
+            let _ = sender.send_trailers(trailers).await;
+        }
+        finish(inspectors, seen, Outcome::Complete);
+    });
+
+    Request::from_parts(parts, inspected)
+}
+
+/// `finish` tells every inspector how much of the `seen` bytes of the body it
+/// was shown.
+fn finish(inspectors: Vec<Box<dyn Inspector>>, seen: u64, outcome: Outcome) {
+    for inspector in inspectors {
+        let truncated = inspector.limit().is_some_and(|limit| seen > limit);
+        inspector.finish(match outcome {
+            Outcome::Complete if truncated => Outcome::Truncated,
+            outcome => outcome,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `Recorder` keeps what it is shown for the test to check, and rejects
+    /// bodies containing `reject`.
+    struct Recorder {
+        limit: Option<u64>,
+        recorded: Arc<Mutex<(Vec<u8>, Option<Outcome>)>>,
+    }
+
+    impl Inspector for Recorder {
+        fn limit(&self) -> Option<u64> {
+            self.limit
+        }
+
+        fn inspect(&mut self, chunk: &[u8]) {
+            self.recorded.lock().unwrap().0.extend_from_slice(chunk);
+        }
+
+        fn verdict(&mut self) -> Result<(), String> {
+            match self.recorded.lock().unwrap().0.starts_with(b"reject") {
+                true => Err("rejected".to_owned()),
+                false => Ok(()),
+            }
+        }
+
+        fn finish(self: Box<Self>, outcome: Outcome) {
+            self.recorded.lock().unwrap().1 = Some(outcome);
+        }
+    }
+
+    type Recorded = (Vec<u8>, Option<Outcome>);
+
+    async fn inspect(
+        content: &'static str,
+        limits: [Option<u64>; 2],
+    ) -> (Option<Vec<u8>>, Option<String>, [Recorded; 2]) {
+        let recorded = limits.map(|_| Arc::new(Mutex::new((Vec::new(), None))));
+        let inspectors = limits
+            .iter()
+            .zip(&recorded)
+            .map(|(limit, recorded)| {
+                Box::new(Recorder {
+                    limit: *limit,
+                    recorded: recorded.clone(),
+                }) as Box<dyn Inspector>
+            })
+            .collect();
+        let req = inspect_body(Request::new(Body::from(content)), inspectors);
+        let rejection = req.extensions().get::<Rejection>().unwrap().clone();
+        let body = hyper::body::to_bytes(req.into_body()).await.ok();
+
+        (
+            body.map(|body| body.to_vec()),
+            rejection.reason(),
+            recorded.map(|recorded| recorded.lock().unwrap().clone()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_inspect_body() {
+        let (body, rejection, [first, second]) = inspect("hello world", [Some(64), None]).await;
+
+        assert_eq!(body, Some(b"hello world".to_vec()));
+        assert_eq!(rejection, None);
+        assert_eq!(first, (b"hello world".to_vec(), Some(Outcome::Complete)));
+        assert_eq!(second, (b"hello world".to_vec(), Some(Outcome::Complete)));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_body_truncated() {
+        let (body, _, [first, second]) = inspect("hello world", [Some(5), None]).await;
+
+        assert_eq!(body, Some(b"hello world".to_vec()));
+        assert_eq!(first, (b"hello".to_vec(), Some(Outcome::Truncated)));
+        assert_eq!(second, (b"hello world".to_vec(), Some(Outcome::Complete)));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_body_rejected() {
+        let (body, rejection, [first, _]) = inspect("reject me", [None, Some(2)]).await;
+
+        assert_eq!(body, None);
+        assert_eq!(rejection, Some("rejected".to_owned()));
+        assert_eq!(first.1, Some(Outcome::Rejected));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_body_without_inspectors() {
+        let req = inspect_body(Request::new(Body::from("hello")), vec![]);
+
+        assert_eq!(req.body().size_hint().exact(), Some(5));
+        assert!(req.extensions().get::<Rejection>().is_none());
+    }
+}
@@ -1,4 +1,18 @@
-mod handler;
+mod body;
+mod checksum;
+mod conditional;
+mod file_cache;
+mod inspect;
+mod python;
+mod range;
 mod static_service;
+mod well_known;
 
+pub use body::{read_body, BodyError};
+pub use checksum::{ChecksumInspector, CONTENT_DIGEST, DIGEST};
+pub use conditional::has_precondition;
+pub use file_cache::FileCache;
+pub use inspect::{inspect_body, Inspector, Outcome};
+pub use python::{check_applications, python_service_handler, Mount, WorkerPool, WorkerStatus};
 pub use static_service::static_service_handler;
+pub use well_known::{well_known_handler, WellKnown};
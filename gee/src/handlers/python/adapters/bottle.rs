@@ -0,0 +1,25 @@
+use pyo3::prelude::*;
+
+use super::import;
+
+/// `prepare` verifies that Bottle can be imported.
+pub fn prepare(py: Python) -> Result<(), String> {
+    import(py, "bottle", "bottle").map(|_| ())
+}
+
+/// `check` verifies that the application is a Bottle application object
+/// rather than the `bottle` module or `Bottle` class.
+pub fn check(application: &PyAny) -> Result<(), String> {
+    if application
+        .is_instance_of::<pyo3::types::PyType>()
+        .unwrap_or(false)
+        || !application.hasattr("routes").map_err(|e| e.to_string())?
+    {
+        return Err(
+            "The application is not a Bottle application object. Use an instance such as `app = bottle.Bottle()` or `bottle.default_app()`."
+                .to_owned(),
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use pyo3::prelude::*;
+
+use super::import;
+use crate::config::ApplicationConfig;
+
+/// `SETTINGS_VARIABLE` is the environment variable Django reads the settings
+/// module from.
+pub const SETTINGS_VARIABLE: &str = "DJANGO_SETTINGS_MODULE";
+
+/// `settings_module` returns the settings module an application names, by
+/// `django_settings_module` or in its `env`, if it names one.
+pub fn settings_module(config: &ApplicationConfig) -> Option<&str> {
+    config.django_settings_module.as_deref().or_else(|| {
+        config
+            .env
+            .as_ref()?
+            .get(SETTINGS_VARIABLE)
+            .map(String::as_str)
+    })
+}
+
+/// `prepare` calls `django.setup()` so that the application can be imported.
+/// `DJANGO_SETTINGS_MODULE` must have been set with the application's `env`.
+pub fn prepare(py: Python) -> Result<(), String> {
+    check_settings_module(py)?;
+
+    import(py, "django", "django")?
+        .call_method0("setup")
+        .map_err(|e| format!("django.setup() failed: {}", e))?;
+
+    Ok(())
+}
+
+/// `check` verifies that Django has been configured with a settings module.
+pub fn check(py: Python) -> Result<(), String> {
+    check_settings_module(py)?;
+
+    let configured: bool = import(py, "django.conf", "django")?
+        .getattr("settings")
+        .and_then(|settings| settings.getattr("configured"))
+        .and_then(|configured| configured.extract())
+        .map_err(|e| e.to_string())?;
+
+    if !configured {
+        return Err("Django settings are not configured.".to_owned());
+    }
+
+    Ok(())
+}
+
+/// `check_settings_module` verifies that `DJANGO_SETTINGS_MODULE` is set.
+fn check_settings_module(py: Python) -> Result<(), String> {
+    let settings_module: Option<String> = py
+        .import("os")
+        .and_then(|os| os.getattr("environ"))
+        .and_then(|environ| environ.call_method1("get", (SETTINGS_VARIABLE,)))
+        .and_then(|value| value.extract())
+        .map_err(|e| e.to_string())?;
+
+    match settings_module {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "{} is not set. Set `django_settings_module` for the application (e.g. \"mysite.settings\").",
+            SETTINGS_VARIABLE
+        )),
+    }
+}
@@ -0,0 +1,33 @@
+use log::warn;
+use pyo3::prelude::*;
+
+use super::import;
+
+/// `prepare` verifies that Flask can be imported.
+pub fn prepare(py: Python) -> Result<(), String> {
+    import(py, "flask", "flask").map(|_| ())
+}
+
+/// `check` verifies that the application is a Flask application object rather
+/// than e.g. the `Flask` class or an application factory, and warns if it is
+/// running in debug mode.
+pub fn check(application: &PyAny) -> Result<(), String> {
+    if !application.hasattr("wsgi_app").map_err(|e| e.to_string())? {
+        return Err(
+            "The application is not a Flask application object. If it is created by a factory, expose the object it returns (e.g. `app = create_app()`) and use its name."
+                .to_owned(),
+        );
+    }
+
+    let debug: bool = application
+        .getattr("debug")
+        .and_then(|debug| debug.extract())
+        .unwrap_or(false);
+    if debug {
+        warn!(
+            "The Flask application is running in debug mode, which must not be used in production."
+        );
+    }
+
+    Ok(())
+}
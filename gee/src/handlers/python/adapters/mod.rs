@@ -0,0 +1,295 @@
+mod bottle;
+mod django;
+mod flask;
+
+use std::{collections::HashMap, error::Error, fmt, str::FromStr};
+
+use log::info;
+use pyo3::prelude::*;
+
+use crate::config::ApplicationConfig;
+
+/// `Framework` enumerates the Python web frameworks Gee has specific support
+/// for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framework {
+    Bottle,
+    Django,
+    Flask,
+}
+
+impl FromStr for Framework {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bottle" => Ok(Framework::Bottle),
+            "django" => Ok(Framework::Django),
+            "flask" => Ok(Framework::Flask),
+            _ => Err(format!(
+                "Unsupported framework: {}. Framework must be Bottle, Django, or Flask.",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Framework {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Framework::Bottle => write!(f, "Bottle"),
+            Framework::Django => write!(f, "Django"),
+            Framework::Flask => write!(f, "Flask"),
+        }
+    }
+}
+
+/// `prepare` applies the framework-specific setup that must happen before the
+/// application is imported. This is driven by the configured `framework`, or
+/// by `django_settings_module` which implies Django.
+pub fn prepare(config: &ApplicationConfig) -> Result<(), Box<dyn Error>> {
+    let framework = match configured_framework(config)? {
+        Some(framework) => framework,
+        None => return Ok(()),
+    };
+
+    Python::with_gil(|py| match framework {
+        Framework::Bottle => bottle::prepare(py),
+        Framework::Django => django::prepare(py),
+        Framework::Flask => flask::prepare(py),
+    })
+    .map_err(|e| {
+        format!(
+            "Cannot start {} application {}: {}",
+            framework, config.path, e
+        )
+        .into()
+    })
+}
+
+/// `env` returns the environment variables the application's framework
+/// needs set while it is imported: `DJANGO_SETTINGS_MODULE` from
+/// `django_settings_module`.
+pub fn env(config: &ApplicationConfig) -> HashMap<String, String> {
+    config
+        .django_settings_module
+        .iter()
+        .map(|settings_module| {
+            (
+                django::SETTINGS_VARIABLE.to_owned(),
+                settings_module.clone(),
+            )
+        })
+        .collect()
+}
+
+/// `check_applications` verifies that the applications can share the
+/// interpreter. Django is set up once per process, so every application
+/// naming a Django settings module must name the same one.
+pub fn check_applications(applications: &[ApplicationConfig]) -> Result<(), String> {
+    let mut first: Option<(&ApplicationConfig, &str)> = None;
+    for application in applications {
+        let settings_module = match django::settings_module(application) {
+            Some(settings_module) => settings_module,
+            None => continue,
+        };
+
+        match first {
+            Some((other, other_module)) if other_module != settings_module => {
+                return Err(format!(
+                    "Applications {} and {} set {} to {} and {}, but Django can only be set up with one settings module per process. Serve them from separate Gee servers.",
+                    other.path,
+                    application.path,
+                    django::SETTINGS_VARIABLE,
+                    other_module,
+                    settings_module
+                ));
+            }
+            Some(_) => {}
+            None => first = Some((application, settings_module)),
+        }
+    }
+
+    Ok(())
+}
+
+/// `check` detects the framework of a loaded application and verifies that
+/// the framework's prerequisites are met, failing with a message explaining
+/// what is missing if they are not.
+pub fn check(config: &ApplicationConfig, application: &Py<PyAny>) -> Result<(), Box<dyn Error>> {
+    let configured = configured_framework(config)?;
+
+    Python::with_gil(|py| {
+        let application = application.as_ref(py);
+        let detected = detect(application)?;
+
+        let framework = match (configured, detected) {
+            (Some(configured), Some(detected)) if configured != detected => {
+                return Err(format!(
+                    "{} is configured as a {} application but is a {} application.",
+                    config.name, configured, detected
+                ));
+            }
+            (Some(framework), _) | (None, Some(framework)) => framework,
+            (None, None) => return Ok(()),
+        };
+
+        info!("Detected {} application {}", framework, config.name);
+
+        match framework {
+            Framework::Bottle => bottle::check(application),
+            Framework::Django => django::check(py),
+            Framework::Flask => flask::check(application),
+        }
+    })
+    .map_err(|e| format!("Cannot start application {}: {}", config.path, e).into())
+}
+
+/// `detect` returns the framework an application object belongs to, based on
+/// the module its type is defined in.
+fn detect(application: &PyAny) -> Result<Option<Framework>, String> {
+    let module: String = application
+        .get_type()
+        .getattr("__module__")
+        .and_then(|module| module.extract())
+        .map_err(|e| e.to_string())?;
+
+    let framework = match module.split('.').next() {
+        Some("bottle") => Some(Framework::Bottle),
+        Some("django") => Some(Framework::Django),
+        Some("flask") => Some(Framework::Flask),
+        _ => None,
+    };
+
+    Ok(framework)
+}
+
+/// `configured_framework` returns the framework set for an application.
+fn configured_framework(config: &ApplicationConfig) -> Result<Option<Framework>, String> {
+    match (&config.framework, &config.django_settings_module) {
+        (Some(framework), _) => framework.parse().map(Some),
+        (None, Some(_)) => Ok(Some(Framework::Django)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// `import` imports a framework's module, failing with a message explaining
+/// how to install it if it cannot be found.
+fn import<'py>(py: Python<'py>, module: &str, package: &str) -> Result<&'py PyModule, String> {
+    py.import(module).map_err(|e| {
+        format!(
+            "{} cannot be imported ({}). Install it into the Python environment Gee runs with, e.g. `pip install {}`.",
+            module, e, package
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn object_from(py: Python, module: &str) -> Py<PyAny> {
+        let code = format!(
+            "class Application:\n    __module__ = '{}'\n\napplication = Application()\n",
+            module
+        );
+        let module = PyModule::from_code(py, &code, "fake.py", "fake").unwrap();
+        module.getattr("application").unwrap().into()
+    }
+
+    fn config(framework: Option<&str>) -> ApplicationConfig {
+        ApplicationConfig {
+            path: "app.py".to_owned(),
+            name: "app".to_owned(),
+            framework: framework.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_framework_from_str() {
+        assert_eq!("Django".parse(), Ok(Framework::Django));
+        assert_eq!("flask".parse(), Ok(Framework::Flask));
+        assert_eq!("bottle".parse(), Ok(Framework::Bottle));
+        assert!("pyramid".parse::<Framework>().is_err());
+    }
+
+    #[test]
+    fn test_detect() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let detect_from = |module| detect(object_from(py, module).as_ref(py)).unwrap();
+
+            assert_eq!(detect_from("flask.app"), Some(Framework::Flask));
+            assert_eq!(
+                detect_from("django.core.handlers.wsgi"),
+                Some(Framework::Django)
+            );
+            assert_eq!(detect_from("bottle"), Some(Framework::Bottle));
+            assert_eq!(detect_from("myproject.wsgi"), None);
+        });
+    }
+
+    #[test]
+    fn test_configured_framework() {
+        assert_eq!(configured_framework(&config(None)), Ok(None));
+        assert_eq!(
+            configured_framework(&config(Some("flask"))),
+            Ok(Some(Framework::Flask))
+        );
+
+        let mut django = config(None);
+        django.django_settings_module = Some("mysite.settings".to_owned());
+        assert_eq!(configured_framework(&django), Ok(Some(Framework::Django)));
+    }
+
+    #[test]
+    fn test_env() {
+        assert!(env(&config(None)).is_empty());
+
+        let mut django = config(None);
+        django.django_settings_module = Some("mysite.settings".to_owned());
+        assert_eq!(
+            env(&django),
+            HashMap::from([(
+                "DJANGO_SETTINGS_MODULE".to_owned(),
+                "mysite.settings".to_owned()
+            )])
+        );
+    }
+
+    #[test]
+    fn test_check_applications() {
+        let mut blog = config(None);
+        blog.path = "blog/wsgi.py".to_owned();
+        blog.django_settings_module = Some("blog.settings".to_owned());
+        let mut shop = config(None);
+        shop.path = "shop/wsgi.py".to_owned();
+        shop.env = Some(HashMap::from([(
+            "DJANGO_SETTINGS_MODULE".to_owned(),
+            "blog.settings".to_owned(),
+        )]));
+
+        assert!(check_applications(&[blog.clone(), shop.clone(), config(None)]).is_ok());
+
+        shop.env = Some(HashMap::from([(
+            "DJANGO_SETTINGS_MODULE".to_owned(),
+            "shop.settings".to_owned(),
+        )]));
+        let error = check_applications(&[blog, config(None), shop]).unwrap_err();
+        assert!(error.contains("blog/wsgi.py and shop/wsgi.py"));
+        assert!(error.contains("blog.settings and shop.settings"));
+    }
+
+    #[test]
+    fn test_check_with_mismatched_framework() {
+        pyo3::prepare_freethreaded_python();
+        let application = Python::with_gil(|py| object_from(py, "flask.app"));
+
+        let error = check(&config(Some("bottle")), &application).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("configured as a Bottle application but is a Flask application"));
+    }
+}
@@ -1,28 +1,398 @@
-use std::fs;
+use std::{
+    collections::HashSet,
+    error::Error,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
-use crate::{environ::Environ, hashmap};
-use pyo3::{prelude::*, types::PyTuple};
+use hyper::StatusCode;
+use log::{debug, error};
+use pyo3::{
+    prelude::*,
+    types::{PyBytes, PyCFunction, PyDict, PyTuple},
+};
 
-// TODO: break this function down into sub-functions. Doing so was giving me some lifetime errors...
-pub fn call_application(environ: Environ) -> Option<Vec<u8>> {
-    println!("Calling application.");
-    println!("{}", environ);
+use super::environ::Environ;
 
-    let code = fs::read_to_string("./app/app.py").expect("Cannot find Python file!");
-    let filename = "app.py";
-    let modulename = "app";
-    let callablename = "print_environ";
+/// `ApplicationResponse` is the response produced by calling a Python
+/// application. The headers Gee acts on itself, a redirect's location, a
+/// `Retry-After`, and cookies, are kept apart from the rest.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApplicationResponse {
+    /// `status` is the status the application passed to `start_response`.
+    pub status: StatusCode,
 
-    let fake_environ = hashmap!["a" => "b"];
+    /// `headers` are the other response headers the application passed to
+    /// `start_response`, in the order they were given.
+    pub headers: Vec<(String, String)>,
+
+    /// `location` is the `Location` header, where a redirect points to.
+    pub location: Option<String>,
+
+    /// `retry_after` is the `Retry-After` header, as the application gave it.
+    pub retry_after: Option<String>,
+
+    /// `cookies` are the values of the `Set-Cookie` headers.
+    pub cookies: Vec<String>,
+
+    /// `body` is the concatenation of everything the application wrote or
+    /// yielded.
+    pub body: Vec<u8>,
+}
+
+impl ApplicationResponse {
+    /// `new` creates a response from what the application passed to
+    /// `start_response`, setting aside the headers Gee acts on.
+    pub fn new(status: StatusCode, headers: Vec<(String, String)>, body: Vec<u8>) -> Self {
+        let mut response = Self {
+            status,
+            body,
+            ..Self::default()
+        };
+
+        for (name, value) in headers {
+            if name.eq_ignore_ascii_case("location") {
+                response.location = Some(value);
+            } else if name.eq_ignore_ascii_case("retry-after") {
+                response.retry_after = Some(value);
+            } else if name.eq_ignore_ascii_case("set-cookie") {
+                response.cookies.push(value);
+            } else {
+                response.headers.push((name, value));
+            }
+        }
+
+        response
+    }
+
+    /// `redirect` returns where the response redirects to, if it is a
+    /// redirect.
+    pub fn redirect(&self) -> Option<&str> {
+        self.location
+            .as_deref()
+            .filter(|_| self.status.is_redirection())
+    }
+
+    /// `all_headers` lists every header of the response, those set aside
+    /// last.
+    pub fn all_headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        let location = self
+            .location
+            .iter()
+            .map(|value| ("Location", value.as_str()));
+        let retry_after = self
+            .retry_after
+            .iter()
+            .map(|value| ("Retry-After", value.as_str()));
+        let cookies = self
+            .cookies
+            .iter()
+            .map(|value| ("Set-Cookie", value.as_str()));
+
+        headers.chain(location).chain(retry_after).chain(cookies)
+    }
+}
+
+/// `StartResponse` holds what the application passed to `start_response` and
+/// its `write` callable.
+#[derive(Default)]
+struct StartResponse {
+    status: Option<String>,
+    headers: Vec<(String, String)>,
+    written: Vec<u8>,
+}
+
+/// `load_application` imports the Python file at `path` and returns the
+/// callable called `name` within it. The directory containing the file is
+/// added to `sys.path` so the application can import its siblings.
+pub fn load_application(path: &Path, name: &str) -> Result<Py<PyAny>, Box<dyn Error>> {
+    let directory = path
+        .parent()
+        .map(|parent| parent.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let module_name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("Invalid application path: {}", path.display()))?
+        .to_owned();
+
+    Python::with_gil(|py| {
+        let sys_path = py.import("sys")?.getattr("path")?;
+        if !sys_path.contains(&directory)? {
+            sys_path.call_method1("insert", (0, &directory))?;
+        }
+
+        let module = py.import(module_name.as_str())?;
+        let callable = module.getattr(name)?;
+
+        Ok(callable.into())
+    })
+    .map_err(|e: PyErr| {
+        format!("Cannot load application {}:{}: {}", path.display(), name, e).into()
+    })
+}
+
+/// `loaded_modules` returns the names of the modules in `sys.modules`, so
+/// that the modules an application imports can be told apart from those
+/// imported before it.
+pub fn loaded_modules() -> Result<HashSet<String>, Box<dyn Error>> {
+    Python::with_gil(|py| {
+        py.import("sys")?
+            .getattr("modules")?
+            .call_method0("keys")?
+            .iter()?
+            .map(|name| name?.extract())
+            .collect::<PyResult<_>>()
+    })
+    .map_err(|e: PyErr| format!("Cannot list the loaded modules: {}", e).into())
+}
+
+/// `unload_application` removes the `modules` imported for the Python file
+/// at `path` from `sys.modules`, so that loading the application again
+/// imports them afresh. Only modules from the directory containing the file
+/// are removed: installed packages, even those in a virtual environment
+/// within the directory, are kept, and so are modules another application
+/// in the directory imported before it.
+pub fn unload_application(path: &Path, modules: &[String]) -> Result<(), Box<dyn Error>> {
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
 
     Python::with_gil(|py| {
-        let module =
-            PyModule::from_code(py, &code, filename, modulename).expect("Cannot load module!");
-        let callable = module.getattr(callablename).expect("Cannot load callable!");
+        let os_path = py.import("os")?.getattr("path")?;
+        let absolute = |path: &str| -> PyResult<String> {
+            os_path.call_method1("abspath", (path,))?.extract()
+        };
+        let root = absolute(&directory.to_string_lossy())?;
+        let root = format!("{}{}", root.trim_end_matches('/'), '/');
+
+        let loaded = py.import("sys")?.getattr("modules")?;
+        for name in modules {
+            let module = loaded.call_method1("get", (name,))?;
+            if module.is_none() {
+                continue;
+            }
+            let file: Option<String> = match module.getattr("__file__") {
+                Ok(file) => file.extract().unwrap_or_default(),
+                Err(_) => None,
+            };
+            let is_local = match file {
+                Some(file) => {
+                    let file = absolute(&file)?;
+                    file.starts_with(&root)
+                        && !file.contains("/site-packages/")
+                        && !file.contains("/dist-packages/")
+                }
+                None => false,
+            };
+            if is_local {
+                loaded.del_item(name)?;
+            }
+        }
+
+        Ok(())
+    })
+    .map_err(|e: PyErr| format!("Cannot unload application {}: {}", path.display(), e).into())
+}
+
+/// `call_application` calls the WSGI `application` with the `environ` and the
+/// request body as `input`, collecting the status, headers, and body it
+/// produces. If the client disconnects, iteration stops and the iterable is
+/// closed right away.
+pub fn call_application(
+    application: &Py<PyAny>,
+    environ: Environ,
+    input: &[u8],
+) -> PyResult<ApplicationResponse> {
+    let started = Arc::new(Mutex::new(StartResponse::default()));
+    let disconnected = environ.client_disconnected.clone();
 
-        let args = PyTuple::new(py, &[fake_environ]);
-        let response = callable.call1(args).expect("Cannot call callable!");
+    let result: PyResult<Vec<u8>> = Python::with_gil(|py| {
+        let environ = environ.to_dict(py, input)?;
+        let start_response = start_response(py, started.clone())?;
+
+        let iterable = application.call1(py, (environ, start_response))?;
+        let iterable = iterable.as_ref(py);
+
+        let mut body = Vec::new();
+        let iterated = iterable.iter().and_then(|items| {
+            for item in items {
+                if disconnected.is_set() {
+                    debug!("Client disconnected, no longer iterating the response");
+                    break;
+                }
+                body.extend_from_slice(item?.downcast::<PyBytes>()?.as_bytes());
+            }
+            Ok(())
+        });
+
+        if iterable.hasattr("close")? {
+            iterable.call_method0("close")?;
+        }
+        iterated?;
+
+        Ok(body)
     });
 
-    Some("Response from Python".as_bytes().to_owned())
+    let body = match result {
+        Ok(body) => body,
+        Err(e) => {
+            Python::with_gil(|py| e.print(py));
+            error!("Application raised an exception: {}", e);
+            return Err(e);
+        }
+    };
+
+    let started = std::mem::take(&mut *started.lock().unwrap());
+    let status = started
+        .status
+        .as_deref()
+        .and_then(|status| status.split_whitespace().next())
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| {
+            pyo3::exceptions::PyRuntimeError::new_err(format!(
+                "Application did not start a valid response: {:?}",
+                started.status
+            ))
+        })?;
+
+    let mut response_body = started.written;
+    response_body.extend(body);
+
+    Ok(ApplicationResponse::new(
+        status,
+        started.headers,
+        response_body,
+    ))
+}
+
+/// `start_response` creates the `start_response` callable passed to the
+/// application, which records the status and headers into `started` and
+/// returns the legacy `write` callable.
+fn start_response(py: Python<'_>, started: Arc<Mutex<StartResponse>>) -> PyResult<&PyAny> {
+    let start_response = PyCFunction::new_closure(
+        move |args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<PyObject> {
+            let py = args.py();
+            let status: String = args.get_item(0)?.extract()?;
+            let headers: Vec<(String, String)> = args.get_item(1)?.extract()?;
+
+            let exc_info = args.get_item(2).ok().filter(|exc_info| !exc_info.is_none());
+            {
+                let mut started = started.lock().unwrap();
+                if started.status.is_some() && exc_info.is_none() {
+                    return Err(pyo3::exceptions::PyAssertionError::new_err(
+                        "start_response has already been called",
+                    ));
+                }
+
+                started.status = Some(status);
+                started.headers = headers;
+            }
+
+            let started = started.clone();
+            let write = PyCFunction::new_closure(
+                move |args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<()> {
+                    let data = args.get_item(0)?.downcast::<PyBytes>()?.as_bytes().to_vec();
+                    started.lock().unwrap().written.extend(data);
+                    Ok(())
+                },
+                py,
+            )?;
+
+            Ok(write.into())
+        },
+        py,
+    )?;
+
+    Ok(start_response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hyper::{Body, Request};
+
+    #[test]
+    fn test_call_application() {
+        pyo3::prepare_freethreaded_python();
+        let application =
+            load_application(Path::new("./src/fixtures/app/app.py"), "simple_app").unwrap();
+
+        let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let environ = Environ::from_request(&req, "127.0.0.1:8080".parse().unwrap());
+
+        let expected = ApplicationResponse {
+            status: StatusCode::OK,
+            headers: vec![("Content-type".to_owned(), "text/plain".to_owned())],
+            body: b"Hello world!\n".to_vec(),
+            ..ApplicationResponse::default()
+        };
+
+        let actual = call_application(&application, environ, &[]).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_call_application_stops_when_client_disconnected() {
+        pyo3::prepare_freethreaded_python();
+        let application =
+            load_application(Path::new("./src/fixtures/app/app.py"), "simple_app").unwrap();
+
+        let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let environ = Environ::from_request(&req, "127.0.0.1:8080".parse().unwrap());
+        environ.client_disconnected.set();
+
+        let actual = call_application(&application, environ, &[]).unwrap();
+
+        assert_eq!(actual.status, StatusCode::OK);
+        assert!(actual.body.is_empty());
+    }
+
+    #[test]
+    fn test_application_response_sets_aside_headers() {
+        let headers = [
+            ("Content-Type", "text/html"),
+            ("Set-Cookie", "a=1"),
+            ("location", "/login"),
+            ("Set-Cookie", "b=2"),
+            ("Retry-After", "120"),
+        ];
+        let response = ApplicationResponse::new(
+            StatusCode::FOUND,
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            vec![],
+        );
+
+        assert_eq!(
+            response.headers,
+            vec![("Content-Type".to_owned(), "text/html".to_owned())]
+        );
+        assert_eq!(response.redirect(), Some("/login"));
+        assert_eq!(response.retry_after.as_deref(), Some("120"));
+        assert_eq!(response.cookies, vec!["a=1", "b=2"]);
+        assert_eq!(
+            response.all_headers().collect::<Vec<_>>(),
+            vec![
+                ("Content-Type", "text/html"),
+                ("Location", "/login"),
+                ("Retry-After", "120"),
+                ("Set-Cookie", "a=1"),
+                ("Set-Cookie", "b=2"),
+            ]
+        );
+
+        let response = ApplicationResponse {
+            status: StatusCode::CREATED,
+            location: Some("/items/1".to_owned()),
+            ..ApplicationResponse::default()
+        };
+        assert_eq!(response.redirect(), None);
+    }
 }
@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use hyper::http::request::Parts;
+use tokio::sync::watch;
+
+use super::application::ApplicationResponse;
+use crate::shareable::{is_shareable, shared_key};
+
+/// `Outcome` is what the first of a set of identical requests tells the
+/// others: the response to share, or `None` if it cannot be shared.
+type Outcome = Option<Arc<Option<ApplicationResponse>>>;
+
+/// `Coalescer` lets only the first of a set of identical, concurrent requests
+/// reach the application and answers the others with its response, so that
+/// an expensive page is not regenerated once for every waiting client.
+#[derive(Default)]
+pub struct Coalescer {
+    /// `in_flight` are the requests being handled, by key, and the channels
+    /// their outcome is announced on.
+    in_flight: Mutex<HashMap<String, watch::Receiver<Outcome>>>,
+}
+
+/// `Flight` is a request's part in a set of identical requests.
+pub enum Flight {
+    /// `Leader` requests are passed to the application.
+    Leader(Leader),
+
+    /// `Follower` requests wait for the leader's response.
+    Follower(watch::Receiver<Outcome>),
+}
+
+/// `Leader` announces the outcome of the request passed to the application.
+/// If it is dropped first, its followers are left to call the application
+/// themselves.
+pub struct Leader {
+    /// `coalescer` is where the request is registered as being handled.
+    coalescer: Arc<Coalescer>,
+
+    /// `key` identifies the request among those being handled.
+    key: String,
+
+    /// `sender` announces the outcome to the followers.
+    sender: watch::Sender<Outcome>,
+}
+
+impl Coalescer {
+    /// `join` makes the request with the given key the leader if no identical
+    /// request is being handled, or a follower of the one that is.
+    pub fn join(self: &Arc<Self>, key: String) -> Flight {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(receiver) = in_flight.get(&key) {
+            return Flight::Follower(receiver.clone());
+        }
+
+        let (sender, receiver) = watch::channel(None);
+        in_flight.insert(key.clone(), receiver);
+
+        Flight::Leader(Leader {
+            coalescer: self.clone(),
+            key,
+            sender,
+        })
+    }
+}
+
+impl Leader {
+    /// `finish` shares the response with the followers if it is shareable.
+    pub fn finish(self, response: Option<&ApplicationResponse>) {
+        let shared = response
+            .filter(|response| {
+                response.cookies.is_empty()
+                    && is_shareable(
+                        response.status,
+                        response.headers.iter().map(|(name, value)| (name, value)),
+                    )
+            })
+            .cloned();
+        self.sender.send_replace(Some(Arc::new(shared)));
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.coalescer.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// `follow` waits for the leader's outcome, returning its response if it
+/// could be shared.
+pub async fn follow(mut receiver: watch::Receiver<Outcome>) -> Option<ApplicationResponse> {
+    let outcome = receiver.wait_for(Option::is_some).await.ok()?;
+
+    outcome.as_ref()?.as_ref().clone()
+}
+
+/// `coalescing_key` returns the key identical requests share, or `None` if
+/// the request may not be coalesced: those with a body may not, nor may those
+/// whose response may not be shared.
+pub fn coalescing_key(parts: &Parts, input: &[u8]) -> Option<String> {
+    if !input.is_empty() {
+        return None;
+    }
+
+    shared_key(&parts.method, &parts.uri, &parts.headers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hyper::{Body, Request, StatusCode};
+
+    fn response(headers: Vec<(&str, &str)>) -> ApplicationResponse {
+        ApplicationResponse::new(
+            StatusCode::OK,
+            headers
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+            b"hello".to_vec(),
+        )
+    }
+
+    fn parts(method: &str, headers: Vec<(&str, &str)>) -> Parts {
+        let mut builder = Request::builder().method(method).uri("/report?year=2024");
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(Body::empty()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn test_coalescing_key() {
+        let plain = coalescing_key(&parts("GET", vec![]), b"");
+        let french = coalescing_key(&parts("GET", vec![("Accept-Language", "fr")]), b"");
+
+        assert!(plain.is_some());
+        assert_ne!(plain, french);
+        assert_eq!(coalescing_key(&parts("POST", vec![]), b""), None);
+        assert_eq!(coalescing_key(&parts("GET", vec![]), b"body"), None);
+        assert_eq!(
+            coalescing_key(&parts("GET", vec![("Cookie", "session=1")]), b""),
+            None
+        );
+    }
+
+    #[test]
+    fn test_coalescing_key_includes_host() {
+        let blog = coalescing_key(&parts("GET", vec![("Host", "blog.example.com")]), b"");
+        let shop = coalescing_key(&parts("GET", vec![("Host", "shop.example.com")]), b"");
+
+        assert!(blog.is_some() && shop.is_some());
+        assert_ne!(blog, shop);
+    }
+
+    #[tokio::test]
+    async fn test_followers_share_leader_response() {
+        let coalescer = Arc::new(Coalescer::default());
+
+        let leader = match coalescer.join("/".to_owned()) {
+            Flight::Leader(leader) => leader,
+            Flight::Follower(_) => panic!("first request should lead"),
+        };
+        let follower = match coalescer.join("/".to_owned()) {
+            Flight::Follower(receiver) => tokio::spawn(follow(receiver)),
+            Flight::Leader(_) => panic!("second request should follow"),
+        };
+
+        leader.finish(Some(&response(vec![])));
+        assert_eq!(follower.await.unwrap(), Some(response(vec![])));
+        assert!(matches!(coalescer.join("/".to_owned()), Flight::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn test_followers_fall_back_when_leader_gives_up() {
+        let coalescer = Arc::new(Coalescer::default());
+
+        let leader = coalescer.join("/".to_owned());
+        let unshareable = match coalescer.join("/".to_owned()) {
+            Flight::Follower(receiver) => receiver,
+            Flight::Leader(_) => panic!("second request should follow"),
+        };
+        drop(leader);
+        assert_eq!(follow(unshareable).await, None);
+
+        let leader = match coalescer.join("/".to_owned()) {
+            Flight::Leader(leader) => leader,
+            Flight::Follower(_) => panic!("request should lead"),
+        };
+        let follower = match coalescer.join("/".to_owned()) {
+            Flight::Follower(receiver) => receiver,
+            Flight::Leader(_) => panic!("second request should follow"),
+        };
+        leader.finish(Some(&response(vec![("Set-Cookie", "a=b")])));
+        assert_eq!(follow(follower).await, None);
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::UNIX_EPOCH;
+
+use super::environ::Environ;
+use crate::client::ClientInfo;
+use crate::config::{Decoration, DecorationsConfig};
+
+/// `HEADER_PREFIX` begins the `HTTP_` variables decorations are passed as.
+const HEADER_PREFIX: &str = "HTTP_X_GEE_";
+
+/// `decorate` sets the values selected by the config in the environ. When the
+/// values are also passed as headers, any `X-Gee-*` headers sent by the client
+/// are removed first so that the application cannot be misled by them.
+pub fn decorate(environ: &mut Environ, config: &DecorationsConfig, client: &ClientInfo) {
+    let headers = config.headers == Some(true);
+    if headers {
+        environ
+            .http_variables
+            .retain(|key, _| !key.starts_with(HEADER_PREFIX));
+    }
+
+    for decoration in config.values.iter() {
+        let value = match value(*decoration, client) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let name = name(*decoration);
+        if headers {
+            environ.http_variables.insert(
+                format!("{}{}", HEADER_PREFIX, name.to_uppercase()),
+                value.clone(),
+            );
+        }
+        environ.extensions.insert(format!("gee.{}", name), value);
+    }
+}
+
+/// `name` returns the name a decoration is passed under.
+fn name(decoration: Decoration) -> &'static str {
+    match decoration {
+        Decoration::RequestStart => "request_start",
+        Decoration::ClientIp => "client_ip",
+        Decoration::GeoCountry => "geo_country",
+        Decoration::GeoAsn => "geo_asn",
+    }
+}
+
+/// `value` computes a decoration for the client, if it is known.
+fn value(decoration: Decoration, client: &ClientInfo) -> Option<String> {
+    match decoration {
+        Decoration::RequestStart => client
+            .received
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|since| format!("{}.{:06}", since.as_secs(), since.subsec_micros())),
+        Decoration::ClientIp => client.client_ip.map(|ip| ip.to_string()),
+        Decoration::GeoCountry => client.country.clone(),
+        Decoration::GeoAsn => client.asn.map(|asn| asn.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::{Method, Version};
+    use std::time::Duration;
+
+    use super::*;
+
+    fn environ() -> Environ {
+        let mut environ = Environ::new(
+            Method::GET,
+            "".to_owned(),
+            "/".to_owned(),
+            "".to_owned(),
+            "".to_owned(),
+            "".to_owned(),
+            "localhost".to_owned(),
+            "8080".to_owned(),
+            Version::HTTP_11,
+        );
+        environ
+            .http_variables
+            .insert("HTTP_X_GEE_CLIENT_IP".to_owned(), "6.6.6.6".to_owned());
+        environ
+    }
+
+    fn client() -> ClientInfo {
+        ClientInfo {
+            remote_addr: Some("10.0.0.1:4000".parse().unwrap()),
+            client_ip: Some("1.2.3.4".parse().unwrap()),
+            received: UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456),
+            country: Some("DE".to_owned()),
+            asn: None,
+        }
+    }
+
+    #[test]
+    fn test_decorate() {
+        let mut environ = environ();
+        let config = DecorationsConfig {
+            values: vec![
+                Decoration::RequestStart,
+                Decoration::ClientIp,
+                Decoration::GeoAsn,
+                Decoration::GeoCountry,
+            ],
+            headers: None,
+        };
+
+        decorate(&mut environ, &config, &client());
+
+        assert_eq!(environ.extensions.len(), 3);
+        assert_eq!(environ.extensions["gee.request_start"], "1700000000.123456");
+        assert_eq!(environ.extensions["gee.client_ip"], "1.2.3.4");
+        assert_eq!(environ.extensions["gee.geo_country"], "DE");
+        assert_eq!(environ.http_variables["HTTP_X_GEE_CLIENT_IP"], "6.6.6.6");
+    }
+
+    #[test]
+    fn test_decorate_headers_replace_client_headers() {
+        let mut environ = environ();
+        environ
+            .http_variables
+            .insert("HTTP_X_GEE_GEO_ASN".to_owned(), "none".to_owned());
+        let config = DecorationsConfig {
+            values: vec![Decoration::ClientIp, Decoration::GeoAsn],
+            headers: Some(true),
+        };
+        decorate(&mut environ, &config, &client());
+
+        assert_eq!(environ.http_variables["HTTP_X_GEE_CLIENT_IP"], "1.2.3.4");
+        assert!(!environ.http_variables.contains_key("HTTP_X_GEE_GEO_ASN"));
+    }
+}
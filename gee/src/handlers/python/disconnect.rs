@@ -0,0 +1,69 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// `Disconnected` records whether the client a request was received from has
+/// gone away before its response was finished.
+#[derive(Clone, Debug, Default)]
+pub struct Disconnected(Arc<AtomicBool>);
+
+/// `DisconnectGuard` marks the client as disconnected if it is dropped while
+/// armed, which happens when the server abandons the request because its
+/// connection closed.
+pub struct DisconnectGuard {
+    disconnected: Disconnected,
+    armed: bool,
+}
+
+impl Disconnected {
+    /// `is_set` returns whether the client has gone away.
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// `set` marks the client as gone.
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    /// `guard` creates an armed guard for the client.
+    pub fn guard(&self) -> DisconnectGuard {
+        DisconnectGuard {
+            disconnected: self.clone(),
+            armed: true,
+        }
+    }
+}
+
+impl DisconnectGuard {
+    /// `disarm` stops the guard from marking the client as disconnected once
+    /// its response has been produced.
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            self.disconnected.set();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_guard() {
+        let disconnected = Disconnected::default();
+
+        disconnected.guard().disarm();
+        assert!(!disconnected.is_set());
+
+        drop(disconnected.guard());
+        assert!(disconnected.is_set());
+    }
+}
@@ -1,17 +1,36 @@
 use hyper::{
-    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    header::{CONTENT_LENGTH, CONTENT_TYPE, HOST},
+    http::uri::{Authority, Scheme},
     Body, Method, Request, Version,
 };
-use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
-use std::{collections::HashMap, fmt};
+use pyo3::{
+    prelude::*,
+    types::{PyBytes, PyCFunction, PyDict, PyTuple},
+};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{collections::HashMap, fmt, net::SocketAddr};
+
+use super::disconnect::Disconnected;
+use crate::trace::trace_extensions;
 
 /// UrlScheme enumerates the kinds of URL protocols supported by Gee.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum UrlScheme {
     HTTP,
     HTTPS,
 }
 
+impl UrlScheme {
+    /// `as_str` returns the scheme as it appears in a URL.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UrlScheme::HTTP => "http",
+            UrlScheme::HTTPS => "https",
+        }
+    }
+}
+
 /// Environ contains values to be passed to the Python server application.
 #[derive(Debug)]
 pub struct Environ {
@@ -37,8 +56,10 @@ pub struct Environ {
     /// The contents of any Content-Length fields in the HTTP request. May be empty or absent.
     pub content_length: String,
 
+    /// The host name the request was addressed to, or the address of the server if the request did not name one.
     pub server_name: String,
 
+    /// The port the request was addressed to, or the port of the server if the request did not name one.
     pub server_port: String,
 
     /// The version of the protocol the client used to send the request. Typically this will be something like
@@ -49,6 +70,20 @@ pub struct Environ {
     /// "HTTP_"). The presence or absence of these variables should correspond with the presence or absence of the appropriate HTTP header in the request.
     pub http_variables: HashMap<String, String>,
 
+    /// The address of the client the request was received from. May be empty or absent.
+    pub remote_addr: String,
+
+    /// Variables the application declared in its `env`, set before the standard variables so that those take
+    /// precedence.
+    pub variables: HashMap<String, String>,
+
+    /// Server-specific variables, whose names begin with "gee.", describing the request.
+    pub extensions: HashMap<String, String>,
+
+    /// Whether the client has gone away, provided to the application as the `gee.client_disconnected` callable
+    /// so that it can stop doing work nobody will receive.
+    pub client_disconnected: Disconnected,
+
     /// The tuple (1, 0), representing WSGI version 1.0.
     wsgi_version: (u32, u32),
 
@@ -59,8 +94,7 @@ pub struct Environ {
     /// Input stream (file-like object) from which the HTTP request body bytes can be read. (The server or gateway may
     /// perform reads on-demand as requested by the application, or it may pre-read the client's request body and
     /// buffer it in-memory or on disk, or use any other technique for providing such an input stream, according to its preference.)
-    // TODO: implement wsgi_input
-    // wsgi_input: Box<dyn Read>,
+    /// Gee pre-reads the request body and provides it as an `io.BytesIO` when the environ is converted with `to_dict`.
 
     /// An output stream (file-like object) to which error output can be written, for the purpose of recording
     /// program or other errors in a standardized and possibly centralized location. This should be a "text mode"
@@ -70,8 +104,7 @@ pub struct Environ {
     /// a log file of some sort. The server's documentation should include an explanation of how to configure this or
     /// where to find the recorded output. A server or gateway may supply different error streams to different
     /// applications, if this is desired.
-    // TODO: implement wsgi_errors
-    // wsgi_errors: Box<dyn Write>,
+    /// Gee provides `sys.stderr` when the environ is converted with `to_dict`.
 
     /// Value should evaluate true if the application object may be simultaneously invoked by another thread in the
     /// same process, and should evaluate false otherwise.
@@ -96,6 +129,7 @@ impl Environ {
     /// - `script_name` is the portion of the URL path which corresponds to the path to the application being called.
     ///   It may be empty if the application corresponds to the "root" of the server.
     // TODO: finish documenting arguments
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         request_method: Method,
         script_name: String,
@@ -118,36 +152,140 @@ impl Environ {
             server_port,
             server_protocol,
             http_variables: HashMap::new(),
+            remote_addr: "".to_owned(),
+            variables: HashMap::new(),
+            extensions: HashMap::new(),
+            client_disconnected: Disconnected::default(),
             wsgi_version: (1, 0),
-            wsgi_url_scheme: UrlScheme::HTTPS,
+            wsgi_url_scheme: UrlScheme::HTTP,
             wsgi_multithread: false,
             wsgi_multiprocess: false,
             wsgi_run_once: false,
         }
     }
 
-    pub fn from_request(req: &Request<Body>) -> Self {
-        Self::new(
-            req.method().clone(),
-            "app".to_owned(),
-            req.uri().path().to_owned(),
-            req.uri().query().unwrap_or("").to_owned(),
+    /// `from_request` creates a new instance of Environ describing the given request. The `server_address` is
+    /// used for `SERVER_NAME` and `SERVER_PORT` if the request does not name a host.
+    pub fn from_request(req: &Request<Body>, server_address: SocketAddr) -> Self {
+        let header = |name| {
             req.headers()
-                .get("content-type")
-                .unwrap_or(&HeaderValue::from_name(CONTENT_TYPE))
-                .to_str()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
                 .unwrap_or("")
-                .to_owned(),
+                .to_owned()
+        };
+
+        let authority = req.uri().authority().cloned().or_else(|| {
             req.headers()
-                .get("content-length")
-                .unwrap_or(&HeaderValue::from_name(CONTENT_LENGTH))
-                .to_str()
-                .unwrap_or("")
-                .to_owned(),
-            "".to_owned(),
+                .get(HOST)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<Authority>().ok())
+        });
+        let (server_name, server_port) = match authority {
+            Some(authority) => (
+                authority.host().to_owned(),
+                authority
+                    .port_u16()
+                    .unwrap_or_else(|| server_address.port())
+                    .to_string(),
+            ),
+            None => (
+                server_address.ip().to_string(),
+                server_address.port().to_string(),
+            ),
+        };
+
+        let mut environ = Self::new(
+            req.method().clone(),
             "".to_owned(),
+            req.uri().path().to_owned(),
+            req.uri().query().unwrap_or("").to_owned(),
+            header(CONTENT_TYPE),
+            header(CONTENT_LENGTH),
+            server_name,
+            server_port,
             req.version(),
-        )
+        );
+
+        if req.uri().scheme() == Some(&Scheme::HTTPS) {
+            environ.wsgi_url_scheme = UrlScheme::HTTPS;
+        }
+
+        for (name, value) in req.headers() {
+            if name == CONTENT_TYPE || name == CONTENT_LENGTH {
+                continue;
+            }
+
+            let key = format!("HTTP_{}", name.as_str().to_uppercase().replace('-', "_"));
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            environ
+                .http_variables
+                .entry(key)
+                .and_modify(|existing| {
+                    existing.push(',');
+                    existing.push_str(&value);
+                })
+                .or_insert(value);
+        }
+        environ.extensions.extend(trace_extensions(req.headers()));
+
+        environ
+    }
+
+    /// `to_dict` converts the Environ into the `environ` dictionary passed to the Python application, with `input`
+    /// provided as the body of the request.
+    pub fn to_dict<'py>(&self, py: Python<'py>, input: &[u8]) -> PyResult<&'py PyDict> {
+        let environ = PyDict::new(py);
+
+        for (key, value) in self.variables.iter() {
+            environ.set_item(key, value)?;
+        }
+
+        environ.set_item("REQUEST_METHOD", self.request_method.as_str())?;
+        environ.set_item("SCRIPT_NAME", &self.script_name)?;
+        environ.set_item("PATH_INFO", &self.path_info)?;
+        environ.set_item("QUERY_STRING", &self.query_string)?;
+        environ.set_item("CONTENT_TYPE", &self.content_type)?;
+        environ.set_item("CONTENT_LENGTH", &self.content_length)?;
+        environ.set_item("SERVER_NAME", &self.server_name)?;
+        environ.set_item("SERVER_PORT", &self.server_port)?;
+        environ.set_item("SERVER_PROTOCOL", format!("{:?}", self.server_protocol))?;
+
+        if !self.remote_addr.is_empty() {
+            environ.set_item("REMOTE_ADDR", &self.remote_addr)?;
+        }
+
+        for (key, value) in self.http_variables.iter() {
+            environ.set_item(key, value)?;
+        }
+
+        for (key, value) in self.extensions.iter() {
+            environ.set_item(key, value)?;
+        }
+
+        let client_disconnected = self.client_disconnected.clone();
+        let client_disconnected = PyCFunction::new_closure(
+            move |_args: &PyTuple, _kwargs: Option<&PyDict>| -> bool {
+                client_disconnected.is_set()
+            },
+            py,
+        )?;
+        environ.set_item("gee.client_disconnected", client_disconnected)?;
+
+        let input = py
+            .import("io")?
+            .getattr("BytesIO")?
+            .call1((PyBytes::new(py, input),))?;
+
+        environ.set_item("wsgi.version", self.wsgi_version)?;
+        environ.set_item("wsgi.url_scheme", self.wsgi_url_scheme.as_str())?;
+        environ.set_item("wsgi.input", input)?;
+        environ.set_item("wsgi.errors", py.import("sys")?.getattr("stderr")?)?;
+        environ.set_item("wsgi.multithread", self.wsgi_multithread)?;
+        environ.set_item("wsgi.multiprocess", self.wsgi_multiprocess)?;
+        environ.set_item("wsgi.run_once", self.wsgi_run_once)?;
+
+        Ok(environ)
     }
 }
 
@@ -0,0 +1,15 @@
+mod adapters;
+mod application;
+mod coalesce;
+mod decorations;
+mod disconnect;
+mod environ;
+mod mount;
+mod python_service;
+mod scoped_env;
+mod workers;
+
+pub use adapters::check_applications;
+pub use mount::Mount;
+pub use python_service::python_service_handler;
+pub use workers::{WorkerPool, WorkerStatus};
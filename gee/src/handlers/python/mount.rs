@@ -0,0 +1,447 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::info;
+use pyo3::{Py, PyAny, Python};
+use regex::Regex;
+
+use super::{
+    adapters,
+    application::{load_application, loaded_modules, unload_application},
+    coalesce::Coalescer,
+    scoped_env::ScopedEnv,
+};
+use crate::config::ApplicationConfig;
+
+/// `DEFAULT_COLD_START_BUDGET` is how long a request waits for a suspended
+/// application to be imported again when no budget is configured.
+const DEFAULT_COLD_START_BUDGET: Duration = Duration::from_secs(10);
+
+/// `Mount` is a loaded Python application along with the route it is mounted
+/// at and how request paths are translated for it. An application with a
+/// `suspend_after` is unloaded once idle and imported again on demand.
+pub struct Mount {
+    /// `route` is the path on the server under which requests are passed to
+    /// the application.
+    pub route: String,
+
+    /// `translation` translates request paths for the application.
+    translation: PathTranslation,
+
+    /// `application` is the Python callable requests are passed to, or
+    /// `None` while the application is suspended. It is only locked to read
+    /// or replace the callable, never across an import or while holding the
+    /// GIL.
+    application: Mutex<Option<Py<PyAny>>>,
+
+    /// `transition` is held while the application is suspended or imported
+    /// again, so that requests arriving during a cold start wait for the
+    /// same import without blocking a runtime thread.
+    transition: tokio::sync::Mutex<()>,
+
+    /// `config` describes the application, to import it again after it is
+    /// suspended.
+    config: ApplicationConfig,
+
+    /// `path` is the path of the Python file containing the application.
+    path: PathBuf,
+
+    /// `modules` are the names of the modules imported for the application,
+    /// which are forgotten when it is unloaded.
+    modules: Mutex<Vec<String>>,
+
+    /// `env` are the environment variables passed in the WSGI environ of
+    /// each request.
+    pub env: HashMap<String, String>,
+
+    /// `coalescer` shares responses between identical concurrent requests,
+    /// if the application coalesces them.
+    pub coalescer: Option<Arc<Coalescer>>,
+
+    /// `suspend_after` is how long the application may go without requests
+    /// before it is suspended, if it is ever.
+    pub suspend_after: Option<Duration>,
+
+    /// `cold_start_budget` is how long a request waits for the application
+    /// to be imported again after it was suspended.
+    pub cold_start_budget: Duration,
+
+    /// `in_flight` is the number of requests routed to the application which
+    /// have not been answered yet.
+    in_flight: AtomicUsize,
+
+    /// `last_used` is when the last request to the application was answered.
+    last_used: Mutex<Instant>,
+}
+
+/// `InFlight` counts a request towards the requests in flight to a mount for
+/// as long as it is held, so that the application is not suspended under it.
+pub struct InFlight(Arc<Mount>);
+
+impl Mount {
+    /// `load` imports the application described by the config, resolving its
+    /// path against the `root_dir`, and compiles its rewrite rules. The setup
+    /// and checks for the application's framework are run around the import,
+    /// with the application's `env` set.
+    pub fn load(config: &ApplicationConfig, root_dir: &str) -> Result<Self, Box<dyn Error>> {
+        let translation = PathTranslation::new(config)?;
+        let path = Path::new(root_dir).join(&config.path);
+        let env = config.env.clone().unwrap_or_default();
+        let (application, modules) = import(config, &path, &env)?;
+
+        Ok(Self {
+            route: config.route().to_owned(),
+            translation,
+            application: Mutex::new(Some(application)),
+            transition: tokio::sync::Mutex::new(()),
+            config: config.clone(),
+            path,
+            modules: Mutex::new(modules),
+            env,
+            coalescer: (config.coalesce == Some(true)).then(Default::default),
+            suspend_after: config.suspend_after.map(Duration::from_secs),
+            cold_start_budget: config
+                .cold_start_budget
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_COLD_START_BUDGET),
+            in_flight: AtomicUsize::new(0),
+            last_used: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// `loaded` returns the application if it is not suspended.
+    pub fn loaded(&self) -> Option<Py<PyAny>> {
+        self.application.lock().unwrap().clone()
+    }
+
+    /// `resume` returns the application, importing it again in a blocking
+    /// task if it is suspended. Requests arriving while it is imported wait
+    /// for the same import, and it counts as a use, so that an import
+    /// outlasting the request which started it is not suspended straight
+    /// away.
+    pub async fn resume(self: Arc<Self>) -> Result<Py<PyAny>, String> {
+        let _transition = self.transition.lock().await;
+        if let Some(application) = self.loaded() {
+            return Ok(application);
+        }
+
+        let started = Instant::now();
+        let mount = self.clone();
+        let (imported, modules) = tokio::task::spawn_blocking(move || {
+            import(&mount.config, &mount.path, &mount.env).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        info!(
+            "Resumed the application at {} in {} ms",
+            self.route,
+            started.elapsed().as_millis()
+        );
+
+        *self.application.lock().unwrap() = Some(imported.clone());
+        *self.modules.lock().unwrap() = modules;
+        *self.last_used.lock().unwrap() = Instant::now();
+        Ok(imported)
+    }
+
+    /// `suspend_if_idle` unloads the application if it has a `suspend_after`
+    /// and has gone that long without requests, forgetting its modules so
+    /// that the memory they hold is freed. It returns whether it did. It
+    /// blocks, so must be called outside of the runtime's threads.
+    pub fn suspend_if_idle(&self) -> Result<bool, Box<dyn Error>> {
+        let suspend_after = match self.suspend_after {
+            Some(suspend_after) => suspend_after,
+            None => return Ok(false),
+        };
+
+        let _transition = self.transition.blocking_lock();
+        let application = {
+            let mut application = self.application.lock().unwrap();
+            let idle = self.in_flight.load(Ordering::SeqCst) == 0
+                && self.last_used.lock().unwrap().elapsed() >= suspend_after;
+            if application.is_none() || !idle {
+                return Ok(false);
+            }
+            application.take()
+        };
+
+        let modules = std::mem::take(&mut *self.modules.lock().unwrap());
+        Python::with_gil(|py| {
+            drop(application);
+            unload_application(&self.path, &modules)?;
+            py.import("gc")?.call_method0("collect")?;
+            Ok::<_, Box<dyn Error>>(())
+        })?;
+        info!(
+            "Suspended the application at {} after {} s without requests",
+            self.route,
+            suspend_after.as_secs()
+        );
+
+        Ok(true)
+    }
+
+    /// `unload` forgets the modules imported for the application, so that
+    /// loading it again imports its code afresh.
+    pub fn unload(&self) -> Result<(), Box<dyn Error>> {
+        let modules = std::mem::take(&mut *self.modules.lock().unwrap());
+        unload_application(&self.path, &modules)
+    }
+
+    /// `split_path` translates a request path into the `SCRIPT_NAME` and
+    /// `PATH_INFO` the application receives.
+    pub fn split_path(&self, path: &str) -> (String, String) {
+        self.translation.split_path(path)
+    }
+}
+
+impl InFlight {
+    /// `enter` counts a request towards the requests in flight to `mount`.
+    pub fn enter(mount: Arc<Mount>) -> Self {
+        mount.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(mount)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        *self.0.last_used.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// `import` imports the application described by the config from the file at
+/// `path`, running the setup and checks for its framework around the import
+/// with its `env` set. It returns the application and the names of the
+/// modules imported for it.
+fn import(
+    config: &ApplicationConfig,
+    path: &Path,
+    env: &HashMap<String, String>,
+) -> Result<(Py<PyAny>, Vec<String>), Box<dyn Error>> {
+    let before = loaded_modules()?;
+    let mut variables = env.clone();
+    variables.extend(adapters::env(config));
+    let scoped_env = ScopedEnv::set(&variables)?;
+    adapters::prepare(config)?;
+    let application = load_application(path, &config.name)?;
+    adapters::check(config, &application)?;
+    drop(scoped_env);
+    let modules = loaded_modules()?
+        .into_iter()
+        .filter(|name| !before.contains(name))
+        .collect();
+
+    Ok((application, modules))
+}
+
+/// `PathTranslation` holds the normalized `strip_prefix` and compiled
+/// `rewrite` rules of an application.
+struct PathTranslation {
+    strip_prefix: Option<String>,
+    rewrites: Vec<(Regex, String)>,
+}
+
+impl PathTranslation {
+    fn new(config: &ApplicationConfig) -> Result<Self, Box<dyn Error>> {
+        let strip_prefix = config
+            .strip_prefix
+            .as_ref()
+            .map(|prefix| prefix.trim_end_matches('/').to_owned())
+            .filter(|prefix| !prefix.is_empty());
+
+        let rewrites = config
+            .rewrite
+            .iter()
+            .flatten()
+            .map(|rewrite| {
+                Regex::new(&rewrite.pattern)
+                    .map(|pattern| (pattern, rewrite.replacement.clone()))
+                    .map_err(|e| format!("Invalid rewrite pattern {}: {}", rewrite.pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            strip_prefix,
+            rewrites,
+        })
+    }
+
+    /// `split_path` removes the `strip_prefix` from the path, if it matches
+    /// whole path segments, to form the `SCRIPT_NAME` and applies the first
+    /// matching rewrite rule to the rest to form the `PATH_INFO`.
+    fn split_path(&self, path: &str) -> (String, String) {
+        let (script_name, path_info) = match self.strip_prefix.as_deref() {
+            Some(prefix) => match path.strip_prefix(prefix) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => (prefix, rest),
+                _ => ("", path),
+            },
+            None => ("", path),
+        };
+
+        let path_info = self
+            .rewrites
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(path_info))
+            .map(|(pattern, replacement)| {
+                pattern
+                    .replace(path_info, replacement.as_str())
+                    .into_owned()
+            })
+            .unwrap_or_else(|| path_info.to_owned());
+
+        (script_name.to_owned(), path_info)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RewriteConfig;
+
+    fn config(strip_prefix: Option<&str>, rewrite: Vec<(&str, &str)>) -> ApplicationConfig {
+        ApplicationConfig {
+            path: "app.py".to_owned(),
+            name: "application".to_owned(),
+            route: Some("/api".to_owned()),
+            strip_prefix: strip_prefix.map(str::to_owned),
+            rewrite: Some(
+                rewrite
+                    .into_iter()
+                    .map(|(pattern, replacement)| RewriteConfig {
+                        pattern: pattern.to_owned(),
+                        replacement: replacement.to_owned(),
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn split(config: &ApplicationConfig, path: &str) -> (String, String) {
+        PathTranslation::new(config).unwrap().split_path(path)
+    }
+
+    #[test]
+    fn test_split_path_without_translation() {
+        let config = config(None, vec![]);
+
+        assert_eq!(
+            split(&config, "/api/v1/users"),
+            ("".to_owned(), "/api/v1/users".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_split_path_with_strip_prefix() {
+        let config = config(Some("/api/v1/"), vec![]);
+
+        assert_eq!(
+            split(&config, "/api/v1/users"),
+            ("/api/v1".to_owned(), "/users".to_owned())
+        );
+        assert_eq!(
+            split(&config, "/api/v1"),
+            ("/api/v1".to_owned(), "".to_owned())
+        );
+        assert_eq!(
+            split(&config, "/api/v10/users"),
+            ("".to_owned(), "/api/v10/users".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_split_path_with_rewrite() {
+        let config = config(
+            Some("/api/v1"),
+            vec![("^/people(/.*)?$", "/users$1"), ("^/users", "/unused")],
+        );
+
+        assert_eq!(
+            split(&config, "/api/v1/people/7"),
+            ("/api/v1".to_owned(), "/users/7".to_owned())
+        );
+        assert_eq!(
+            split(&config, "/api/v1/users/7"),
+            ("/api/v1".to_owned(), "/unused/7".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_suspend_and_resume() {
+        pyo3::prepare_freethreaded_python();
+        let config = ApplicationConfig {
+            path: "src/fixtures/app/app.py".to_owned(),
+            name: "simple_app".to_owned(),
+            suspend_after: Some(0),
+            ..Default::default()
+        };
+        let mount = Arc::new(Mount::load(&config, ".").unwrap());
+
+        let in_flight = InFlight::enter(mount.clone());
+        assert!(!mount.suspend_if_idle().unwrap());
+        drop(in_flight);
+
+        assert!(mount.suspend_if_idle().unwrap());
+        assert!(mount.loaded().is_none());
+        assert!(!mount.suspend_if_idle().unwrap());
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        assert!(runtime.block_on(mount.clone().resume()).is_ok());
+        assert!(mount.loaded().is_some());
+    }
+
+    #[test]
+    fn test_suspend_keeps_modules_of_other_applications() {
+        pyo3::prepare_freethreaded_python();
+        let root_dir = std::env::temp_dir().join(format!("gee-shared-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&root_dir).unwrap();
+        for (file, helper) in [
+            ("gee_first", "gee_first_helper"),
+            ("gee_second", "gee_second_helper"),
+        ] {
+            std::fs::write(root_dir.join(format!("{}.py", helper)), "").unwrap();
+            std::fs::write(
+                root_dir.join(format!("{}.py", file)),
+                format!(
+                    "import {}\n\ndef app(environ, start_response):\n    return []\n",
+                    helper
+                ),
+            )
+            .unwrap();
+        }
+        let mount = |file: &str| {
+            let config = ApplicationConfig {
+                path: format!("{}.py", file),
+                name: "app".to_owned(),
+                suspend_after: Some(0),
+                ..Default::default()
+            };
+            Mount::load(&config, &root_dir.to_string_lossy()).unwrap()
+        };
+        let first = mount("gee_first");
+        let _second = mount("gee_second");
+
+        assert!(first.suspend_if_idle().unwrap());
+        let loaded = loaded_modules().unwrap();
+        assert!(!loaded.contains("gee_first"));
+        assert!(!loaded.contains("gee_first_helper"));
+        assert!(loaded.contains("gee_second"));
+        assert!(loaded.contains("gee_second_helper"));
+
+        std::fs::remove_dir_all(&root_dir).unwrap();
+    }
+
+    #[test]
+    fn test_path_translation_with_invalid_pattern() {
+        assert!(PathTranslation::new(&config(None, vec![("(", "/")])).is_err());
+    }
+}
@@ -1 +1,309 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Instant, UNIX_EPOCH},
+};
 
+use hyper::{
+    header::{HeaderName, HeaderValue, ETAG, RETRY_AFTER},
+    Body, Request, Response, StatusCode, Uri,
+};
+use log::{debug, error, warn};
+use pyo3::{Py, PyAny};
+
+use super::{
+    application::{call_application, ApplicationResponse},
+    coalesce::{coalescing_key, follow, Flight},
+    decorations::decorate,
+    disconnect::DisconnectGuard,
+    environ::Environ,
+    mount::{InFlight, Mount},
+    workers::WorkerPool,
+};
+use crate::config::Config;
+use crate::context::RequestContext;
+use crate::handlers::body::{read_body, BodyError};
+use crate::handlers::conditional::{etag, evaluate_conditionals};
+use crate::handlers::inspect::Rejection;
+
+/// `python_service_handler` passes the request to the Python application of
+/// the `mount` once a worker is free and returns its response. If the request
+/// is shed by the admission queue, a 503 is returned instead. If the client
+/// disconnects while the application runs, the application is told through
+/// `gee.client_disconnected`. If the application coalesces requests and an
+/// identical one is already being handled, its response is shared instead.
+/// If the request is safe and its validators call for it, a 412 or 304 is
+/// returned in its place.
+pub async fn python_service_handler(
+    req: Request<Body>,
+    mount: Arc<Mount>,
+    workers: Arc<WorkerPool>,
+    config: Config,
+) -> Response<Body> {
+    let mut environ = Environ::from_request(&req, config.socket_address());
+    (environ.script_name, environ.path_info) = mount.split_path(req.uri().path());
+    environ.variables = mount.env.clone();
+    if let Some(context) = RequestContext::of(&req) {
+        if let Some(remote_addr) = context.client.remote_addr {
+            environ.remote_addr = remote_addr.ip().to_string();
+        }
+        if let Some(decorations) = &config.decorations {
+            decorate(&mut environ, decorations, &context.client);
+        }
+        environ
+            .extensions
+            .insert("gee.request_id".to_owned(), context.request_id.clone());
+        if let Some(route) = &context.route {
+            environ
+                .extensions
+                .insert("gee.route".to_owned(), route.clone());
+        }
+    }
+    let disconnect_guard = environ.client_disconnected.guard();
+    let (parts, body) = req.into_parts();
+
+    let limits = config.limits(parts.uri.path());
+    let input = match read_body(body, limits.max_body_size).await {
+        Ok(input) => input,
+        Err(BodyError::TooLarge) => return status_response(StatusCode::PAYLOAD_TOO_LARGE),
+        Err(BodyError::Read(e)) => {
+            match parts
+                .extensions
+                .get::<Rejection>()
+                .and_then(Rejection::reason)
+            {
+                Some(reason) => warn!("Rejected request to {}: {}", parts.uri, reason),
+                None => error!("Cannot read request body: {}", e),
+            }
+            return status_response(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    let flight = match (&mount.coalescer, coalescing_key(&parts, &input)) {
+        (Some(coalescer), Some(key)) => Some(coalescer.join(key)),
+        _ => None,
+    };
+    let call = Call {
+        mount: &mount,
+        workers: &workers,
+        uri: &parts.uri,
+        environ,
+        input,
+        disconnect_guard,
+        context: parts.extensions.get::<Arc<RequestContext>>().cloned(),
+    };
+    let result = match flight {
+        Some(Flight::Leader(leader)) => {
+            let result = call.run().await;
+            leader.finish(result.as_ref().ok());
+            result
+        }
+        Some(Flight::Follower(receiver)) => match follow(receiver).await {
+            Some(application_response) => {
+                debug!("Shared a coalesced response to {}", parts.uri);
+                Ok(application_response)
+            }
+            None => call.run().await,
+        },
+        None => call.run().await,
+    };
+    let application_response = match result {
+        Ok(application_response) => application_response,
+        Err(response) => return response,
+    };
+
+    if let Some(location) = application_response.redirect() {
+        debug!(
+            "Application redirected {} to {} with {}",
+            parts.uri, location, application_response.status
+        );
+    }
+    if let Some(retry_after) = &application_response.retry_after {
+        debug!(
+            "Application asked clients of {} to retry after {}",
+            parts.uri, retry_after
+        );
+    }
+
+    let mut rsp = Response::builder().status(application_response.status);
+    for (name, value) in application_response.all_headers() {
+        match (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            (Ok(name), Ok(value)) => rsp = rsp.header(name, value),
+            _ => {
+                error!(
+                    "Application returned an invalid header: {}: {}",
+                    name, value
+                );
+                return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    let needs_etag = config.application_etags == Some(true)
+        && application_response.status == StatusCode::OK
+        && !rsp
+            .headers_ref()
+            .is_some_and(|headers| headers.contains_key(ETAG));
+    if needs_etag {
+        rsp = rsp.header(ETAG, etag(&application_response.body));
+    }
+
+    let response = rsp.body(Body::from(application_response.body)).unwrap();
+
+    // The application has already acted on unsafe requests, so their
+    // responses are passed through as they are.
+    if !parts.method.is_safe() {
+        return response;
+    }
+
+    evaluate_conditionals(&parts.method, &parts.headers, response)
+}
+
+/// `Call` is a request about to be passed to the application.
+struct Call<'a> {
+    /// `mount` is the application the request is passed to.
+    mount: &'a Arc<Mount>,
+
+    /// `workers` admits the request to the application.
+    workers: &'a WorkerPool,
+
+    /// `uri` is the URI of the request, for logging.
+    uri: &'a Uri,
+
+    /// `environ` is the WSGI environ the application is called with.
+    environ: Environ,
+
+    /// `input` is the body of the request.
+    input: Vec<u8>,
+
+    /// `disconnect_guard` marks the client as gone if the call is abandoned.
+    disconnect_guard: DisconnectGuard,
+
+    /// `context` holds the deadline of the request, if it has one.
+    context: Option<Arc<RequestContext>>,
+}
+
+impl Call<'_> {
+    /// `run` calls the application once a worker is free, returning the
+    /// response to send in its place if the request is shed, the application
+    /// fails, or the request's deadline passes first. An application still
+    /// running at the deadline keeps its worker until it returns, and is told
+    /// the client is gone.
+    async fn run(mut self) -> Result<ApplicationResponse, Response<Body>> {
+        let deadline = self.context.as_ref().and_then(|context| context.deadline);
+        let in_flight = InFlight::enter(self.mount.clone());
+
+        let permit = match within(deadline, self.workers.admit()).await {
+            Some(Ok(permit)) => permit,
+            Some(Err(rejection)) => {
+                warn!("Shed request to {}: {:?}", self.uri, rejection);
+                return Err(self.workers.rejection_response());
+            }
+            None => {
+                warn!("Request to {} timed out waiting for a worker", self.uri);
+                return Err(status_response(StatusCode::GATEWAY_TIMEOUT));
+            }
+        };
+
+        let application = match self.mount.loaded() {
+            Some(application) => application,
+            None => self.resume().await?,
+        };
+
+        if let Some(context) = &self.context {
+            set_deadline(&mut self.environ, context);
+        }
+        let (environ, input) = (self.environ, self.input);
+        let call = tokio::task::spawn_blocking(move || {
+            let result = call_application(&application, environ, &input);
+            drop(permit);
+            drop(in_flight);
+            result
+        });
+        let result = match within(deadline, call).await {
+            Some(result) => result,
+            None => {
+                warn!("Application did not answer {} in time", self.uri);
+                return Err(status_response(StatusCode::GATEWAY_TIMEOUT));
+            }
+        };
+        self.disconnect_guard.disarm();
+
+        match result {
+            Ok(Ok(application_response)) => Ok(application_response),
+            _ => Err(status_response(StatusCode::INTERNAL_SERVER_ERROR)),
+        }
+    }
+
+    /// `resume` imports the suspended application again, returning a 503 in
+    /// its place if the import takes longer than the cold start budget. The
+    /// import carries on in its own task, so that later requests find the
+    /// application ready.
+    async fn resume(&self) -> Result<Py<PyAny>, Response<Body>> {
+        let budget = self.mount.cold_start_budget;
+        let resume = tokio::spawn(self.mount.clone().resume());
+
+        match tokio::time::timeout(budget, resume).await {
+            Ok(Ok(Ok(application))) => Ok(application),
+            Ok(Ok(Err(e))) => {
+                error!(
+                    "Cannot resume the application at {}: {}",
+                    self.mount.route, e
+                );
+                Err(status_response(StatusCode::INTERNAL_SERVER_ERROR))
+            }
+            Ok(Err(_)) => Err(status_response(StatusCode::INTERNAL_SERVER_ERROR)),
+            Err(_) => {
+                warn!(
+                    "Application at {} did not resume in time for {}",
+                    self.mount.route, self.uri
+                );
+                Err(Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .header(RETRY_AFTER, budget.as_secs().max(1))
+                    .body(Body::empty())
+                    .unwrap())
+            }
+        }
+    }
+}
+
+/// `within` waits for a future until the deadline, if there is one,
+/// returning `None` if the deadline passes first.
+async fn within<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// `set_deadline` passes the deadline of a request to the application as
+/// `gee.deadline`, in seconds since the epoch, and the time left before it
+/// as `gee.deadline_remaining`, in seconds.
+fn set_deadline(environ: &mut Environ, context: &RequestContext) {
+    let (deadline, remaining) = match (context.deadline_time(), context.remaining()) {
+        (Some(deadline), Some(remaining)) => (deadline, remaining),
+        _ => return,
+    };
+    let since = deadline.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    environ.extensions.insert(
+        "gee.deadline".to_owned(),
+        format!("{}.{:06}", since.as_secs(), since.subsec_micros()),
+    );
+    environ.extensions.insert(
+        "gee.deadline_remaining".to_owned(),
+        format!("{:.3}", remaining.as_secs_f64()),
+    );
+}
+
+/// `status_response` builds an empty response with the given status.
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use log::warn;
+use pyo3::prelude::*;
+
+/// `ScopedEnv` sets environment variables in `os.environ` and puts back the
+/// values they replaced when it is dropped. The interpreter, and so
+/// `os.environ`, is shared by every application, so an application's `env`
+/// is only set while it is imported.
+pub struct ScopedEnv {
+    /// `previous` are the values the variables had before they were set, or
+    /// `None` for variables that were not set.
+    previous: Vec<(String, Option<String>)>,
+}
+
+impl ScopedEnv {
+    /// `set` sets the variables, remembering the values they replace.
+    pub fn set(variables: &HashMap<String, String>) -> PyResult<Self> {
+        Python::with_gil(|py| {
+            let environ = py.import("os")?.getattr("environ")?;
+
+            let mut previous = Vec::new();
+            for (name, value) in variables {
+                let old: Option<String> = environ.call_method1("get", (name,))?.extract()?;
+                previous.push((name.clone(), old));
+                environ.set_item(name, value)?;
+            }
+
+            Ok(Self { previous })
+        })
+    }
+
+    /// `restore` puts back the values the variables had before they were set.
+    fn restore(&mut self) -> PyResult<()> {
+        Python::with_gil(|py| {
+            let environ = py.import("os")?.getattr("environ")?;
+
+            for (name, old) in self.previous.drain(..) {
+                match old {
+                    Some(old) => environ.set_item(name, old)?,
+                    None => {
+                        environ.call_method1("pop", (name, py.None()))?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl Drop for ScopedEnv {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            warn!("Cannot restore the environment after an import: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get(name: &str) -> Option<String> {
+        Python::with_gil(|py| {
+            py.import("os")
+                .and_then(|os| os.getattr("environ"))
+                .and_then(|environ| environ.call_method1("get", (name,)))
+                .and_then(|value| value.extract())
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn test_scoped_env() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let environ = py.import("os")?.getattr("environ")?;
+            environ.set_item("GEE_SCOPED_ENV_KEPT", "original")?;
+            environ.call_method1("pop", ("GEE_SCOPED_ENV_NEW", py.None()))?;
+            PyResult::Ok(())
+        })
+        .unwrap();
+        let variables = HashMap::from([
+            ("GEE_SCOPED_ENV_KEPT".to_owned(), "replaced".to_owned()),
+            ("GEE_SCOPED_ENV_NEW".to_owned(), "added".to_owned()),
+        ]);
+
+        let scoped = ScopedEnv::set(&variables).unwrap();
+        assert_eq!(get("GEE_SCOPED_ENV_KEPT").as_deref(), Some("replaced"));
+        assert_eq!(get("GEE_SCOPED_ENV_NEW").as_deref(), Some("added"));
+
+        drop(scoped);
+        assert_eq!(get("GEE_SCOPED_ENV_KEPT").as_deref(), Some("original"));
+        assert_eq!(get("GEE_SCOPED_ENV_NEW"), None);
+    }
+}
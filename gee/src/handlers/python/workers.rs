@@ -0,0 +1,214 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use hyper::{header::RETRY_AFTER, Body, Response, StatusCode};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{config::Config, metrics::Metrics};
+
+/// `DEFAULT_WORKERS` is the number of requests the Python applications may
+/// handle at once if `workers` is not configured.
+const DEFAULT_WORKERS: usize = 8;
+
+/// `DEFAULT_RETRY_AFTER` is the number of seconds shed requests are told to
+/// wait before retrying if `retry_after` is not configured.
+const DEFAULT_RETRY_AFTER: u64 = 1;
+
+/// `Rejection` enumerates the reasons the admission queue sheds a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The queue already held `max_depth` requests.
+    QueueFull,
+
+    /// The request waited in the queue for longer than `max_wait`.
+    QueueTimeout,
+}
+
+/// `WorkerStatus` is a snapshot of the state of a `WorkerPool`.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct WorkerStatus {
+    pub workers: usize,
+    pub busy: usize,
+    pub waiting: usize,
+}
+
+/// `WorkerPool` bounds how many requests are passed to the Python applications
+/// at once. Requests that cannot be handled right away wait in an admission
+/// queue, which sheds them if it is full or they have waited too long.
+pub struct WorkerPool {
+    size: usize,
+    workers: Arc<Semaphore>,
+    max_depth: Option<usize>,
+    max_wait: Option<Duration>,
+    retry_after: u64,
+    waiting: AtomicUsize,
+    metrics: Arc<Metrics>,
+}
+
+impl WorkerPool {
+    /// `new` creates a new `WorkerPool` sized by the `workers` and `queue`
+    /// settings of the config, failing if there are no workers, as every
+    /// request would wait in the queue forever.
+    pub fn new(config: &Config, metrics: Arc<Metrics>) -> Result<Self, String> {
+        let queue = config.queue.clone().unwrap_or_default();
+        let size = match config.workers {
+            Some(0) => return Err("The workers must be at least 1".to_owned()),
+            Some(workers) => workers,
+            None => DEFAULT_WORKERS,
+        };
+
+        Ok(Self {
+            size,
+            workers: Arc::new(Semaphore::new(size)),
+            max_depth: queue.max_depth,
+            max_wait: queue.max_wait.map(Duration::from_millis),
+            retry_after: queue.retry_after.unwrap_or(DEFAULT_RETRY_AFTER),
+            waiting: AtomicUsize::new(0),
+            metrics,
+        })
+    }
+
+    /// `admit` waits for a worker to become free and returns the permit that
+    /// holds it, or the reason the request was shed.
+    pub async fn admit(&self) -> Result<OwnedSemaphorePermit, Rejection> {
+        if let Ok(permit) = self.workers.clone().try_acquire_owned() {
+            self.metrics.queue_wait.observe(Duration::ZERO);
+            return Ok(permit);
+        }
+
+        let depth = self.waiting.fetch_add(1, Ordering::SeqCst) + 1;
+        let _waiting = Waiting(self);
+        if self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            self.metrics.queue_rejected_full.inc();
+            return Err(Rejection::QueueFull);
+        }
+        self.metrics.queue_depth.set(depth as i64);
+
+        let started = Instant::now();
+        let acquire = self.workers.clone().acquire_owned();
+        let permit = match self.max_wait {
+            Some(max_wait) => tokio::time::timeout(max_wait, acquire).await.ok(),
+            None => Some(acquire.await),
+        };
+        self.metrics.queue_wait.observe(started.elapsed());
+
+        match permit {
+            Some(Ok(permit)) => Ok(permit),
+            _ => {
+                self.metrics.queue_rejected_timeout.inc();
+                Err(Rejection::QueueTimeout)
+            }
+        }
+    }
+
+    /// `status` describes how many workers there are, how many are handling
+    /// requests, and how many requests are waiting for one.
+    pub fn status(&self) -> WorkerStatus {
+        WorkerStatus {
+            workers: self.size,
+            busy: self.size.saturating_sub(self.workers.available_permits()),
+            waiting: self.waiting.load(Ordering::SeqCst),
+        }
+    }
+
+    /// `rejection_response` builds the 503 sent to the client for a shed
+    /// request.
+    pub fn rejection_response(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header(RETRY_AFTER, self.retry_after)
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+/// `Waiting` removes a request from the queue depth when it leaves the queue,
+/// including when the client goes away while it waits.
+struct Waiting<'a>(&'a WorkerPool);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let depth = self.0.waiting.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.0.metrics.queue_depth.set(depth as i64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::QueueConfig;
+
+    fn pool(workers: usize, max_depth: Option<usize>, max_wait: Option<u64>) -> Arc<WorkerPool> {
+        let mut config = Config::new_default();
+        config.workers = Some(workers);
+        config.queue = Some(QueueConfig {
+            max_depth,
+            max_wait,
+            retry_after: Some(5),
+        });
+
+        Arc::new(WorkerPool::new(&config, Arc::new(Metrics::new())).unwrap())
+    }
+
+    #[test]
+    fn test_new_rejects_zero_workers() {
+        let mut config = Config::new_default();
+        config.workers = Some(0);
+
+        assert!(WorkerPool::new(&config, Arc::new(Metrics::new())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_admit_sheds_when_queue_is_full() {
+        let pool = pool(1, Some(1), None);
+        let _busy = pool.admit().await.unwrap();
+
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.admit().await.map(|_| ()) }
+        });
+        while pool.metrics.queue_depth.get() < 1 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            pool.status(),
+            WorkerStatus {
+                workers: 1,
+                busy: 1,
+                waiting: 1,
+            }
+        );
+
+        assert_eq!(pool.admit().await.unwrap_err(), Rejection::QueueFull);
+        assert_eq!(pool.metrics.queue_rejected_full.get(), 1);
+
+        drop(_busy);
+        assert_eq!(queued.await.unwrap(), Ok(()));
+        assert_eq!(pool.metrics.queue_depth.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_admit_sheds_after_max_wait() {
+        let pool = pool(1, None, Some(10));
+        let _busy = pool.admit().await.unwrap();
+
+        assert_eq!(pool.admit().await.unwrap_err(), Rejection::QueueTimeout);
+        assert_eq!(pool.metrics.queue_rejected_timeout.get(), 1);
+        assert_eq!(pool.metrics.queue_depth.get(), 0);
+        assert_eq!(pool.metrics.queue_wait.count(), 2);
+    }
+
+    #[test]
+    fn test_rejection_response() {
+        let response = pool(1, None, None).rejection_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "5");
+    }
+}
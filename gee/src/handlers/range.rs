@@ -0,0 +1,350 @@
+use hyper::{
+    body::Bytes,
+    header::{
+        HeaderMap, HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+        IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+    Body, Method, Response, StatusCode,
+};
+use std::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::checksum::CONTENT_DIGEST;
+use super::conditional::{header_date, header_str};
+
+/// `MAX_RANGES` is the largest number of ranges served in one response.
+/// Requests for more are answered with the whole content.
+const MAX_RANGES: usize = 32;
+
+/// `BOUNDARIES` counts the `multipart/byteranges` bodies built, so that each
+/// gets its own boundary.
+static BOUNDARIES: AtomicU64 = AtomicU64::new(0);
+
+/// `apply_range` answers a GET request carrying a `Range` with the parts of
+/// the `content` of a successful response it asks for: a 206 with a
+/// `Content-Range` for one range, a 206 `multipart/byteranges` body for
+/// several, or a 416 if none of them can be satisfied. Malformed ranges, and
+/// an `If-Range` the response does not match, are answered with the whole
+/// content.
+pub fn apply_range(
+    method: &Method,
+    req_headers: &HeaderMap,
+    response: Response<Body>,
+    content: &Bytes,
+) -> Response<Body> {
+    if method != Method::GET
+        || response.status() != StatusCode::OK
+        || !is_range_current(req_headers, response.headers())
+    {
+        return response;
+    }
+
+    let ranges = match header_str(req_headers, RANGE).and_then(parse_ranges) {
+        Some(ranges) => ranges,
+        None => return response,
+    };
+
+    let length = content.len() as u64;
+    let ranges: Vec<_> = ranges
+        .into_iter()
+        .filter_map(|range| range.resolve(length))
+        .collect();
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.remove(CONTENT_DIGEST);
+
+    let body = match ranges.as_slice() {
+        [] => {
+            parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+            parts.headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{}", length)).unwrap(),
+            );
+            Body::empty()
+        }
+        [range] => {
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            parts.headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&content_range(range, length)).unwrap(),
+            );
+            Body::from(content.slice(range.start as usize..range.end as usize))
+        }
+        ranges => {
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            let boundary = boundary();
+            let body = multipart(ranges, content, parts.headers.get(CONTENT_TYPE), &boundary);
+            parts.headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", boundary))
+                    .unwrap(),
+            );
+            Body::from(body)
+        }
+    };
+
+    Response::from_parts(parts, body)
+}
+
+/// `with_accept_ranges` advertises that byte ranges of a response may be
+/// requested.
+pub fn with_accept_ranges(rsp: hyper::http::response::Builder) -> hyper::http::response::Builder {
+    rsp.header(ACCEPT_RANGES, "bytes")
+}
+
+/// `ByteRange` is a range of bytes as requested, before the length of the
+/// content is known.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// `FromTo` is `first-last`, or `first-` if `last` is `None`.
+    FromTo(u64, Option<u64>),
+
+    /// `Suffix` is `-length`, the last `length` bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// `resolve` returns the offsets of the range within content of the given
+    /// length, or `None` if it is not satisfiable.
+    fn resolve(&self, length: u64) -> Option<Range<u64>> {
+        match *self {
+            ByteRange::FromTo(first, _) if first >= length => None,
+            ByteRange::FromTo(first, last) => {
+                let end = last.map_or(length, |last| last.saturating_add(1).min(length));
+                Some(first..end)
+            }
+            ByteRange::Suffix(0) => None,
+            ByteRange::Suffix(suffix) => Some(length.saturating_sub(suffix)..length),
+        }
+        .filter(|range| !range.is_empty())
+    }
+}
+
+/// `parse_ranges` parses a `Range` header of the form `bytes=0-99,-500`,
+/// returning `None` if it is malformed, does not use bytes, or asks for more
+/// than `MAX_RANGES` ranges.
+fn parse_ranges(header: &str) -> Option<Vec<ByteRange>> {
+    let (unit, ranges) = header.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let ranges = ranges
+        .split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (first, last) = range.split_once('-')?;
+            match (first.trim(), last.trim()) {
+                ("", suffix) => Some(ByteRange::Suffix(suffix.parse().ok()?)),
+                (first, "") => Some(ByteRange::FromTo(first.parse().ok()?, None)),
+                (first, last) => {
+                    let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                    (first <= last).then_some(ByteRange::FromTo(first, Some(last)))
+                }
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    (!ranges.is_empty() && ranges.len() <= MAX_RANGES).then_some(ranges)
+}
+
+/// `is_range_current` returns whether the `If-Range` of a request, if it has
+/// one, matches the response, either as a strong `ETag` or as the exact
+/// `Last-Modified` date.
+fn is_range_current(req_headers: &HeaderMap, resp_headers: &HeaderMap) -> bool {
+    let if_range = match header_str(req_headers, IF_RANGE) {
+        Some(if_range) => if_range.trim(),
+        None => return true,
+    };
+
+    if if_range.starts_with('"') {
+        return header_str(resp_headers, ETAG).is_some_and(|etag| etag.trim() == if_range);
+    }
+    if if_range.starts_with("W/") {
+        return false;
+    }
+
+    match (
+        header_date(req_headers, IF_RANGE),
+        header_date(resp_headers, LAST_MODIFIED),
+    ) {
+        (Some(if_range), Some(last_modified)) => if_range == last_modified,
+        _ => false,
+    }
+}
+
+/// `content_range` formats the `Content-Range` of a range of the content.
+fn content_range(range: &Range<u64>, length: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, length)
+}
+
+/// `boundary` returns a new boundary for a `multipart/byteranges` body.
+fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or(0);
+
+    format!(
+        "gee-{:08x}{:08x}",
+        nanos,
+        BOUNDARIES.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// `multipart` builds a `multipart/byteranges` body holding each range of the
+/// content, with its `Content-Range` and the `Content-Type` of the content.
+fn multipart(
+    ranges: &[Range<u64>],
+    content: &Bytes,
+    content_type: Option<&HeaderValue>,
+    boundary: &str,
+) -> Vec<u8> {
+    let length = content.len() as u64;
+    let mut body = Vec::new();
+
+    for range in ranges {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(b"Content-Type: ");
+            body.extend_from_slice(content_type.as_bytes());
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(
+            format!("Content-Range: {}\r\n\r\n", content_range(range, length)).as_bytes(),
+        );
+        body.extend_from_slice(&content[range.start as usize..range.end as usize]);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    body
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONTENT: &[u8] = b"0123456789abcdefghij";
+
+    fn request_headers(headers: Vec<(&'static str, &str)>) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(name, value.parse().unwrap());
+        }
+        map
+    }
+
+    fn response() -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, "application/pdf")
+            .header(ETAG, "\"abc\"")
+            .header(LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT")
+            .body(Body::from(CONTENT))
+            .unwrap()
+    }
+
+    async fn ranged(headers: Vec<(&'static str, &str)>) -> (Response<Body>, Vec<u8>) {
+        let content = Bytes::from_static(CONTENT);
+        let response = apply_range(
+            &Method::GET,
+            &request_headers(headers),
+            response(),
+            &content,
+        );
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap().to_vec();
+
+        (Response::from_parts(parts, Body::empty()), body)
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        assert_eq!(
+            parse_ranges("bytes=0-4, 10-, -3"),
+            Some(vec![
+                ByteRange::FromTo(0, Some(4)),
+                ByteRange::FromTo(10, None),
+                ByteRange::Suffix(3),
+            ])
+        );
+        assert_eq!(parse_ranges("items=0-4"), None);
+        assert_eq!(parse_ranges("bytes=4-0"), None);
+        assert_eq!(parse_ranges("bytes=a-b"), None);
+        assert_eq!(parse_ranges("bytes="), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(ByteRange::FromTo(0, Some(4)).resolve(20), Some(0..5));
+        assert_eq!(ByteRange::FromTo(15, Some(99)).resolve(20), Some(15..20));
+        assert_eq!(
+            ByteRange::FromTo(0, Some(u64::MAX)).resolve(20),
+            Some(0..20)
+        );
+        assert_eq!(ByteRange::FromTo(20, None).resolve(20), None);
+        assert_eq!(ByteRange::Suffix(5).resolve(20), Some(15..20));
+        assert_eq!(ByteRange::Suffix(50).resolve(20), Some(0..20));
+        assert_eq!(ByteRange::Suffix(0).resolve(20), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_range_single() {
+        let (response, body) = ranged(vec![("Range", "bytes=2-5")]).await;
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-5/20");
+        assert_eq!(body, b"2345");
+    }
+
+    #[tokio::test]
+    async fn test_apply_range_multipart() {
+        let (response, body) = ranged(vec![("Range", "bytes=0-1,-2,100-")]).await;
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = response.headers()[CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            format!(
+                "--{0}\r\nContent-Type: application/pdf\r\nContent-Range: bytes 0-1/20\r\n\r\n01\r\n\
+                 --{0}\r\nContent-Type: application/pdf\r\nContent-Range: bytes 18-19/20\r\n\r\nij\r\n\
+                 --{0}--\r\n",
+                boundary
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_range_not_satisfiable() {
+        let (response, body) = ranged(vec![("Range", "bytes=20-")]).await;
+
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes */20");
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_range_if_range() {
+        let (response, _) = ranged(vec![("Range", "bytes=0-1"), ("If-Range", "\"abc\"")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+        let (response, body) = ranged(vec![("Range", "bytes=0-1"), ("If-Range", "\"xyz\"")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body, CONTENT);
+
+        let (response, _) = ranged(vec![
+            ("Range", "bytes=0-1"),
+            ("If-Range", "Wed, 21 Oct 2015 07:28:00 GMT"),
+        ])
+        .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    }
+}
@@ -1,13 +1,95 @@
-pub fn static_service_handler(req: Request<Body>) -> Self::Future<Response<Body>> {
-    let path = req.uri().path();
-    let static_path = self
-        .resolve_static_path(path)
-        .expect("Cannot resolve static path");
-    serve_file(&static_path);
+use hyper::{
+    body::Bytes,
+    header::{ETAG, LAST_MODIFIED},
+    Body, Request, Response, StatusCode,
+};
+use log::debug;
+use std::{fs::Metadata, io, path::PathBuf, sync::Arc, time::UNIX_EPOCH};
+
+use super::checksum::digest_headers;
+use super::conditional::evaluate_conditionals;
+use super::file_cache::FileCache;
+use super::range::{apply_range, with_accept_ranges};
+
+/// `static_service_handler` serves the file at `static_path` in response to
+/// the request, or a 404 if the file cannot be read. The response carries an
+/// `ETag` and `Last-Modified` derived from the file's metadata, and the
+/// request's validators are evaluated against them before any `Range` it
+/// asks for is served. With `digests`, the response also carries the SHA-256
+/// of the file. The file is read through the `file_cache` if there is one.
+pub async fn static_service_handler(
+    req: Request<Body>,
+    static_path: PathBuf,
+    digests: bool,
+    file_cache: Option<Arc<FileCache>>,
+) -> Response<Body> {
+    debug!(
+        "Serving {} from {}",
+        req.uri().path(),
+        static_path.display()
+    );
 
     let rsp = Response::builder();
-    let response = match request_result {
-        Some(content) => rsp.status(200).body(Body::from(content)).unwrap(),
-        None => rsp.status(404).body(Body::from(vec![])).unwrap(),
+    let file = match file_cache {
+        Some(file_cache) => file_cache
+            .read(static_path)
+            .await
+            .map(|(content, metadata)| (content, Some(metadata))),
+        None => read_file(static_path).await,
+    };
+
+    let (response, content) = match file {
+        Ok((content, metadata)) => {
+            let mut rsp = with_accept_ranges(rsp.status(StatusCode::OK));
+            if let Some(metadata) = metadata {
+                rsp = with_validators(rsp, &metadata);
+            }
+            if digests {
+                for (name, value) in digest_headers(&content) {
+                    rsp = rsp.header(name, value);
+                }
+            }
+            (rsp.body(Body::from(content.clone())).unwrap(), content)
+        }
+        Err(_) => (
+            rsp.status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+            Bytes::new(),
+        ),
+    };
+
+    let response = evaluate_conditionals(req.method(), req.headers(), response);
+    apply_range(req.method(), req.headers(), response, &content)
+}
+
+/// `read_file` reads the content of the file at `static_path` and its
+/// metadata, if it can be inspected.
+async fn read_file(static_path: PathBuf) -> io::Result<(Bytes, Option<Metadata>)> {
+    let content = tokio::fs::read(&static_path).await?;
+    let metadata = tokio::fs::metadata(&static_path).await.ok();
+
+    Ok((Bytes::from(content), metadata))
+}
+
+/// `with_validators` adds an `ETag` built from the size and modification time
+/// of a file and its `Last-Modified` time to a response.
+fn with_validators(
+    rsp: hyper::http::response::Builder,
+    metadata: &Metadata,
+) -> hyper::http::response::Builder {
+    let modified = match metadata.modified() {
+        Ok(modified) => modified,
+        Err(_) => return rsp,
     };
+    let modified_secs = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    rsp.header(
+        ETAG,
+        format!("\"{:x}-{:x}\"", metadata.len(), modified_secs),
+    )
+    .header(LAST_MODIFIED, httpdate::fmt_http_date(modified))
 }
@@ -0,0 +1,220 @@
+use hyper::{
+    header::{CONTENT_TYPE, ETAG},
+    Body, Request, Response, StatusCode,
+};
+use std::fmt::Write;
+
+use super::conditional::{etag, evaluate_conditionals};
+use crate::config::{RobotsConfig, SecurityConfig, WellKnownConfig};
+
+/// `ROBOTS_PATH` is the path `robots.txt` is served at.
+pub const ROBOTS_PATH: &str = "/robots.txt";
+
+/// `SECURITY_PATH` is the path `security.txt` is served at.
+pub const SECURITY_PATH: &str = "/.well-known/security.txt";
+
+/// `WellKnown` holds the files synthesized from the `[well_known]` config.
+#[derive(Debug, Default)]
+pub struct WellKnown {
+    /// `robots` is the content of `robots.txt`, if declared.
+    robots: Option<String>,
+
+    /// `security` is the content of `security.txt`, if declared.
+    security: Option<String>,
+}
+
+impl WellKnown {
+    /// `new` renders the files declared by the config, failing if a
+    /// `security.txt` is missing a field RFC 9116 requires.
+    pub fn new(config: &WellKnownConfig) -> Result<Self, String> {
+        let robots = match (&config.robots, &config.sitemaps) {
+            (None, None) => None,
+            (robots, sitemaps) => Some(render_robots(
+                robots.as_deref().unwrap_or_default(),
+                sitemaps.as_deref().unwrap_or_default(),
+            )),
+        };
+
+        let security = match &config.security {
+            Some(security) => Some(render_security(security)?),
+            None => None,
+        };
+
+        Ok(Self { robots, security })
+    }
+
+    /// `document` returns the content synthesized for a request path, if any.
+    pub fn document(&self, path: &str) -> Option<&str> {
+        match path {
+            ROBOTS_PATH => self.robots.as_deref(),
+            SECURITY_PATH => self.security.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// `well_known_handler` serves a synthesized file as plain text, with an
+/// `ETag` derived from its content.
+pub fn well_known_handler(req: Request<Body>, content: &str) -> Response<Body> {
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(ETAG, etag(content.as_bytes()))
+        .body(Body::from(content.to_owned()))
+        .unwrap();
+
+    evaluate_conditionals(req.method(), req.headers(), response)
+}
+
+/// `render_robots` writes a `robots.txt` with a group for each set of rules
+/// followed by the sitemaps.
+fn render_robots(robots: &[RobotsConfig], sitemaps: &[String]) -> String {
+    let mut content = String::new();
+
+    for rules in robots {
+        if !content.is_empty() {
+            content.push('\n');
+        }
+
+        writeln!(content, "User-agent: {}", rules.user_agent).unwrap();
+        for path in rules.allow.iter().flatten() {
+            writeln!(content, "Allow: {}", path).unwrap();
+        }
+        for path in rules.disallow.iter().flatten() {
+            writeln!(content, "Disallow: {}", path).unwrap();
+        }
+        if let Some(crawl_delay) = rules.crawl_delay {
+            writeln!(content, "Crawl-delay: {}", crawl_delay).unwrap();
+        }
+    }
+
+    if !sitemaps.is_empty() && !content.is_empty() {
+        content.push('\n');
+    }
+    for sitemap in sitemaps {
+        writeln!(content, "Sitemap: {}", sitemap).unwrap();
+    }
+
+    content
+}
+
+/// `render_security` writes a `security.txt` in the field order of RFC 9116.
+fn render_security(security: &SecurityConfig) -> Result<String, String> {
+    if security.contact.is_empty() {
+        return Err("`well_known.security` must have at least one `contact`".to_owned());
+    }
+    if security.expires.is_empty() {
+        return Err("`well_known.security` must have an `expires` date".to_owned());
+    }
+
+    let mut content = String::new();
+    let mut field = |name: &str, values: &[String]| {
+        for value in values {
+            writeln!(content, "{}: {}", name, value).unwrap();
+        }
+    };
+
+    field("Contact", &security.contact);
+    field("Expires", std::slice::from_ref(&security.expires));
+    field(
+        "Encryption",
+        security.encryption.as_deref().unwrap_or_default(),
+    );
+    field(
+        "Acknowledgments",
+        security.acknowledgments.as_deref().unwrap_or_default(),
+    );
+    field(
+        "Preferred-Languages",
+        security.preferred_languages.as_slice(),
+    );
+    field(
+        "Canonical",
+        security.canonical.as_deref().unwrap_or_default(),
+    );
+    field("Policy", security.policy.as_deref().unwrap_or_default());
+    field("Hiring", security.hiring.as_deref().unwrap_or_default());
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_robots() {
+        let robots = vec![
+            RobotsConfig {
+                user_agent: "*".to_owned(),
+                disallow: Some(vec!["/admin".to_owned(), "/api".to_owned()]),
+                ..RobotsConfig::default()
+            },
+            RobotsConfig {
+                user_agent: "BadBot".to_owned(),
+                allow: Some(vec!["/public".to_owned()]),
+                crawl_delay: Some(10),
+                ..RobotsConfig::default()
+            },
+        ];
+        let sitemaps = vec!["https://example.com/sitemap.xml".to_owned()];
+
+        assert_eq!(
+            render_robots(&robots, &sitemaps),
+            "User-agent: *\n\
+             Disallow: /admin\n\
+             Disallow: /api\n\
+             \n\
+             User-agent: BadBot\n\
+             Allow: /public\n\
+             Crawl-delay: 10\n\
+             \n\
+             Sitemap: https://example.com/sitemap.xml\n"
+        );
+    }
+
+    #[test]
+    fn test_render_security() {
+        let security = SecurityConfig {
+            contact: vec!["mailto:security@example.com".to_owned()],
+            expires: "2026-12-31T23:59:59Z".to_owned(),
+            preferred_languages: Some("en, de".to_owned()),
+            policy: Some(vec!["https://example.com/security".to_owned()]),
+            ..SecurityConfig::default()
+        };
+
+        assert_eq!(
+            render_security(&security).unwrap(),
+            "Contact: mailto:security@example.com\n\
+             Expires: 2026-12-31T23:59:59Z\n\
+             Preferred-Languages: en, de\n\
+             Policy: https://example.com/security\n"
+        );
+    }
+
+    #[test]
+    fn test_render_security_requires_contact() {
+        let security = SecurityConfig {
+            expires: "2026-12-31T23:59:59Z".to_owned(),
+            ..SecurityConfig::default()
+        };
+
+        assert!(render_security(&security).is_err());
+    }
+
+    #[test]
+    fn test_document() {
+        let well_known = WellKnown::new(&WellKnownConfig {
+            sitemaps: Some(vec!["https://example.com/sitemap.xml".to_owned()]),
+            ..WellKnownConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            well_known.document(ROBOTS_PATH),
+            Some("Sitemap: https://example.com/sitemap.xml\n")
+        );
+        assert_eq!(well_known.document(SECURITY_PATH), None);
+        assert_eq!(well_known.document("/index.html"), None);
+    }
+}
@@ -0,0 +1,236 @@
+use jiff::Timestamp;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Serialize, Serializer};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// `DEFAULT_CAPACITY` is the number of records kept if `log_buffer` is not
+/// configured.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// `BUFFERED_LEVEL` is the least severe level always kept in the buffer,
+/// whether or not `RUST_LOG` prints it.
+const BUFFERED_LEVEL: Level = Level::Info;
+
+/// `LOG_BUFFER` keeps the most recent log records of the process.
+pub static LOG_BUFFER: LogBuffer = LogBuffer::new(DEFAULT_CAPACITY);
+
+/// `LogBuffer` keeps the most recent log records in memory, so that recent
+/// activity can be inspected through the control socket on hosts where
+/// standard output is not captured.
+pub struct LogBuffer {
+    /// `capacity` is the number of records kept.
+    capacity: AtomicUsize,
+
+    /// `records` are the records kept, oldest first.
+    records: Mutex<VecDeque<LogRecord>>,
+}
+
+/// `LogRecord` is a log record kept in the buffer.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct LogRecord {
+    /// `time` is when the record was logged, in RFC 3339 format.
+    pub time: String,
+
+    /// `level` is the severity of the record.
+    #[serde(serialize_with = "serialize_level")]
+    pub level: Level,
+
+    /// `target` is the module the record was logged from.
+    pub target: String,
+
+    /// `message` is the logged message.
+    pub message: String,
+}
+
+/// `BufferedLogger` keeps the records it is given in the `LOG_BUFFER` and
+/// passes the ones its inner logger is enabled for on to it.
+struct BufferedLogger<L> {
+    inner: L,
+}
+
+impl LogBuffer {
+    /// `new` creates an empty buffer keeping `capacity` records.
+    const fn new(capacity: usize) -> Self {
+        Self {
+            capacity: AtomicUsize::new(capacity),
+            records: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// `set_capacity` changes the number of records kept, dropping the oldest
+    /// records if there are more. A capacity of 0 turns the buffer off.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+
+        let mut records = self.records.lock().unwrap();
+        while records.len() > capacity {
+            records.pop_front();
+        }
+    }
+
+    /// `push` keeps a record, dropping the oldest one if the buffer is full.
+    fn push(&self, record: LogRecord) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+
+        let mut records = self.records.lock().unwrap();
+        while records.len() >= capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// `tail` returns the last `count` records at least as severe as `level`,
+    /// oldest first.
+    pub fn tail(&self, count: usize, level: Level) -> Vec<LogRecord> {
+        let records = self.records.lock().unwrap();
+        let mut tail: Vec<_> = records
+            .iter()
+            .rev()
+            .filter(|record| record.level <= level)
+            .take(count)
+            .cloned()
+            .collect();
+        tail.reverse();
+
+        tail
+    }
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<5} {} > {}",
+            self.time, self.level, self.target, self.message
+        )
+    }
+}
+
+impl<L: Log> Log for BufferedLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= BUFFERED_LEVEL || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= BUFFERED_LEVEL {
+            LOG_BUFFER.push(LogRecord {
+                time: Timestamp::now().to_string(),
+                level: record.level(),
+                target: record.target().to_owned(),
+                message: record.args().to_string(),
+            });
+        }
+
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// `init` sets up the logger printing the records `RUST_LOG` enables, as
+/// `pretty_env_logger::init` does, while keeping the recent records at
+/// `BUFFERED_LEVEL` or above in the `LOG_BUFFER`.
+pub fn init() {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    let inner = builder.build();
+
+    log::set_max_level(inner.filter().max(BUFFERED_LEVEL.to_level_filter()));
+    log::set_boxed_logger(Box::new(BufferedLogger { inner }))
+        .expect("The logger is only initialized once");
+}
+
+/// `serialize_level` serializes a level by its name.
+fn serialize_level<S: Serializer>(level: &Level, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(level)
+}
+
+/// `parse_level` parses the name of a log level, e.g. `warn`.
+pub fn parse_level(level: &str) -> Result<Level, String> {
+    level
+        .parse::<LevelFilter>()
+        .ok()
+        .and_then(|filter| filter.to_level())
+        .ok_or_else(|| format!("Invalid log level {}", level))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(level: Level, message: &str) -> LogRecord {
+        LogRecord {
+            time: "2024-01-01T00:00:00Z".to_owned(),
+            level,
+            target: "gee::server".to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_push_drops_oldest() {
+        let buffer = LogBuffer::new(2);
+        buffer.push(record(Level::Info, "one"));
+        buffer.push(record(Level::Info, "two"));
+        buffer.push(record(Level::Info, "three"));
+
+        assert_eq!(
+            buffer.tail(10, Level::Trace),
+            vec![record(Level::Info, "two"), record(Level::Info, "three")]
+        );
+
+        buffer.set_capacity(0);
+        buffer.push(record(Level::Info, "four"));
+        assert!(buffer.tail(10, Level::Trace).is_empty());
+    }
+
+    #[test]
+    fn test_tail() {
+        let buffer = LogBuffer::new(10);
+        buffer.push(record(Level::Warn, "one"));
+        buffer.push(record(Level::Info, "two"));
+        buffer.push(record(Level::Error, "three"));
+        buffer.push(record(Level::Warn, "four"));
+
+        assert_eq!(
+            buffer.tail(2, Level::Warn),
+            vec![record(Level::Error, "three"), record(Level::Warn, "four")]
+        );
+        assert_eq!(
+            buffer.tail(1, Level::Trace),
+            vec![record(Level::Warn, "four")]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            record(Level::Info, "Mounted app.py:application at /").to_string(),
+            "2024-01-01T00:00:00Z INFO  gee::server > Mounted app.py:application at /"
+        );
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("warn"), Ok(Level::Warn));
+        assert_eq!(parse_level("ERROR"), Ok(Level::Error));
+        assert!(parse_level("off").is_err());
+        assert!(parse_level("loud").is_err());
+    }
+}
@@ -1,18 +1,53 @@
 extern crate log;
 extern crate pretty_env_logger;
 
-#[macro_use]
-
 mod cli;
+mod client;
 mod config;
+mod context;
+mod geoip;
+mod handlers;
+mod logs;
 mod macros;
+mod metrics;
+mod percent;
+mod server;
+mod shareable;
+mod trace;
+mod version;
+
+use clap::Parser;
 
-use config::Config;
+use cli::{Cli, Commands, ConfigCommands};
 
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
+    logs::init();
+
+    let result = match Cli::parse().command {
+        Some(Commands::Init { format }) => cli::init(&format),
+        Some(Commands::Serve { config }) => cli::serve(config).await,
+        Some(Commands::Config {
+            command: ConfigCommands::Migrate { config, dry_run },
+        }) => cli::migrate(config, dry_run),
+        Some(Commands::Validate {
+            config,
+            startup,
+            listening,
+        }) => cli::verify(config, startup, listening),
+        Some(Commands::Routes { config }) => cli::routes(config),
+        Some(Commands::Ctl { config, command }) => cli::ctl(config, command),
+        Some(Commands::E2e {
+            suite,
+            config,
+            junit,
+        }) => cli::e2e(config, suite, junit).await,
+        Some(Commands::Version { json }) => cli::version(json),
+        None => cli::serve(None).await,
+    };
 
-    let config = Config::new_default();
-    println!("{}", config);
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
 }
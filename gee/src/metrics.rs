@@ -0,0 +1,246 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// `WAIT_BUCKETS` are the upper bounds, in seconds, of the buckets wait times
+/// are counted in.
+const WAIT_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// `Metrics` are the measurements the Gee server exports about itself, in the
+/// Prometheus text exposition format.
+pub struct Metrics {
+    /// `queue_depth` is the number of requests waiting for a Python worker.
+    pub queue_depth: Gauge,
+
+    /// `queue_wait` is how long requests waited for a Python worker.
+    pub queue_wait: Histogram,
+
+    /// `queue_rejected_full` counts requests shed because the queue was full.
+    pub queue_rejected_full: Counter,
+
+    /// `queue_rejected_timeout` counts requests shed because they waited
+    /// longer than the maximum queue wait.
+    pub queue_rejected_timeout: Counter,
+
+    /// `waf_blocked` counts requests refused by a WAF rule.
+    pub waf_blocked: Counter,
+
+    /// `waf_logged` counts requests matching a WAF rule in log mode.
+    pub waf_logged: Counter,
+}
+
+impl Metrics {
+    /// `new` creates a new `Metrics` instance with every measurement at zero.
+    pub fn new() -> Self {
+        Self {
+            queue_depth: Gauge::default(),
+            queue_wait: Histogram::new(&WAIT_BUCKETS),
+            queue_rejected_full: Counter::default(),
+            queue_rejected_timeout: Counter::default(),
+            waf_blocked: Counter::default(),
+            waf_logged: Counter::default(),
+        }
+    }
+
+    /// `render` returns the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        self.queue_depth.render(
+            &mut out,
+            "gee_queue_depth",
+            "Requests waiting for a Python worker.",
+        );
+        self.queue_wait.render(
+            &mut out,
+            "gee_queue_wait_seconds",
+            "Time requests waited for a Python worker.",
+        );
+
+        writeln!(
+            out,
+            "# HELP gee_queue_rejected_total Requests shed by the admission queue."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE gee_queue_rejected_total counter").unwrap();
+        writeln!(
+            out,
+            "gee_queue_rejected_total{{reason=\"full\"}} {}",
+            self.queue_rejected_full.get()
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "gee_queue_rejected_total{{reason=\"timeout\"}} {}",
+            self.queue_rejected_timeout.get()
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP gee_waf_matched_total Requests matching a WAF rule."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE gee_waf_matched_total counter").unwrap();
+        writeln!(
+            out,
+            "gee_waf_matched_total{{action=\"blocked\"}} {}",
+            self.waf_blocked.get()
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "gee_waf_matched_total{{action=\"logged\"}} {}",
+            self.waf_logged.get()
+        )
+        .unwrap();
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `Counter` is a measurement that only increases.
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// `Gauge` is a measurement that may increase or decrease.
+#[derive(Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} gauge", name).unwrap();
+        writeln!(out, "{} {}", name, self.get()).unwrap();
+    }
+}
+
+/// `Histogram` counts observed durations in cumulative buckets.
+pub struct Histogram {
+    bounds: Vec<f64>,
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    /// `new` creates a histogram with buckets of the given upper bounds, in
+    /// seconds.
+    pub fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// `observe` records a duration.
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter()) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// `count` returns the number of observed durations.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter()) {
+            writeln!(
+                out,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                bucket.load(Ordering::Relaxed)
+            )
+            .unwrap();
+        }
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count()).unwrap();
+        writeln!(
+            out,
+            "{}_sum {}",
+            name,
+            self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        )
+        .unwrap();
+        writeln!(out, "{}_count {}", name, self.count()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::new(&[0.1, 1.0]);
+        histogram.observe(Duration::from_millis(50));
+        histogram.observe(Duration::from_millis(500));
+        histogram.observe(Duration::from_secs(2));
+
+        let mut out = String::new();
+        histogram.render(&mut out, "wait", "Wait.");
+
+        assert!(out.contains("wait_bucket{le=\"0.1\"} 1\n"));
+        assert!(out.contains("wait_bucket{le=\"1\"} 2\n"));
+        assert!(out.contains("wait_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("wait_sum 2.55\n"));
+        assert!(out.contains("wait_count 3\n"));
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.queue_depth.set(3);
+        metrics.queue_rejected_full.inc();
+
+        let out = metrics.render();
+
+        assert!(out.contains("# TYPE gee_queue_depth gauge\ngee_queue_depth 3\n"));
+        assert!(out.contains("gee_queue_rejected_total{reason=\"full\"} 1\n"));
+        assert!(out.contains("gee_queue_rejected_total{reason=\"timeout\"} 0\n"));
+        assert!(out.contains("gee_queue_wait_seconds_count 0\n"));
+    }
+}
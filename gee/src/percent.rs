@@ -0,0 +1,44 @@
+/// `percent_decode_once` decodes the `%XX` escapes of a path or query string
+/// a single time, leaving invalid escapes as they are. With `plus_as_space`,
+/// `+` is decoded as a space.
+pub fn percent_decode_once(text: &str, plus_as_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (b'+', _) if plus_as_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode_once() {
+        assert_eq!(percent_decode_once("/a%20b", false), "/a b");
+        assert_eq!(percent_decode_once("a+%252e", true), "a %2e");
+        assert_eq!(percent_decode_once("a+b", false), "a+b");
+        assert_eq!(percent_decode_once("100%", false), "100%");
+        assert_eq!(percent_decode_once("%zz%4", false), "%zz%4");
+    }
+}
@@ -0,0 +1,526 @@
+use hyper::{
+    header::{HeaderMap, HOST, REFERER, USER_AGENT},
+    Body, Method, Request, StatusCode, Uri, Version,
+};
+use log::warn;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::config::{AccessLogConfig, AccessLogDestinationConfig};
+use crate::context::RequestContext;
+
+/// `DEFAULT_FORMAT` is the line logged for each request when neither a format
+/// nor fields are configured.
+const DEFAULT_FORMAT: &str =
+    "{remote_addr} \"{method} {uri} {protocol}\" {status} {bytes} {duration_ms}ms";
+
+/// `AccessLog` writes a line for the requests the Gee server answers,
+/// sampling the successful ones. Each log may be limited to the requests to
+/// a host or under a route.
+#[derive(Debug)]
+pub struct AccessLog {
+    /// `segments` make up the logged line.
+    segments: Vec<Segment>,
+
+    /// `sample_every` is the number of successful requests logged as one.
+    sample_every: u64,
+
+    /// `successes` counts the successful requests answered so far.
+    successes: AtomicU64,
+
+    /// `host` is the host whose requests are logged, or `None` for any.
+    host: Option<String>,
+
+    /// `route` is the path under which requests are logged, or `None` for
+    /// any.
+    route: Option<String>,
+
+    /// `output` is where lines are written.
+    output: Output,
+}
+
+/// `Output` is where an access log writes its lines.
+#[derive(Debug)]
+enum Output {
+    /// `Stdout` lines are printed to standard output.
+    Stdout,
+
+    /// `File` lines are appended to a file.
+    File(Mutex<File>),
+}
+
+/// `Segment` is a part of the logged line.
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    /// `Literal` text is logged as written.
+    Literal(String),
+
+    /// `Field` is replaced with a value of the request or its response.
+    Field(Field),
+}
+
+/// `Field` is a value of a request or its response that may be logged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    RemoteAddr,
+    ClientIp,
+    RequestId,
+    Route,
+    Method,
+    Path,
+    Query,
+    Uri,
+    Protocol,
+    Host,
+    UserAgent,
+    Referer,
+    Status,
+    Bytes,
+    DurationMs,
+    Time,
+}
+
+/// `Entry` holds what is known about a request when it is received, to be
+/// logged once it has been answered.
+pub struct Entry {
+    context: Arc<RequestContext>,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+}
+
+impl AccessLog {
+    /// `from_config` creates the access logs a request may be written to:
+    /// the `destinations`, in order, followed by the main log, which takes
+    /// every request. Relative paths are resolved against the `root_dir`.
+    pub fn from_config(config: &AccessLogConfig, root_dir: &Path) -> Result<Vec<Self>, String> {
+        let mut logs = Vec::new();
+        for destination in config.destinations.iter().flatten() {
+            logs.push(Self::destination(config, destination, root_dir)?);
+        }
+
+        logs.push(Self {
+            output: Output::open(config.path.as_deref(), root_dir)?,
+            ..Self::new(config)?
+        });
+
+        Ok(logs)
+    }
+
+    /// `destination` creates the log of a destination, taking the format and
+    /// sampling it does not set from the main log.
+    fn destination(
+        config: &AccessLogConfig,
+        destination: &AccessLogDestinationConfig,
+        root_dir: &Path,
+    ) -> Result<Self, String> {
+        let (format, fields) = if destination.format.is_some() || destination.fields.is_some() {
+            (&destination.format, &destination.fields)
+        } else {
+            (&config.format, &config.fields)
+        };
+        let merged = AccessLogConfig {
+            format: format.clone(),
+            fields: fields.clone(),
+            sample_every: destination.sample_every.or(config.sample_every),
+            ..AccessLogConfig::default()
+        };
+
+        Ok(Self {
+            host: destination
+                .host
+                .as_ref()
+                .map(|host| host.to_ascii_lowercase()),
+            route: destination.route.clone(),
+            output: Output::open(destination.path.as_deref(), root_dir)?,
+            ..Self::new(&merged)?
+        })
+    }
+
+    /// `new` creates a new `AccessLog` writing every request to standard
+    /// output, failing if the format refers to an unknown field.
+    pub fn new(config: &AccessLogConfig) -> Result<Self, String> {
+        let segments = match (&config.format, &config.fields) {
+            (Some(format), _) => parse_format(format)?,
+            (None, Some(fields)) => {
+                let mut segments = Vec::new();
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        segments.push(Segment::Literal(" ".to_owned()));
+                    }
+                    segments.push(Segment::Field(field.parse()?));
+                }
+                segments
+            }
+            (None, None) => parse_format(DEFAULT_FORMAT)?,
+        };
+
+        Ok(Self {
+            segments,
+            sample_every: config.sample_every.unwrap_or(1).max(1),
+            successes: AtomicU64::new(0),
+            host: None,
+            route: None,
+            output: Output::Stdout,
+        })
+    }
+
+    /// `matches` returns whether a request is written to this log.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let host_matches = self
+            .host
+            .as_ref()
+            .is_none_or(|host| entry.context.host.as_ref() == Some(host));
+        let route_matches = self
+            .route
+            .as_ref()
+            .is_none_or(|route| entry.uri.path().starts_with(route.as_str()));
+
+        host_matches && route_matches
+    }
+
+    /// `log` writes the line for a request answered with `status` and a body
+    /// of `bytes`, if the request is sampled.
+    pub fn log(&self, entry: &Entry, status: StatusCode, bytes: Option<u64>) {
+        if self.is_sampled(status) {
+            let line = self.format(entry, status, bytes, entry.context.received.elapsed());
+            self.output.write(&line);
+        }
+    }
+
+    /// `is_sampled` decides whether a request answered with `status` is
+    /// logged. Every request answered with an error is logged, while only the
+    /// first of every `sample_every` successful requests is.
+    fn is_sampled(&self, status: StatusCode) -> bool {
+        if status.is_client_error() || status.is_server_error() {
+            return true;
+        }
+
+        self.successes
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.sample_every)
+    }
+
+    /// `format` builds the line logged for a request.
+    fn format(
+        &self,
+        entry: &Entry,
+        status: StatusCode,
+        bytes: Option<u64>,
+        duration: Duration,
+    ) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(field) => entry
+                    .field(*field, status, bytes, duration)
+                    .unwrap_or_else(|| "-".to_owned()),
+            })
+            .collect()
+    }
+}
+
+impl Output {
+    /// `open` opens the file at `path` for appending, creating it if needed,
+    /// or returns standard output if there is no path.
+    fn open(path: Option<&str>, root_dir: &Path) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => root_dir.join(path),
+            None => return Ok(Self::Stdout),
+        };
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map(|file| Self::File(Mutex::new(file)))
+            .map_err(|e| format!("Cannot open access log {}: {}", path.display(), e))
+    }
+
+    /// `write` writes a line.
+    fn write(&self, line: &str) {
+        match self {
+            Self::Stdout => println!("{}", line),
+            Self::File(file) => {
+                if let Err(e) = writeln!(file.lock().unwrap(), "{}", line) {
+                    warn!("Cannot write to the access log: {}", e);
+                }
+            }
+        }
+    }
+}
+
+impl Entry {
+    /// `from_request` records a request as it is received, along with its
+    /// context.
+    pub fn from_request(req: &Request<Body>, context: Arc<RequestContext>) -> Self {
+        Self {
+            context,
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+        }
+    }
+
+    /// `field` returns the value of a field, if the request has one.
+    fn field(
+        &self,
+        field: Field,
+        status: StatusCode,
+        bytes: Option<u64>,
+        duration: Duration,
+    ) -> Option<String> {
+        match field {
+            Field::RemoteAddr => self
+                .context
+                .client
+                .remote_addr
+                .map(|addr| addr.ip().to_string()),
+            Field::ClientIp => self.context.client.client_ip.map(|ip| ip.to_string()),
+            Field::RequestId => Some(self.context.request_id.clone()),
+            Field::Route => self.context.route.clone(),
+            Field::Method => Some(self.method.to_string()),
+            Field::Path => Some(self.uri.path().to_owned()),
+            Field::Query => self.uri.query().map(str::to_owned),
+            Field::Uri => Some(self.uri.to_string()),
+            Field::Protocol => Some(format!("{:?}", self.version)),
+            Field::Host => self.header(HOST),
+            Field::UserAgent => self.header(USER_AGENT),
+            Field::Referer => self.header(REFERER),
+            Field::Status => Some(status.as_u16().to_string()),
+            Field::Bytes => bytes.map(|bytes| bytes.to_string()),
+            Field::DurationMs => Some(format!("{:.3}", duration.as_secs_f64() * 1000.0)),
+            Field::Time => Some(httpdate::fmt_http_date(self.context.client.received)),
+        }
+    }
+
+    /// `header` returns the value of a request header, if it is valid text.
+    fn header(&self, name: hyper::header::HeaderName) -> Option<String> {
+        self.headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remote_addr" => Ok(Self::RemoteAddr),
+            "client_ip" => Ok(Self::ClientIp),
+            "request_id" => Ok(Self::RequestId),
+            "route" => Ok(Self::Route),
+            "method" => Ok(Self::Method),
+            "path" => Ok(Self::Path),
+            "query" => Ok(Self::Query),
+            "uri" => Ok(Self::Uri),
+            "protocol" => Ok(Self::Protocol),
+            "host" => Ok(Self::Host),
+            "user_agent" => Ok(Self::UserAgent),
+            "referer" => Ok(Self::Referer),
+            "status" => Ok(Self::Status),
+            "bytes" => Ok(Self::Bytes),
+            "duration_ms" => Ok(Self::DurationMs),
+            "time" => Ok(Self::Time),
+            _ => Err(format!("Unknown access log field `{}`", s)),
+        }
+    }
+}
+
+/// `parse_format` splits a format string into the literal text and the
+/// `{field}` placeholders it is made of.
+fn parse_format(format: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut rest = format;
+
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            segments.push(Segment::Literal(rest[..start].to_owned()));
+        }
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed `{{` in access log format `{}`", format))?;
+        segments.push(Segment::Field(rest[start + 1..start + end].parse()?));
+        rest = &rest[start + end + 1..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest.to_owned()));
+    }
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{ClientInfo, TrustedProxies};
+    use std::net::SocketAddr;
+
+    fn entry() -> Entry {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/static/hello.txt?lang=en")
+            .header("Host", "example.com")
+            .header("User-Agent", "curl/8.0")
+            .body(Body::empty())
+            .unwrap();
+
+        let client = ClientInfo::from_request(
+            &req,
+            Some(SocketAddr::from(([10, 0, 0, 1], 5000))),
+            &TrustedProxies::default(),
+        );
+        let context = RequestContext {
+            request_id: "req-1".to_owned(),
+            ..RequestContext::new(&req, client, Some("/static".to_owned()))
+        };
+
+        Entry::from_request(&req, Arc::new(context))
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(
+            parse_format("{method} {path} took {duration_ms}").unwrap(),
+            vec![
+                Segment::Field(Field::Method),
+                Segment::Literal(" ".to_owned()),
+                Segment::Field(Field::Path),
+                Segment::Literal(" took ".to_owned()),
+                Segment::Field(Field::DurationMs),
+            ]
+        );
+        assert!(parse_format("{method} {nope}").is_err());
+        assert!(parse_format("{method").is_err());
+    }
+
+    #[test]
+    fn test_format_default() {
+        let access_log = AccessLog::new(&AccessLogConfig::default()).unwrap();
+
+        assert_eq!(
+            access_log.format(
+                &entry(),
+                StatusCode::OK,
+                Some(12),
+                Duration::from_micros(1500)
+            ),
+            "10.0.0.1 \"GET /static/hello.txt?lang=en HTTP/1.1\" 200 12 1.500ms"
+        );
+    }
+
+    #[test]
+    fn test_format_fields() {
+        let access_log = AccessLog::new(&AccessLogConfig {
+            fields: Some(vec![
+                "request_id".to_owned(),
+                "route".to_owned(),
+                "client_ip".to_owned(),
+                "status".to_owned(),
+                "host".to_owned(),
+                "referer".to_owned(),
+                "user_agent".to_owned(),
+                "bytes".to_owned(),
+            ]),
+            ..AccessLogConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            access_log.format(&entry(), StatusCode::NOT_FOUND, None, Duration::ZERO),
+            "req-1 /static 10.0.0.1 404 example.com - curl/8.0 -"
+        );
+    }
+
+    #[test]
+    fn test_new_rejects_unknown_field() {
+        let config = AccessLogConfig {
+            fields: Some(vec!["status".to_owned(), "cookie".to_owned()]),
+            ..AccessLogConfig::default()
+        };
+
+        assert_eq!(
+            AccessLog::new(&config).unwrap_err(),
+            "Unknown access log field `cookie`"
+        );
+    }
+
+    #[test]
+    fn test_from_config_destinations() {
+        let dir = std::env::temp_dir().join(format!("gee-access-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = AccessLogConfig {
+            fields: Some(vec!["path".to_owned(), "status".to_owned()]),
+            destinations: Some(vec![
+                AccessLogDestinationConfig {
+                    host: Some("Example.com".to_owned()),
+                    route: Some("/static".to_owned()),
+                    path: Some("example.log".to_owned()),
+                    ..AccessLogDestinationConfig::default()
+                },
+                AccessLogDestinationConfig {
+                    host: Some("other.example".to_owned()),
+                    format: Some("{host} {status}".to_owned()),
+                    ..AccessLogDestinationConfig::default()
+                },
+            ]),
+            ..AccessLogConfig::default()
+        };
+        let logs = AccessLog::from_config(&config, &dir).unwrap();
+        assert_eq!(logs.len(), 3);
+
+        let entry = entry();
+        let matching: Vec<bool> = logs.iter().map(|log| log.matches(&entry)).collect();
+        assert_eq!(matching, vec![true, false, true]);
+
+        logs[0].log(&entry, StatusCode::OK, None);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("example.log")).unwrap(),
+            "/static/hello.txt 200\n"
+        );
+        assert_eq!(
+            logs[1].format(&entry, StatusCode::OK, None, Duration::ZERO),
+            "example.com 200"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let config = AccessLogConfig {
+            path: Some("missing-dir/access.log".to_owned()),
+            ..AccessLogConfig::default()
+        };
+        assert!(AccessLog::from_config(&config, Path::new("/nonexistent")).is_err());
+    }
+
+    #[test]
+    fn test_is_sampled() {
+        let access_log = AccessLog::new(&AccessLogConfig {
+            sample_every: Some(3),
+            ..AccessLogConfig::default()
+        })
+        .unwrap();
+
+        let sampled: Vec<bool> = (0..6)
+            .map(|_| access_log.is_sampled(StatusCode::OK))
+            .collect();
+        assert_eq!(sampled, vec![true, false, false, true, false, false]);
+
+        assert!(access_log.is_sampled(StatusCode::NOT_FOUND));
+        assert!(access_log.is_sampled(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+}
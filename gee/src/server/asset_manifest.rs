@@ -0,0 +1,225 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::{
+    header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE},
+    Body, Response,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::config::{Config, RouteConfig};
+
+/// `IMMUTABLE` is the `Cache-Control` of fingerprinted assets, whose content
+/// never changes under the same name.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// `AssetManifest` maps the files of a static route to fingerprinted names
+/// that change whenever their content does, so that applications can
+/// reference assets which are cached for good. It is generated at startup,
+/// served as JSON, and used to resolve the fingerprinted names.
+pub struct AssetManifest {
+    /// `path` is the path on the server the manifest is served at.
+    pub path: String,
+
+    /// `json` is the manifest served.
+    json: String,
+
+    /// `assets` are the files on disk, by fingerprinted request path.
+    assets: HashMap<String, PathBuf>,
+}
+
+/// `Asset` is an entry of the manifest.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct Asset {
+    /// `path` is the fingerprinted path the file is served at.
+    path: String,
+
+    /// `integrity` is the subresource integrity hash of the file.
+    integrity: String,
+}
+
+impl AssetManifest {
+    /// `new` fingerprints every file of the static route a route config asks
+    /// for a manifest of, failing if the route is not a static route or its
+    /// files cannot be read.
+    pub fn new(route: &RouteConfig, config: &Config) -> Result<Option<Self>, String> {
+        let path = match &route.asset_manifest {
+            Some(path) => path.clone(),
+            None => return Ok(None),
+        };
+        let dir = config
+            .static_routes
+            .as_ref()
+            .and_then(|static_routes| static_routes.get(&route.route))
+            .ok_or_else(|| {
+                format!(
+                    "The asset_manifest of {} must be on a static route",
+                    route.route
+                )
+            })?;
+        let root = Path::new(&config.root_dir).join(dir);
+
+        let mut files = Vec::new();
+        walk(&root, &mut files)
+            .map_err(|e| format!("Cannot read static files in {}: {}", root.display(), e))?;
+
+        let mut manifest = BTreeMap::new();
+        let mut assets = HashMap::new();
+        for file in files {
+            let content = fs::read(&file)
+                .map_err(|e| format!("Cannot fingerprint {}: {}", file.display(), e))?;
+            let name = file
+                .strip_prefix(&root)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+
+            let digest = Sha256::digest(&content);
+            let fingerprinted = format!(
+                "{}/{}",
+                route.route.trim_end_matches('/'),
+                fingerprint(&name, &digest)
+            );
+            assets.insert(fingerprinted.clone(), file);
+            manifest.insert(
+                name,
+                Asset {
+                    path: fingerprinted,
+                    integrity: format!("sha256-{}", STANDARD.encode(digest)),
+                },
+            );
+        }
+
+        Ok(Some(Self {
+            path,
+            json: serde_json::to_string(&manifest).unwrap(),
+            assets,
+        }))
+    }
+
+    /// `asset` returns the file served at a fingerprinted path, if there is
+    /// one.
+    pub fn asset(&self, path: &str) -> Option<&Path> {
+        self.assets.get(path).map(PathBuf::as_path)
+    }
+
+    /// `response` serves the manifest as JSON.
+    pub fn response(&self) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(self.json.clone()))
+            .unwrap()
+    }
+}
+
+/// `cache_forever` marks the response to a request for a fingerprinted asset
+/// as cacheable for good.
+pub fn cache_forever(mut response: Response<Body>) -> Response<Body> {
+    if response.status().is_success() {
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static(IMMUTABLE));
+    }
+
+    response
+}
+
+/// `walk` collects the paths of the files in a directory and its
+/// subdirectories.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// `fingerprint` inserts the first eight hex digits of a digest into the name
+/// of a file, before its extension.
+fn fingerprint(name: &str, digest: &[u8]) -> String {
+    let hash: String = digest[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((dir, file)) => (format!("{}/", dir), file),
+        None => (String::new(), name),
+    };
+
+    match file.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!("{}{}.{}.{}", dir, stem, hash, extension)
+        }
+        _ => format!("{}{}.{}", dir, file, hash),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hashmap;
+
+    #[test]
+    fn test_fingerprint() {
+        let digest = [0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+
+        assert_eq!(fingerprint("app.css", &digest), "app.1a2b3c4d.css");
+        assert_eq!(
+            fingerprint("js/vendor.min.js", &digest),
+            "js/vendor.min.1a2b3c4d.js"
+        );
+        assert_eq!(fingerprint("LICENSE", &digest), "LICENSE.1a2b3c4d");
+        assert_eq!(fingerprint(".env", &digest), ".env.1a2b3c4d");
+    }
+
+    #[test]
+    fn test_new() {
+        let root = std::env::temp_dir().join(format!("gee-asset-manifest-{}", std::process::id()));
+        fs::create_dir_all(root.join("assets/css")).unwrap();
+        fs::write(root.join("assets/css/app.css"), "body {}").unwrap();
+
+        let mut config = Config::new_default();
+        config.root_dir = root.to_string_lossy().into_owned();
+        config.static_routes = Some(hashmap! {
+            "/static".to_owned() => "assets".to_owned()
+        });
+        let route = RouteConfig {
+            route: "/static".to_owned(),
+            asset_manifest: Some("/static-manifest.json".to_owned()),
+            ..RouteConfig::default()
+        };
+        let manifest = AssetManifest::new(&route, &config).unwrap().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let digest = Sha256::digest(b"body {}");
+        let fingerprinted = format!("/static/{}", fingerprint("css/app.css", &digest));
+        assert_eq!(
+            manifest.json,
+            format!(
+                r#"{{"css/app.css":{{"path":"{}","integrity":"sha256-{}"}}}}"#,
+                fingerprinted,
+                STANDARD.encode(digest)
+            )
+        );
+        assert_eq!(
+            manifest.asset(&fingerprinted),
+            Some(root.join("assets/css/app.css").as_path())
+        );
+        assert_eq!(manifest.asset("/static/css/app.css"), None);
+
+        let application_route = RouteConfig {
+            route: "/api".to_owned(),
+            ..route
+        };
+        assert!(AssetManifest::new(&application_route, &config).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use hyper::{
+    header::{ACCEPT_RANGES, ALLOW, CONTENT_TYPE},
+    Body, Response, StatusCode,
+};
+use serde::Serialize;
+
+use super::methods::RouteMethods;
+use crate::config::{Config, RouteConfig};
+
+/// `STATIC_METHODS` are the methods static routes answer.
+const STATIC_METHODS: [&str; 3] = ["GET", "HEAD", "OPTIONS"];
+
+/// `APPLICATION_METHODS` are the methods passed to the applications.
+const APPLICATION_METHODS: [&str; 7] = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+
+/// `Capabilities` describe what the configured server can do: the routes it
+/// serves, the methods they accept, and the optional features enabled. They
+/// answer `OPTIONS *` and are served as JSON at the `capabilities_path`.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `version` is the version of Gee.
+    version: &'static str,
+
+    /// `routes` are the static routes and applications, by route.
+    routes: Vec<RouteCapabilities>,
+
+    /// `features` are the optional features the config enables.
+    features: Vec<&'static str>,
+}
+
+/// `RouteCapabilities` describe a route on the server.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct RouteCapabilities {
+    /// `route` is the path on the server the handler serves under.
+    route: String,
+
+    /// `handler` is `static` or `application`.
+    handler: &'static str,
+
+    /// `methods` are the methods the route accepts.
+    methods: Vec<String>,
+}
+
+impl Capabilities {
+    /// `new` describes the server the config sets up.
+    pub fn new(config: &Config) -> Self {
+        let methods = |route: &str, default: &[&str]| {
+            config
+                .routes
+                .iter()
+                .flatten()
+                .filter(|route_config| route_config.route == route)
+                .find_map(|route_config| RouteMethods::new(route_config).ok().flatten())
+                .map(|methods| methods.names())
+                .unwrap_or_else(|| default.iter().map(|method| method.to_string()).collect())
+        };
+        let mut routes: Vec<_> = config
+            .static_routes
+            .iter()
+            .flatten()
+            .map(|(route, _)| RouteCapabilities {
+                route: route.clone(),
+                handler: "static",
+                methods: methods(route, &STATIC_METHODS),
+            })
+            .chain(
+                config
+                    .all_applications()
+                    .iter()
+                    .map(|application| RouteCapabilities {
+                        route: application.route().to_owned(),
+                        handler: "application",
+                        methods: methods(application.route(), &APPLICATION_METHODS),
+                    }),
+            )
+            .collect();
+        routes.sort_by(|a, b| a.route.cmp(&b.route).then(a.handler.cmp(b.handler)));
+
+        let routes_with = |has: fn(&RouteConfig) -> bool| config.routes.iter().flatten().any(has);
+        let features = [
+            (config.static_routes.is_some(), "byte-ranges"),
+            (config.application_etags == Some(true), "application-etags"),
+            (config.verify_checksums == Some(true), "verify-checksums"),
+            (
+                config.static_digests == Some(true)
+                    || routes_with(|route| route.static_digests == Some(true)),
+                "static-digests",
+            ),
+            (config.geoip.is_some(), "geoip"),
+            (config.waf.is_some(), "waf"),
+            (config.metrics_path.is_some(), "metrics"),
+            (config.readiness_path.is_some(), "readiness"),
+            (
+                config.traffic_accounting == Some(true),
+                "traffic-accounting",
+            ),
+            (config.well_known.is_some(), "well-known"),
+            (config.schedules.is_some(), "schedules"),
+            (config.file_cache.is_some(), "file-cache"),
+            (config.precompress.is_some(), "precompression"),
+            (config.error_pages.is_some(), "error-pages"),
+            (
+                config
+                    .all_applications()
+                    .iter()
+                    .any(|application| application.coalesce == Some(true)),
+                "request-coalescing",
+            ),
+            (
+                config
+                    .all_applications()
+                    .iter()
+                    .any(|application| application.suspend_after.is_some()),
+                "application-suspension",
+            ),
+            (routes_with(|route| route.mirror.is_some()), "mirrors"),
+            (
+                routes_with(|route| route.digest_auth.is_some()),
+                "digest-auth",
+            ),
+            (
+                routes_with(|route| route.openapi.is_some()),
+                "openapi-validation",
+            ),
+            (routes_with(|route| route.cache.is_some()), "response-cache"),
+            (
+                routes_with(|route| route.transforms.is_some()),
+                "transforms",
+            ),
+            (
+                routes_with(|route| route.signing_secret.is_some()),
+                "signed-urls",
+            ),
+            (
+                routes_with(|route| route.max_bandwidth.is_some()),
+                "bandwidth-limits",
+            ),
+            (
+                routes_with(|route| route.asset_manifest.is_some()),
+                "asset-manifests",
+            ),
+            (
+                routes_with(|route| route.negotiation.is_some()),
+                "content-negotiation",
+            ),
+            (
+                routes_with(|route| route.faults.is_some()),
+                "fault-injection",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, feature)| enabled.then_some(feature))
+        .collect();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            routes,
+            features,
+        }
+    }
+
+    /// `allow` lists every method some route on the server accepts.
+    pub fn allow(&self) -> String {
+        let has_applications = self
+            .routes
+            .iter()
+            .any(|route| route.handler == "application");
+
+        if has_applications {
+            APPLICATION_METHODS.join(", ")
+        } else {
+            STATIC_METHODS.join(", ")
+        }
+    }
+
+    /// `options_response` answers `OPTIONS *` with the methods the server
+    /// accepts, advertising byte ranges if there are static routes.
+    pub fn options_response(&self) -> Response<Body> {
+        let mut rsp = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(ALLOW, self.allow());
+        if self.features.contains(&"byte-ranges") {
+            rsp = rsp.header(ACCEPT_RANGES, "bytes");
+        }
+
+        rsp.body(Body::empty()).unwrap()
+    }
+
+    /// `json_response` serves the capabilities as JSON.
+    pub fn json_response(&self) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_string(self).unwrap()))
+            .unwrap()
+    }
+}
+
+/// `static_options_response` answers an `OPTIONS` request to a static route
+/// with the methods the route accepts: those it is configured with, or else
+/// those static routes accept.
+pub fn static_options_response(allow: Option<String>) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(ALLOW, allow.unwrap_or_else(|| STATIC_METHODS.join(", ")))
+        .header(ACCEPT_RANGES, "bytes")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ApplicationConfig;
+
+    #[test]
+    fn test_new() {
+        let mut config = Config::new_default();
+        config.applications = Some(vec![ApplicationConfig {
+            path: "api.py".to_owned(),
+            name: "application".to_owned(),
+            route: Some("/api".to_owned()),
+            ..ApplicationConfig::default()
+        }]);
+        config.routes = Some(vec![
+            RouteConfig {
+                route: "/static/downloads".to_owned(),
+                signing_secret: Some("s3cret".to_owned()),
+                ..RouteConfig::default()
+            },
+            RouteConfig {
+                route: "/api".to_owned(),
+                methods: Some(vec!["POST".to_owned()]),
+                ..RouteConfig::default()
+            },
+        ]);
+        let capabilities = Capabilities::new(&config);
+
+        assert_eq!(
+            capabilities
+                .routes
+                .iter()
+                .map(|route| (route.route.as_str(), route.handler))
+                .collect::<Vec<_>>(),
+            vec![("/api", "application"), ("/static", "static")]
+        );
+        assert_eq!(capabilities.routes[0].methods, vec!["POST"]);
+        assert_eq!(
+            capabilities.routes[1].methods,
+            vec!["GET", "HEAD", "OPTIONS"]
+        );
+        assert_eq!(capabilities.features, vec!["byte-ranges", "signed-urls"]);
+        assert_eq!(
+            capabilities.allow(),
+            "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS"
+        );
+    }
+
+    #[test]
+    fn test_allow_static_only() {
+        let capabilities = Capabilities::new(&Config::new_default());
+
+        assert_eq!(capabilities.allow(), "GET, HEAD, OPTIONS");
+        assert_eq!(
+            capabilities.options_response().headers()[ACCEPT_RANGES],
+            "bytes"
+        );
+    }
+}
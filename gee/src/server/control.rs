@@ -0,0 +1,416 @@
+use log::{debug, error, info, warn, Level};
+use std::{
+    env,
+    ffi::OsString,
+    fmt::Write,
+    io,
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, OnceLock},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use super::state::State;
+use crate::config::Config;
+use crate::handlers::{check_applications, Mount};
+use crate::logs::{parse_level, LOG_BUFFER};
+
+/// `LAUNCH` is how the server was started, recorded by `record_launch`.
+static LAUNCH: OnceLock<Result<Launch, String>> = OnceLock::new();
+
+/// `Launch` is how a process was started. Candidate configs are checked in a
+/// child started the same way as the server, so that it resolves the same
+/// Python interpreter, `sys.path`, and relative paths.
+struct Launch {
+    /// `exe` is the path of the executable.
+    exe: PathBuf,
+
+    /// `dir` is the working directory.
+    dir: PathBuf,
+
+    /// `vars` are the environment variables.
+    vars: Vec<(OsString, OsString)>,
+}
+
+impl Launch {
+    /// `current` returns how the current process runs now.
+    fn current() -> Result<Self, String> {
+        Ok(Self {
+            exe: env::current_exe()
+                .map_err(|e| format!("Cannot find the gee executable: {}", e))?,
+            dir: env::current_dir()
+                .map_err(|e| format!("Cannot find the working directory: {}", e))?,
+            vars: env::vars_os().collect(),
+        })
+    }
+}
+
+/// `record_launch` records how the server was started, before its
+/// applications can change its environment or working directory.
+pub fn record_launch() {
+    LAUNCH.get_or_init(Launch::current);
+}
+
+/// `ERROR_PREFIX` begins the reply to a command that failed.
+pub const ERROR_PREFIX: &str = "error: ";
+
+/// `listen` binds the control socket at `path`, replacing a socket left
+/// behind by an earlier server, and answers the commands sent to it until the
+/// server stops. Each connection sends a single line holding a command and
+/// receives the reply before the connection is closed.
+pub fn listen(path: &Path, state: Arc<State>) -> io::Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Control socket listening at {}", path.display());
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(answer(stream, state.clone()));
+                }
+                Err(e) => {
+                    error!("Cannot accept control connection: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// `answer` reads a command from a control connection and writes its reply.
+/// Commands run on the blocking pool, as restarting the applications imports
+/// them.
+async fn answer(stream: UnixStream, state: Arc<State>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    if let Err(e) = BufReader::new(reader).read_line(&mut line).await {
+        warn!("Cannot read control command: {}", e);
+        return;
+    }
+    debug!("Control command: {}", line.trim());
+
+    let reply = tokio::task::spawn_blocking(move || {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        run(&words, &state)
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("The command failed: {}", e)));
+    let reply = match reply {
+        Ok(reply) => reply,
+        Err(e) => format!("{}{}\n", ERROR_PREFIX, e),
+    };
+
+    if let Err(e) = writer.write_all(reply.as_bytes()).await {
+        warn!("Cannot reply to control command: {}", e);
+    }
+}
+
+/// `run` carries out a control command, returning its reply.
+pub fn run(words: &[&str], state: &State) -> Result<String, String> {
+    match words {
+        ["route", "disable", route] => disable_route(state, route, None),
+        ["route", "disable", route, status] => {
+            let status = status
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid status {}", status))?;
+            disable_route(state, route, Some(status))
+        }
+        ["route", "enable", route] => {
+            if state.disabled_routes.enable(route) {
+                info!("Enabled route {}", route);
+                Ok(format!("Enabled {}\n", route))
+            } else {
+                Err(format!("{} is not disabled", route))
+            }
+        }
+        ["route", "list"] => {
+            let mut reply = String::new();
+            for (route, status) in state.disabled_routes.list() {
+                writeln!(reply, "{} disabled ({})", route, status.as_u16()).unwrap();
+            }
+            Ok(reply)
+        }
+        ["faults", "enable", route] => set_faults(state, route, true),
+        ["faults", "disable", route] => set_faults(state, route, false),
+        ["faults", "list"] => {
+            let mut reply = String::new();
+            for faults in state.faults.iter() {
+                let status = if faults.is_enabled() {
+                    "enabled"
+                } else {
+                    "disabled"
+                };
+                writeln!(reply, "{} faults {}", faults.route, status).unwrap();
+            }
+            Ok(reply)
+        }
+        ["top", count] => top(state, count),
+        ["restart-app"] => restart_applications(state),
+        ["check-config", path @ ..] if !path.is_empty() => check_config(state, &path.join(" ")),
+        ["logs", count] => tail_logs(count, Level::Info),
+        ["logs", count, level] => tail_logs(count, parse_level(level)?),
+        [] => Err("No command given".to_owned()),
+        _ => Err(format!("Unknown command: {}", words.join(" "))),
+    }
+}
+
+/// `disable_route` disables a route, which must begin with `/`.
+fn disable_route(state: &State, route: &str, status: Option<u16>) -> Result<String, String> {
+    if !route.starts_with('/') {
+        return Err(format!("Route {} must begin with /", route));
+    }
+
+    let status = state.disabled_routes.disable(route, status)?;
+    warn!("Disabled route {}, answering with {}", route, status);
+
+    Ok(format!("Disabled {} ({})\n", route, status.as_u16()))
+}
+
+/// `set_faults` switches the injection of the faults configured for a route
+/// on or off.
+fn set_faults(state: &State, route: &str, enabled: bool) -> Result<String, String> {
+    let faults = state
+        .faults
+        .iter()
+        .find(|faults| faults.route == route)
+        .ok_or_else(|| format!("No faults are configured for {}", route))?;
+
+    faults.set_enabled(enabled);
+    if enabled {
+        warn!("Enabled the faults of {}", route);
+        Ok(format!("Enabled the faults of {}\n", route))
+    } else {
+        info!("Disabled the faults of {}", route);
+        Ok(format!("Disabled the faults of {}\n", route))
+    }
+}
+
+/// `check_config` runs every check the server runs at startup against the
+/// config file at `path` without applying it. The checks run in a child
+/// `gee validate --startup`, so that importing the candidate applications
+/// and building their state cannot affect the running server, whose address
+/// need not be free. The child runs the server's executable with the working
+/// directory and environment the server was started with. If the executable
+/// has been replaced on disk since, the new one runs the checks.
+fn check_config(state: &State, path: &str) -> Result<String, String> {
+    let launch = LAUNCH.get_or_init(Launch::current).as_ref()?;
+    let output = Command::new(&launch.exe)
+        .args(["validate", "--startup", "--config", path, "--listening"])
+        .arg(state.config.socket_address().to_string())
+        .current_dir(&launch.dir)
+        .env_clear()
+        .envs(launch.vars.iter().map(|(name, value)| (name, value)))
+        .output()
+        .map_err(|e| format!("Cannot check config {}: {}", path, e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("The check failed")
+            .to_owned());
+    }
+    info!("Checked config {}", path);
+
+    Ok(format!("{} is valid\n", path))
+}
+
+/// `check_startup` runs every check the server runs at startup against a
+/// config: its applications are imported, its state is built, and its
+/// address must be free unless a running server is `listening` at it.
+pub fn check_startup(config: &Config, listening: Option<SocketAddr>) -> Result<(), String> {
+    let applications = config.all_applications();
+    check_applications(&applications)?;
+    if !applications.is_empty() {
+        pyo3::prepare_freethreaded_python();
+    }
+    let mut mounts = Vec::new();
+    for application in applications.iter() {
+        let mount = Mount::load(application, &config.root_dir).map_err(|e| e.to_string())?;
+        mounts.push(Arc::new(mount));
+    }
+
+    let address = config.socket_address();
+    if Some(address) != listening {
+        TcpListener::bind(address).map_err(|e| format!("Cannot listen at {}: {}", address, e))?;
+    }
+
+    State::new(config.clone(), mounts).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// `top` replies with the `count` routes and hosts which sent the most bytes.
+fn top(state: &State, count: &str) -> Result<String, String> {
+    let count = count
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid count {}", count))?;
+    let traffic = state
+        .traffic
+        .as_ref()
+        .ok_or("Traffic accounting is not enabled")?;
+
+    Ok(traffic.top(count))
+}
+
+/// `tail_logs` replies with the last `count` buffered log records at least
+/// as severe as `level`, one per line.
+fn tail_logs(count: &str, level: Level) -> Result<String, String> {
+    let count = count
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid count {}", count))?;
+
+    let mut reply = String::new();
+    for record in LOG_BUFFER.tail(count, level) {
+        writeln!(reply, "{}", record).unwrap();
+    }
+    Ok(reply)
+}
+
+/// `restart_applications` imports the Python applications afresh and
+/// replaces their mounts. Requests keep being routed to the old mounts until
+/// every application has loaded, and they are kept if one fails to.
+fn restart_applications(state: &State) -> Result<String, String> {
+    let applications = state.config.all_applications();
+    if applications.is_empty() {
+        return Err("No applications are mounted".to_owned());
+    }
+    info!("Restarting the applications");
+
+    let root_dir = &state.config.root_dir;
+    let mut mounts = Vec::new();
+    for mount in state.mounts.read().unwrap().iter() {
+        mount.unload().map_err(|e| e.to_string())?;
+    }
+    for application in applications.iter() {
+        let mount = Mount::load(application, root_dir).map_err(|e| {
+            error!("Cannot restart the applications: {}", e);
+            e.to_string()
+        })?;
+        mounts.push(Arc::new(mount));
+    }
+
+    *state.mounts.write().unwrap() = mounts;
+    info!("Restarted the applications");
+
+    Ok(format!("Restarted {} applications\n", applications.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{FaultConfig, RouteConfig};
+    use crate::server::faults::Faults;
+
+    #[test]
+    fn test_run_route_commands() {
+        let state = State::for_tests(Config::new_default());
+
+        assert_eq!(
+            run(&["route", "disable", "/beta", "404"], &state),
+            Ok("Disabled /beta (404)\n".to_owned())
+        );
+        assert_eq!(
+            run(&["route", "list"], &state),
+            Ok("/beta disabled (404)\n".to_owned())
+        );
+        assert_eq!(
+            run(&["route", "enable", "/beta"], &state),
+            Ok("Enabled /beta\n".to_owned())
+        );
+        assert!(run(&["route", "enable", "/beta"], &state).is_err());
+        assert!(run(&["route", "disable", "beta"], &state).is_err());
+        assert!(run(&["route", "disable", "/beta", "200"], &state).is_err());
+        assert!(run(&["reticulate"], &state).is_err());
+    }
+
+    #[test]
+    fn test_run_faults_commands() {
+        let mut state = State::for_tests(Config::new_default());
+        let route = RouteConfig {
+            route: "/api".to_owned(),
+            faults: Some(FaultConfig {
+                enabled: Some(false),
+                ..FaultConfig::default()
+            }),
+            ..RouteConfig::default()
+        };
+        state
+            .faults
+            .push(Arc::new(Faults::new(&route).unwrap().unwrap()));
+
+        assert_eq!(
+            run(&["faults", "list"], &state),
+            Ok("/api faults disabled\n".to_owned())
+        );
+        assert_eq!(
+            run(&["faults", "enable", "/api"], &state),
+            Ok("Enabled the faults of /api\n".to_owned())
+        );
+        assert_eq!(
+            run(&["faults", "list"], &state),
+            Ok("/api faults enabled\n".to_owned())
+        );
+        assert!(run(&["faults", "disable", "/api"], &state).is_ok());
+        assert!(!state.faults[0].is_enabled());
+        assert!(run(&["faults", "enable", "/web"], &state).is_err());
+    }
+
+    #[test]
+    fn test_run_restart_app_without_applications() {
+        let state = State::for_tests(Config::new_default());
+
+        assert_eq!(
+            run(&["restart-app"], &state),
+            Err("No applications are mounted".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_run_logs() {
+        let state = State::for_tests(Config::new_default());
+
+        assert!(run(&["logs", "10"], &state).is_ok());
+        assert!(run(&["logs", "10", "warn"], &state).is_ok());
+        assert!(run(&["logs", "many"], &state).is_err());
+        assert!(run(&["logs", "10", "loud"], &state).is_err());
+    }
+
+    #[test]
+    fn test_check_startup() {
+        let state = State::for_tests(Config::new_default());
+        let dir = std::env::temp_dir().join(format!("gee-check-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let valid = dir.join("valid.toml");
+        std::fs::write(
+            &valid,
+            "address = \"127.0.0.1\"\nport = 8080\nroot_dir = \".\"\n",
+        )
+        .unwrap();
+        let invalid = dir.join("invalid.toml");
+        std::fs::write(
+            &invalid,
+            "address = \"127.0.0.1\"\nport = 8080\nroot_dir = \".\"\ntrusted_proxies = [\"nowhere\"]\n",
+        )
+        .unwrap();
+
+        let listening = Some(state.config.socket_address());
+        let valid = Config::from_file(&valid).unwrap();
+        assert_eq!(check_startup(&valid, listening), Ok(()));
+        let invalid = Config::from_file(&invalid).unwrap();
+        assert!(check_startup(&invalid, listening).is_err());
+        assert!(run(&["check-config"], &state).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use hyper::{
+    header::{HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION},
+    Body, Request,
+};
+use log::info;
+use std::fmt::Write;
+
+use crate::handlers::{Inspector, Outcome};
+
+/// `REDACTED_HEADERS` carry credentials, which are left out of dumps.
+const REDACTED_HEADERS: [HeaderName; 3] = [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION];
+
+/// `DebugDump` logs a request and the beginning of its body once the body
+/// has been passed to the handler, so that what a misbehaving client sends
+/// can be seen without a proxy in front of Gee.
+pub struct DebugDump {
+    /// `head` is the request line and headers, as they were received.
+    head: String,
+
+    /// `body` is the part of the body seen so far.
+    body: Vec<u8>,
+
+    /// `limit` is the number of bytes of the body logged.
+    limit: u64,
+}
+
+impl DebugDump {
+    /// `new` starts the dump of a request, which logs up to `limit` bytes of
+    /// its body.
+    pub fn new(req: &Request<Body>, request_id: &str, limit: u64) -> Self {
+        let mut head = format!(
+            "{} {} {:?} ({})",
+            req.method(),
+            req.uri(),
+            req.version(),
+            request_id
+        );
+        for (name, value) in req.headers() {
+            let value = if REDACTED_HEADERS.contains(name) {
+                "[redacted]".into()
+            } else {
+                String::from_utf8_lossy(value.as_bytes())
+            };
+            write!(head, "\n{}: {}", name, value).unwrap();
+        }
+
+        Self {
+            head,
+            body: Vec::new(),
+            limit,
+        }
+    }
+
+    /// `render` writes out the dump of the request.
+    fn render(&self, outcome: Outcome) -> String {
+        let note = match outcome {
+            Outcome::Complete => "",
+            Outcome::Truncated => "\n[truncated]",
+            Outcome::Rejected => "\n[rejected]",
+            Outcome::Failed => "\n[incomplete]",
+        };
+
+        format!("{}\n\n{}{}", self.head, self.body.escape_ascii(), note)
+    }
+}
+
+impl Inspector for DebugDump {
+    fn limit(&self) -> Option<u64> {
+        Some(self.limit)
+    }
+
+    fn inspect(&mut self, chunk: &[u8]) {
+        self.body.extend_from_slice(chunk);
+    }
+
+    fn finish(self: Box<Self>, outcome: Outcome) {
+        info!("Request dump:\n{}", self.render(outcome));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let req = Request::post("/webhooks")
+            .header("content-type", "application/json")
+            .header("cookie", "session=s3cret")
+            .body(Body::empty())
+            .unwrap();
+        let mut dump = DebugDump::new(&req, "req-1", 64);
+        dump.body.extend_from_slice(b"{\"ok\":true}\n");
+
+        assert_eq!(
+            dump.render(Outcome::Truncated),
+            "POST /webhooks HTTP/1.1 (req-1)\ncontent-type: application/json\ncookie: [redacted]\n\n{\\\"ok\\\":true}\\n\n[truncated]"
+        );
+    }
+}
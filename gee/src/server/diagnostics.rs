@@ -0,0 +1,316 @@
+use jiff::Timestamp;
+use log::{error, Level};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    fs, io,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use super::capabilities::Capabilities;
+use super::state::State;
+use crate::handlers::WorkerStatus;
+use crate::logs::{LogRecord, LOG_BUFFER};
+
+/// `MAX_BUNDLES` is the number of bundles written before later panics are
+/// only logged, so that a panic repeated by every request cannot fill the
+/// disk.
+const MAX_BUNDLES: usize = 10;
+
+/// `LOG_RECORDS` is the number of recent log records included in a bundle.
+const LOG_RECORDS: usize = 200;
+
+/// `REDACTED` replaces the values of secrets in the config of a bundle.
+const REDACTED: &str = "[redacted]";
+
+/// `SECRET_KEYS` are the fragments of the names of config values that are
+/// redacted.
+const SECRET_KEYS: [&str; 3] = ["secret", "password", "credential"];
+
+/// `SECRET_MAPS` are the paths of the config maps whose values are all
+/// redacted, whatever their names: the environment of the applications, which
+/// commonly holds database URLs and API keys, and the hashes digest
+/// authentication checks passwords against, which are as good as passwords.
+const SECRET_MAPS: [&[&str]; 2] = [
+    &["applications", "env"],
+    &["routes", "digest_auth", "users"],
+];
+
+/// `BUNDLES_WRITTEN` counts the bundles written by this process.
+static BUNDLES_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// `Bundle` is the state of the server when it panicked, written as JSON so
+/// that a bug report can include what the server was doing.
+#[derive(Serialize)]
+struct Bundle<'a> {
+    /// `version` is the version of Gee.
+    version: &'static str,
+
+    /// `git_commit` is the commit Gee was built from.
+    git_commit: &'static str,
+
+    /// `time` is when the panic happened.
+    time: String,
+
+    /// `panic` describes the panic.
+    panic: Panic,
+
+    /// `backtrace` is the backtrace of the panicking thread.
+    backtrace: String,
+
+    /// `config` is the effective config, with secrets redacted.
+    config: Value,
+
+    /// `capabilities` are the routes and features of the server.
+    capabilities: &'a Capabilities,
+
+    /// `workers` is the state of the worker pool.
+    workers: WorkerStatus,
+
+    /// `disabled_routes` are the routes disabled at the time, with the status
+    /// their requests are answered with.
+    disabled_routes: Vec<(String, u16)>,
+
+    /// `logs` are the most recent log records.
+    logs: Vec<LogRecord>,
+}
+
+/// `Panic` describes where and why a thread panicked.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct Panic {
+    /// `message` is the message the thread panicked with.
+    message: String,
+
+    /// `location` is the file, line, and column of the panic, if known.
+    location: Option<String>,
+
+    /// `thread` is the name of the thread, if it has one.
+    thread: Option<String>,
+}
+
+/// `install` sets a panic hook which writes a diagnostics bundle to `dir`
+/// and logs its path once the previous hook has reported the panic. Bundles
+/// stop being written once the server's state is dropped.
+pub fn install(dir: PathBuf, state: &Arc<State>) {
+    let state = Arc::downgrade(state);
+    let previous = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+
+        let state = match state.upgrade() {
+            Some(state) => state,
+            None => return,
+        };
+        if BUNDLES_WRITTEN.fetch_add(1, Ordering::SeqCst) >= MAX_BUNDLES {
+            error!("Not writing a diagnostics bundle: too many have been written");
+            return;
+        }
+
+        match write_bundle(&dir, &state, Panic::from_info(info)) {
+            Ok(path) => error!("Wrote diagnostics bundle to {}", path.display()),
+            Err(e) => error!(
+                "Cannot write diagnostics bundle to {}: {}",
+                dir.display(),
+                e
+            ),
+        }
+    }));
+}
+
+impl Panic {
+    /// `from_info` describes the panic the hook was called for.
+    fn from_info(info: &PanicHookInfo) -> Self {
+        Self {
+            message: payload_message(info.payload()),
+            location: info.location().map(|location| location.to_string()),
+            thread: std::thread::current().name().map(str::to_owned),
+        }
+    }
+}
+
+/// `write_bundle` writes a bundle describing the server and the panic to a
+/// new file in `dir`, returning its path.
+fn write_bundle(dir: &Path, state: &State, panic: Panic) -> io::Result<PathBuf> {
+    let now = Timestamp::now();
+    let mut config = serde_json::to_value(&state.config).map_err(io::Error::other)?;
+    redact(&mut config);
+    for path in SECRET_MAPS {
+        redact_map(&mut config, path);
+    }
+
+    let bundle = Bundle {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("GEE_GIT_COMMIT"),
+        time: now.to_string(),
+        panic,
+        backtrace: Backtrace::force_capture().to_string(),
+        config,
+        capabilities: &state.capabilities,
+        workers: state.workers.status(),
+        disabled_routes: state
+            .disabled_routes
+            .list()
+            .into_iter()
+            .map(|(route, status)| (route, status.as_u16()))
+            .collect(),
+        logs: LOG_BUFFER.tail(LOG_RECORDS, Level::Trace),
+    };
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "gee-diagnostics-{}-{}-{}.json",
+        now.as_second(),
+        std::process::id(),
+        BUNDLES_WRITTEN.load(Ordering::SeqCst)
+    ));
+    fs::write(
+        &path,
+        serde_json::to_vec_pretty(&bundle).map_err(io::Error::other)?,
+    )?;
+
+    Ok(path)
+}
+
+/// `payload_message` returns the message a thread panicked with.
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_owned()
+    }
+}
+
+/// `redact` replaces every value whose name looks like it holds a secret.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if !value.is_null() && SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+                    *value = Value::from(REDACTED);
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// `redact_map` replaces every value of the maps at `path`, descending into
+/// the elements of lists along the way.
+fn redact_map(value: &mut Value, path: &[&str]) {
+    match (value, path.split_first()) {
+        (Value::Array(values), _) => values.iter_mut().for_each(|value| redact_map(value, path)),
+        (Value::Object(map), Some((key, rest))) => {
+            if let Some(value) = map.get_mut(*key) {
+                redact_map(value, rest);
+            }
+        }
+        (Value::Object(map), None) => map
+            .values_mut()
+            .for_each(|value| *value = Value::from(REDACTED)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{ApplicationConfig, Config, DigestAuthConfig, RouteConfig};
+    use crate::hashmap;
+
+    #[test]
+    fn test_redact() {
+        let mut config = serde_json::json!({
+            "port": 8080,
+            "routes": [{ "route": "/downloads", "signing_secret": "s3cret" }],
+            "database_password": null,
+        });
+        redact(&mut config);
+
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "port": 8080,
+                "routes": [{ "route": "/downloads", "signing_secret": "[redacted]" }],
+                "database_password": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_payload_message() {
+        assert_eq!(payload_message(&"worker died"), "worker died");
+        assert_eq!(payload_message(&"worker died".to_owned()), "worker died");
+        assert_eq!(payload_message(&42), "Box<dyn Any>");
+    }
+
+    #[test]
+    fn test_write_bundle() {
+        let dir = std::env::temp_dir().join(format!("gee-diagnostics-{}", std::process::id()));
+        let mut config = Config::new_default();
+        config.routes = Some(vec![RouteConfig {
+            route: "/downloads".to_owned(),
+            signing_secret: Some("s3cret".to_owned()),
+            digest_auth: Some(DigestAuthConfig {
+                realm: "downloads".to_owned(),
+                users: hashmap!["alice".to_owned() => "ab".repeat(32)],
+                ..DigestAuthConfig::default()
+            }),
+            ..RouteConfig::default()
+        }]);
+        config.applications = Some(vec![ApplicationConfig {
+            path: "app.py".to_owned(),
+            name: "app".to_owned(),
+            env: Some(hashmap![
+                "DATABASE_URL".to_owned() => "postgres://app:hunter2@db/app".to_owned()
+            ]),
+            ..ApplicationConfig::default()
+        }]);
+        let state = State::for_tests(config);
+        let panic = Panic {
+            message: "worker died".to_owned(),
+            location: Some("src/main.rs:1:1".to_owned()),
+            thread: Some("main".to_owned()),
+        };
+
+        let path = write_bundle(&dir, &state, panic).unwrap();
+        let bundle = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let bundle: Value = serde_json::from_str(&bundle).unwrap();
+        assert_eq!(bundle["panic"]["message"], "worker died");
+        assert_eq!(
+            bundle["config"]["routes"][0]["signing_secret"],
+            "[redacted]"
+        );
+        assert_eq!(
+            bundle["config"]["routes"][0]["digest_auth"]["users"]["alice"],
+            "[redacted]"
+        );
+        assert_eq!(
+            bundle["config"]["applications"][0]["env"]["DATABASE_URL"],
+            "[redacted]"
+        );
+        assert!(!bundle.to_string().contains("hunter2"));
+        assert_eq!(bundle["workers"]["busy"], 0);
+        assert!(bundle["logs"].is_array());
+        assert!(bundle["capabilities"]["routes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|route| route["route"] == "/static"));
+    }
+}
@@ -0,0 +1,445 @@
+use hmac::{Hmac, Mac};
+use hyper::{
+    header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    Body, Method, Request, Response, StatusCode,
+};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::config::RouteConfig;
+
+/// `DEFAULT_NONCE_LIFETIME` is the number of seconds a nonce may be used for
+/// when no lifetime is configured.
+const DEFAULT_NONCE_LIFETIME: u64 = 300;
+
+/// `DigestAuth` authenticates the requests under a route with HTTP Digest
+/// authentication (RFC 7616) using SHA-256. Nonces are signed by the server,
+/// so none are kept for clients that never authenticate, and the nonce count
+/// of each nonce in use is tracked so that captured requests cannot be
+/// replayed.
+pub struct DigestAuth {
+    /// `route` is the path on the server the requests are authenticated under.
+    pub route: String,
+
+    /// `realm` is the protection space the credentials belong to.
+    realm: String,
+
+    /// `users` maps user names to the hash of `user:realm:password`.
+    users: HashMap<String, String>,
+
+    /// `secret` signs the nonces, and is made afresh each time the server
+    /// starts.
+    secret: Vec<u8>,
+
+    /// `opaque` is returned unchanged by clients.
+    opaque: String,
+
+    /// `nonce_lifetime` is the number of seconds a nonce may be used for.
+    nonce_lifetime: u64,
+
+    /// `counts` are when each nonce in use was issued and the highest nonce
+    /// count it was used with.
+    counts: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+/// `Refusal` is why a request was not authenticated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Refusal {
+    /// `Unauthorized` requests carried no credentials or wrong ones.
+    Unauthorized,
+
+    /// `Stale` requests carried the right credentials with an expired nonce,
+    /// so the client may retry with a new one without asking the user.
+    Stale,
+}
+
+impl DigestAuth {
+    /// `new` creates the authentication of a route, failing if a user's hash
+    /// is not a hex SHA-256 hash.
+    pub fn new(route: &RouteConfig) -> Result<Option<Self>, String> {
+        let config = match &route.digest_auth {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+
+        let mut users = HashMap::new();
+        for (user, hash) in config.users.iter() {
+            if hash.len() != 64 || !hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err(format!(
+                    "The digest_auth hash of {} for {} must be a hex SHA-256 hash",
+                    user, route.route
+                ));
+            }
+            users.insert(user.clone(), hash.to_ascii_lowercase());
+        }
+
+        let secret = os_random::<32>()?.to_vec();
+
+        Ok(Some(Self {
+            route: route.route.clone(),
+            realm: config.realm.clone(),
+            users,
+            secret,
+            opaque: hex(&os_random::<8>()?),
+            nonce_lifetime: config.nonce_lifetime.unwrap_or(DEFAULT_NONCE_LIFETIME),
+            counts: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// `check` returns the user a request is authenticated as, or why it is
+    /// not.
+    pub fn check(&self, req: &Request<Body>) -> Result<String, Refusal> {
+        let authorization = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        let uri = req
+            .uri()
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+
+        self.check_at(req.method(), uri, authorization, unix_now())
+    }
+
+    /// `challenge` answers a request that is not authenticated with a 401
+    /// carrying a new nonce.
+    pub fn challenge(&self, refusal: Refusal) -> Response<Body> {
+        self.challenge_at(refusal == Refusal::Stale, unix_now())
+    }
+
+    /// `check_at` authenticates a request as if it were received at the Unix
+    /// timestamp `now`.
+    fn check_at(
+        &self,
+        method: &Method,
+        uri: &str,
+        authorization: Option<&str>,
+        now: u64,
+    ) -> Result<String, Refusal> {
+        let credentials = match authorization.and_then(|value| {
+            let (scheme, params) = value.split_once(' ')?;
+            scheme
+                .eq_ignore_ascii_case("digest")
+                .then(|| parse_params(params))?
+        }) {
+            Some(credentials) => credentials,
+            None => {
+                debug!("Request to {} carried no digest credentials", uri);
+                return Err(Refusal::Unauthorized);
+            }
+        };
+        let param = |name: &str| credentials.get(name).map(String::as_str);
+
+        let user = param("username").unwrap_or_default();
+        let refuse = |reason: &str| {
+            warn!(
+                "Refused digest credentials of {:?} for {}: {}",
+                user, self.route, reason
+            );
+            Err(Refusal::Unauthorized)
+        };
+
+        if !param("algorithm").is_some_and(|algorithm| algorithm.eq_ignore_ascii_case("SHA-256")) {
+            return refuse("the algorithm is not SHA-256");
+        }
+        if param("qop") != Some("auth") {
+            return refuse("the qop is not auth");
+        }
+        if param("realm") != Some(self.realm.as_str()) {
+            return refuse("the realm does not match");
+        }
+        if param("uri") != Some(uri) {
+            return refuse("the uri does not match the request");
+        }
+        if param("opaque").is_some_and(|opaque| opaque != self.opaque) {
+            return refuse("the opaque does not match");
+        }
+
+        let (nonce, nc, cnonce, response) = match (
+            param("nonce"),
+            param("nc"),
+            param("cnonce"),
+            param("response"),
+        ) {
+            (Some(nonce), Some(nc), Some(cnonce), Some(response)) => (nonce, nc, cnonce, response),
+            _ => return refuse("a parameter is missing"),
+        };
+        let issued = match self.verify_nonce(nonce) {
+            Some(issued) => issued,
+            None => return refuse("the nonce was not issued by this server"),
+        };
+        let count = match u64::from_str_radix(nc, 16) {
+            Ok(count) => count,
+            Err(_) => return refuse("the nonce count is invalid"),
+        };
+        let ha1 = match self.users.get(user) {
+            Some(ha1) => ha1,
+            None => return refuse("the user is unknown"),
+        };
+
+        let ha2 = sha256_hex(&[method.as_str(), uri]);
+        let expected = sha256_hex(&[ha1, nonce, nc, cnonce, "auth", &ha2]);
+        if !constant_time_eq(
+            expected.as_bytes(),
+            response.to_ascii_lowercase().as_bytes(),
+        ) {
+            return refuse("the password is wrong");
+        }
+
+        if issued.saturating_add(self.nonce_lifetime) < now {
+            debug!("Nonce of {:?} for {} is stale", user, self.route);
+            return Err(Refusal::Stale);
+        }
+
+        let mut counts = self.counts.lock().unwrap();
+        counts.retain(|_, (issued, _)| issued.saturating_add(self.nonce_lifetime) >= now);
+        let last = counts.entry(nonce.to_owned()).or_insert((issued, 0));
+        if count <= last.1 {
+            return refuse("the nonce count was already used");
+        }
+        last.1 = count;
+
+        Ok(user.to_owned())
+    }
+
+    /// `nonce` issues a nonce at the Unix timestamp `now`: the time it was
+    /// issued and a random value, signed by the server.
+    fn nonce(&self, now: u64) -> String {
+        let salt = os_random::<8>().expect("the OS random number generator failed");
+        let value = format!("{:x}.{}", now, hex(&salt));
+        format!("{}.{}", value, hex(&self.mac(&value)))
+    }
+
+    /// `verify_nonce` returns when a nonce was issued, if it was issued by the
+    /// server.
+    fn verify_nonce(&self, nonce: &str) -> Option<u64> {
+        let (value, signature) = nonce.rsplit_once('.')?;
+        if !constant_time_eq(hex(&self.mac(value)).as_bytes(), signature.as_bytes()) {
+            return None;
+        }
+
+        let (issued, _) = value.split_once('.')?;
+        u64::from_str_radix(issued, 16).ok()
+    }
+
+    /// `mac` signs a value with the secret.
+    fn mac(&self, value: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(value.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// `challenge_at` builds the challenge with a nonce issued at the Unix
+    /// timestamp `now`, marked `stale` if the client only needs to retry with
+    /// it.
+    fn challenge_at(&self, stale: bool, now: u64) -> Response<Body> {
+        let mut challenge = format!(
+            "Digest realm=\"{}\", qop=\"auth\", algorithm=SHA-256, nonce=\"{}\", opaque=\"{}\"",
+            self.realm.replace('\\', "\\\\").replace('"', "\\\""),
+            self.nonce(now),
+            self.opaque
+        );
+        if stale {
+            challenge.push_str(", stale=true");
+        }
+
+        Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(WWW_AUTHENTICATE, challenge)
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+/// `parse_params` parses the comma-separated `name=value` parameters of an
+/// `Authorization` header, whose values may be quoted strings.
+fn parse_params(params: &str) -> Option<HashMap<String, String>> {
+    let mut parsed = HashMap::new();
+    let mut rest = params.trim_start();
+
+    while !rest.is_empty() {
+        let (name, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next()? {
+                        (_, '\\') => value.push(chars.next()?.1),
+                        (end, '"') => break end,
+                        (_, c) => value.push(c),
+                    }
+                };
+                (value, &quoted[end + 1..])
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].trim_end().to_owned(), &after[end..])
+            }
+        };
+
+        parsed.insert(name.trim().to_ascii_lowercase(), value);
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    Some(parsed)
+}
+
+/// `sha256_hex` returns the hex SHA-256 hash of the `parts` joined by colons.
+fn sha256_hex(parts: &[&str]) -> String {
+    hex(&Sha256::digest(parts.join(":").as_bytes()))
+}
+
+/// `os_random` returns bytes from the operating system's cryptographically
+/// secure random number generator, as the nonces must be unpredictable.
+fn os_random<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Cannot generate random bytes: {}", e))?;
+
+    Ok(bytes)
+}
+
+/// `hex` encodes bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `constant_time_eq` compares two secrets in a time independent of where
+/// they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// `unix_now` returns the current Unix timestamp.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::DigestAuthConfig;
+    use crate::hashmap;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn digest_auth() -> DigestAuth {
+        let route = RouteConfig {
+            route: "/admin".to_owned(),
+            digest_auth: Some(DigestAuthConfig {
+                realm: "internal".to_owned(),
+                users: hashmap!["alice".to_owned() => sha256_hex(&["alice", "internal", "s3cret"])],
+                nonce_lifetime: Some(60),
+            }),
+            ..RouteConfig::default()
+        };
+
+        DigestAuth::new(&route).unwrap().unwrap()
+    }
+
+    fn authorization(auth: &DigestAuth, nonce: &str, nc: &str, password: &str) -> String {
+        let ha1 = sha256_hex(&["alice", "internal", password]);
+        let ha2 = sha256_hex(&["GET", "/admin/?page=2"]);
+        let response = sha256_hex(&[&ha1, nonce, nc, "c0ffee", "auth", &ha2]);
+
+        format!(
+            "Digest username=\"alice\", realm=\"internal\", uri=\"/admin/?page=2\", \
+             algorithm=SHA-256, qop=auth, nonce=\"{}\", nc={}, cnonce=\"c0ffee\", \
+             response=\"{}\", opaque=\"{}\"",
+            nonce, nc, response, auth.opaque
+        )
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_hashes() {
+        let route = RouteConfig {
+            route: "/admin".to_owned(),
+            digest_auth: Some(DigestAuthConfig {
+                realm: "internal".to_owned(),
+                users: hashmap!["alice".to_owned() => "s3cret".to_owned()],
+                nonce_lifetime: None,
+            }),
+            ..RouteConfig::default()
+        };
+
+        assert!(DigestAuth::new(&route).is_err());
+        assert!(DigestAuth::new(&RouteConfig::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_check_at() {
+        let auth = digest_auth();
+        let nonce = auth.nonce(NOW);
+        let check = |authorization: &str, now: u64| {
+            auth.check_at(&Method::GET, "/admin/?page=2", Some(authorization), now)
+        };
+
+        assert_eq!(
+            check(&authorization(&auth, &nonce, "00000001", "s3cret"), NOW),
+            Ok("alice".to_owned())
+        );
+        assert_eq!(
+            check(&authorization(&auth, &nonce, "00000001", "s3cret"), NOW),
+            Err(Refusal::Unauthorized)
+        );
+        assert_eq!(
+            check(&authorization(&auth, &nonce, "00000002", "s3cret"), NOW + 1),
+            Ok("alice".to_owned())
+        );
+        assert_eq!(
+            check(&authorization(&auth, &nonce, "00000003", "wrong"), NOW),
+            Err(Refusal::Unauthorized)
+        );
+        assert_eq!(
+            check(
+                &authorization(&auth, &nonce, "00000003", "s3cret"),
+                NOW + 61
+            ),
+            Err(Refusal::Stale)
+        );
+
+        let forged = format!("{:x}.0.{}", NOW, "0".repeat(64));
+        assert_eq!(
+            check(&authorization(&auth, &forged, "00000001", "s3cret"), NOW),
+            Err(Refusal::Unauthorized)
+        );
+        assert_eq!(
+            auth.check_at(&Method::GET, "/admin/?page=2", None, NOW),
+            Err(Refusal::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn test_challenge() {
+        let auth = digest_auth();
+
+        let response = auth.challenge_at(true, NOW);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let challenge = response.headers()[WWW_AUTHENTICATE].to_str().unwrap();
+        assert!(challenge.starts_with("Digest realm=\"internal\", qop=\"auth\", algorithm=SHA-256"));
+        assert!(challenge.ends_with(", stale=true"));
+    }
+
+    #[test]
+    fn test_parse_params() {
+        let params =
+            parse_params("username=\"a\\\"b\", qop=auth, nc=00000001 ,uri=\"/x,y\"").unwrap();
+
+        assert_eq!(params["username"], "a\"b");
+        assert_eq!(params["qop"], "auth");
+        assert_eq!(params["nc"], "00000001");
+        assert_eq!(params["uri"], "/x,y");
+        assert!(parse_params("username=\"unterminated").is_none());
+    }
+}
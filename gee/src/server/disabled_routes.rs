@@ -0,0 +1,127 @@
+use hyper::StatusCode;
+use std::{collections::BTreeMap, sync::RwLock};
+
+use crate::config::Config;
+
+/// `DEFAULT_DISABLED_STATUS` answers requests to disabled routes when no
+/// status is configured.
+pub const DEFAULT_DISABLED_STATUS: StatusCode = StatusCode::SERVICE_UNAVAILABLE;
+
+/// `DisabledRoutes` are the routes whose requests are answered with a status
+/// instead of being handled. Routes are disabled in the config or at runtime
+/// through the control socket.
+#[derive(Debug, Default)]
+pub struct DisabledRoutes {
+    /// `routes` map each disabled route to the status its requests are
+    /// answered with.
+    routes: RwLock<BTreeMap<String, StatusCode>>,
+}
+
+impl DisabledRoutes {
+    /// `from_config` disables the `routes` entries with `enabled = false`.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let disabled = Self::default();
+
+        for route in config.routes.iter().flatten() {
+            if route.enabled == Some(false) {
+                disabled.disable(&route.route, route.disabled_status)?;
+            }
+        }
+
+        Ok(disabled)
+    }
+
+    /// `disable` answers requests under `route` with `status`, or a 503 if no
+    /// status is given. Only 4xx and 5xx statuses are accepted.
+    pub fn disable(&self, route: &str, status: Option<u16>) -> Result<StatusCode, String> {
+        let status = match status {
+            Some(status) => StatusCode::from_u16(status)
+                .ok()
+                .filter(|status| status.is_client_error() || status.is_server_error())
+                .ok_or_else(|| format!("{} is not an error status", status))?,
+            None => DEFAULT_DISABLED_STATUS,
+        };
+
+        self.routes
+            .write()
+            .unwrap()
+            .insert(route.to_owned(), status);
+
+        Ok(status)
+    }
+
+    /// `enable` resumes handling requests under `route`, returning whether it
+    /// was disabled.
+    pub fn enable(&self, route: &str) -> bool {
+        self.routes.write().unwrap().remove(route).is_some()
+    }
+
+    /// `status` returns the status a request to `path` is answered with if it
+    /// falls under a disabled route.
+    pub fn status(&self, path: &str) -> Option<StatusCode> {
+        self.routes
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(route, _)| path.starts_with(route.as_str()))
+            .max_by_key(|(route, _)| route.len())
+            .map(|(_, status)| *status)
+    }
+
+    /// `list` returns the disabled routes and their statuses.
+    pub fn list(&self) -> Vec<(String, StatusCode)> {
+        self.routes
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(route, status)| (route.clone(), *status))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RouteConfig;
+
+    #[test]
+    fn test_from_config() {
+        let config = Config {
+            routes: Some(vec![
+                RouteConfig {
+                    route: "/beta".to_owned(),
+                    enabled: Some(false),
+                    disabled_status: Some(404),
+                    ..RouteConfig::default()
+                },
+                RouteConfig {
+                    route: "/api".to_owned(),
+                    enabled: Some(true),
+                    ..RouteConfig::default()
+                },
+            ]),
+            ..Config::new_default()
+        };
+        let disabled = DisabledRoutes::from_config(&config).unwrap();
+
+        assert_eq!(disabled.status("/beta/page"), Some(StatusCode::NOT_FOUND));
+        assert_eq!(disabled.status("/api"), None);
+    }
+
+    #[test]
+    fn test_disable_and_enable() {
+        let disabled = DisabledRoutes::default();
+
+        assert_eq!(disabled.disable("/beta", None), Ok(DEFAULT_DISABLED_STATUS));
+        assert!(disabled.disable("/beta", Some(200)).is_err());
+        assert_eq!(disabled.status("/beta/page"), Some(DEFAULT_DISABLED_STATUS));
+        assert_eq!(
+            disabled.list(),
+            vec![("/beta".to_owned(), DEFAULT_DISABLED_STATUS)]
+        );
+
+        assert!(disabled.enable("/beta"));
+        assert!(!disabled.enable("/beta"));
+        assert_eq!(disabled.status("/beta/page"), None);
+    }
+}
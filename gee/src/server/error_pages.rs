@@ -0,0 +1,277 @@
+use hyper::{
+    body::HttpBody,
+    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Method, Request, Response, StatusCode,
+};
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+use crate::context::RequestContext;
+
+/// `ErrorPages` replace the empty bodies of error responses with pages
+/// configured by status, so that errors can be branded without an
+/// application. Pages are templates whose `{{path}}`, `{{status}}`,
+/// `{{request_id}}`, and `{{host}}` placeholders are filled in for each
+/// response.
+#[derive(Default)]
+pub struct ErrorPages {
+    /// `pages` are the templates of the pages, by status.
+    pages: HashMap<StatusCode, Template>,
+}
+
+/// `PageContext` holds the values the placeholders of a page are filled in
+/// with, taken from the request before it is handled.
+pub struct PageContext {
+    /// `path` is the path the request was sent to.
+    path: String,
+
+    /// `host` is the host the request was addressed to.
+    host: String,
+
+    /// `request_id` is the ID of the request.
+    request_id: String,
+}
+
+/// `Template` is the text of a page split around its placeholders.
+struct Template {
+    /// `parts` are rendered in order.
+    parts: Vec<Part>,
+}
+
+/// `Part` is a piece of a template.
+#[derive(Debug, PartialEq, Eq)]
+enum Part {
+    /// `Text` is rendered as is.
+    Text(String),
+
+    /// `Path` is replaced with the path of the request.
+    Path,
+
+    /// `Status` is replaced with the status code of the response.
+    Status,
+
+    /// `RequestId` is replaced with the ID of the request.
+    RequestId,
+
+    /// `Host` is replaced with the host of the request.
+    Host,
+}
+
+impl ErrorPages {
+    /// `new` reads the pages, by status, from their files. Relative paths are
+    /// resolved against the `root_dir`.
+    pub fn new(pages: &HashMap<String, String>, root_dir: &str) -> Result<Self, String> {
+        let pages = pages
+            .iter()
+            .map(|(status, file)| {
+                let status = status
+                    .parse::<u16>()
+                    .ok()
+                    .and_then(|status| StatusCode::from_u16(status).ok())
+                    .filter(|status| status.is_client_error() || status.is_server_error())
+                    .ok_or_else(|| format!("Invalid error page status {}", status))?;
+
+                let path = Path::new(root_dir).join(file);
+                let content = read_to_string(&path)
+                    .map_err(|e| format!("Cannot read error page {}: {}", path.display(), e))?;
+                let template = Template::parse(&content)
+                    .map_err(|e| format!("Invalid error page {}: {}", path.display(), e))?;
+
+                Ok((status, template))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { pages })
+    }
+
+    /// `is_empty` returns whether no pages are configured.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// `apply` gives an error response with an empty body the page of its
+    /// status, if there is one. Responses with a body are passed through, so
+    /// that the errors applications describe themselves are left alone.
+    pub fn apply(&self, response: Response<Body>, context: &PageContext) -> Response<Body> {
+        let template = match self.pages.get(&response.status()) {
+            Some(template) if response.body().size_hint().exact() == Some(0) => template,
+            _ => return response,
+        };
+
+        let page = template.render(context, response.status());
+        let (mut parts, _) = response.into_parts();
+        parts.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(page.len()));
+
+        Response::from_parts(parts, Body::from(page))
+    }
+}
+
+impl PageContext {
+    /// `from_request` takes the values of the placeholders from a request
+    /// and its context, or returns `None` for a HEAD request, which is
+    /// answered without a page.
+    pub fn from_request(req: &Request<Body>, context: &RequestContext) -> Option<Self> {
+        if req.method() == Method::HEAD {
+            return None;
+        }
+
+        Some(Self {
+            path: req.uri().path().to_owned(),
+            host: context.host.clone().unwrap_or_default(),
+            request_id: context.request_id.clone(),
+        })
+    }
+}
+
+impl Template {
+    /// `parse` splits a page around its placeholders, failing if one is not
+    /// known.
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = content;
+
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..]
+                .find("}}")
+                .map(|end| start + end)
+                .ok_or_else(|| "unclosed placeholder".to_owned())?;
+
+            parts.push(Part::Text(rest[..start].to_owned()));
+            parts.push(match rest[start + 2..end].trim() {
+                "path" => Part::Path,
+                "status" => Part::Status,
+                "request_id" => Part::RequestId,
+                "host" => Part::Host,
+                name => return Err(format!("unknown placeholder {{{{{}}}}}", name)),
+            });
+            rest = &rest[end + 2..];
+        }
+        parts.push(Part::Text(rest.to_owned()));
+
+        Ok(Self { parts })
+    }
+
+    /// `render` fills in the placeholders, escaping the values taken from the
+    /// request for HTML.
+    fn render(&self, context: &PageContext, status: StatusCode) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Path => escape(&context.path),
+                Part::Status => status.as_str().to_owned(),
+                Part::RequestId => escape(&context.request_id),
+                Part::Host => escape(&context.host),
+            })
+            .collect()
+    }
+}
+
+/// `escape` escapes text for use in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{ClientInfo, TrustedProxies};
+    use hyper::header::HOST;
+
+    fn context() -> PageContext {
+        PageContext {
+            path: "/missing<script>".to_owned(),
+            host: "example.com".to_owned(),
+            request_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_template_parse() {
+        assert_eq!(
+            Template::parse("<p>{{ path }} on {{host}}</p>")
+                .unwrap()
+                .parts,
+            vec![
+                Part::Text("<p>".to_owned()),
+                Part::Path,
+                Part::Text(" on ".to_owned()),
+                Part::Host,
+                Part::Text("</p>".to_owned()),
+            ]
+        );
+        assert!(Template::parse("{{user}}").is_err());
+        assert!(Template::parse("{{path").is_err());
+    }
+
+    #[test]
+    fn test_template_render() {
+        let template =
+            Template::parse("{{status}}: {{path}} was not found ({{request_id}})").unwrap();
+
+        assert_eq!(
+            template.render(&context(), StatusCode::NOT_FOUND),
+            "404: /missing&lt;script&gt; was not found (4bf92f3577b34da6a3ce929d0e0e4736)"
+        );
+    }
+
+    #[test]
+    fn test_page_context_from_request() {
+        let req = Request::builder()
+            .uri("/missing")
+            .header(HOST, "example.com")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(Body::empty())
+            .unwrap();
+        let client = ClientInfo::from_request(&req, None, &TrustedProxies::default());
+        let request_context = RequestContext::new(&req, client, None);
+        let context = PageContext::from_request(&req, &request_context).unwrap();
+
+        assert_eq!(context.path, "/missing");
+        assert_eq!(context.host, "example.com");
+        assert_eq!(context.request_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        let head = Request::head("/missing").body(Body::empty()).unwrap();
+        assert!(PageContext::from_request(&head, &request_context).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        let error_pages = ErrorPages {
+            pages: HashMap::from([(
+                StatusCode::NOT_FOUND,
+                Template::parse("Not found: {{path}}").unwrap(),
+            )]),
+        };
+        let response = |status, body: &'static str| {
+            Response::builder()
+                .status(status)
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let page = error_pages.apply(response(StatusCode::NOT_FOUND, ""), &context());
+        assert_eq!(page.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+        let body = hyper::body::to_bytes(page.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"Not found: /missing&lt;script&gt;");
+
+        let described = error_pages.apply(response(StatusCode::NOT_FOUND, "gone"), &context());
+        let body = hyper::body::to_bytes(described.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"gone");
+
+        let other = error_pages.apply(response(StatusCode::FORBIDDEN, ""), &context());
+        assert!(other.headers().get(CONTENT_TYPE).is_none());
+    }
+}
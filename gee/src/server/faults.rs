@@ -0,0 +1,215 @@
+use hyper::{Body, Response, StatusCode};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crate::config::RouteConfig;
+use crate::trace::random_id;
+
+/// `DEFAULT_ERROR_STATUS` answers injected errors when no status is
+/// configured.
+const DEFAULT_ERROR_STATUS: StatusCode = StatusCode::INTERNAL_SERVER_ERROR;
+
+/// `Faults` inject latency, errors, and dropped connections into a share of
+/// the requests under a route, so that clients' retries and timeouts can be
+/// tested against Gee in staging.
+pub struct Faults {
+    /// `route` is the path on the server faults are injected under.
+    pub route: String,
+
+    /// `enabled` is whether faults are injected, switched at runtime through
+    /// the control socket.
+    enabled: AtomicBool,
+
+    /// `latency` is how long delayed requests wait before they are handled.
+    latency: Option<Duration>,
+
+    /// `latency_percent` is the percentage of requests delayed.
+    latency_percent: f64,
+
+    /// `error_percent` is the percentage of requests answered with an error.
+    error_percent: f64,
+
+    /// `error_status` is the status of injected errors.
+    error_status: StatusCode,
+
+    /// `drop_percent` is the percentage of requests whose connection is
+    /// closed without an answer.
+    drop_percent: f64,
+}
+
+/// `Fault` is what happens to a request in place of being handled.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// `Error` answers the request with a status.
+    Error(StatusCode),
+
+    /// `Drop` closes the connection without an answer.
+    Drop,
+}
+
+impl Faults {
+    /// `new` creates the faults of a route, failing if a percentage is out of
+    /// range or the error status is not an error.
+    pub fn new(route: &RouteConfig) -> Result<Option<Self>, String> {
+        let config = match &route.faults {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+
+        let percent = |name, percent: Option<f64>, default| {
+            let percent = percent.unwrap_or(default);
+            if (0.0..=100.0).contains(&percent) {
+                Ok(percent)
+            } else {
+                Err(format!(
+                    "The faults {} of {} must be between 0 and 100",
+                    name, route.route
+                ))
+            }
+        };
+        let error_status = match config.error_status {
+            Some(status) => StatusCode::from_u16(status)
+                .ok()
+                .filter(|status| status.is_client_error() || status.is_server_error())
+                .ok_or_else(|| {
+                    format!(
+                        "The faults error_status of {} must be an error",
+                        route.route
+                    )
+                })?,
+            None => DEFAULT_ERROR_STATUS,
+        };
+
+        Ok(Some(Self {
+            route: route.route.clone(),
+            enabled: AtomicBool::new(config.enabled != Some(false)),
+            latency: config.latency.map(Duration::from_millis),
+            latency_percent: percent("latency_percent", config.latency_percent, 100.0)?,
+            error_percent: percent("error_percent", config.error_percent, 0.0)?,
+            error_status,
+            drop_percent: percent("drop_percent", config.drop_percent, 0.0)?,
+        }))
+    }
+
+    /// `is_enabled` returns whether faults are injected.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// `set_enabled` switches the injection of faults on or off.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// `inject` delays the request if it is picked to be, then returns the
+    /// fault to answer it with in place of handling it, if any.
+    pub async fn inject(&self) -> Option<Fault> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        if let Some(latency) = self.latency.filter(|_| chance(self.latency_percent)) {
+            tokio::time::sleep(latency).await;
+        }
+
+        if chance(self.drop_percent) {
+            Some(Fault::Drop)
+        } else if chance(self.error_percent) {
+            Some(Fault::Error(self.error_status))
+        } else {
+            None
+        }
+    }
+}
+
+impl Fault {
+    /// `response` builds the response of a fault. A dropped request is
+    /// answered with a body that is aborted before any of it is sent, which
+    /// makes the server close the connection.
+    pub fn response(&self) -> Response<Body> {
+        let (status, body) = match self {
+            Self::Error(status) => (*status, Body::empty()),
+            Self::Drop => {
+                let (sender, body) = Body::channel();
+                sender.abort();
+                (StatusCode::OK, body)
+            }
+        };
+
+        Response::builder().status(status).body(body).unwrap()
+    }
+}
+
+/// `chance` returns true for a random `percent` of calls.
+fn chance(percent: f64) -> bool {
+    percent > 0.0 && ((random_id() % 10_000) as f64) < percent * 100.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::FaultConfig;
+
+    fn route(faults: FaultConfig) -> RouteConfig {
+        RouteConfig {
+            route: "/api".to_owned(),
+            faults: Some(faults),
+            ..RouteConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_config() {
+        assert!(Faults::new(&RouteConfig::default()).unwrap().is_none());
+        assert!(Faults::new(&route(FaultConfig {
+            error_percent: Some(120.0),
+            ..FaultConfig::default()
+        }))
+        .is_err());
+        assert!(Faults::new(&route(FaultConfig {
+            error_status: Some(200),
+            ..FaultConfig::default()
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_chance() {
+        assert!(!chance(0.0));
+        assert!(chance(100.0));
+
+        let hits = (0..10_000).filter(|_| chance(25.0)).count();
+        assert!((2_000..3_000).contains(&hits));
+    }
+
+    #[tokio::test]
+    async fn test_inject() {
+        let faults = Faults::new(&route(FaultConfig {
+            error_percent: Some(100.0),
+            error_status: Some(503),
+            ..FaultConfig::default()
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            faults.inject().await,
+            Some(Fault::Error(StatusCode::SERVICE_UNAVAILABLE))
+        );
+
+        faults.set_enabled(false);
+        assert_eq!(faults.inject().await, None);
+
+        let faults = Faults::new(&route(FaultConfig {
+            enabled: Some(false),
+            drop_percent: Some(100.0),
+            ..FaultConfig::default()
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(faults.inject().await, None);
+        faults.set_enabled(true);
+        assert_eq!(faults.inject().await, Some(Fault::Drop));
+    }
+}
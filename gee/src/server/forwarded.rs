@@ -0,0 +1,180 @@
+use hyper::header::{HeaderMap, HeaderValue, FORWARDED, HOST};
+use std::net::IpAddr;
+
+use crate::client::ClientInfo;
+use crate::config::ForwardedMode;
+
+/// `X_FORWARDED_FOR` lists the addresses a request was forwarded from.
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// `X_FORWARDED_PROTO` is the protocol the original request was sent with.
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// `X_FORWARDED_HOST` is the host the original request was addressed to.
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// `PROTO` is the protocol Gee receives requests with.
+const PROTO: &str = "http";
+
+/// `set_forwarded` describes the client of a request about to be forwarded to
+/// another server in RFC 7239 `Forwarded` and legacy `X-Forwarded-For`,
+/// `X-Forwarded-Proto`, and `X-Forwarded-Host` headers. It must be called
+/// before the `Host` of the request is changed.
+///
+/// In `Append` mode, Gee's peer is added to the chains the request arrived
+/// with, and the protocol and host a previous proxy recorded are kept. In
+/// `Replace` mode, the headers describe only the client resolved through the
+/// trusted proxies.
+pub fn set_forwarded(headers: &mut HeaderMap, client: Option<&ClientInfo>, mode: ForwardedMode) {
+    let host = headers
+        .get(HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let ip = client.and_then(|client| match mode {
+        ForwardedMode::Append => client.remote_addr.map(|addr| addr.ip()),
+        ForwardedMode::Replace => client.client_ip,
+    });
+
+    let mut element = format!("for={}", node(ip));
+    if let Some(host) = &host {
+        element.push_str(&format!(";host={}", quote(host)));
+    }
+    element.push_str(&format!(";proto={}", PROTO));
+
+    if mode == ForwardedMode::Replace {
+        for name in [
+            FORWARDED.as_str(),
+            X_FORWARDED_FOR,
+            X_FORWARDED_PROTO,
+            X_FORWARDED_HOST,
+        ] {
+            headers.remove(name);
+        }
+    }
+
+    append(headers, FORWARDED.as_str(), &element);
+    if let Some(ip) = ip {
+        append(headers, X_FORWARDED_FOR, &ip.to_string());
+    }
+    if !headers.contains_key(X_FORWARDED_PROTO) {
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(PROTO));
+    }
+    if let Some(host) = host.filter(|_| !headers.contains_key(X_FORWARDED_HOST)) {
+        if let Ok(host) = HeaderValue::from_str(&host) {
+            headers.insert(X_FORWARDED_HOST, host);
+        }
+    }
+}
+
+/// `append` adds an entry to the comma-separated list in a header, merging
+/// the values it was sent with into one.
+fn append(headers: &mut HeaderMap, name: &'static str, entry: &str) {
+    let mut entries: Vec<_> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .collect();
+    entries.push(entry.to_owned());
+
+    if let Ok(value) = HeaderValue::from_str(&entries.join(", ")) {
+        headers.insert(name, value);
+    }
+}
+
+/// `node` formats an address as a `Forwarded` node, bracketing and quoting
+/// IPv6 addresses, or `unknown` if the address is not known.
+fn node(ip: Option<IpAddr>) -> String {
+    match ip {
+        Some(IpAddr::V4(ip)) => ip.to_string(),
+        Some(IpAddr::V6(ip)) => format!("\"[{}]\"", ip),
+        None => "unknown".to_owned(),
+    }
+}
+
+/// `quote` formats a `Forwarded` parameter value as a token, or as a quoted
+/// string if it contains characters a token may not.
+fn quote(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+
+    if is_token {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn client(remote_addr: &str, client_ip: &str) -> ClientInfo {
+        ClientInfo {
+            remote_addr: Some(remote_addr.parse().unwrap()),
+            client_ip: Some(client_ip.parse().unwrap()),
+            received: SystemTime::now(),
+            country: None,
+            asn: None,
+        }
+    }
+
+    fn headers(pairs: Vec<(&'static str, &'static str)>) -> HeaderMap {
+        pairs
+            .into_iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_set_forwarded_append() {
+        let mut headers = headers(vec![
+            ("host", "example.com:8080"),
+            ("forwarded", "for=203.0.113.7"),
+            ("x-forwarded-for", "203.0.113.7"),
+            ("x-forwarded-proto", "https"),
+        ]);
+        let client = client("10.0.0.2:51234", "203.0.113.7");
+        set_forwarded(&mut headers, Some(&client), ForwardedMode::Append);
+
+        assert_eq!(
+            headers[FORWARDED],
+            "for=203.0.113.7, for=10.0.0.2;host=\"example.com:8080\";proto=http"
+        );
+        assert_eq!(headers[X_FORWARDED_FOR], "203.0.113.7, 10.0.0.2");
+        assert_eq!(headers[X_FORWARDED_PROTO], "https");
+        assert_eq!(headers[X_FORWARDED_HOST], "example.com:8080");
+    }
+
+    #[test]
+    fn test_set_forwarded_replace() {
+        let mut headers = headers(vec![
+            ("host", "example.com"),
+            ("forwarded", "for=198.51.100.1"),
+            ("x-forwarded-for", "198.51.100.1, 10.0.0.2"),
+            ("x-forwarded-proto", "http"),
+        ]);
+        let client = client("[::1]:51234", "2001:db8::7");
+        set_forwarded(&mut headers, Some(&client), ForwardedMode::Replace);
+
+        assert_eq!(
+            headers[FORWARDED],
+            "for=\"[2001:db8::7]\";host=example.com;proto=http"
+        );
+        assert_eq!(headers[X_FORWARDED_FOR], "2001:db8::7");
+        assert_eq!(headers[X_FORWARDED_PROTO], "http");
+        assert_eq!(headers[X_FORWARDED_HOST], "example.com");
+    }
+
+    #[test]
+    fn test_set_forwarded_unknown_client() {
+        let mut headers = HeaderMap::new();
+        set_forwarded(&mut headers, None, ForwardedMode::Append);
+
+        assert_eq!(headers[FORWARDED], "for=unknown;proto=http");
+        assert!(!headers.contains_key(X_FORWARDED_FOR));
+    }
+}
@@ -0,0 +1,85 @@
+use hyper::StatusCode;
+
+use crate::client::ClientInfo;
+use crate::config::GeoRules;
+
+/// `check_geo` refuses a request with a 451 if its client is located outside
+/// the allowed countries or inside a denied country or autonomous system.
+/// Clients whose country is unknown are refused only when there is an allow
+/// list.
+pub fn check_geo(client: &ClientInfo, rules: &GeoRules) -> Result<(), StatusCode> {
+    let country = client.country.as_deref();
+    let listed = |countries: &[String]| {
+        country.is_some_and(|country| {
+            countries
+                .iter()
+                .any(|listed| listed.eq_ignore_ascii_case(country))
+        })
+    };
+
+    let refused = rules
+        .allow_countries
+        .is_some_and(|allowed| !listed(allowed))
+        || rules.deny_countries.is_some_and(listed)
+        || rules
+            .deny_asns
+            .is_some_and(|denied| client.asn.is_some_and(|asn| denied.contains(&asn)));
+
+    if refused {
+        Err(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn client(country: Option<&str>, asn: Option<u32>) -> ClientInfo {
+        ClientInfo {
+            remote_addr: None,
+            client_ip: None,
+            received: SystemTime::now(),
+            country: country.map(str::to_owned),
+            asn,
+        }
+    }
+
+    #[test]
+    fn test_check_geo_allow_countries() {
+        let allowed = ["DE".to_owned(), "FR".to_owned()];
+        let rules = GeoRules {
+            allow_countries: Some(&allowed),
+            ..GeoRules::default()
+        };
+
+        assert_eq!(check_geo(&client(Some("de"), None), &rules), Ok(()));
+        assert_eq!(
+            check_geo(&client(Some("US"), None), &rules),
+            Err(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS)
+        );
+        assert_eq!(
+            check_geo(&client(None, None), &rules),
+            Err(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS)
+        );
+    }
+
+    #[test]
+    fn test_check_geo_deny() {
+        let denied = ["KP".to_owned()];
+        let asns = [64512];
+        let rules = GeoRules {
+            deny_countries: Some(&denied),
+            deny_asns: Some(&asns),
+            ..GeoRules::default()
+        };
+
+        assert_eq!(check_geo(&client(Some("DE"), Some(3320)), &rules), Ok(()));
+        assert_eq!(check_geo(&client(None, None), &rules), Ok(()));
+        assert!(check_geo(&client(Some("KP"), None), &rules).is_err());
+        assert!(check_geo(&client(Some("DE"), Some(64512)), &rules).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use hyper::{http::uri::Authority, Body, Request, Response, StatusCode};
+
+/// `HostError` enumerates the reasons a request may be rejected based on the
+/// host it addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostError {
+    /// The request did not name a host and `allowed_hosts` is configured.
+    Missing,
+
+    /// The request named a host that could not be parsed.
+    Malformed,
+
+    /// The request named a host that is not in `allowed_hosts`.
+    NotAllowed,
+}
+
+impl HostError {
+    /// `status` returns the status code the request is rejected with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            HostError::Missing | HostError::Malformed => StatusCode::BAD_REQUEST,
+            HostError::NotAllowed => StatusCode::MISDIRECTED_REQUEST,
+        }
+    }
+
+    /// `response` builds the response sent to the client for this error.
+    pub fn response(&self) -> Response<Body> {
+        let status = self.status();
+
+        Response::builder()
+            .status(status)
+            .body(Body::from(status.canonical_reason().unwrap_or("")))
+            .unwrap()
+    }
+}
+
+/// `validate_host` checks the host a request is addressed to against the
+/// `allowed_hosts` and returns the normalized host name (lowercase and without
+/// a port) if there is one. The authority of an absolute request URI takes
+/// precedence over the `Host` header.
+pub fn validate_host(
+    req: &Request<Body>,
+    allowed_hosts: Option<&[String]>,
+) -> Result<Option<String>, HostError> {
+    let host = match req.uri().authority() {
+        Some(authority) => Some(normalize(authority.as_str())?),
+        None => match req.headers().get(hyper::header::HOST) {
+            Some(value) => Some(normalize(
+                value.to_str().map_err(|_| HostError::Malformed)?,
+            )?),
+            None => None,
+        },
+    };
+
+    let allowed_hosts = match allowed_hosts {
+        Some(allowed_hosts) => allowed_hosts,
+        None => return Ok(host),
+    };
+
+    match host {
+        Some(host) if allowed_hosts.iter().any(|pattern| is_match(&host, pattern)) => {
+            Ok(Some(host))
+        }
+        Some(_) => Err(HostError::NotAllowed),
+        None => Err(HostError::Missing),
+    }
+}
+
+/// `normalize` parses a host and optional port and returns the lowercase host
+/// name without the port or a trailing dot.
+fn normalize(value: &str) -> Result<String, HostError> {
+    if value.is_empty() || value.contains('@') {
+        return Err(HostError::Malformed);
+    }
+
+    let authority: Authority = value.parse().map_err(|_| HostError::Malformed)?;
+    let host = authority.host().trim_end_matches('.').to_lowercase();
+
+    if host.is_empty() {
+        return Err(HostError::Malformed);
+    }
+
+    Ok(host)
+}
+
+/// `is_match` returns whether the `host` is matched by an `allowed_hosts`
+/// entry.
+fn is_match(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_lowercase();
+
+    if pattern == "*" {
+        return true;
+    }
+
+    match pattern.strip_prefix('.') {
+        Some(domain) => host == domain || host.ends_with(&pattern),
+        None => host == pattern,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(host: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/");
+        if let Some(host) = host {
+            builder = builder.header("Host", host);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_validate_host_without_allowed_hosts() {
+        assert_eq!(
+            validate_host(&request(Some("Example.com:8080")), None),
+            Ok(Some("example.com".to_string()))
+        );
+        assert_eq!(validate_host(&request(None), None), Ok(None));
+        assert_eq!(
+            validate_host(&request(Some("bad host")), None),
+            Err(HostError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_validate_host_with_allowed_hosts() {
+        let allowed_hosts = vec!["example.com".to_string(), ".gee-wsgi.com".to_string()];
+        let allowed_hosts = Some(allowed_hosts.as_slice());
+
+        assert_eq!(
+            validate_host(&request(Some("example.com")), allowed_hosts),
+            Ok(Some("example.com".to_string()))
+        );
+        assert_eq!(
+            validate_host(&request(Some("gee-wsgi.com")), allowed_hosts),
+            Ok(Some("gee-wsgi.com".to_string()))
+        );
+        assert_eq!(
+            validate_host(&request(Some("docs.gee-wsgi.com")), allowed_hosts),
+            Ok(Some("docs.gee-wsgi.com".to_string()))
+        );
+        assert_eq!(
+            validate_host(&request(Some("evil.com")), allowed_hosts),
+            Err(HostError::NotAllowed)
+        );
+        assert_eq!(
+            validate_host(&request(Some("notgee-wsgi.com")), allowed_hosts),
+            Err(HostError::NotAllowed)
+        );
+        assert_eq!(
+            validate_host(&request(Some("user@example.com")), allowed_hosts),
+            Err(HostError::Malformed)
+        );
+        assert_eq!(
+            validate_host(&request(None), allowed_hosts),
+            Err(HostError::Missing)
+        );
+    }
+
+    #[test]
+    fn test_validate_host_with_wildcard() {
+        let allowed_hosts = vec!["*".to_string()];
+
+        assert_eq!(
+            validate_host(&request(Some("anything.test")), Some(&allowed_hosts)),
+            Ok(Some("anything.test".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_host_error_status() {
+        assert_eq!(HostError::Missing.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(HostError::Malformed.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            HostError::NotAllowed.status(),
+            StatusCode::MISDIRECTED_REQUEST
+        );
+    }
+}
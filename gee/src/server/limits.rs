@@ -0,0 +1,89 @@
+use hyper::{header::CONTENT_LENGTH, Body, Request, StatusCode};
+
+use crate::config::Limits;
+
+/// `check_limits` rejects a request whose headers are larger or more numerous
+/// than its limits allow with a 431, or whose declared `Content-Length` is
+/// larger than its body may be with a 413. Bodies without a declared length
+/// are limited as they are read.
+pub fn check_limits(req: &Request<Body>, limits: &Limits) -> Result<(), StatusCode> {
+    let headers = req.headers();
+
+    if limits
+        .max_headers
+        .is_some_and(|max_headers| headers.len() > max_headers)
+    {
+        return Err(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    if let Some(max_header_size) = limits.max_header_size {
+        let header_size: usize = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        if header_size > max_header_size {
+            return Err(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+        }
+    }
+
+    if let Some(max_body_size) = limits.max_body_size {
+        let content_length = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.is_some_and(|content_length| content_length > max_body_size) {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(headers: Vec<(&str, &str)>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/upload");
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let limits = Limits {
+            max_body_size: Some(100),
+            max_header_size: Some(30),
+            max_headers: Some(2),
+        };
+
+        assert_eq!(
+            check_limits(&request(vec![("Content-Length", "100")]), &limits),
+            Ok(())
+        );
+        assert_eq!(
+            check_limits(&request(vec![("Content-Length", "101")]), &limits),
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        assert_eq!(
+            check_limits(&request(vec![("A", "1"), ("B", "2"), ("C", "3")]), &limits),
+            Err(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+        assert_eq!(
+            check_limits(
+                &request(vec![("Cookie", "a-rather-long-session-cookie")]),
+                &limits
+            ),
+            Err(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        );
+        assert_eq!(
+            check_limits(
+                &request(vec![("Cookie", "a-rather-long-session-cookie")]),
+                &Limits::default()
+            ),
+            Ok(())
+        );
+    }
+}
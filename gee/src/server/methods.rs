@@ -0,0 +1,109 @@
+use hyper::{header::ALLOW, Body, Method, Response, StatusCode};
+
+use crate::config::RouteConfig;
+
+/// `RouteMethods` are the request methods accepted under a route. Requests
+/// using other methods are refused before any handler sees them.
+pub struct RouteMethods {
+    /// `route` is the path on the server the methods are accepted under.
+    pub route: String,
+
+    /// `methods` are the accepted methods, in the order they are configured.
+    methods: Vec<Method>,
+}
+
+impl RouteMethods {
+    /// `new` creates the accepted methods of a route, failing if a method is
+    /// not a valid token or none are listed.
+    pub fn new(route: &RouteConfig) -> Result<Option<Self>, String> {
+        let configured = match &route.methods {
+            Some(methods) => methods,
+            None => return Ok(None),
+        };
+        if configured.is_empty() {
+            return Err(format!("The methods of {} must not be empty", route.route));
+        }
+
+        let mut methods = Vec::new();
+        for method in configured {
+            let method = Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .map_err(|_| format!("Invalid method {} for {}", method, route.route))?;
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+        if methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+            let get = methods.iter().position(|method| method == Method::GET);
+            methods.insert(get.unwrap() + 1, Method::HEAD);
+        }
+
+        Ok(Some(Self {
+            route: route.route.clone(),
+            methods,
+        }))
+    }
+
+    /// `allows` returns whether a request using `method` is accepted.
+    pub fn allows(&self, method: &Method) -> bool {
+        self.methods.contains(method)
+    }
+
+    /// `names` lists the accepted methods.
+    pub fn names(&self) -> Vec<String> {
+        self.methods.iter().map(Method::to_string).collect()
+    }
+
+    /// `allow` lists the accepted methods as an `Allow` header value.
+    pub fn allow(&self) -> String {
+        self.names().join(", ")
+    }
+
+    /// `refusal` answers a request using a method that is not accepted.
+    pub fn refusal(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(ALLOW, self.allow())
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn route(methods: &[&str]) -> RouteConfig {
+        RouteConfig {
+            route: "/webhooks".to_owned(),
+            methods: Some(methods.iter().map(|method| method.to_string()).collect()),
+            ..RouteConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(RouteMethods::new(&RouteConfig::default())
+            .unwrap()
+            .is_none());
+        assert!(RouteMethods::new(&route(&[])).is_err());
+        assert!(RouteMethods::new(&route(&["GET POST"])).is_err());
+
+        let methods = RouteMethods::new(&route(&["get", "OPTIONS", "GET"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(methods.allow(), "GET, HEAD, OPTIONS");
+    }
+
+    #[test]
+    fn test_allows() {
+        let methods = RouteMethods::new(&route(&["POST"])).unwrap().unwrap();
+
+        assert!(methods.allows(&Method::POST));
+        assert!(!methods.allows(&Method::GET));
+        assert!(!methods.allows(&Method::HEAD));
+
+        let response = methods.refusal();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "POST");
+    }
+}
@@ -0,0 +1,361 @@
+use hyper::{
+    client::HttpConnector,
+    header::{HeaderMap, HeaderValue, HOST},
+    http::request::Parts,
+    Body, Client, Request, StatusCode, Uri,
+};
+use log::{debug, warn};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::UNIX_EPOCH,
+};
+
+use super::forwarded::set_forwarded;
+use super::state::State;
+use crate::config::{ForwardedMode, MirrorConfig};
+use crate::context::RequestContext;
+use crate::handlers::{python_service_handler, read_body, BodyError, Mount};
+
+/// `X_REQUEST_DEADLINE` tells an upstream when a request must be answered by.
+const X_REQUEST_DEADLINE: &str = "x-request-deadline";
+
+/// `Mirror` sends copies of the requests under a route to a shadow target so
+/// that a new version of an application can be tried against real traffic.
+pub struct Mirror {
+    /// `route` is the path on the server the requests are copied under.
+    pub route: String,
+
+    /// `target` receives the copies.
+    target: Target,
+
+    /// `sample_percent` is the percentage of requests that are copied.
+    sample_percent: f64,
+
+    /// `max_body_size` is the size of the largest body that is copied.
+    max_body_size: Option<u64>,
+
+    /// `forwarded` is how the copies sent upstream describe the client.
+    forwarded: ForwardedMode,
+
+    /// `requests` counts the requests seen so far, for sampling.
+    requests: AtomicU64,
+
+    /// `client` sends copies to upstream targets.
+    client: Client<HttpConnector>,
+}
+
+/// `Target` is where copies of requests are sent.
+enum Target {
+    /// `Mount` copies are passed to the application mounted at a route,
+    /// looked up for each copy so that restarted applications receive them.
+    Mount(String),
+
+    /// `Upstream` copies are sent to another HTTP server.
+    Upstream(Uri),
+}
+
+impl Mirror {
+    /// `new` creates the mirror of a route, failing if its target is not a
+    /// mounted application or a valid `http://` URL.
+    pub fn new(route: &str, config: &MirrorConfig, mounts: &[Arc<Mount>]) -> Result<Self, String> {
+        let target = match (&config.mount, &config.upstream) {
+            (Some(mount), None) => mounts
+                .iter()
+                .find(|candidate| candidate.route == *mount)
+                .map(|mount| Target::Mount(mount.route.clone()))
+                .ok_or_else(|| {
+                    format!(
+                        "No application is mounted at {} to mirror {} to",
+                        mount, route
+                    )
+                })?,
+            (None, Some(upstream)) => {
+                let uri = upstream
+                    .parse::<Uri>()
+                    .map_err(|e| format!("Invalid mirror upstream {}: {}", upstream, e))?;
+                if uri.scheme_str() != Some("http") || uri.authority().is_none() {
+                    return Err(format!(
+                        "Mirror upstream {} must be an http:// URL",
+                        upstream
+                    ));
+                }
+                Target::Upstream(uri)
+            }
+            _ => {
+                return Err(format!(
+                    "The mirror of {} must have exactly one of `mount` or `upstream`",
+                    route
+                ))
+            }
+        };
+
+        let sample_percent = config.sample_percent.unwrap_or(100.0);
+        if !(0.0..=100.0).contains(&sample_percent) {
+            return Err(format!(
+                "The mirror sample_percent of {} must be between 0 and 100",
+                route
+            ));
+        }
+
+        Ok(Self {
+            route: route.to_owned(),
+            target,
+            sample_percent,
+            max_body_size: config.max_body_size,
+            forwarded: config.forwarded.unwrap_or_default(),
+            requests: AtomicU64::new(0),
+            client: Client::new(),
+        })
+    }
+
+    /// `is_sampled` decides whether the next request is copied. Requests are
+    /// spread evenly so that exactly `sample_percent` of them are copied.
+    pub fn is_sampled(&self) -> bool {
+        let n = self.requests.fetch_add(1, Ordering::Relaxed) as f64;
+        let rate = self.sample_percent / 100.0;
+
+        ((n + 1.0) * rate).floor() > (n * rate).floor()
+    }
+
+    /// `tee` reads the body of a request within `limit` and sends a copy of
+    /// the request to the target in the background if its body is small
+    /// enough, returning the request to be passed to the primary handler.
+    pub async fn tee(
+        self: Arc<Self>,
+        req: Request<Body>,
+        limit: Option<u64>,
+        state: Arc<State>,
+    ) -> Result<Request<Body>, StatusCode> {
+        let (parts, body) = req.into_parts();
+        let body = match read_body(body, limit).await {
+            Ok(body) => body,
+            Err(BodyError::TooLarge) => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(BodyError::Read(_)) => return Err(StatusCode::BAD_REQUEST),
+        };
+
+        if self
+            .max_body_size
+            .is_none_or(|max_body_size| body.len() as u64 <= max_body_size)
+        {
+            let copy = copy_request(&parts, body.clone());
+            tokio::spawn(async move { self.send(copy, state).await });
+        }
+
+        Ok(Request::from_parts(parts, Body::from(body)))
+    }
+
+    /// `send` passes a copy of a request to the target and discards the
+    /// response.
+    async fn send(&self, mut copy: Request<Body>, state: Arc<State>) {
+        let uri = copy.uri().clone();
+
+        let status = match &self.target {
+            Target::Mount(route) => {
+                let mount = state
+                    .mounts
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .find(|mount| mount.route == *route)
+                    .cloned();
+                let mount = match mount {
+                    Some(mount) => mount,
+                    None => {
+                        warn!(
+                            "Cannot mirror request to {}: nothing is mounted at {}",
+                            uri, route
+                        );
+                        return;
+                    }
+                };
+                let response = python_service_handler(
+                    copy,
+                    mount,
+                    state.workers.clone(),
+                    state.config.clone(),
+                )
+                .await;
+                response.status()
+            }
+            Target::Upstream(upstream) => {
+                let context = RequestContext::of(&copy).cloned();
+                let client = context.as_ref().map(|context| &context.client);
+                set_forwarded(copy.headers_mut(), client, self.forwarded);
+                set_deadline(copy.headers_mut(), context.as_ref());
+                *copy.uri_mut() = upstream_uri(upstream, &uri);
+                copy.headers_mut().remove(HOST);
+                match self.client.request(copy).await {
+                    Ok(response) => response.status(),
+                    Err(e) => {
+                        warn!("Cannot mirror request to {} to {}: {}", uri, upstream, e);
+                        return;
+                    }
+                }
+            }
+        };
+
+        debug!("Mirrored request to {} answered with {}", uri, status);
+    }
+}
+
+/// `set_deadline` tells an upstream when the request must be answered by, in
+/// milliseconds since the epoch, replacing any deadline sent by the client.
+fn set_deadline(headers: &mut HeaderMap, context: Option<&RequestContext>) {
+    headers.remove(X_REQUEST_DEADLINE);
+
+    let deadline = context
+        .and_then(RequestContext::deadline_time)
+        .and_then(|deadline| deadline.duration_since(UNIX_EPOCH).ok());
+    if let Some(deadline) = deadline {
+        headers.insert(
+            X_REQUEST_DEADLINE,
+            HeaderValue::from(deadline.as_millis() as u64),
+        );
+    }
+}
+
+/// `copy_request` builds a copy of a request with the given body, keeping the
+/// context Gee attached to it.
+fn copy_request(parts: &Parts, body: Vec<u8>) -> Request<Body> {
+    let mut copy = Request::builder()
+        .method(parts.method.clone())
+        .uri(parts.uri.clone())
+        .version(parts.version)
+        .body(Body::from(body))
+        .unwrap();
+    *copy.headers_mut() = parts.headers.clone();
+    if let Some(context) = parts.extensions.get::<Arc<RequestContext>>() {
+        copy.extensions_mut().insert(context.clone());
+    }
+
+    copy
+}
+
+/// `upstream_uri` appends the path and query of a request to the upstream
+/// URL.
+fn upstream_uri(upstream: &Uri, uri: &Uri) -> Uri {
+    let base = upstream.path().trim_end_matches('/');
+    let path_and_query = uri
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+
+    Uri::builder()
+        .scheme("http")
+        .authority(upstream.authority().unwrap().clone())
+        .path_and_query(format!("{}{}", base, path_and_query))
+        .build()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{ClientInfo, TrustedProxies};
+
+    fn mirror(sample_percent: f64) -> Mirror {
+        let config = MirrorConfig {
+            upstream: Some("http://127.0.0.1:9000/shadow".to_owned()),
+            sample_percent: Some(sample_percent),
+            ..MirrorConfig::default()
+        };
+
+        Mirror::new("/api", &config, &[]).unwrap()
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_targets() {
+        let config = |mount: Option<&str>, upstream: Option<&str>| MirrorConfig {
+            mount: mount.map(str::to_owned),
+            upstream: upstream.map(str::to_owned),
+            ..MirrorConfig::default()
+        };
+
+        assert!(Mirror::new("/", &config(None, None), &[]).is_err());
+        assert!(Mirror::new("/", &config(Some("/v2"), None), &[]).is_err());
+        assert!(Mirror::new("/", &config(None, Some("https://example.com")), &[]).is_err());
+        assert!(Mirror::new("/", &config(None, Some("/relative")), &[]).is_err());
+        assert!(Mirror::new("/", &config(None, Some("http://example.com")), &[]).is_ok());
+    }
+
+    #[test]
+    fn test_is_sampled() {
+        let sampled = |mirror: Mirror| (0..100).filter(|_| mirror.is_sampled()).count();
+
+        assert_eq!(sampled(mirror(100.0)), 100);
+        assert_eq!(sampled(mirror(25.0)), 25);
+        assert_eq!(sampled(mirror(0.0)), 0);
+    }
+
+    #[test]
+    fn test_set_deadline() {
+        let req = Request::new(Body::empty());
+        let client = ClientInfo::from_request(&req, None, &TrustedProxies::default());
+        let context = RequestContext::new(&req, client, None);
+        let mut headers = HeaderMap::new();
+        headers.insert(X_REQUEST_DEADLINE, HeaderValue::from_static("1"));
+
+        set_deadline(&mut headers, Some(&context));
+        assert!(headers.get(X_REQUEST_DEADLINE).is_none());
+
+        let context = context.with_timeout(Some(std::time::Duration::from_secs(30)));
+        set_deadline(&mut headers, Some(&context));
+        let deadline: u128 = headers[X_REQUEST_DEADLINE]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        assert!(deadline > now && deadline <= now + 30_000);
+    }
+
+    #[test]
+    fn test_upstream_uri() {
+        let upstream = "http://127.0.0.1:9000/shadow/".parse().unwrap();
+
+        assert_eq!(
+            upstream_uri(&upstream, &"/api/users?page=2".parse().unwrap()),
+            "http://127.0.0.1:9000/shadow/api/users?page=2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tee() {
+        let mirror = Arc::new(
+            Mirror::new(
+                "/api",
+                &MirrorConfig {
+                    upstream: Some("http://127.0.0.1:9/".to_owned()),
+                    max_body_size: Some(4),
+                    ..MirrorConfig::default()
+                },
+                &[],
+            )
+            .unwrap(),
+        );
+        let state = Arc::new(State::for_tests(crate::config::Config::new_default()));
+
+        let req = Request::builder()
+            .uri("/api/users")
+            .body(Body::from("hello"))
+            .unwrap();
+        let req = mirror.clone().tee(req, None, state.clone()).await.unwrap();
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        let req = Request::builder()
+            .uri("/api/users")
+            .body(Body::from("hello"))
+            .unwrap();
+        assert_eq!(
+            mirror.tee(req, Some(2), state).await.unwrap_err(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+}
@@ -1,5 +1,35 @@
+mod access_log;
+mod asset_manifest;
+mod capabilities;
+mod control;
+mod debug_dump;
+mod diagnostics;
+mod digest_auth;
+mod disabled_routes;
+mod error_pages;
+mod faults;
+mod forwarded;
+mod geo;
+mod host;
+mod limits;
+mod methods;
+mod mirror;
+mod negotiation;
+mod openapi;
+mod precompress;
+mod response_cache;
+mod schedule;
+#[allow(clippy::module_inception)]
 mod server;
 mod service;
 mod service_builder;
+mod signed_url;
+mod state;
+mod static_health;
+mod throttle;
+mod traffic;
+mod transform;
+mod waf;
 
+pub use self::control::{check_startup, ERROR_PREFIX as CONTROL_ERROR_PREFIX};
 pub use self::server::Server;
@@ -0,0 +1,417 @@
+use hyper::{
+    header::{
+        HeaderName, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_TYPE, VARY,
+    },
+    http::HeaderMap,
+    Body, Response,
+};
+use std::path::{Path, PathBuf};
+
+use crate::config::NegotiationConfig;
+
+/// `MEDIA_TYPES` are the media types of the extensions type variants are
+/// commonly served with.
+const MEDIA_TYPES: [(&str, &str); 12] = [
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+    ("md", "text/markdown"),
+    ("yaml", "application/yaml"),
+    ("rss", "application/rss+xml"),
+    ("atom", "application/atom+xml"),
+    ("pdf", "application/pdf"),
+    ("svg", "image/svg+xml"),
+];
+
+/// `Variant` describes the variant of a static file chosen for a request, so
+/// that its response can say how it was chosen.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Variant {
+    /// `vary` are the request headers the choice depended on.
+    vary: Vec<HeaderName>,
+
+    /// `content_type` is the media type of the chosen type variant, if one
+    /// was chosen and its type is known.
+    content_type: Option<&'static str>,
+
+    /// `language` is the language of the chosen language variant, if one was
+    /// chosen.
+    language: Option<String>,
+}
+
+/// `negotiate` picks the variant of the static file at `path` to serve,
+/// returning its path. A type variant is only chosen if the file itself does
+/// not exist, and a language variant of the chosen file is preferred to the
+/// file itself. The configured default is chosen when the request accepts
+/// none of the variants on disk, or when it accepts several equally. The disk
+/// is probed on a blocking task.
+pub async fn negotiate(
+    path: PathBuf,
+    headers: &HeaderMap,
+    config: &NegotiationConfig,
+) -> (PathBuf, Variant) {
+    let (headers, config) = (headers.clone(), config.clone());
+    let fallback = path.clone();
+
+    tokio::task::spawn_blocking(move || select_variant(path, &headers, &config))
+        .await
+        .unwrap_or((fallback, Variant::default()))
+}
+
+/// `select_variant` picks the variant of the static file at `path` to serve
+/// to a request with `headers`.
+fn select_variant(
+    path: PathBuf,
+    headers: &HeaderMap,
+    config: &NegotiationConfig,
+) -> (PathBuf, Variant) {
+    let mut variant = Variant::default();
+    let languages = config.languages.as_deref().unwrap_or_default();
+
+    let mut path = path;
+    if let Some(types) = config
+        .types
+        .as_deref()
+        .filter(|_| !exists(&path, languages))
+    {
+        let available: Vec<&str> = types
+            .iter()
+            .map(String::as_str)
+            .filter(|extension| exists(&with_extension(&path, extension), languages))
+            .collect();
+
+        let accepted = header(headers, &ACCEPT);
+        let default = config.default_type.as_deref();
+        let extension = choose(&available, default, |extension| {
+            let media_type = media_type(extension).unwrap_or("application/octet-stream");
+            accepted.map_or(1.0, |accepted| {
+                quality(accepted, media_type, media_range_specificity)
+            })
+        })
+        .or_else(|| fallback(&available, default));
+
+        if let Some(extension) = extension {
+            variant.vary.push(ACCEPT);
+            variant.content_type = media_type(extension);
+            path = with_extension(&path, extension);
+        }
+    }
+
+    let available: Vec<&str> = languages
+        .iter()
+        .map(String::as_str)
+        .filter(|language| with_extension(&path, language).is_file())
+        .collect();
+    if !available.is_empty() {
+        variant.vary.push(ACCEPT_LANGUAGE);
+
+        let accepted = header(headers, &ACCEPT_LANGUAGE);
+        let default = config
+            .default_language
+            .as_deref()
+            .or_else(|| languages.first().map(String::as_str));
+        let language = choose(&available, default, |language| {
+            accepted.map_or(1.0, |accepted| {
+                quality(accepted, language, language_range_specificity)
+            })
+        })
+        .or_else(|| default.filter(|default| available.contains(default)))
+        .or_else(|| available.first().copied().filter(|_| !path.is_file()));
+
+        if let Some(language) = language {
+            variant.language = Some(language.to_owned());
+            path = with_extension(&path, language);
+        }
+    }
+
+    (path, variant)
+}
+
+impl Variant {
+    /// `apply` adds the headers describing the variant to its response.
+    pub fn apply(&self, mut response: Response<Body>) -> Response<Body> {
+        let headers = response.headers_mut();
+        for name in &self.vary {
+            headers.append(VARY, HeaderValue::from_name(name.clone()));
+        }
+
+        if response.status().is_success() {
+            let headers = response.headers_mut();
+            if let Some(content_type) = self.content_type {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            }
+            if let Some(language) = self
+                .language
+                .as_deref()
+                .and_then(|language| HeaderValue::from_str(language).ok())
+            {
+                headers.insert(CONTENT_LANGUAGE, language);
+            }
+        }
+
+        response
+    }
+}
+
+/// `choose` picks the candidate with the highest quality, with ties going
+/// to the `default` and then to the candidate listed first, or `None` if no
+/// candidate is acceptable.
+pub fn choose<'a>(
+    candidates: &[&'a str],
+    default: Option<&str>,
+    quality: impl Fn(&str) -> f32,
+) -> Option<&'a str> {
+    let (defaults, others): (Vec<&str>, Vec<&str>) = candidates
+        .iter()
+        .partition(|candidate| Some(**candidate) == default);
+
+    let mut best: Option<(&str, f32)> = None;
+    for candidate in defaults.into_iter().chain(others) {
+        let quality = quality(candidate);
+        if quality > best.map_or(0.0, |(_, best)| best) {
+            best = Some((candidate, quality));
+        }
+    }
+
+    best.map(|(candidate, _)| candidate)
+}
+
+/// `fallback` picks the `default` if it is one of the candidates, or else
+/// the candidate listed first.
+fn fallback<'a>(candidates: &[&'a str], default: Option<&str>) -> Option<&'a str> {
+    candidates
+        .iter()
+        .find(|candidate| Some(**candidate) == default)
+        .or_else(|| candidates.first())
+        .copied()
+}
+
+/// `quality` returns the quality an `Accept` or `Accept-Language` header
+/// value gives a media type or language tag: that of the most specific range
+/// matching it, or 0 if none does.
+pub fn quality(accepted: &str, name: &str, specificity: fn(&str, &str) -> Option<u8>) -> f32 {
+    accepted
+        .split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let range = params.next()?.trim();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+
+            specificity(&range.to_ascii_lowercase(), &name.to_ascii_lowercase())
+                .map(|specificity| (specificity, quality))
+        })
+        .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map_or(0.0, |(_, quality)| quality)
+}
+
+/// `media_range_specificity` returns how specifically a media range, e.g.
+/// `text/*`, matches a media type, or `None` if it does not.
+fn media_range_specificity(range: &str, media_type: &str) -> Option<u8> {
+    let (kind, _) = media_type.split_once('/')?;
+
+    if range == media_type {
+        Some(2)
+    } else if range.strip_suffix("/*") == Some(kind) {
+        Some(1)
+    } else if range == "*/*" {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// `language_range_specificity` returns how specifically a language range,
+/// e.g. `en`, matches a language tag, or `None` if it does not. A range
+/// matches the tags it is a prefix of, and a tag also matches the ranges it
+/// is a prefix of, so that `en-GB` readers are served an `en` variant.
+fn language_range_specificity(range: &str, language: &str) -> Option<u8> {
+    let is_prefix = |prefix: &str, tag: &str| {
+        tag.strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('-'))
+    };
+
+    if range == language {
+        Some(3)
+    } else if is_prefix(range, language) {
+        Some(2)
+    } else if is_prefix(language, range) {
+        Some(1)
+    } else if range == "*" {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// `media_type` returns the media type files with an extension are served
+/// as, if it is known.
+fn media_type(extension: &str) -> Option<&'static str> {
+    MEDIA_TYPES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+        .map(|(_, media_type)| *media_type)
+}
+
+/// `header` returns the value of a request header, if it is valid text.
+fn header<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// `exists` returns whether the file at `path`, or one of its language
+/// variants, exists.
+fn exists(path: &Path, languages: &[String]) -> bool {
+    path.is_file()
+        || languages
+            .iter()
+            .any(|language| with_extension(path, language).is_file())
+}
+
+/// `with_extension` appends an extension to a path, e.g. `index.html` to
+/// `index.html.de`.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+
+    path.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hyper::StatusCode;
+    use std::fs;
+
+    #[test]
+    fn test_quality() {
+        let accepted = "text/html;q=0.8, application/json, text/*;q=0.5, */*;q=0.1";
+
+        assert_eq!(
+            quality(accepted, "application/json", media_range_specificity),
+            1.0
+        );
+        assert_eq!(quality(accepted, "text/html", media_range_specificity), 0.8);
+        assert_eq!(
+            quality(accepted, "text/plain", media_range_specificity),
+            0.5
+        );
+        assert_eq!(quality(accepted, "image/png", media_range_specificity), 0.1);
+        assert_eq!(
+            quality("text/html", "image/png", media_range_specificity),
+            0.0
+        );
+
+        let accepted = "de-CH, en;q=0.5, fr;q=0";
+        assert_eq!(quality(accepted, "de", language_range_specificity), 1.0);
+        assert_eq!(quality(accepted, "en-US", language_range_specificity), 0.5);
+        assert_eq!(quality(accepted, "fr", language_range_specificity), 0.0);
+        assert_eq!(quality("*, fr;q=0", "fr", language_range_specificity), 0.0);
+    }
+
+    #[test]
+    fn test_choose() {
+        let languages = ["en", "de", "fr"];
+        let choose_language = |accepted: &str| {
+            choose(&languages, Some("de"), |language| {
+                quality(accepted, language, language_range_specificity)
+            })
+        };
+
+        assert_eq!(choose_language("fr, en;q=0.9"), Some("fr"));
+        assert_eq!(choose_language("*"), Some("de"));
+        assert_eq!(choose_language("en, *;q=0.5"), Some("en"));
+        assert_eq!(choose_language("es"), None);
+        assert_eq!(fallback(&languages, Some("de")), Some("de"));
+        assert_eq!(fallback(&languages, Some("es")), Some("en"));
+        assert_eq!(fallback(&[], None), None);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let dir = std::env::temp_dir().join(format!("gee-negotiation-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in [
+            "index.html",
+            "index.html.de",
+            "report.json",
+            "report.html.en",
+            "report.html.de",
+        ] {
+            fs::write(dir.join(file), file).unwrap();
+        }
+        let config = NegotiationConfig {
+            languages: Some(vec!["en".to_owned(), "de".to_owned()]),
+            types: Some(vec!["html".to_owned(), "json".to_owned()]),
+            ..NegotiationConfig::default()
+        };
+        let negotiate = |file: &str, headers: &[(HeaderName, &'static str)]| {
+            let headers = headers
+                .iter()
+                .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+                .collect();
+            let (path, variant) = select_variant(dir.join(file), &headers, &config);
+            (
+                path.file_name().unwrap().to_string_lossy().into_owned(),
+                variant,
+            )
+        };
+
+        let (path, variant) = negotiate("index.html", &[(ACCEPT_LANGUAGE, "de-DE, en;q=0.5")]);
+        assert_eq!(path, "index.html.de");
+        assert_eq!(variant.vary, vec![ACCEPT_LANGUAGE]);
+        assert_eq!(variant.language.as_deref(), Some("de"));
+
+        let (path, variant) = negotiate("index.html", &[(ACCEPT_LANGUAGE, "fr")]);
+        assert_eq!(path, "index.html");
+        assert_eq!(variant.language, None);
+
+        let (path, variant) = negotiate("report", &[(ACCEPT, "application/json")]);
+        assert_eq!(path, "report.json");
+        assert_eq!(variant.vary, vec![ACCEPT]);
+        assert_eq!(variant.content_type, Some("application/json"));
+
+        let (path, variant) = negotiate("report", &[]);
+        assert_eq!(path, "report.html.en");
+        assert_eq!(variant.vary, vec![ACCEPT, ACCEPT_LANGUAGE]);
+        assert_eq!(variant.content_type, Some("text/html"));
+
+        let (path, _) = negotiate("report", &[(ACCEPT_LANGUAGE, "de")]);
+        assert_eq!(path, "report.html.de");
+
+        let (path, variant) = negotiate("missing", &[]);
+        assert_eq!(path, "missing");
+        assert_eq!(variant, Variant::default());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply() {
+        let variant = Variant {
+            vary: vec![ACCEPT, ACCEPT_LANGUAGE],
+            content_type: Some("application/json"),
+            language: Some("de".to_owned()),
+        };
+
+        let response = variant.apply(Response::new(Body::empty()));
+        let vary: Vec<_> = response.headers().get_all(VARY).iter().collect();
+        assert_eq!(vary, vec!["accept", "accept-language"]);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[CONTENT_LANGUAGE], "de");
+
+        let not_found = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+        let response = variant.apply(not_found);
+        assert_eq!(response.headers().get_all(VARY).iter().count(), 2);
+        assert!(response.headers().get(CONTENT_LANGUAGE).is_none());
+    }
+}
@@ -0,0 +1,717 @@
+use hyper::{
+    header::{ALLOW, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, TRANSFER_ENCODING},
+    Body, Method, Request, Response, StatusCode,
+};
+use regex::Regex;
+use serde_json::Value;
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+use crate::config::RouteConfig;
+use crate::percent::percent_decode_once;
+
+/// `METHODS` are the operations a path item of an OpenAPI document may hold.
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// `OpenApi` validates the requests under a route against an OpenAPI 3
+/// document before they are handled: their path, method, required
+/// parameters, and content type. It checks what can be checked cheaply and
+/// without reading the body, so that the application only sees requests it
+/// documents.
+pub struct OpenApi {
+    /// `route` is the path on the server the requests are validated under.
+    pub route: String,
+
+    /// `base_path` is the path of the first of the document's `servers`,
+    /// which the paths of the document are relative to.
+    base_path: String,
+
+    /// `paths` are the documented paths, the most specific first.
+    paths: Vec<PathItem>,
+}
+
+/// `PathItem` is a documented path and its operations.
+struct PathItem {
+    /// `template` is the path as it is documented, e.g. `/users/{id}`.
+    template: String,
+
+    /// `pattern` matches the path, capturing its path parameters in order.
+    pattern: Regex,
+
+    /// `names` are the names of the path parameters, in order.
+    names: Vec<String>,
+
+    /// `operations` are the documented methods and what they require.
+    operations: Vec<(Method, Operation)>,
+}
+
+/// `Operation` is what an operation requires of a request.
+struct Operation {
+    /// `parameters` are the parameters of the operation and of its path.
+    parameters: Vec<Parameter>,
+
+    /// `body` is the request body of the operation, if it documents one.
+    body: Option<RequestBody>,
+}
+
+/// `Parameter` is a documented parameter.
+#[derive(Clone)]
+struct Parameter {
+    /// `name` is the name of the parameter.
+    name: String,
+
+    /// `location` is where the parameter is sent: `path`, `query`,
+    /// `header`, or `cookie`.
+    location: String,
+
+    /// `required` is whether the parameter must be sent.
+    required: bool,
+
+    /// `schema` is what its value must be.
+    schema: Schema,
+}
+
+/// `Schema` is the part of a parameter's schema checked by Gee.
+#[derive(Clone, Debug, PartialEq)]
+enum Schema {
+    /// `Integer` values must parse as integers.
+    Integer,
+
+    /// `Number` values must parse as numbers.
+    Number,
+
+    /// `Boolean` values must be `true` or `false`.
+    Boolean,
+
+    /// `Enum` values must be one of the listed values.
+    Enum(Vec<String>),
+
+    /// `Any` value is accepted.
+    Any,
+}
+
+/// `RequestBody` is a documented request body.
+struct RequestBody {
+    /// `required` is whether a body must be sent.
+    required: bool,
+
+    /// `media_types` are the content types the body may have, which may be
+    /// ranges such as `image/*`.
+    media_types: Vec<String>,
+}
+
+/// `Violation` is how a request fails to match the document.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// `UnknownPath` requests are to a path the document does not list.
+    UnknownPath,
+
+    /// `Method` requests use a method the path does not document, which
+    /// lists the methods it does.
+    Method(Vec<Method>),
+
+    /// `Invalid` requests lack a required parameter or send one of the wrong
+    /// type, or send a body of the wrong type, as described.
+    Invalid(String),
+}
+
+impl OpenApi {
+    /// `new` reads the OpenAPI document of a route, a JSON or YAML file
+    /// relative to the `root_dir`, failing if it cannot be read or lists no
+    /// paths.
+    pub fn new(route: &RouteConfig, root_dir: &str) -> Result<Option<Self>, String> {
+        let file = match &route.openapi {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let path = Path::new(root_dir).join(file);
+        let content = read_to_string(&path)
+            .map_err(|e| format!("Cannot read OpenAPI document {}: {}", path.display(), e))?;
+        let document: Value = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            _ => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| format!("Invalid OpenAPI document {}: {}", path.display(), e))?;
+
+        Self::from_document(&route.route, &document)
+            .map(Some)
+            .map_err(|e| format!("Invalid OpenAPI document {}: {}", path.display(), e))
+    }
+
+    /// `from_document` compiles the paths of an OpenAPI document.
+    fn from_document(route: &str, document: &Value) -> Result<Self, String> {
+        let items = document
+            .get("paths")
+            .and_then(Value::as_object)
+            .ok_or("it lists no paths")?;
+
+        let mut paths = Vec::new();
+        for (template, item) in items {
+            let item = resolve(document, item);
+            let shared = parameters(document, item.get("parameters"))?;
+
+            let mut operations = Vec::new();
+            for method in METHODS {
+                let operation = match item.get(method) {
+                    Some(operation) => resolve(document, operation),
+                    None => continue,
+                };
+
+                let mut parameters = parameters(document, operation.get("parameters"))?;
+                for parameter in shared.iter() {
+                    let overridden = parameters.iter().any(|overriding: &Parameter| {
+                        overriding.name == parameter.name
+                            && overriding.location == parameter.location
+                    });
+                    if !overridden {
+                        parameters.push(parameter.clone());
+                    }
+                }
+
+                let body = operation
+                    .get("requestBody")
+                    .map(|body| request_body(resolve(document, body)));
+                operations.push((
+                    Method::from_bytes(method.to_ascii_uppercase().as_bytes()).unwrap(),
+                    Operation { parameters, body },
+                ));
+            }
+
+            let (pattern, names) = compile_template(template)?;
+            paths.push(PathItem {
+                template: template.clone(),
+                pattern,
+                names,
+                operations,
+            });
+        }
+
+        paths.sort_by_key(|path| (path.names.len(), std::cmp::Reverse(path.template.len())));
+
+        Ok(Self {
+            route: route.to_owned(),
+            base_path: base_path(document),
+            paths,
+        })
+    }
+
+    /// `validate` checks a request against the document.
+    pub fn validate(&self, req: &Request<Body>) -> Result<(), Violation> {
+        let path = req
+            .uri()
+            .path()
+            .strip_prefix(self.base_path.as_str())
+            .filter(|path| path.starts_with('/'))
+            .ok_or(Violation::UnknownPath)?;
+        let (item, captures) = self
+            .paths
+            .iter()
+            .find_map(|item| item.pattern.captures(path).map(|captures| (item, captures)))
+            .ok_or(Violation::UnknownPath)?;
+
+        let operation = match item.operation(req.method()) {
+            Some(operation) => operation,
+            None if req.method() == Method::OPTIONS => return Ok(()),
+            None => {
+                return Err(Violation::Method(
+                    item.operations
+                        .iter()
+                        .map(|(method, _)| method.clone())
+                        .collect(),
+                ))
+            }
+        };
+
+        let path_values: HashMap<&str, String> = item
+            .names
+            .iter()
+            .zip(captures.iter().skip(1))
+            .filter_map(|(name, value)| {
+                Some((name.as_str(), percent_decode_once(value?.as_str(), false)))
+            })
+            .collect();
+        let query = req.uri().query().unwrap_or_default();
+        for parameter in operation.parameters.iter() {
+            let value = match parameter.location.as_str() {
+                "path" => path_values.get(parameter.name.as_str()).cloned(),
+                "query" => query_value(query, &parameter.name),
+                "header" => req
+                    .headers()
+                    .get(parameter.name.as_str())
+                    .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned()),
+                "cookie" => cookie_value(req, &parameter.name),
+                _ => continue,
+            };
+
+            match value {
+                Some(value) => parameter.check(&value)?,
+                None if parameter.required => {
+                    return Err(Violation::Invalid(format!(
+                        "the {} parameter {} is required",
+                        parameter.location, parameter.name
+                    )))
+                }
+                None => {}
+            }
+        }
+
+        if let Some(body) = &operation.body {
+            body.check(req)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PathItem {
+    /// `operation` returns the operation of a method, a HEAD request falling
+    /// back to the GET operation if it has none of its own.
+    fn operation(&self, method: &Method) -> Option<&Operation> {
+        let find = |method: &Method| {
+            self.operations
+                .iter()
+                .find(|(documented, _)| documented == method)
+                .map(|(_, operation)| operation)
+        };
+
+        find(method).or_else(|| {
+            if method == Method::HEAD {
+                find(&Method::GET)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl Parameter {
+    /// `check` checks a value sent for the parameter against its schema.
+    fn check(&self, value: &str) -> Result<(), Violation> {
+        let valid = match &self.schema {
+            Schema::Integer => value.parse::<i64>().is_ok(),
+            Schema::Number => value.parse::<f64>().is_ok_and(f64::is_finite),
+            Schema::Boolean => value == "true" || value == "false",
+            Schema::Enum(values) => values.iter().any(|allowed| allowed == value),
+            Schema::Any => true,
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(Violation::Invalid(format!(
+                "the {} parameter {} does not match its schema",
+                self.location, self.name
+            )))
+        }
+    }
+}
+
+impl RequestBody {
+    /// `check` checks that a request sends a body if one is required, and
+    /// that the body it sends is of a documented type.
+    fn check(&self, req: &Request<Body>) -> Result<(), Violation> {
+        let has_body = req.headers().contains_key(TRANSFER_ENCODING)
+            || req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .is_some_and(|length| length.trim() != "0");
+        if !has_body {
+            return if self.required {
+                Err(Violation::Invalid("a request body is required".to_owned()))
+            } else {
+                Ok(())
+            };
+        }
+        if self.media_types.is_empty() {
+            return Ok(());
+        }
+
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        let accepted =
+            self.media_types
+                .iter()
+                .any(|media_type| match media_type.strip_suffix("/*") {
+                    Some("*") => true,
+                    Some(kind) => content_type
+                        .split_once('/')
+                        .is_some_and(|(content_kind, _)| content_kind == kind),
+                    None => *media_type == content_type,
+                });
+
+        if accepted {
+            Ok(())
+        } else {
+            Err(Violation::Invalid(format!(
+                "the content type {:?} is not one of {}",
+                content_type,
+                self.media_types.join(", ")
+            )))
+        }
+    }
+}
+
+impl Violation {
+    /// `response` answers a request violating the document: a 404 for an
+    /// undocumented path, a 405 for an undocumented method, and a 400
+    /// describing any other violation.
+    pub fn response(&self) -> Response<Body> {
+        let response = Response::builder().header(CONTENT_TYPE, "text/plain");
+
+        match self {
+            Violation::UnknownPath => response
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+            Violation::Method(methods) => response
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(
+                    ALLOW,
+                    methods
+                        .iter()
+                        .map(Method::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+                .body(Body::empty())
+                .unwrap(),
+            Violation::Invalid(reason) => response
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid request: {}\n", reason)))
+                .unwrap(),
+        }
+    }
+}
+
+/// `resolve` follows a local `$ref`, e.g. `#/components/parameters/id`, to
+/// the value it refers to. Other values are returned as they are.
+fn resolve<'a>(document: &'a Value, value: &'a Value) -> &'a Value {
+    let mut value = value;
+    for _ in 0..8 {
+        match value
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| document.pointer(pointer))
+        {
+            Some(referenced) => value = referenced,
+            None => break,
+        }
+    }
+
+    value
+}
+
+/// `parameters` reads a list of parameters.
+fn parameters(document: &Value, list: Option<&Value>) -> Result<Vec<Parameter>, String> {
+    let list = match list {
+        Some(list) => list.as_array().ok_or("parameters must be a list")?,
+        None => return Ok(vec![]),
+    };
+
+    list.iter()
+        .map(|parameter| {
+            let parameter = resolve(document, parameter);
+            let field = |name: &str| parameter.get(name).and_then(Value::as_str);
+            let (name, location) = match (field("name"), field("in")) {
+                (Some(name), Some(location)) => (name, location),
+                _ => return Err("a parameter lacks its name or in".to_owned()),
+            };
+
+            Ok(Parameter {
+                name: name.to_owned(),
+                location: location.to_owned(),
+                required: location == "path"
+                    || parameter.get("required").and_then(Value::as_bool) == Some(true),
+                schema: schema(
+                    parameter
+                        .get("schema")
+                        .map(|schema| resolve(document, schema)),
+                ),
+            })
+        })
+        .collect()
+}
+
+/// `schema` reads the part of a parameter's schema Gee checks.
+fn schema(schema: Option<&Value>) -> Schema {
+    let schema = match schema {
+        Some(schema) => schema,
+        None => return Schema::Any,
+    };
+
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return Schema::Enum(
+            values
+                .iter()
+                .map(|value| match value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                })
+                .collect(),
+        );
+    }
+
+    match schema.get("type").and_then(Value::as_str) {
+        Some("integer") => Schema::Integer,
+        Some("number") => Schema::Number,
+        Some("boolean") => Schema::Boolean,
+        _ => Schema::Any,
+    }
+}
+
+/// `request_body` reads a request body.
+fn request_body(body: &Value) -> RequestBody {
+    RequestBody {
+        required: body.get("required").and_then(Value::as_bool) == Some(true),
+        media_types: body
+            .get("content")
+            .and_then(Value::as_object)
+            .map(|content| {
+                content
+                    .keys()
+                    .map(|media_type| media_type.to_ascii_lowercase())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// `compile_template` compiles a path template into a pattern capturing its
+/// parameters, which each match a non-empty part of a single segment.
+fn compile_template(template: &str) -> Result<(Regex, Vec<String>), String> {
+    let mut pattern = String::from("^");
+    let mut names = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("the path {} has an unclosed parameter", template))?;
+        pattern.push_str(&regex::escape(&rest[..start]));
+        pattern.push_str("([^/]+)");
+        names.push(rest[start + 1..end].to_owned());
+        rest = &rest[end + 1..];
+    }
+    pattern.push_str(&regex::escape(rest));
+    pattern.push('$');
+
+    Regex::new(&pattern)
+        .map(|pattern| (pattern, names))
+        .map_err(|e| e.to_string())
+}
+
+/// `base_path` returns the path of the first of the document's `servers`,
+/// without a trailing slash, or an empty path if it has none.
+fn base_path(document: &Value) -> String {
+    let url = document
+        .get("servers")
+        .and_then(|servers| servers.get(0))
+        .and_then(|server| server.get("url"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest
+            .find('/')
+            .map(|start| &rest[start..])
+            .unwrap_or_default(),
+        None => url,
+    };
+
+    path.trim_end_matches('/').to_owned()
+}
+
+/// `query_value` returns the decoded value of the first query parameter
+/// called `name`.
+fn query_value(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (percent_decode_once(key, true) == name).then(|| percent_decode_once(value, true))
+    })
+}
+
+/// `cookie_value` returns the value of the cookie called `name`.
+fn cookie_value(req: &Request<Body>, name: &str) -> Option<String> {
+    req.headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then(|| value.to_owned())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DOCUMENT: &str = r##"
+openapi: 3.0.3
+servers:
+  - url: https://api.example.com/v1/
+paths:
+  /users/{id}:
+    parameters:
+      - $ref: "#/components/parameters/id"
+    get:
+      parameters:
+        - name: fields
+          in: query
+          schema:
+            type: string
+            enum: [name, email]
+    put:
+      requestBody:
+        required: true
+        content:
+          application/json: {}
+  /users/me:
+    get:
+      parameters:
+        - name: X-Tenant
+          in: header
+          required: true
+  /reports/{year}.csv:
+    get:
+      parameters:
+        - name: year
+          in: path
+          schema:
+            type: integer
+        - name: session
+          in: cookie
+          required: true
+components:
+  parameters:
+    id:
+      name: id
+      in: path
+      required: true
+      schema:
+        type: integer
+"##;
+
+    fn openapi() -> OpenApi {
+        let document: Value = serde_yaml::from_str(DOCUMENT).unwrap();
+        OpenApi::from_document("/v1", &document).unwrap()
+    }
+
+    fn request(method: &str, uri: &str, headers: &[(&str, &str)]) -> Request<Body> {
+        let mut builder = Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_validate_paths_and_methods() {
+        let openapi = openapi();
+
+        assert_eq!(
+            openapi.validate(&request("GET", "/v1/users/7", &[])),
+            Ok(())
+        );
+        assert_eq!(
+            openapi.validate(&request("HEAD", "/v1/users/7", &[])),
+            Ok(())
+        );
+        assert_eq!(
+            openapi.validate(&request("OPTIONS", "/v1/users/7", &[])),
+            Ok(())
+        );
+        assert_eq!(
+            openapi.validate(&request("GET", "/v1/groups/7", &[])),
+            Err(Violation::UnknownPath)
+        );
+        assert_eq!(
+            openapi.validate(&request("GET", "/users/7", &[])),
+            Err(Violation::UnknownPath)
+        );
+        assert_eq!(
+            openapi.validate(&request("DELETE", "/v1/users/7", &[])),
+            Err(Violation::Method(vec![Method::GET, Method::PUT]))
+        );
+    }
+
+    #[test]
+    fn test_validate_parameters() {
+        let openapi = openapi();
+        let invalid = |req| matches!(openapi.validate(&req), Err(Violation::Invalid(_)));
+
+        assert!(invalid(request("GET", "/v1/users/seven", &[])));
+        assert!(invalid(request("GET", "/v1/users/7?fields=password", &[])));
+        assert!(!invalid(request("GET", "/v1/users/7?fields=email", &[])));
+
+        assert!(invalid(request("GET", "/v1/users/me", &[])));
+        assert!(!invalid(request(
+            "GET",
+            "/v1/users/me",
+            &[("x-tenant", "acme")]
+        )));
+
+        assert!(invalid(request("GET", "/v1/reports/2024.csv", &[])));
+        assert!(invalid(request(
+            "GET",
+            "/v1/reports/last.csv",
+            &[("cookie", "session=1")]
+        )));
+        assert!(!invalid(request(
+            "GET",
+            "/v1/reports/2024.csv",
+            &[("cookie", "theme=dark; session=1")]
+        )));
+    }
+
+    #[test]
+    fn test_validate_request_body() {
+        let openapi = openapi();
+        let invalid = |req| matches!(openapi.validate(&req), Err(Violation::Invalid(_)));
+
+        assert!(invalid(request("PUT", "/v1/users/7", &[])));
+        assert!(invalid(request(
+            "PUT",
+            "/v1/users/7",
+            &[("content-length", "2"), ("content-type", "text/plain")]
+        )));
+        assert!(!invalid(request(
+            "PUT",
+            "/v1/users/7",
+            &[
+                ("content-length", "2"),
+                ("content-type", "application/json; charset=utf-8")
+            ]
+        )));
+    }
+
+    #[test]
+    fn test_violation_response() {
+        let response = Violation::Method(vec![Method::GET, Method::PUT]).response();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, PUT");
+
+        let response = Violation::Invalid("a request body is required".to_owned()).response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_compile_template() {
+        let (pattern, names) = compile_template("/reports/{year}.{format}").unwrap();
+
+        assert_eq!(names, vec!["year", "format"]);
+        assert!(pattern.is_match("/reports/2024.csv"));
+        assert!(!pattern.is_match("/reports/2024/q1.csv"));
+        assert!(compile_template("/users/{id").is_err());
+    }
+}
@@ -0,0 +1,512 @@
+use flate2::{write::GzEncoder, Compression};
+use hyper::{
+    header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, VARY},
+    http::HeaderMap,
+    Body, Response,
+};
+use log::{debug, info, warn};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use super::negotiation::{choose, quality};
+use crate::config::Config;
+
+/// `DEFAULT_EXTENSIONS` are the extensions of the text formats compressed
+/// when none are configured.
+const DEFAULT_EXTENSIONS: [&str; 11] = [
+    "html", "htm", "css", "js", "mjs", "json", "map", "svg", "txt", "xml", "wasm",
+];
+
+/// `DEFAULT_MIN_SIZE` is the size below which files are not worth
+/// compressing.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// `DEFAULT_MAX_SIZE` is the size above which files are not compressed, so
+/// that startup is not held up by a few large files.
+const DEFAULT_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// `Encoding` is a content coding a static file may be compressed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+/// `Precompress` finds the compressed variants of static files, beside them
+/// or in its cache directory, and generates the missing ones.
+pub struct Precompress {
+    /// `cache_dir` holds the generated variants, at the absolute path of the
+    /// file they compress.
+    cache_dir: PathBuf,
+
+    /// `roots` are the directories the static routes serve.
+    roots: Vec<PathBuf>,
+
+    /// `generate` is whether missing variants are generated at startup.
+    pub generate: bool,
+
+    /// `encodings` are the codings served, in order of preference.
+    encodings: Vec<Encoding>,
+
+    /// `extensions` are the extensions of the files compressed.
+    extensions: Vec<String>,
+
+    /// `min_size` is the size of the smallest file compressed.
+    min_size: u64,
+
+    /// `max_size` is the size of the largest file compressed.
+    max_size: u64,
+}
+
+/// `Compressed` describes the compressed variant of a static file chosen for
+/// a request, so that its response can say how it was chosen.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Compressed {
+    /// `encoding` is the coding of the chosen variant, or `None` if the
+    /// client accepts none of the variants on disk.
+    encoding: Option<Encoding>,
+}
+
+impl Encoding {
+    /// `parse` reads a content coding as it is configured and sent in
+    /// `Accept-Encoding`.
+    fn parse(token: &str) -> Option<Self> {
+        match token {
+            "br" => Some(Self::Brotli),
+            "gzip" => Some(Self::Gzip),
+            _ => None,
+        }
+    }
+
+    /// `token` is the name of the coding in `Content-Encoding`.
+    fn token(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    /// `extension` is appended to the name of a file compressed with the
+    /// coding.
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gz",
+        }
+    }
+
+    /// `compress` compresses content with the coding, as tightly as it can.
+    fn compress(self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Brotli => {
+                let mut compressed = Vec::new();
+                let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+                encoder.write_all(content)?;
+                drop(encoder);
+                Ok(compressed)
+            }
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+impl Precompress {
+    /// `new` creates the compressed variants of the static routes, failing if
+    /// an encoding is not supported.
+    pub fn new(config: &Config) -> Result<Option<Self>, String> {
+        let (precompress, cache_dir) = match (&config.precompress, config.precompress_cache_dir()) {
+            (Some(precompress), Some(cache_dir)) => (precompress, cache_dir),
+            _ => return Ok(None),
+        };
+
+        let encodings = match &precompress.encodings {
+            Some(encodings) => encodings
+                .iter()
+                .map(|token| {
+                    Encoding::parse(token)
+                        .ok_or_else(|| format!("Unsupported precompress encoding {}", token))
+                })
+                .collect::<Result<_, _>>()?,
+            None => vec![Encoding::Brotli, Encoding::Gzip],
+        };
+
+        let extensions = match &precompress.extensions {
+            Some(extensions) => extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            None => DEFAULT_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        };
+
+        let roots = config
+            .static_routes
+            .iter()
+            .flatten()
+            .map(|(_, dir)| Path::new(&config.root_dir).join(dir))
+            .collect();
+
+        Ok(Some(Self {
+            cache_dir,
+            roots,
+            generate: precompress.generate != Some(false),
+            encodings,
+            extensions,
+            min_size: precompress.min_size.unwrap_or(DEFAULT_MIN_SIZE),
+            max_size: precompress.max_size.unwrap_or(DEFAULT_MAX_SIZE),
+        }))
+    }
+
+    /// `select` picks the compressed variant of the static file at `path` to
+    /// serve, returning its path. The file itself is served if it has no
+    /// up-to-date variants, or if the client accepts none of them. The disk is
+    /// probed on a blocking task.
+    pub async fn select(
+        self: &Arc<Self>,
+        path: PathBuf,
+        headers: &HeaderMap,
+    ) -> (PathBuf, Option<Compressed>) {
+        let precompress = self.clone();
+        let accepted = headers
+            .get(ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+        let fallback = path.clone();
+
+        tokio::task::spawn_blocking(move || precompress.find_variant(path, &accepted))
+            .await
+            .unwrap_or((fallback, None))
+    }
+
+    /// `find_variant` picks the variant of the file at `path` to serve to a
+    /// client sending `accepted` as its `Accept-Encoding`.
+    fn find_variant(&self, path: PathBuf, accepted: &str) -> (PathBuf, Option<Compressed>) {
+        let modified = match self.compressible(&path) {
+            Some(modified) => modified,
+            None => return (path, None),
+        };
+
+        let available: Vec<(Encoding, PathBuf)> = self
+            .encodings
+            .iter()
+            .filter_map(|encoding| {
+                self.variant(&path, *encoding, modified)
+                    .map(|variant| (*encoding, variant))
+            })
+            .collect();
+        if available.is_empty() {
+            return (path, None);
+        }
+
+        let tokens: Vec<&str> = available
+            .iter()
+            .map(|(encoding, _)| encoding.token())
+            .collect();
+        let chosen = choose(&tokens, None, |token| {
+            quality(accepted, token, coding_specificity)
+        });
+
+        match available
+            .into_iter()
+            .find(|(encoding, _)| Some(encoding.token()) == chosen)
+        {
+            Some((encoding, variant)) => (
+                variant,
+                Some(Compressed {
+                    encoding: Some(encoding),
+                }),
+            ),
+            None => (path, Some(Compressed { encoding: None })),
+        }
+    }
+
+    /// `generate_all` walks the static routes and compresses every file
+    /// without an up-to-date variant into the cache directory, returning the
+    /// number of variants written.
+    pub fn generate_all(&self) -> usize {
+        let mut files = Vec::new();
+        for root in &self.roots {
+            walk(root, &self.cache_dir, &mut files);
+        }
+
+        let mut generated = 0;
+        for path in files {
+            match self.generate_variants(&path) {
+                Ok(count) => generated += count,
+                Err(e) => warn!("Cannot precompress {}: {}", path.display(), e),
+            }
+        }
+
+        info!(
+            "Precompressed {} static file variants into {}",
+            generated,
+            self.cache_dir.display()
+        );
+        generated
+    }
+
+    /// `generate_variants` writes the missing variants of a file, returning
+    /// how many were written. Each is written to a temporary file first, so
+    /// that requests never see one half written.
+    fn generate_variants(&self, path: &Path) -> io::Result<usize> {
+        let modified = match self.compressible(path) {
+            Some(modified) => modified,
+            None => return Ok(0),
+        };
+
+        let mut content = None;
+        let mut generated = 0;
+        for encoding in &self.encodings {
+            if self.variant(path, *encoding, modified).is_some() {
+                continue;
+            }
+            let cached = match self.cache_path(path, *encoding) {
+                Some(cached) => cached,
+                None => continue,
+            };
+            if content.is_none() {
+                content = Some(fs::read(path)?);
+            }
+
+            let compressed = encoding.compress(content.as_deref().unwrap())?;
+            fs::create_dir_all(cached.parent().unwrap())?;
+            let partial = with_extension(&cached, "partial");
+            fs::write(&partial, compressed)?;
+            fs::rename(&partial, &cached)?;
+            debug!("Precompressed {} to {}", path.display(), cached.display());
+            generated += 1;
+        }
+
+        Ok(generated)
+    }
+
+    /// `compressible` returns when the file at `path` was last modified, if
+    /// it is a file whose extension and size are compressed.
+    fn compressible(&self, path: &Path) -> Option<SystemTime> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        if !self.extensions.contains(&extension) {
+            return None;
+        }
+
+        let metadata = fs::metadata(path).ok()?;
+        let size = metadata.len();
+        if !metadata.is_file() || size < self.min_size || size > self.max_size {
+            return None;
+        }
+
+        metadata.modified().ok()
+    }
+
+    /// `variant` returns the path of an up-to-date variant of a file, looking
+    /// beside the file and then in the cache directory.
+    fn variant(&self, path: &Path, encoding: Encoding, modified: SystemTime) -> Option<PathBuf> {
+        let is_fresh = |variant: &Path| {
+            fs::metadata(variant)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|variant_modified| variant_modified >= modified)
+        };
+
+        let beside = with_extension(path, encoding.extension());
+        if is_fresh(&beside) {
+            return Some(beside);
+        }
+
+        self.cache_path(path, encoding)
+            .filter(|cached| is_fresh(cached))
+    }
+
+    /// `cache_path` returns where the variant of a file is generated: at the
+    /// file's canonical path within the cache directory.
+    fn cache_path(&self, path: &Path, encoding: Encoding) -> Option<PathBuf> {
+        let canonical = fs::canonicalize(path).ok()?;
+        let relative: PathBuf = canonical
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+
+        Some(with_extension(
+            &self.cache_dir.join(relative),
+            encoding.extension(),
+        ))
+    }
+}
+
+impl Compressed {
+    /// `apply` adds the headers describing the variant to its response.
+    pub fn apply(&self, mut response: Response<Body>) -> Response<Body> {
+        response
+            .headers_mut()
+            .append(VARY, HeaderValue::from_name(ACCEPT_ENCODING));
+
+        if let Some(encoding) = self.encoding.filter(|_| response.status().is_success()) {
+            response
+                .headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.token()));
+        }
+
+        response
+    }
+}
+
+/// `coding_specificity` returns how specifically an `Accept-Encoding` entry
+/// matches a content coding, or `None` if it does not.
+fn coding_specificity(range: &str, coding: &str) -> Option<u8> {
+    if range == coding {
+        Some(1)
+    } else if range == "*" {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// `walk` collects the files under `dir`, skipping the cache directory.
+fn walk(dir: &Path, cache_dir: &Path, files: &mut Vec<PathBuf>) {
+    if dir == cache_dir {
+        return;
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Cannot precompress {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => walk(&path, cache_dir, files),
+            Ok(_) => files.push(path),
+            Err(_) => {}
+        }
+    }
+}
+
+/// `with_extension` appends an extension to a path, e.g. `br` to `app.js`.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::PrecompressConfig;
+    use crate::hashmap;
+    use std::io::Read;
+
+    fn precompress(root_dir: &Path) -> Precompress {
+        let mut config = Config::new_default();
+        config.root_dir = root_dir.to_string_lossy().into_owned();
+        config.static_routes = Some(hashmap!["/".to_owned() => "www".to_owned()]);
+        config.precompress = Some(PrecompressConfig {
+            cache_dir: "cache".to_owned(),
+            min_size: Some(16),
+            ..PrecompressConfig::default()
+        });
+
+        Precompress::new(&config).unwrap().unwrap()
+    }
+
+    fn accepting(encodings: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_str(encodings).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_new_rejects_unknown_encodings() {
+        let mut config = Config::new_default();
+        assert!(Precompress::new(&config).unwrap().is_none());
+
+        config.precompress = Some(PrecompressConfig {
+            cache_dir: "cache".to_owned(),
+            encodings: Some(vec!["zstd".to_owned()]),
+            ..PrecompressConfig::default()
+        });
+        assert!(Precompress::new(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_and_select() {
+        let dir = std::env::temp_dir().join(format!("gee-precompress-{}", std::process::id()));
+        fs::create_dir_all(dir.join("www")).unwrap();
+        let script = dir.join("www").join("app.js");
+        let content = "console.log('hello');\n".repeat(20);
+        fs::write(&script, &content).unwrap();
+        fs::write(dir.join("www").join("tiny.js"), "1").unwrap();
+        fs::write(dir.join("www").join("logo.png"), content.as_bytes()).unwrap();
+        let precompress = Arc::new(precompress(&dir));
+
+        assert_eq!(precompress.generate_all(), 2);
+        assert_eq!(precompress.generate_all(), 0);
+
+        let (path, compressed) = precompress
+            .select(script.clone(), &accepting("gzip, br"))
+            .await;
+        assert_eq!(
+            compressed,
+            Some(Compressed {
+                encoding: Some(Encoding::Brotli)
+            })
+        );
+        assert!(path.starts_with(dir.join("cache")));
+        assert!(path.to_string_lossy().ends_with("app.js.br"));
+
+        let (path, _) = precompress
+            .select(script.clone(), &accepting("gzip;q=1, br;q=0.5"))
+            .await;
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(fs::File::open(path).unwrap())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, content);
+
+        let (path, compressed) = precompress
+            .select(script.clone(), &accepting("identity"))
+            .await;
+        assert_eq!(path, script);
+        assert_eq!(compressed, Some(Compressed { encoding: None }));
+
+        let beside = dir.join("www").join("app.js.gz");
+        fs::write(&beside, "built").unwrap();
+        assert_eq!(
+            precompress.select(script, &accepting("gzip")).await.0,
+            beside
+        );
+
+        let png = dir.join("www").join("logo.png");
+        assert_eq!(
+            precompress.select(png.clone(), &accepting("br")).await,
+            (png, None)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply() {
+        let compressed = Compressed {
+            encoding: Some(Encoding::Gzip),
+        };
+        let response = compressed.apply(Response::new(Body::empty()));
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+
+        let response = Compressed { encoding: None }.apply(Response::new(Body::empty()));
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+    }
+}
@@ -0,0 +1,682 @@
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{HeaderName, HeaderValue, AGE, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING},
+    Body, Request, Response,
+};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::config::{CacheStorageKind, RouteConfig};
+use crate::shareable::{is_shareable, shared_key};
+
+/// `DEFAULT_TTL` is the number of seconds a response is cached for when no
+/// `ttl` is configured.
+const DEFAULT_TTL: u64 = 60;
+
+/// `DEFAULT_MAX_ENTRIES` is the number of responses kept when no
+/// `max_entries` is configured.
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// `DEFAULT_MAX_BYTES` is the total size of the responses kept on disk when
+/// no `max_bytes` is configured.
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// `SWEEP_INTERVAL` is how often the expired responses are removed from the
+/// storages.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `DEFAULT_MAX_BODY_SIZE` is the size of the largest body cached when no
+/// `max_body_size` is configured.
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// `CacheStorage` is where a response cache keeps its responses. Its methods
+/// may block, e.g. on the disk, and are called from blocking tasks.
+pub trait CacheStorage: Send + Sync {
+    /// `get` returns the response stored under `key`, expired or not.
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+
+    /// `put` stores a response under `key`, replacing any stored before.
+    fn put(&self, key: &str, response: CachedResponse);
+
+    /// `remove` removes the response stored under `key`.
+    fn remove(&self, key: &str);
+
+    /// `sweep` removes the responses which expired before `now`, in seconds
+    /// since the epoch.
+    fn sweep(&self, now: u64);
+}
+
+/// `CachedResponse` is a stored response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedResponse {
+    /// `head` is the status, headers and lifetime of the response.
+    pub head: CachedHead,
+
+    /// `body` is the body of the response.
+    pub body: Bytes,
+}
+
+/// `CachedHead` is everything about a stored response but its body.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CachedHead {
+    /// `status` is the status of the response.
+    pub status: u16,
+
+    /// `headers` are the headers of the response, in order.
+    pub headers: Vec<(String, String)>,
+
+    /// `stored` is when the response was stored, in seconds since the epoch.
+    pub stored: u64,
+
+    /// `expires` is when it stops being served, in seconds since the epoch.
+    pub expires: u64,
+}
+
+/// `ResponseCache` keeps the responses to GET requests under a route in a
+/// storage, so that pages which are the same for every client are generated
+/// once per `ttl` rather than once per request.
+pub struct ResponseCache {
+    /// `route` is the path on the server the responses are cached under.
+    pub route: String,
+
+    /// `storage` keeps the responses.
+    storage: Box<dyn CacheStorage>,
+
+    /// `ttl` is the number of seconds a response is served for.
+    ttl: u64,
+
+    /// `max_body_size` is the size of the largest body cached.
+    max_body_size: usize,
+}
+
+/// `MemoryStorage` keeps responses in the process, evicting those closest to
+/// expiring when full.
+pub struct MemoryStorage {
+    /// `entries` are the responses by key.
+    entries: Mutex<HashMap<String, CachedResponse>>,
+
+    /// `max_entries` is the number of responses kept.
+    max_entries: usize,
+}
+
+/// `DiskStorage` keeps each response in a file named after the hash of its
+/// key, its head as a line of JSON followed by its body, evicting those
+/// closest to expiring when full.
+pub struct DiskStorage {
+    /// `dir` is the directory of the files.
+    dir: PathBuf,
+
+    /// `index` is the expiry and size of each file, by path.
+    index: Mutex<HashMap<PathBuf, (u64, u64)>>,
+
+    /// `max_entries` is the number of responses kept.
+    max_entries: usize,
+
+    /// `max_bytes` is the total size of the files kept.
+    max_bytes: u64,
+}
+
+impl ResponseCache {
+    /// `new` creates the cache of a route, creating the directory of a disk
+    /// storage, and failing if it has none or it cannot be created.
+    pub fn new(route: &RouteConfig, root_dir: &str) -> Result<Option<Self>, String> {
+        let cache = match &route.cache {
+            Some(cache) => cache,
+            None => return Ok(None),
+        };
+
+        let max_entries = cache.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+        let storage: Box<dyn CacheStorage> = match cache.storage.unwrap_or_default() {
+            CacheStorageKind::Memory => Box::new(MemoryStorage::new(max_entries)),
+            CacheStorageKind::Disk => {
+                let dir = cache
+                    .dir
+                    .as_ref()
+                    .ok_or_else(|| format!("The disk cache of {} needs a dir", route.route))?;
+                Box::new(DiskStorage::new(
+                    Path::new(root_dir).join(dir),
+                    max_entries,
+                    cache.max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+                )?)
+            }
+        };
+
+        Ok(Some(Self {
+            route: route.route.clone(),
+            storage,
+            ttl: cache.ttl.unwrap_or(DEFAULT_TTL),
+            max_body_size: cache.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE),
+        }))
+    }
+
+    /// `key` returns the key a request's response is cached under, or `None`
+    /// if its response may not be shared between clients.
+    pub fn key(req: &Request<Body>) -> Option<String> {
+        shared_key(req.method(), req.uri(), req.headers())
+    }
+
+    /// `lookup` returns the cached response to a request, with its `Age`,
+    /// unless it has expired.
+    pub async fn lookup(self: &Arc<Self>, key: &str) -> Option<Response<Body>> {
+        let cache = self.clone();
+        let key = key.to_owned();
+        let cached = tokio::task::spawn_blocking(move || {
+            let cached = cache.storage.get(&key)?;
+            if cached.head.expires <= unix_now() {
+                cache.storage.remove(&key);
+                return None;
+            }
+            Some(cached)
+        })
+        .await
+        .ok()??;
+
+        let mut response = Response::builder().status(cached.head.status);
+        for (name, value) in cached.head.headers.iter() {
+            response = response.header(name.as_str(), value.as_str());
+        }
+        let age = unix_now().saturating_sub(cached.head.stored);
+
+        response.header(AGE, age).body(Body::from(cached.body)).ok()
+    }
+
+    /// `store` passes a response through, storing it once its body has been
+    /// sent if it may be shared between clients and its body is no larger
+    /// than `max_body_size`.
+    pub fn store(self: Arc<Self>, key: String, response: Response<Body>) -> Response<Body> {
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str(), String::from_utf8_lossy(value.as_bytes())));
+        if !is_shareable(response.status(), headers)
+            || response
+                .body()
+                .size_hint()
+                .exact()
+                .is_some_and(|size| size > self.max_body_size as u64)
+        {
+            return response;
+        }
+
+        let (mut parts, mut body) = response.into_parts();
+        if let Some(length) = body.size_hint().exact() {
+            parts
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from(length));
+        }
+        let now = unix_now();
+        let head = CachedHead {
+            status: parts.status.as_u16(),
+            headers: parts
+                .headers
+                .iter()
+                .filter(|(name, _)| !is_hop_by_hop(name))
+                .filter_map(|(name, value)| {
+                    Some((name.as_str().to_owned(), value.to_str().ok()?.to_owned()))
+                })
+                .collect(),
+            stored: now,
+            expires: now + self.ttl,
+        };
+
+        let (mut sender, stored) = Body::channel();
+        tokio::spawn(async move {
+            let mut buffer = Some(Vec::new());
+            while let Some(chunk) = body.data().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        debug!("Stopped caching a response: {}", e);
+                        sender.abort();
+                        return;
+                    }
+                };
+
+                if let Some(bytes) = buffer.as_mut() {
+                    if bytes.len() + chunk.len() > self.max_body_size {
+                        buffer = None;
+                    } else {
+                        bytes.extend_from_slice(&chunk);
+                    }
+                }
+                if sender.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+
+            if let Some(body) = buffer {
+                let _ = tokio::task::spawn_blocking(move || {
+                    self.storage.put(
+                        &key,
+                        CachedResponse {
+                            head,
+                            body: Bytes::from(body),
+                        },
+                    )
+                })
+                .await;
+            }
+        });
+
+        Response::from_parts(parts, stored)
+    }
+}
+
+/// `sweep` removes the expired responses from the storages of the caches
+/// every `SWEEP_INTERVAL` until the server stops, so that responses which are
+/// not requested again do not stay stored.
+pub fn sweep(caches: Vec<Arc<ResponseCache>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let caches = caches.clone();
+            let _ = tokio::task::spawn_blocking(move || {
+                let now = unix_now();
+                for cache in caches {
+                    cache.storage.sweep(now);
+                }
+            })
+            .await;
+        }
+    });
+}
+
+impl MemoryStorage {
+    /// `new` creates an empty storage keeping up to `max_entries` responses.
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            max_entries,
+        }
+    }
+}
+
+impl CacheStorage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(key) {
+            let now = unix_now();
+            entries.retain(|_, entry| entry.head.expires > now);
+        }
+        if entries.len() >= self.max_entries && !entries.contains_key(key) {
+            let evicted = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.head.expires)
+                .map(|(key, _)| key.clone());
+            if let Some(evicted) = evicted {
+                entries.remove(&evicted);
+            }
+        }
+        if self.max_entries > 0 {
+            entries.insert(key.to_owned(), response);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn sweep(&self, now: u64) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.head.expires > now);
+    }
+}
+
+impl DiskStorage {
+    /// `new` creates a storage in `dir` keeping up to `max_entries` responses
+    /// and `max_bytes` bytes, creating the directory and indexing the
+    /// responses stored by an earlier run.
+    pub fn new(dir: PathBuf, max_entries: usize, max_bytes: u64) -> Result<Self, String> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Cannot create the cache dir {}: {}", dir.display(), e))?;
+        let files = fs::read_dir(&dir)
+            .map_err(|e| format!("Cannot read the cache dir {}: {}", dir.display(), e))?;
+
+        let mut index = HashMap::new();
+        for path in files.filter_map(|file| Some(file.ok()?.path())) {
+            match Self::read_head(&path) {
+                Ok((head, size)) if path.extension().is_none() => {
+                    index.insert(path, (head.expires, size));
+                }
+                _ => {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+
+        let storage = Self {
+            dir,
+            index: Mutex::new(index),
+            max_entries,
+            max_bytes,
+        };
+        storage.evict(&mut storage.index.lock().unwrap(), None, 0);
+
+        Ok(storage)
+    }
+
+    /// `path` returns the file a key is stored in.
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = Sha256::digest(key.as_bytes());
+        let name: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        self.dir.join(name)
+    }
+
+    /// `read` reads a stored response.
+    fn read(path: &Path) -> std::io::Result<CachedResponse> {
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let head = serde_json::from_str(&line)?;
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body)?;
+
+        Ok(CachedResponse {
+            head,
+            body: Bytes::from(body),
+        })
+    }
+
+    /// `read_head` reads the head of a stored response and the size of its
+    /// file.
+    fn read_head(path: &Path) -> std::io::Result<(CachedHead, u64)> {
+        let file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut line = String::new();
+        BufReader::new(file).read_line(&mut line)?;
+
+        Ok((serde_json::from_str(&line)?, size))
+    }
+
+    /// `evict` removes the files of expired responses, then those closest to
+    /// expiring, until `size` more bytes can be stored at `path` within the
+    /// bounds.
+    fn evict(&self, index: &mut HashMap<PathBuf, (u64, u64)>, path: Option<&Path>, size: u64) {
+        let now = unix_now();
+        loop {
+            let entries = index.len() + path.map_or(0, |path| !index.contains_key(path) as usize);
+            let bytes = index
+                .iter()
+                .filter(|(stored, _)| Some(stored.as_path()) != path)
+                .map(|(_, (_, size))| size)
+                .sum::<u64>()
+                + size;
+            if entries <= self.max_entries && bytes <= self.max_bytes {
+                return;
+            }
+
+            let evicted = index
+                .iter()
+                .filter(|(stored, _)| Some(stored.as_path()) != path)
+                .min_by_key(|(_, (expires, _))| (*expires > now, *expires))
+                .map(|(stored, _)| stored.clone());
+            match evicted {
+                Some(evicted) => {
+                    let _ = fs::remove_file(&evicted);
+                    index.remove(&evicted);
+                }
+                None => return,
+            }
+        }
+    }
+}
+
+impl CacheStorage for DiskStorage {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        let path = self.path(key);
+        match Self::read(&path) {
+            Ok(response) => Some(response),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("Cannot read the cached {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        let path = self.path(key);
+        let partial = path.with_extension("partial");
+        let mut content = serde_json::to_vec(&response.head).unwrap();
+        content.push(b'\n');
+        content.extend_from_slice(&response.body);
+        let size = content.len() as u64;
+        if self.max_entries == 0 || size > self.max_bytes {
+            return;
+        }
+
+        let mut index = self.index.lock().unwrap();
+        self.evict(&mut index, Some(&path), size);
+        match fs::write(&partial, content).and_then(|_| fs::rename(&partial, &path)) {
+            Ok(()) => {
+                index.insert(path, (response.head.expires, size));
+            }
+            Err(e) => {
+                warn!("Cannot write the cached {}: {}", path.display(), e);
+                let _ = fs::remove_file(&partial);
+            }
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let path = self.path(key);
+        let _ = fs::remove_file(&path);
+        self.index.lock().unwrap().remove(&path);
+    }
+
+    fn sweep(&self, now: u64) {
+        self.index.lock().unwrap().retain(|path, (expires, _)| {
+            if *expires > now {
+                return true;
+            }
+            let _ = fs::remove_file(path);
+            false
+        });
+    }
+}
+
+/// `is_hop_by_hop` returns whether a header describes the connection or the
+/// framing of the body rather than the response, so is not stored.
+fn is_hop_by_hop(name: &HeaderName) -> bool {
+    [CONNECTION, TRANSFER_ENCODING, CONTENT_LENGTH, AGE].contains(name)
+}
+
+/// `unix_now` returns the number of seconds since the epoch.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ResponseCacheConfig;
+    use hyper::header::{CACHE_CONTROL, HOST, VARY};
+
+    fn cached(expires: u64) -> CachedResponse {
+        CachedResponse {
+            head: CachedHead {
+                status: 200,
+                headers: vec![("content-type".to_owned(), "text/html".to_owned())],
+                stored: 0,
+                expires,
+            },
+            body: Bytes::from_static(b"<p>hello</p>"),
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        let req = |method: &str, header: Option<(&str, &str)>| {
+            let mut builder = Request::builder()
+                .method(method)
+                .uri("/page?q=1")
+                .header(HOST, "example.com");
+            if let Some((name, value)) = header {
+                builder = builder.header(name, value);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        assert_eq!(
+            ResponseCache::key(&req("GET", None)),
+            Some("/page?q=1\nexample.com\n\n\n".to_owned())
+        );
+        assert_ne!(
+            ResponseCache::key(&req("GET", Some(("accept-encoding", "br")))),
+            ResponseCache::key(&req("GET", None))
+        );
+        assert!(ResponseCache::key(&req("POST", None)).is_none());
+        assert!(ResponseCache::key(&req("GET", Some(("cookie", "session=1")))).is_none());
+    }
+
+    #[test]
+    fn test_memory_storage_evicts() {
+        let storage = MemoryStorage::new(2);
+        storage.put("a", cached(unix_now() + 10));
+        storage.put("b", cached(unix_now() + 20));
+        storage.put("c", cached(unix_now() + 30));
+
+        assert!(storage.get("a").is_none());
+        assert!(storage.get("b").is_some());
+        assert!(storage.get("c").is_some());
+
+        storage.remove("b");
+        assert!(storage.get("b").is_none());
+    }
+
+    #[test]
+    fn test_memory_storage_sweep() {
+        let storage = MemoryStorage::new(2);
+        storage.put("a", cached(10));
+        storage.put("b", cached(30));
+        storage.sweep(20);
+
+        assert!(storage.get("a").is_none());
+        assert!(storage.get("b").is_some());
+    }
+
+    #[test]
+    fn test_disk_storage() {
+        let dir = std::env::temp_dir().join(format!("gee-response-cache-{}", std::process::id()));
+        let storage = DiskStorage::new(dir.clone(), 10, DEFAULT_MAX_BYTES).unwrap();
+        assert!(storage.get("/page").is_none());
+
+        storage.put("/page", cached(42));
+        let reopened = DiskStorage::new(dir.clone(), 10, DEFAULT_MAX_BYTES).unwrap();
+        assert_eq!(reopened.get("/page"), Some(cached(42)));
+
+        reopened.remove("/page");
+        assert!(storage.get("/page").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_disk_storage_evicts() {
+        let dir =
+            std::env::temp_dir().join(format!("gee-response-cache-evicts-{}", std::process::id()));
+        let storage = DiskStorage::new(dir.clone(), 2, DEFAULT_MAX_BYTES).unwrap();
+        storage.put("a", cached(unix_now() + 10));
+        storage.put("b", cached(unix_now() + 20));
+        storage.put("c", cached(unix_now() + 30));
+        assert!(storage.get("a").is_none());
+        assert!(storage.get("b").is_some());
+        assert!(storage.get("c").is_some());
+
+        let size = storage.index.lock().unwrap()[&storage.path("c")].1;
+        let reopened = DiskStorage::new(dir.clone(), 2, size).unwrap();
+        assert!(reopened.get("b").is_none());
+        assert!(reopened.get("c").is_some());
+
+        reopened.put("d", cached(unix_now() + 40));
+        assert!(reopened.get("c").is_none());
+        assert!(reopened.get("d").is_some());
+
+        reopened.sweep(unix_now() + 50);
+        assert!(reopened.get("d").is_none());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_store_and_lookup() {
+        let route = RouteConfig {
+            route: "/".to_owned(),
+            cache: Some(ResponseCacheConfig::default()),
+            ..RouteConfig::default()
+        };
+        let cache = Arc::new(ResponseCache::new(&route, ".").unwrap().unwrap());
+        assert!(cache.lookup("/").await.is_none());
+
+        let response = Response::builder()
+            .header(CONTENT_LENGTH, 5)
+            .body(Body::from("hello"))
+            .unwrap();
+        let response = cache.clone().store("/".to_owned(), response);
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+        // The response is stored by a task of its own once it has been sent.
+        for _ in 0..100 {
+            if cache.storage.get("/").is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        let response = cache.lookup("/").await.unwrap();
+        assert_eq!(response.headers()[AGE], "0");
+        assert!(!response.headers().contains_key(CONTENT_LENGTH));
+        assert_eq!(
+            hyper::body::to_bytes(response.into_body()).await.unwrap(),
+            "hello"
+        );
+
+        let private = Response::builder()
+            .header(CACHE_CONTROL, "private")
+            .body(Body::from("secret"))
+            .unwrap();
+        let response = cache.clone().store("/private".to_owned(), private);
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(cache.storage.get("/private").is_none());
+
+        let varied = Response::builder()
+            .header(VARY, "User-Agent")
+            .body(Body::from("hello"))
+            .unwrap();
+        let response = cache.clone().store("/varied".to_owned(), varied);
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(cache.storage.get("/varied").is_none());
+    }
+
+    #[test]
+    fn test_new_requires_disk_dir() {
+        let route = RouteConfig {
+            route: "/".to_owned(),
+            cache: Some(ResponseCacheConfig {
+                storage: Some(CacheStorageKind::Disk),
+                ..ResponseCacheConfig::default()
+            }),
+            ..RouteConfig::default()
+        };
+
+        assert!(ResponseCache::new(&route, ".").is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use jiff::{civil::Weekday, tz::TimeZone, Timestamp, Zoned};
+
+use crate::config::{Config, ScheduleConfig};
+
+/// `Schedules` are the windows of time during which requests under a route
+/// are served static content in place of their usual handler.
+pub struct Schedules {
+    /// `time_zone` is the time zone the windows are evaluated in.
+    time_zone: TimeZone,
+
+    /// `schedules` are the configured windows.
+    schedules: Vec<Schedule>,
+}
+
+/// `Schedule` is a window of time during which requests under a route are
+/// served from a target.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Schedule {
+    /// `route` is the path on the server the schedule applies under.
+    pub route: String,
+
+    /// `target` is what requests are served from during the window.
+    pub target: Target,
+
+    /// `start` is the minute of the day the window opens.
+    start: u16,
+
+    /// `end` is the minute of the day the window closes.
+    end: u16,
+
+    /// `days` are the days of the week the window opens on.
+    days: Vec<Weekday>,
+}
+
+/// `Target` is what requests are served from during a window.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Target {
+    /// `StaticDir` serves requests from a directory like a static route.
+    StaticDir(String),
+
+    /// `File` serves a single file in response to every request.
+    File(String),
+}
+
+/// `WEEKDAYS` are the days of the week in the order they are named.
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("mon", Weekday::Monday),
+    ("tue", Weekday::Tuesday),
+    ("wed", Weekday::Wednesday),
+    ("thu", Weekday::Thursday),
+    ("fri", Weekday::Friday),
+    ("sat", Weekday::Saturday),
+    ("sun", Weekday::Sunday),
+];
+
+impl Schedules {
+    /// `from_config` parses the configured schedules and looks up the
+    /// configured time zone.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let time_zone = match &config.timezone {
+            Some(name) => {
+                TimeZone::get(name).map_err(|e| format!("Unknown timezone {}: {}", name, e))?
+            }
+            None => TimeZone::system(),
+        };
+
+        let schedules = config
+            .schedules
+            .iter()
+            .flatten()
+            .map(Schedule::new)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            time_zone,
+            schedules,
+        })
+    }
+
+    /// `active` returns the active schedule with the longest route the path
+    /// falls under, if there is one.
+    pub fn active(&self, path: &str) -> Option<&Schedule> {
+        if self.schedules.is_empty() {
+            return None;
+        }
+
+        let now = Timestamp::now().to_zoned(self.time_zone.clone());
+        self.active_at(path, &now)
+    }
+
+    /// `active_at` returns the schedule active at `now` with the longest route
+    /// the path falls under, if there is one.
+    fn active_at(&self, path: &str, now: &Zoned) -> Option<&Schedule> {
+        self.schedules
+            .iter()
+            .filter(|schedule| path.starts_with(schedule.route.as_str()))
+            .filter(|schedule| schedule.is_active(now))
+            .max_by_key(|schedule| schedule.route.len())
+    }
+}
+
+impl Default for Schedules {
+    fn default() -> Self {
+        Self {
+            time_zone: TimeZone::UTC,
+            schedules: vec![],
+        }
+    }
+}
+
+impl Schedule {
+    /// `new` parses a configured schedule.
+    fn new(config: &ScheduleConfig) -> Result<Self, String> {
+        let target = match (&config.static_dir, &config.file) {
+            (Some(static_dir), None) => Target::StaticDir(static_dir.clone()),
+            (None, Some(file)) => Target::File(file.clone()),
+            _ => {
+                return Err(format!(
+                    "The schedule for {} must have exactly one of `static_dir` or `file`",
+                    config.route
+                ))
+            }
+        };
+
+        let days = match &config.days {
+            Some(days) => days
+                .iter()
+                .map(|day| parse_weekday(day).ok_or_else(|| format!("Invalid day {}", day)))
+                .collect::<Result<_, _>>()?,
+            None => WEEKDAYS.iter().map(|(_, weekday)| *weekday).collect(),
+        };
+
+        Ok(Self {
+            route: config.route.clone(),
+            target,
+            start: parse_time(&config.start)?,
+            end: parse_time(&config.end)?,
+            days,
+        })
+    }
+
+    /// `is_active` returns whether the window is open at `now`. A window
+    /// running overnight belongs to the day it opened on.
+    fn is_active(&self, now: &Zoned) -> bool {
+        let minute = now.hour() as u16 * 60 + now.minute() as u16;
+        let today = now.weekday();
+
+        if self.start <= self.end {
+            self.days.contains(&today) && self.start <= minute && minute < self.end
+        } else if minute >= self.start {
+            self.days.contains(&today)
+        } else {
+            minute < self.end && self.days.contains(&today.previous())
+        }
+    }
+}
+
+/// `parse_time` parses an `HH:MM` time of day into the minute of the day.
+fn parse_time(time: &str) -> Result<u16, String> {
+    let invalid = || format!("Invalid time {}, expected HH:MM", time);
+
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let hour = hour.parse::<u16>().map_err(|_| invalid())?;
+    let minute = minute.parse::<u16>().map_err(|_| invalid())?;
+    if hour > 24 || minute > 59 || (hour == 24 && minute > 0) {
+        return Err(invalid());
+    }
+
+    Ok(hour * 60 + minute)
+}
+
+/// `parse_weekday` parses the name of a day of the week, e.g. `mon` or
+/// `Monday`.
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    let day = day.to_ascii_lowercase();
+
+    WEEKDAYS
+        .iter()
+        .find(|(name, _)| day.starts_with(name))
+        .map(|(_, weekday)| *weekday)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schedule(start: &str, end: &str, days: Option<Vec<&str>>) -> ScheduleConfig {
+        ScheduleConfig {
+            route: "/".to_owned(),
+            start: start.to_owned(),
+            end: end.to_owned(),
+            days: days.map(|days| days.into_iter().map(str::to_owned).collect()),
+            file: Some("closed.html".to_owned()),
+            ..ScheduleConfig::default()
+        }
+    }
+
+    /// `at` is the given time on a day in the week of Monday 2024-01-01.
+    fn at(day: i8, hour: i8, minute: i8) -> Zoned {
+        jiff::civil::date(2024, 1, day)
+            .at(hour, minute, 0, 0)
+            .to_zoned(TimeZone::UTC)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("09:30"), Ok(570));
+        assert_eq!(parse_time("24:00"), Ok(1440));
+        assert!(parse_time("9").is_err());
+        assert!(parse_time("12:60").is_err());
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_schedules() {
+        let mut config = schedule("09:00", "17:00", Some(vec!["someday"]));
+        assert!(Schedule::new(&config).is_err());
+
+        config.days = None;
+        config.static_dir = Some("./closed".to_owned());
+        assert!(Schedule::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_is_active() {
+        let schedule =
+            Schedule::new(&schedule("09:00", "17:00", Some(vec!["mon", "Friday"]))).unwrap();
+
+        assert!(schedule.is_active(&at(1, 9, 0)));
+        assert!(!schedule.is_active(&at(1, 17, 0)));
+        assert!(!schedule.is_active(&at(2, 12, 0)));
+        assert!(schedule.is_active(&at(5, 16, 59)));
+    }
+
+    #[test]
+    fn test_is_active_overnight() {
+        let schedule = Schedule::new(&schedule("17:00", "09:00", Some(vec!["fri"]))).unwrap();
+
+        assert!(schedule.is_active(&at(5, 18, 0)));
+        assert!(schedule.is_active(&at(6, 8, 59)));
+        assert!(!schedule.is_active(&at(6, 18, 0)));
+        assert!(!schedule.is_active(&at(5, 8, 0)));
+    }
+
+    #[test]
+    fn test_active_at_prefers_longest_route() {
+        let mut shop = schedule("00:00", "24:00", None);
+        shop.route = "/shop".to_owned();
+        let schedules = Schedules {
+            time_zone: TimeZone::UTC,
+            schedules: vec![
+                Schedule::new(&schedule("00:00", "24:00", None)).unwrap(),
+                Schedule::new(&shop).unwrap(),
+            ],
+        };
+
+        assert_eq!(
+            schedules
+                .active_at("/shop/cart", &at(1, 12, 0))
+                .map(|schedule| schedule.route.as_str()),
+            Some("/shop")
+        );
+        assert_eq!(
+            schedules
+                .active_at("/about", &at(1, 12, 0))
+                .map(|schedule| schedule.route.as_str()),
+            Some("/")
+        );
+    }
+}
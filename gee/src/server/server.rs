@@ -1,10 +1,17 @@
-use std::net::SocketAddr;
+use std::{sync::Arc, time::Duration};
 
 use hyper::Server as HyperServer;
-use log::info;
+use log::{error, info, warn};
 
+use super::control;
+use super::diagnostics;
+use super::response_cache;
 use super::service_builder::ServiceBuilder;
+use super::state::State;
 use crate::config::Config;
+use crate::handlers::{check_applications, Mount};
+use crate::logs::LOG_BUFFER;
+use crate::version::VersionInfo;
 
 /// Server is a wrapper around a `hyper::Server` that allows configuration of
 /// the Gee server.
@@ -12,32 +19,114 @@ pub struct Server {
     /// `config` is the global immutable configuration for the Gee server used
     /// to properly construct the server and the processes it spawns.
     config: Config,
-
-    /// `server` is the `hyper::Server` that will be used to serve requests.
-    server: HyperServer<I, S>,
 }
 
 impl Server {
     /// `new` creates a new `Server` instance using a config object.
     pub fn new(config: Config) -> Self {
-        let address = SocketAddr::new(config.address, config.port);
-
-        let server = HyperServer::bind(&address).serve(ServiceBuilder {
-            config: config.clone(),
-        });
-
-        Self { config, server }
+        Self { config }
     }
 
-    /// `start` starts the server.
+    /// `start` binds the server to the configured address and serves requests
+    /// until the server stops.
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.config.application.is_some() && self.config.application_name.is_some() {
+        control::record_launch();
+        let applications = self.config.all_applications();
+        info!(
+            "Starting gee {}",
+            VersionInfo::current(!applications.is_empty()).to_json()
+        );
+
+        if self.config.uses_legacy_application() {
+            warn!("`application` and `application_name` are deprecated. Run `gee config migrate` to move them into `[[applications]]`.");
+        }
+
+        check_applications(&applications)?;
+        if !applications.is_empty() {
             pyo3::prepare_freethreaded_python();
         }
 
-        self.server.await?;
+        let mut mounts = Vec::new();
+        for application in applications.iter() {
+            let mount = Mount::load(application, &self.config.root_dir)?;
+            info!(
+                "Mounted {}:{} at {}",
+                application.path, application.name, mount.route
+            );
+            mounts.push(Arc::new(mount));
+        }
+
+        let state = Arc::new(State::new(self.config.clone(), mounts)?);
+
+        if let Some(precompress) = state.precompress.clone().filter(|p| p.generate) {
+            tokio::task::spawn_blocking(move || precompress.generate_all());
+        }
+
+        if applications
+            .iter()
+            .any(|application| application.suspend_after.is_some())
+        {
+            suspend_idle_applications(state.clone());
+        }
+
+        if !state.static_health.is_empty() {
+            state.static_health.clone().watch();
+        }
+
+        if !state.response_caches.is_empty() {
+            response_cache::sweep(state.response_caches.clone());
+        }
+
+        if let Some(capacity) = self.config.log_buffer {
+            LOG_BUFFER.set_capacity(capacity);
+        }
+
+        if let Some(dir) = self.config.diagnostics_dir_path() {
+            diagnostics::install(dir, &state);
+        }
+
+        if let Some(path) = self.config.control_socket_path() {
+            control::listen(&path, state.clone())?;
+        }
+
+        let server =
+            HyperServer::try_bind(&self.config.socket_address())?.serve(ServiceBuilder { state });
 
         info!("Gee server running at {}", self.config.socket_address());
+        server.await?;
+
         Ok(())
     }
 }
+
+/// `SUSPEND_CHECK_INTERVAL` is how often the applications are checked for
+/// having gone idle.
+const SUSPEND_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `suspend_idle_applications` suspends the applications which have gone
+/// without requests for longer than their `suspend_after`, checking the
+/// current mounts every `SUSPEND_CHECK_INTERVAL` until the server stops.
+fn suspend_idle_applications(state: Arc<State>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SUSPEND_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mounts: Vec<Arc<Mount>> = state
+                .mounts
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|mount| mount.suspend_after.is_some())
+                .cloned()
+                .collect();
+            let _ = tokio::task::spawn_blocking(move || {
+                for mount in mounts {
+                    if let Err(e) = mount.suspend_if_idle() {
+                        error!("Cannot suspend the application at {}: {}", mount.route, e);
+                    }
+                }
+            })
+            .await;
+        }
+    });
+}
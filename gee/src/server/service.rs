@@ -1,88 +1,964 @@
-use crate::handlers::static_service_handler;
-use hyper::{service::Service as HyperService, Body, Request, Response};
-use log::{debug, info};
+use hyper::{
+    body::HttpBody, header::CONTENT_TYPE, service::Service as HyperService, Body, Method, Request,
+    Response, StatusCode,
+};
+use log::{debug, info, warn};
 use std::{
-    future::{self, Future},
+    future::Future,
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
-use crate::config::Config;
+use super::access_log::Entry;
+use super::asset_manifest::cache_forever;
+use super::capabilities::static_options_response;
+use super::debug_dump::DebugDump;
+use super::digest_auth::DigestAuth;
+use super::error_pages::PageContext;
+use super::faults::Faults;
+use super::geo::check_geo;
+use super::host::validate_host;
+use super::limits::check_limits;
+use super::methods::RouteMethods;
+use super::mirror::Mirror;
+use super::negotiation::negotiate;
+use super::openapi::OpenApi;
+use super::response_cache::ResponseCache;
+use super::schedule::Target;
+use super::signed_url::check_signature;
+use super::state::State;
+use super::throttle::Throttle;
+use super::transform::Transform;
+use crate::client::ClientInfo;
+use crate::config::{is_under, WafMode};
+use crate::context::RequestContext;
+use crate::handlers::{
+    has_precondition, inspect_body, python_service_handler, static_service_handler,
+    well_known_handler, ChecksumInspector, Inspector, Mount,
+};
+use crate::trace::ensure_trace_context;
 
 /// `Service` handles the requests received by Gee, routing them to the correct
 /// handler based on the request path. These handlers could be static file
 /// handlers or Python handlers which pass the request to a callable.
+#[derive(Clone)]
 pub struct Service {
-    /// `config` is the global, immutable configuration used to construct and
-    /// run the Gee server.
-    config: Config,
+    /// `state` is shared by every `Service` the Gee server creates.
+    state: Arc<State>,
+
+    /// `remote_addr` is the address of the client the requests are received
+    /// from, if known.
+    remote_addr: Option<SocketAddr>,
+}
+
+/// `Route` is the handler a request has been routed to.
+enum Route {
+    /// `Static` requests are served from the resolved path, if there is one.
+    Static(Option<PathBuf>),
+
+    /// `Application` requests are passed to the mounted Python application.
+    Application(Arc<Mount>),
+
+    /// `NotFound` requests fall under no route.
+    NotFound,
 }
 
 impl Service {
-    fn get_handler(
-        &self,
-        path: &str,
-    ) -> Option<impl Fn(Request<Body>, Config) -> Future<Response<Body>>> {
-        if self.config.is_static_path(path) {
-            Some(static_service_handler)
+    /// `new` creates a new `Service` instance for the requests received from
+    /// `remote_addr`.
+    pub fn new(state: Arc<State>, remote_addr: Option<SocketAddr>) -> Self {
+        Self { state, remote_addr }
+    }
+
+    /// `route` picks the handler for a request path. A schedule active for
+    /// the path takes precedence; otherwise the static route or application
+    /// mounted at the longest prefix of the path is chosen, with static routes
+    /// winning ties.
+    fn route(&self, path: &str) -> Route {
+        if let Some(schedule) = self.state.schedules.active(path) {
+            let root_dir = Path::new(&self.state.config.root_dir);
+            return Route::Static(match &schedule.target {
+                Target::File(file) => Some(root_dir.join(file)),
+                Target::StaticDir(dir) => {
+                    join_static_path(root_dir, dir, &path[schedule.route.len()..])
+                }
+            });
         }
 
-        None
+        match (self.state.config.static_route(path), self.mount(path)) {
+            (Some((route, _)), Some(mount)) if mount.route.len() > route.len() => {
+                Route::Application(mount)
+            }
+            (Some(_), _) => Route::Static(self.resolve_static_path(path)),
+            (None, Some(mount)) => Route::Application(mount),
+            (None, None) => Route::NotFound,
+        }
+    }
+
+    /// `mount` returns the application mounted at the most specific route a
+    /// request path is under, if any.
+    fn mount(&self, path: &str) -> Option<Arc<Mount>> {
+        self.state
+            .mounts
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|mount| is_under(path, &mount.route))
+            .max_by_key(|mount| mount.route.len())
+            .cloned()
+    }
+
+    /// `matched_route` returns the route a request path falls under, picked
+    /// as `route` picks its handler, to describe the request by.
+    fn matched_route(&self, path: &str) -> Option<String> {
+        if let Some(schedule) = self.state.schedules.active(path) {
+            return Some(schedule.route.clone());
+        }
+
+        match (self.state.config.static_route(path), self.mount(path)) {
+            (Some((route, _)), Some(mount)) if mount.route.len() > route.len() => {
+                Some(mount.route.clone())
+            }
+            (Some((route, _)), _) => Some(route.to_owned()),
+            (None, mount) => mount.map(|mount| mount.route.clone()),
+        }
+    }
+
+    /// `context` works out the context of a request: who sent it, where to,
+    /// the route it falls under, and when it must be answered by.
+    fn context(&self, req: &Request<Body>) -> RequestContext {
+        let mut client =
+            ClientInfo::from_request(req, self.remote_addr, &self.state.trusted_proxies);
+        if let (Some(geoip), Some(ip)) = (&self.state.geoip, client.client_ip) {
+            client.country = geoip.country(ip);
+            client.asn = geoip.asn(ip);
+        }
+
+        let path = req.uri().path();
+        RequestContext::new(req, client, self.matched_route(path))
+            .with_timeout(self.state.config.handler_timeout(path))
     }
 
     /// `resolve_static_path` receives the `path` from the URI (e.g. /static/hello.txt) and checks it against the
     /// `static_routes` defined on the service. These `static_routes` map URI paths to UNIX-like paths (e.g.
     /// /static => ./static/). If there exists a key in `static_routes` which begins with the same characters
     /// as the `path`, the key will be stripped from the beginning of the `path` and replaced with corresponding
-    /// value so that the server can look up the file and serve it to the user. If the resulting `path` is a directory,
-    /// `index.html` will be appended to the path so that the default web page may be served.
-    fn resolve_static_path(&self, path: &str) -> Option<String> {
-        let matching_route = self
-            .static_routes
-            .iter()
-            .filter(|(server_path, _)| path.starts_with(*server_path))
-            .next();
+    /// value so that the server can look up the file and serve it to the user.
+    /// A fingerprinted path from an asset manifest resolves to its file.
+    fn resolve_static_path(&self, path: &str) -> Option<PathBuf> {
+        if let Some(asset) = self.asset(path) {
+            return Some(asset.to_owned());
+        }
 
-        let static_route = match matching_route {
-            Some(static_route) => static_route,
-            None => return None,
-        };
+        let static_route = self.state.config.static_route(path)?;
+
+        join_static_path(
+            Path::new(&self.state.config.root_dir),
+            static_route.1,
+            &path[static_route.0.len()..],
+        )
+    }
 
-        let mut static_path = static_route.1.clone();
-        static_path.push_str(&path[static_route.0.len()..path.len()]);
+    /// `asset` returns the file a fingerprinted path from an asset manifest
+    /// is served from, if it is one.
+    fn asset(&self, path: &str) -> Option<&Path> {
+        self.state
+            .asset_manifests
+            .iter()
+            .find_map(|asset_manifest| asset_manifest.asset(path))
+    }
 
-        if static_path.chars().last().unwrap() == '/' {
-            static_path.push_str("index.html")
+    /// `inspectors` returns the inspectors the body of a request is shown to
+    /// as it streams to its handler.
+    fn inspectors(&self, req: &Request<Body>, context: &RequestContext) -> Vec<Box<dyn Inspector>> {
+        let mut inspectors: Vec<Box<dyn Inspector>> = Vec::new();
+        let path = req.uri().path();
+        if self.state.config.debug_dump(path) {
+            let limit = self.state.config.inspect_limit(path);
+            inspectors.push(Box::new(DebugDump::new(req, &context.request_id, limit)));
         }
+        if self.state.config.verify_checksums == Some(true) {
+            if let Some(inspector) = ChecksumInspector::new(req.headers()) {
+                inspectors.push(Box::new(inspector));
+            }
+        }
+
+        inspectors
+    }
+
+    /// `methods` returns the methods accepted under the route configured for a
+    /// path, if they are constrained.
+    fn methods(&self, path: &str) -> Option<&RouteMethods> {
+        let route = self.state.config.route_config(path)?;
+
+        self.state
+            .methods
+            .iter()
+            .find(|methods| methods.route == route.route)
+    }
+
+    /// `digest_auth` returns the digest authentication of the most specific
+    /// route protecting a path, if any.
+    fn digest_auth(&self, path: &str) -> Option<&DigestAuth> {
+        let route = self
+            .state
+            .config
+            .route_config_with(path, |route| route.digest_auth.is_some())?;
 
-        Some(static_path)
+        self.state
+            .digest_auths
+            .iter()
+            .find(|digest_auth| digest_auth.route == route.route)
     }
+
+    /// `openapi` returns the OpenAPI document of the most specific route
+    /// validating a path, if any.
+    fn openapi(&self, path: &str) -> Option<&OpenApi> {
+        let route = self
+            .state
+            .config
+            .route_config_with(path, |route| route.openapi.is_some())?;
+
+        self.state
+            .openapis
+            .iter()
+            .find(|openapi| openapi.route == route.route)
+    }
+
+    /// `mirror` returns the mirror of the route configured for a path, if it
+    /// has one.
+    fn mirror(&self, path: &str) -> Option<Arc<Mirror>> {
+        let route = self.state.config.route_config(path)?;
+
+        self.state
+            .mirrors
+            .iter()
+            .find(|mirror| mirror.route == route.route)
+            .cloned()
+    }
+
+    /// `transform` returns the transform of the route configured for a path,
+    /// if it has one.
+    fn transform(&self, path: &str) -> Option<Arc<Transform>> {
+        let route = self.state.config.route_config(path)?;
+
+        self.state
+            .transforms
+            .iter()
+            .find(|transform| transform.route == route.route)
+            .cloned()
+    }
+
+    /// `throttle` returns the throttle of the route configured for a path, if
+    /// it has one.
+    fn throttle(&self, path: &str) -> Option<Arc<Throttle>> {
+        let route = self.state.config.route_config(path)?;
+
+        self.state
+            .throttles
+            .iter()
+            .find(|throttle| throttle.route == route.route)
+            .cloned()
+    }
+
+    /// `response_cache` returns the response cache of the route configured for
+    /// a path, if it has one.
+    fn response_cache(&self, path: &str) -> Option<Arc<ResponseCache>> {
+        let route = self.state.config.route_config(path)?;
+
+        self.state
+            .response_caches
+            .iter()
+            .find(|response_cache| response_cache.route == route.route)
+            .cloned()
+    }
+
+    /// `faults` returns the faults of the route configured for a path, if it
+    /// has enabled ones.
+    fn faults(&self, path: &str) -> Option<Arc<Faults>> {
+        let route = self.state.config.route_config(path)?;
+
+        self.state
+            .faults
+            .iter()
+            .find(|faults| faults.route == route.route && faults.is_enabled())
+            .cloned()
+    }
+}
+
+/// `is_static_file` returns whether a static route serves a file that exists on
+/// disk at `static_path`, in which case it is served in place of any file
+/// synthesized from the `[well_known]` config.
+async fn is_static_file(static_path: Option<PathBuf>) -> bool {
+    match static_path {
+        Some(static_path) => tokio::fs::metadata(static_path)
+            .await
+            .is_ok_and(|metadata| metadata.is_file()),
+        None => false,
+    }
+}
+
+/// `join_static_path` joins the `remainder` of a request path to the directory
+/// it is served from. If the result is a directory, `index.html` is appended
+/// so that the default web page may be served. Relative directories are
+/// resolved against the `root_dir`, and remainders which attempt to climb out
+/// of the directory are refused.
+fn join_static_path(root_dir: &Path, dir: &str, remainder: &str) -> Option<PathBuf> {
+    if Path::new(remainder)
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return None;
+    }
+
+    let mut static_path = dir.to_owned();
+    static_path.push_str(remainder);
+
+    if static_path.ends_with('/') {
+        static_path.push_str("index.html")
+    }
+
+    Some(root_dir.join(static_path))
 }
 
+/// `ResponseFuture` resolves to the response to a request.
+type ResponseFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, hyper::Error>> + Send>>;
+
 impl HyperService<Request<Body>> for Service {
     type Response = Response<Body>;
     type Error = hyper::Error;
-    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+    type Future = ResponseFuture;
 
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Ok(()).into()
     }
 
     /// `call` receives a request from the caller and routes it to the correct
-    /// handler then returns the response to the caller.
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
-        info!("{} request received at {}", req.method(), req.uri());
+    /// handler then returns the response to the caller, logging it once it
+    /// has been answered.
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        ensure_trace_context(req.headers_mut());
+        let context = Arc::new(self.context(&req));
+        req.extensions_mut().insert(context.clone());
+
+        let entry = if self.state.access_logs.is_empty() {
+            None
+        } else {
+            Some(Entry::from_request(&req, context.clone()))
+        };
+        let page_context = if self.state.error_pages.is_empty() {
+            None
+        } else {
+            PageContext::from_request(&req, &context)
+        };
+        let (req, tracked) = match &self.state.traffic {
+            Some(traffic) => {
+                let (req, tracked) = traffic.track(req, &context);
+                (req, Some(tracked))
+            }
+            None => (req, None),
+        };
+        let response = self.respond(req, &context);
+
+        let response: ResponseFuture = match page_context {
+            None => response,
+            Some(context) => {
+                let state = self.state.clone();
+                Box::pin(async move {
+                    let response = response.await?;
+                    Ok(state.error_pages.apply(response, &context))
+                })
+            }
+        };
+
+        let response: ResponseFuture = match entry {
+            None => response,
+            Some(entry) => {
+                let state = self.state.clone();
+                Box::pin(async move {
+                    let response = response.await?;
+                    let access_log = state
+                        .access_logs
+                        .iter()
+                        .find(|access_log| access_log.matches(&entry));
+                    if let Some(access_log) = access_log {
+                        access_log.log(
+                            &entry,
+                            response.status(),
+                            response.body().size_hint().exact(),
+                        );
+                    }
+                    Ok(response)
+                })
+            }
+        };
+
+        match tracked {
+            None => response,
+            Some(tracked) => Box::pin(async move { Ok(tracked.response(response.await?)) }),
+        }
+    }
+}
+
+impl Service {
+    /// `respond` validates a request and routes it to the correct handler.
+    /// The `context` of the request is also attached to it as an extension.
+    fn respond(&self, req: Request<Body>, context: &RequestContext) -> ResponseFuture {
+        info!(
+            "{} request received at {} ({})",
+            req.method(),
+            req.uri(),
+            context.request_id
+        );
         debug!("{:#?}", req);
 
-        let handler = self.get_handler(&req.uri().path());
-        let response = handler(req, self.config.clone());
+        let client = &context.client;
+        if let Err(status) = check_geo(client, &self.state.config.geo_rules(req.uri().path())) {
+            warn!(
+                "Refused request to {} from {:?} in {:?}",
+                req.uri(),
+                client.client_ip,
+                client.country
+            );
+            return Box::pin(async move { Ok(status_response(status)) });
+        }
 
-        future::ready(Ok(response))
+        if let Err(error) = validate_host(&req, self.state.config.allowed_hosts.as_deref()) {
+            warn!("Rejected request to {}: {:?} host", req.uri(), error);
+            return Box::pin(async move { Ok(error.response()) });
+        }
+
+        if let Some(waf) = &self.state.waf {
+            if let Some(rule) = waf.check(&req) {
+                let client_ip = client.client_ip;
+                if waf.mode == WafMode::Enforce {
+                    warn!(
+                        "WAF refused request to {} from {:?}: {}",
+                        req.uri(),
+                        client_ip,
+                        rule
+                    );
+                    self.state.metrics.waf_blocked.inc();
+                    let status = waf.status;
+                    return Box::pin(async move { Ok(status_response(status)) });
+                }
+                warn!(
+                    "WAF matched request to {} from {:?}: {}",
+                    req.uri(),
+                    client_ip,
+                    rule
+                );
+                self.state.metrics.waf_logged.inc();
+            }
+        }
+
+        if let Err(status) = check_limits(&req, &self.state.config.limits(req.uri().path())) {
+            warn!("Rejected request to {}: {}", req.uri(), status);
+            return Box::pin(async move { Ok(status_response(status)) });
+        }
+
+        if let Some(status) = self.state.disabled_routes.status(req.uri().path()) {
+            debug!("Route for {} is disabled", req.uri());
+            return Box::pin(async move { Ok(status_response(status)) });
+        }
+
+        let methods = self.methods(req.uri().path());
+        if let Some(methods) = methods.filter(|methods| !methods.allows(req.method())) {
+            warn!(
+                "Refused {} request to {}: only {} accepted",
+                req.method(),
+                req.uri(),
+                methods.allow()
+            );
+            let response = methods.refusal();
+            return Box::pin(async { Ok(response) });
+        }
+
+        if let Some(digest_auth) = self.digest_auth(req.uri().path()) {
+            match digest_auth.check(&req) {
+                Ok(user) => debug!("Authenticated {} as {}", req.uri(), user),
+                Err(refusal) => {
+                    let response = digest_auth.challenge(refusal);
+                    return Box::pin(async { Ok(response) });
+                }
+            }
+        }
+
+        if let Some(openapi) = self.openapi(req.uri().path()) {
+            if let Err(violation) = openapi.validate(&req) {
+                warn!(
+                    "Rejected {} request to {}: {:?}",
+                    req.method(),
+                    req.uri(),
+                    violation
+                );
+                let response = violation.response();
+                return Box::pin(async { Ok(response) });
+            }
+        }
+
+        if self
+            .state
+            .config
+            .requires_precondition(req.method().as_str())
+            && !has_precondition(req.headers())
+        {
+            warn!(
+                "Rejected {} request to {} without a precondition",
+                req.method(),
+                req.uri()
+            );
+            return Box::pin(async { Ok(status_response(StatusCode::PRECONDITION_REQUIRED)) });
+        }
+
+        if req.method() == Method::OPTIONS && req.uri() == "*" {
+            let response = self.state.capabilities.options_response();
+            return Box::pin(async { Ok(response) });
+        }
+
+        if self.state.config.capabilities_path.as_deref() == Some(req.uri().path()) {
+            let response = self.state.capabilities.json_response();
+            return Box::pin(async { Ok(response) });
+        }
+
+        if self.state.config.metrics_path.as_deref() == Some(req.uri().path()) {
+            let mut metrics = self.state.metrics.render();
+            if let Some(traffic) = &self.state.traffic {
+                traffic.render(&mut metrics);
+            }
+            return Box::pin(async move {
+                Ok(Response::builder()
+                    .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(Body::from(metrics))
+                    .unwrap())
+            });
+        }
+
+        if self.state.config.readiness_path.as_deref() == Some(req.uri().path()) {
+            let response = self.state.static_health.readiness_response();
+            return Box::pin(async { Ok(response) });
+        }
+
+        if let Some(asset_manifest) = self
+            .state
+            .asset_manifests
+            .iter()
+            .find(|asset_manifest| asset_manifest.path == req.uri().path())
+        {
+            let response = asset_manifest.response();
+            return Box::pin(async { Ok(response) });
+        }
+
+        if let Some(content) = self.state.well_known.document(req.uri().path()) {
+            let content = content.to_owned();
+            let static_path = self.resolve_static_path(req.uri().path());
+            let service = self.clone();
+            let context = context.clone();
+            return Box::pin(async move {
+                if !is_static_file(static_path).await {
+                    return Ok(well_known_handler(req, &content));
+                }
+                service.dispatch(req, &context).await
+            });
+        }
+
+        self.dispatch(req, context)
+    }
+
+    /// `dispatch` routes a request which passed every check to its handler.
+    fn dispatch(&self, req: Request<Body>, context: &RequestContext) -> ResponseFuture {
+        let methods = self.methods(req.uri().path());
+        let mut route = self.route(req.uri().path());
+        if let (Route::Static(_), &Method::OPTIONS) = (&route, req.method()) {
+            let response = static_options_response(methods.map(RouteMethods::allow));
+            return Box::pin(async { Ok(response) });
+        }
+        if let (Route::Static(_), Some(secret)) =
+            (&route, self.state.config.signing_secret(req.uri().path()))
+        {
+            if let Err(status) = check_signature(req.uri(), secret) {
+                warn!(
+                    "Refused request to {} without a valid signature",
+                    req.uri().path()
+                );
+                return Box::pin(async move { Ok(status_response(status)) });
+            }
+        }
+
+        let static_route = match &route {
+            Route::Static(_) => self
+                .state
+                .config
+                .static_route(req.uri().path())
+                .map(|(route, _)| route.to_owned()),
+            _ => None,
+        };
+        if let Some(response) = static_route
+            .as_deref()
+            .and_then(|route| self.state.static_health.unavailable(route))
+        {
+            return Box::pin(async { Ok(response) });
+        }
+
+        let fingerprinted =
+            matches!(route, Route::Static(_)) && self.asset(req.uri().path()).is_some();
+
+        let inspectors = self.inspectors(&req, context);
+        let req = inspect_body(req, inspectors);
+
+        let config = self.state.config.clone();
+        let workers = self.state.workers.clone();
+        let mirror = self
+            .mirror(req.uri().path())
+            .filter(|mirror| mirror.is_sampled());
+        let transform = self
+            .transform(req.uri().path())
+            .filter(|_| req.method() != Method::HEAD);
+        let throttle = self.throttle(req.uri().path());
+        let response_cache = self
+            .response_cache(req.uri().path())
+            .and_then(|response_cache| Some((response_cache, ResponseCache::key(&req)?)));
+        let faults = self.faults(req.uri().path());
+        let state = self.state.clone();
+        let static_health = self.state.static_health.clone();
+        let file_cache = self.state.file_cache.clone();
+        let precompress = self.state.precompress.clone();
+
+        Box::pin(async move {
+            if let Some(fault) = match faults {
+                Some(faults) => faults.inject().await,
+                None => None,
+            } {
+                return Ok(fault.response());
+            }
+
+            let cached = match &response_cache {
+                Some((response_cache, key)) => response_cache.lookup(key).await,
+                None => None,
+            };
+            if let Some(response) = cached {
+                debug!("Answered {} from the cache", req.uri());
+                return Ok(match throttle {
+                    Some(throttle) => throttle.apply(response),
+                    None => response,
+                });
+            }
+
+            let req = match mirror {
+                Some(mirror) => {
+                    let limit = config.limits(req.uri().path()).max_body_size;
+                    match mirror.tee(req, limit, state).await {
+                        Ok(req) => req,
+                        Err(status) => return Ok(status_response(status)),
+                    }
+                }
+                None => req,
+            };
+
+            let variant = match (&mut route, config.negotiation(req.uri().path())) {
+                (Route::Static(Some(static_path)), Some(negotiation)) if !fingerprinted => {
+                    let (path, variant) =
+                        negotiate(std::mem::take(static_path), req.headers(), negotiation).await;
+                    *static_path = path;
+                    Some(variant)
+                }
+                _ => None,
+            };
+            let compressed = match (&mut route, precompress) {
+                (Route::Static(Some(static_path)), Some(precompress)) => {
+                    let (path, compressed) = precompress
+                        .select(std::mem::take(static_path), req.headers())
+                        .await;
+                    *static_path = path;
+                    compressed
+                }
+                _ => None,
+            };
+
+            let response = match route {
+                Route::Static(Some(static_path)) => {
+                    let digests = config.static_digests(req.uri().path());
+                    static_service_handler(req, static_path, digests, file_cache).await
+                }
+                Route::Application(mount) => {
+                    python_service_handler(req, mount, workers, config).await
+                }
+                Route::Static(None) | Route::NotFound => status_response(StatusCode::NOT_FOUND),
+            };
+            let response = match static_route {
+                Some(route) if response.status() == StatusCode::NOT_FOUND => {
+                    tokio::task::spawn_blocking(move || static_health.recheck(&route))
+                        .await
+                        .ok()
+                        .flatten()
+                        .unwrap_or(response)
+                }
+                _ => response,
+            };
+            let response = match variant {
+                Some(variant) => variant.apply(response),
+                None => response,
+            };
+            let response = match compressed {
+                Some(compressed) => compressed.apply(response),
+                None => response,
+            };
+            let response = if fingerprinted {
+                cache_forever(response)
+            } else {
+                response
+            };
+
+            let response = match transform {
+                Some(transform) => transform.apply(response),
+                None => response,
+            };
+            let response = match response_cache {
+                Some((response_cache, key)) => response_cache.store(key, response),
+                None => response,
+            };
+
+            Ok(match throttle {
+                Some(throttle) => throttle.apply(response),
+                None => response,
+            })
+        })
     }
 }
 
+/// `status_response` builds an empty response with the given status.
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
 #[cfg(test)]
 mod test {
-    use crate::config::Config;
+    use crate::config::{
+        ApplicationConfig, Config, DigestAuthConfig, RouteConfig, WellKnownConfig,
+    };
+    use crate::handlers::WellKnown;
+    use crate::hashmap;
 
     use super::*;
+
+    fn new_service(config: Config) -> Service {
+        Service::new(Arc::new(State::for_tests(config)), None)
+    }
+
+    fn service(static_routes: Vec<(&str, &str)>) -> Service {
+        let mut config = Config::new_default();
+        config.static_routes = Some(
+            static_routes
+                .into_iter()
+                .map(|(route, dir)| (route.to_owned(), dir.to_owned()))
+                .collect(),
+        );
+
+        new_service(config)
+    }
+
+    #[test]
+    fn test_resolve_static_path() {
+        let service = service(vec![("/static", "./static/")]);
+
+        assert_eq!(
+            service.resolve_static_path("/static/hello.txt"),
+            Some(PathBuf::from("./static/hello.txt"))
+        );
+        assert_eq!(
+            service.resolve_static_path("/static/"),
+            Some(PathBuf::from("./static/index.html"))
+        );
+        assert_eq!(service.resolve_static_path("/other"), None);
+        assert_eq!(service.resolve_static_path("/static_backup/x"), None);
+    }
+
+    #[test]
+    fn test_resolve_static_path_prefers_longest_route() {
+        let mut config = Config::new_default();
+        config.static_routes = Some(hashmap![
+            "/".to_owned() => "./site/".to_owned(),
+            "/static".to_owned() => "./static".to_owned()
+        ]);
+        let service = new_service(config);
+
+        assert_eq!(
+            service.resolve_static_path("/static/app.js"),
+            Some(PathBuf::from("./static/app.js"))
+        );
+        assert_eq!(
+            service.resolve_static_path("/about.html"),
+            Some(PathBuf::from("./site/about.html"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_static_path_refuses_parent_dir() {
+        let service = service(vec![("/", "./")]);
+
+        assert_eq!(service.resolve_static_path("/../etc/passwd"), None);
+        assert_eq!(service.resolve_static_path("/a/../../etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_mount_matches_whole_segments() {
+        pyo3::prepare_freethreaded_python();
+        let application = ApplicationConfig {
+            path: "src/fixtures/app/app.py".to_owned(),
+            name: "simple_app".to_owned(),
+            route: Some("/api".to_owned()),
+            ..Default::default()
+        };
+        let service = new_service(Config::new_default());
+        service
+            .state
+            .mounts
+            .write()
+            .unwrap()
+            .push(Arc::new(Mount::load(&application, ".").unwrap()));
+
+        assert!(service.mount("/api").is_some());
+        assert!(service.mount("/api/users").is_some());
+        assert!(service.mount("/apiary").is_none());
+        assert_eq!(service.matched_route("/apiary"), None);
+    }
+
+    #[tokio::test]
+    async fn test_call_protects_nested_routes() {
+        let mut config = Config::new_default();
+        config.routes = Some(vec![
+            RouteConfig {
+                route: "/admin".to_owned(),
+                digest_auth: Some(DigestAuthConfig {
+                    realm: "admin".to_owned(),
+                    ..DigestAuthConfig::default()
+                }),
+                ..RouteConfig::default()
+            },
+            RouteConfig {
+                route: "/admin/uploads".to_owned(),
+                max_body_size: Some(1024),
+                ..RouteConfig::default()
+            },
+        ]);
+        let mut state = State::for_tests(config.clone());
+        for route in config.routes.iter().flatten() {
+            state.digest_auths.extend(DigestAuth::new(route).unwrap());
+        }
+        let mut service = Service::new(Arc::new(state), None);
+
+        for (path, status) in [
+            ("/admin", StatusCode::UNAUTHORIZED),
+            ("/admin/uploads/a.png", StatusCode::UNAUTHORIZED),
+            ("/adminx", StatusCode::NOT_FOUND),
+        ] {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let response = service.call(req).await.unwrap();
+            assert_eq!(response.status(), status, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_rejects_disallowed_host() {
+        let mut config = Config::new_default();
+        config.allowed_hosts = Some(vec!["example.com".to_owned()]);
+        let mut service = new_service(config);
+
+        let req = Request::builder()
+            .uri("/static/")
+            .header("Host", "evil.com")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::MISDIRECTED_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_call_requires_precondition() {
+        let mut config = Config::new_default();
+        config.require_preconditions = Some(vec!["put".to_owned()]);
+        let mut service = new_service(config);
+
+        let req = Request::builder()
+            .method("PUT")
+            .uri("/static/hello.txt")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+        let req = Request::builder()
+            .method("PUT")
+            .uri("/nowhere")
+            .header("If-Match", "\"abc\"")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_call_answers_options() {
+        let mut service = new_service(Config::new_default());
+
+        let req = Request::builder()
+            .method("OPTIONS")
+            .uri("*")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["Allow"], "GET, HEAD, OPTIONS");
+
+        let req = Request::builder()
+            .method("OPTIONS")
+            .uri("/static/hello.txt")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_call_serves_well_known_unless_file_exists() {
+        let root_dir = std::env::temp_dir().join(format!("gee-well-known-{}", std::process::id()));
+        std::fs::create_dir_all(&root_dir).unwrap();
+
+        let mut config = Config::new_default();
+        config.root_dir = root_dir.to_string_lossy().into_owned();
+        config.static_routes = Some(hashmap!["/".to_owned() => "./".to_owned()]);
+        let mut service = new_service(config);
+        Arc::get_mut(&mut service.state).unwrap().well_known = WellKnown::new(&WellKnownConfig {
+            sitemaps: Some(vec!["https://example.com/sitemap.xml".to_owned()]),
+            ..WellKnownConfig::default()
+        })
+        .unwrap();
+
+        let req = Request::builder()
+            .uri("/robots.txt")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"Sitemap: https://example.com/sitemap.xml\n");
+
+        std::fs::write(root_dir.join("robots.txt"), "User-agent: *\n").unwrap();
+        let req = Request::builder()
+            .uri("/robots.txt")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"User-agent: *\n");
+
+        std::fs::remove_dir_all(&root_dir).unwrap();
+    }
 }
@@ -1,19 +1,21 @@
 use std::{
     future,
+    sync::Arc,
     task::{Context, Poll},
 };
 
-use hyper::service::Service as HyperService;
+use hyper::{server::conn::AddrStream, service::Service as HyperService};
 
 use super::service::Service;
-use crate::Config;
+use super::state::State;
 
-/// `ServiceBuilder` creates a new instance of `Service` based on given config.
+/// `ServiceBuilder` creates a new instance of `Service` for each connection
+/// the Gee server accepts.
 pub struct ServiceBuilder {
-    pub config: Config,
+    pub state: Arc<State>,
 }
 
-impl<T> HyperService<T> for ServiceBuilder {
+impl HyperService<&AddrStream> for ServiceBuilder {
     type Response = Service;
     type Error = std::io::Error;
     type Future = future::Ready<Result<Self::Response, Self::Error>>;
@@ -22,9 +24,10 @@ impl<T> HyperService<T> for ServiceBuilder {
         Ok(()).into()
     }
 
-    fn call(&mut self, _: T) -> Self::Future {
-        future::ready(Ok(Service {
-            config: self.config.clone(),
-        }))
+    fn call(&mut self, conn: &AddrStream) -> Self::Future {
+        future::ready(Ok(Service::new(
+            self.state.clone(),
+            Some(conn.remote_addr()),
+        )))
     }
 }
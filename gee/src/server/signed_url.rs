@@ -0,0 +1,122 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use hyper::{StatusCode, Uri};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `check_signature` rejects a request with a 403 unless its query carries an
+/// `expires` Unix timestamp that has not passed and a `signature` that is the
+/// unpadded, URL-safe base64 HMAC-SHA256 of `{path}:{expires}` made with the
+/// `secret`.
+pub fn check_signature(uri: &Uri, secret: &str) -> Result<(), StatusCode> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    check_signature_at(uri, secret, now)
+}
+
+/// `check_signature_at` checks the signature of a request as if it were
+/// received at the Unix timestamp `now`.
+fn check_signature_at(uri: &Uri, secret: &str, now: u64) -> Result<(), StatusCode> {
+    let (mut expires, mut signature) = (None, None);
+    for pair in uri.query().unwrap_or_default().split('&') {
+        match pair.split_once('=') {
+            Some(("expires", value)) => expires = Some(value),
+            Some(("signature", value)) => signature = Some(value),
+            _ => {}
+        }
+    }
+
+    let (expires, signature) = match (expires, signature) {
+        (Some(expires), Some(signature)) => (expires, signature),
+        _ => return Err(StatusCode::FORBIDDEN),
+    };
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| StatusCode::FORBIDDEN)?;
+    if expires.parse::<u64>().map_err(|_| StatusCode::FORBIDDEN)? < now {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    mac(secret, uri.path(), expires)
+        .verify_slice(&signature)
+        .map_err(|_| StatusCode::FORBIDDEN)
+}
+
+/// `mac` computes the HMAC of a path and its expiry.
+fn mac(secret: &str, path: &str, expires: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(path.as_bytes());
+    mac.update(b":");
+    mac.update(expires.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECRET: &str = "s3cret";
+
+    fn signed(path: &str, expires: u64) -> Uri {
+        let signature = mac(SECRET, path, &expires.to_string())
+            .finalize()
+            .into_bytes();
+
+        format!(
+            "{}?expires={}&signature={}",
+            path,
+            expires,
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_signature_at() {
+        let uri = signed("/downloads/report.pdf", 1_700_000_000);
+
+        assert_eq!(check_signature_at(&uri, SECRET, 1_699_999_999), Ok(()));
+        assert_eq!(check_signature_at(&uri, SECRET, 1_700_000_000), Ok(()));
+        assert_eq!(
+            check_signature_at(&uri, SECRET, 1_700_000_001),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            check_signature_at(&uri, "other", 1_699_999_999),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    #[test]
+    fn test_check_signature_at_rejects_tampering() {
+        let uri = signed("/downloads/report.pdf", 1_700_000_000);
+        let query = uri.query().unwrap();
+
+        let other_path: Uri = format!("/downloads/secret.pdf?{}", query).parse().unwrap();
+        assert_eq!(
+            check_signature_at(&other_path, SECRET, 0),
+            Err(StatusCode::FORBIDDEN)
+        );
+
+        let later: Uri = format!(
+            "/downloads/report.pdf?{}",
+            query.replace("1700000000", "1800000000")
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(
+            check_signature_at(&later, SECRET, 0),
+            Err(StatusCode::FORBIDDEN)
+        );
+
+        let unsigned: Uri = "/downloads/report.pdf?expires=1700000000".parse().unwrap();
+        assert_eq!(
+            check_signature_at(&unsigned, SECRET, 0),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+}
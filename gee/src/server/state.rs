@@ -0,0 +1,294 @@
+use log::debug;
+use std::{
+    error::Error,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use super::access_log::AccessLog;
+use super::asset_manifest::AssetManifest;
+use super::capabilities::Capabilities;
+use super::digest_auth::DigestAuth;
+use super::disabled_routes::DisabledRoutes;
+use super::error_pages::ErrorPages;
+use super::faults::Faults;
+use super::methods::RouteMethods;
+use super::mirror::Mirror;
+use super::openapi::OpenApi;
+use super::precompress::Precompress;
+use super::response_cache::ResponseCache;
+use super::schedule::Schedules;
+use super::static_health::StaticHealth;
+use super::throttle::Throttle;
+use super::traffic::Traffic;
+use super::transform::Transform;
+use super::waf::Waf;
+use crate::client::TrustedProxies;
+use crate::config::Config;
+use crate::geoip::GeoIp;
+use crate::handlers::{FileCache, Mount, WellKnown, WorkerPool};
+use crate::metrics::Metrics;
+
+/// `State` is shared by every `Service` the Gee server creates.
+pub struct State {
+    /// `config` is the global, immutable configuration used to construct and
+    /// run the Gee server.
+    pub config: Config,
+
+    /// `mounts` are the Python applications requests may be routed to. They
+    /// are replaced when the applications are restarted, while requests
+    /// already routed finish with the mounts they were routed to.
+    pub mounts: RwLock<Vec<Arc<Mount>>>,
+
+    /// `workers` admits requests to the Python applications.
+    pub workers: Arc<WorkerPool>,
+
+    /// `metrics` are the measurements the Gee server exports about itself.
+    pub metrics: Arc<Metrics>,
+
+    /// `access_logs` log the requests answered, if they are configured. A
+    /// request is logged to the first matching one.
+    pub access_logs: Vec<AccessLog>,
+
+    /// `well_known` are the files synthesized from the config.
+    pub well_known: WellKnown,
+
+    /// `trusted_proxies` are the proxies whose `X-Forwarded-For` is believed.
+    pub trusted_proxies: TrustedProxies,
+
+    /// `waf` filters requests before they are routed, if it is configured.
+    pub waf: Option<Waf>,
+
+    /// `geoip` locates clients, if its databases are configured.
+    pub geoip: Option<GeoIp>,
+
+    /// `digest_auths` authenticate the requests under their routes.
+    pub digest_auths: Vec<DigestAuth>,
+
+    /// `openapis` validate the requests under their routes.
+    pub openapis: Vec<OpenApi>,
+
+    /// `mirrors` copy the requests under their routes to shadow targets.
+    pub mirrors: Vec<Arc<Mirror>>,
+
+    /// `methods` are the request methods accepted under their routes.
+    pub methods: Vec<RouteMethods>,
+
+    /// `disabled_routes` are answered with a status instead of being handled.
+    pub disabled_routes: DisabledRoutes,
+
+    /// `schedules` serve static content under routes during their windows.
+    pub schedules: Schedules,
+
+    /// `transforms` rewrite the HTML responses under their routes.
+    pub transforms: Vec<Arc<Transform>>,
+
+    /// `file_cache` keeps static files open, if it is configured.
+    pub file_cache: Option<Arc<FileCache>>,
+
+    /// `precompress` finds the compressed variants of static files, if it is
+    /// configured.
+    pub precompress: Option<Arc<Precompress>>,
+
+    /// `traffic` counts what is received and sent under each route and for
+    /// each host, if traffic accounting is enabled.
+    pub traffic: Option<Traffic>,
+
+    /// `static_health` tracks whether the directories of the static routes
+    /// can be read.
+    pub static_health: Arc<StaticHealth>,
+
+    /// `throttles` limit the bandwidth of the responses under their routes.
+    pub throttles: Vec<Arc<Throttle>>,
+
+    /// `response_caches` keep the responses under their routes.
+    pub response_caches: Vec<Arc<ResponseCache>>,
+
+    /// `faults` inject latency, errors, and dropped connections into the
+    /// requests under their routes.
+    pub faults: Vec<Arc<Faults>>,
+
+    /// `capabilities` describe the routes and features of the server.
+    pub capabilities: Capabilities,
+
+    /// `error_pages` replace the empty bodies of error responses.
+    pub error_pages: ErrorPages,
+
+    /// `asset_manifests` fingerprint the files of static routes.
+    pub asset_manifests: Vec<AssetManifest>,
+}
+
+impl State {
+    /// `new` builds the state of a server serving the config with the
+    /// applications already mounted, failing if any part of the config is
+    /// invalid.
+    pub fn new(config: Config, mounts: Vec<Arc<Mount>>) -> Result<Self, Box<dyn Error>> {
+        let metrics = Arc::new(Metrics::new());
+        let workers = Arc::new(WorkerPool::new(&config, metrics.clone())?);
+
+        let access_logs = match &config.access_log {
+            Some(access_log) => AccessLog::from_config(access_log, Path::new(&config.root_dir))?,
+            None => vec![],
+        };
+
+        let well_known = match &config.well_known {
+            Some(well_known) => WellKnown::new(well_known)?,
+            None => WellKnown::default(),
+        };
+
+        let trusted_proxies =
+            TrustedProxies::new(config.trusted_proxies.as_deref().unwrap_or_default())?;
+
+        let geoip = match &config.geoip {
+            Some(geoip) => Some(GeoIp::new(geoip, &config.root_dir)?),
+            None => None,
+        };
+
+        let waf = match &config.waf {
+            Some(waf) => Some(Waf::new(waf)?),
+            None => None,
+        };
+
+        let mut methods = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(route_methods) = RouteMethods::new(route)? {
+                methods.push(route_methods);
+            }
+        }
+
+        let mut digest_auths = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(digest_auth) = DigestAuth::new(route)? {
+                digest_auths.push(digest_auth);
+            }
+        }
+
+        let mut openapis = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(openapi) = OpenApi::new(route, &config.root_dir)? {
+                openapis.push(openapi);
+            }
+        }
+
+        let mut mirrors = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(mirror) = &route.mirror {
+                mirrors.push(Arc::new(Mirror::new(&route.route, mirror, &mounts)?));
+            }
+        }
+
+        let transforms = config
+            .routes
+            .iter()
+            .flatten()
+            .filter(|route| route.transforms.is_some())
+            .map(|route| Transform::new(route).map(Arc::new))
+            .collect::<Result<_, _>>()?;
+
+        let mut response_caches = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(response_cache) = ResponseCache::new(route, &config.root_dir)? {
+                response_caches.push(Arc::new(response_cache));
+            }
+        }
+
+        let mut throttles = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(throttle) = Throttle::new(route)? {
+                throttles.push(Arc::new(throttle));
+            }
+        }
+
+        let mut faults = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(route_faults) = Faults::new(route)? {
+                faults.push(Arc::new(route_faults));
+            }
+        }
+
+        let mut asset_manifests = Vec::new();
+        for route in config.routes.iter().flatten() {
+            if let Some(asset_manifest) = AssetManifest::new(route, &config)? {
+                debug!(
+                    "Serving the asset manifest of {} at {}",
+                    route.route, asset_manifest.path
+                );
+                asset_manifests.push(asset_manifest);
+            }
+        }
+
+        let error_pages = match &config.error_pages {
+            Some(error_pages) => ErrorPages::new(error_pages, &config.root_dir)?,
+            None => ErrorPages::default(),
+        };
+
+        Ok(Self {
+            mounts: RwLock::new(mounts),
+            workers,
+            metrics,
+            access_logs,
+            well_known,
+            trusted_proxies,
+            waf,
+            geoip,
+            methods,
+            digest_auths,
+            openapis,
+            mirrors,
+            disabled_routes: DisabledRoutes::from_config(&config)?,
+            schedules: Schedules::from_config(&config)?,
+            transforms,
+            throttles,
+            response_caches,
+            faults,
+            capabilities: Capabilities::new(&config),
+            error_pages,
+            asset_manifests,
+            precompress: Precompress::new(&config)?.map(Arc::new),
+            static_health: Arc::new(StaticHealth::new(&config)?),
+            traffic: (config.traffic_accounting == Some(true)).then(Traffic::default),
+            file_cache: config
+                .file_cache
+                .as_ref()
+                .map(|file_cache| Arc::new(FileCache::new(file_cache))),
+            config,
+        })
+    }
+
+    /// `for_tests` creates the state of a server with no applications and
+    /// nothing optional enabled.
+    #[cfg(test)]
+    pub fn for_tests(config: Config) -> Self {
+        let metrics = Arc::new(Metrics::new());
+        let workers = Arc::new(WorkerPool::new(&config, metrics.clone()).unwrap());
+
+        Self {
+            capabilities: Capabilities::new(&config),
+            static_health: Arc::new(StaticHealth::new(&config).unwrap()),
+            config,
+            mounts: RwLock::new(vec![]),
+            workers,
+            metrics,
+            access_logs: vec![],
+            well_known: WellKnown::default(),
+            trusted_proxies: TrustedProxies::default(),
+            waf: None,
+            geoip: None,
+            methods: vec![],
+            digest_auths: vec![],
+            openapis: vec![],
+            mirrors: vec![],
+            disabled_routes: DisabledRoutes::default(),
+            schedules: Schedules::default(),
+            transforms: vec![],
+            file_cache: None,
+            precompress: None,
+            traffic: None,
+            throttles: vec![],
+            response_caches: vec![],
+            faults: vec![],
+            error_pages: ErrorPages::default(),
+            asset_manifests: vec![],
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use hyper::{
+    body::Bytes,
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    Body, Response, StatusCode,
+};
+use log::{info, warn};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::config::Config;
+
+/// `DEFAULT_CHECK_INTERVAL` is how often the static directories are checked
+/// when no interval is configured.
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `StaticHealth` keeps track of whether the directories the static routes
+/// serve can be read, so that a directory deleted or unmounted at runtime is
+/// answered with a fallback rather than a stream of 404s, and is served again
+/// once it returns.
+pub struct StaticHealth {
+    /// `roots` are the directories of the static routes.
+    roots: Vec<StaticRoot>,
+
+    /// `interval` is the time between checks.
+    pub interval: Duration,
+}
+
+/// `StaticRoot` is the directory of a static route and what to answer with
+/// while it is missing.
+struct StaticRoot {
+    /// `route` is the path on the server the directory is served under.
+    route: String,
+
+    /// `dir` is the directory.
+    dir: PathBuf,
+
+    /// `available` is whether the directory could be read when last checked.
+    available: AtomicBool,
+
+    /// `critical` is whether the server is not ready while it is missing.
+    critical: bool,
+
+    /// `status` answers requests while the directory is missing.
+    status: StatusCode,
+
+    /// `page` is the body of those answers, if one is configured.
+    page: Option<Bytes>,
+}
+
+impl StaticHealth {
+    /// `new` checks the directories of the static routes for the first time,
+    /// failing if the check interval is zero, a fallback status is not an
+    /// error, or a fallback page cannot be read.
+    pub fn new(config: &Config) -> Result<Self, String> {
+        let root_dir = Path::new(&config.root_dir);
+        let interval = match config.static_check_interval {
+            Some(0) => return Err("The static_check_interval must be at least 1".to_owned()),
+            Some(interval) => Duration::from_secs(interval),
+            None => DEFAULT_CHECK_INTERVAL,
+        };
+
+        let mut roots = Vec::new();
+        for (route, dir) in config.static_routes.iter().flatten() {
+            let route_config = config.route_config(route);
+            let status = route_config
+                .and_then(|route_config| route_config.unavailable_status)
+                .unwrap_or(503);
+            let status = StatusCode::from_u16(status)
+                .ok()
+                .filter(|status| status.is_client_error() || status.is_server_error())
+                .ok_or_else(|| format!("The unavailable_status of {} must be an error", route))?;
+            let page = match route_config
+                .and_then(|route_config| route_config.unavailable_page.as_ref())
+            {
+                Some(page) => Some(Bytes::from(fs::read(root_dir.join(page)).map_err(|e| {
+                    format!("Cannot read the unavailable_page {}: {}", page, e)
+                })?)),
+                None => None,
+            };
+
+            let dir = root_dir.join(dir);
+            let available = is_readable(&dir);
+            if !available {
+                warn!(
+                    "The directory {} served at {} is missing",
+                    dir.display(),
+                    route
+                );
+            }
+            roots.push(StaticRoot {
+                route: route.clone(),
+                dir,
+                available: AtomicBool::new(available),
+                critical: route_config.and_then(|route_config| route_config.critical) == Some(true),
+                status,
+                page,
+            });
+        }
+
+        Ok(Self { roots, interval })
+    }
+
+    /// `is_empty` returns whether there are no static routes to check.
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// `watch` checks the directories every `interval` until the server stops.
+    pub fn watch(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                let health = self.clone();
+                let _ = tokio::task::spawn_blocking(move || {
+                    for root in &health.roots {
+                        root.check();
+                    }
+                })
+                .await;
+            }
+        });
+    }
+
+    /// `unavailable` returns the fallback response for a static route whose
+    /// directory is missing, or `None` while it is available.
+    pub fn unavailable(&self, route: &str) -> Option<Response<Body>> {
+        self.root(route)
+            .filter(|root| !root.available.load(Ordering::Relaxed))
+            .map(|root| root.response(self.interval))
+    }
+
+    /// `recheck` checks the directory of a static route straight away, after
+    /// a file under it could not be read, returning the fallback response if
+    /// the directory has gone missing.
+    pub fn recheck(&self, route: &str) -> Option<Response<Body>> {
+        let root = self.root(route)?;
+        if root.check() {
+            None
+        } else {
+            Some(root.response(self.interval))
+        }
+    }
+
+    /// `missing_critical` lists the critical routes whose directories are
+    /// missing.
+    pub fn missing_critical(&self) -> Vec<&str> {
+        self.roots
+            .iter()
+            .filter(|root| root.critical && !root.available.load(Ordering::Relaxed))
+            .map(|root| root.route.as_str())
+            .collect()
+    }
+
+    /// `readiness_response` answers a readiness probe: a 200 while every
+    /// critical directory is available and a 503 naming the missing ones
+    /// otherwise.
+    pub fn readiness_response(&self) -> Response<Body> {
+        let missing = self.missing_critical();
+        let (status, body) = if missing.is_empty() {
+            (StatusCode::OK, "ready\n".to_owned())
+        } else {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("missing: {}\n", missing.join(", ")),
+            )
+        };
+
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "text/plain")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    /// `root` returns the directory of a static route.
+    fn root(&self, route: &str) -> Option<&StaticRoot> {
+        self.roots.iter().find(|root| root.route == route)
+    }
+}
+
+impl StaticRoot {
+    /// `check` records whether the directory can be read, logging when it
+    /// goes missing or returns, and returns whether it can.
+    fn check(&self) -> bool {
+        let available = is_readable(&self.dir);
+        let was_available = self.available.swap(available, Ordering::Relaxed);
+
+        match (was_available, available) {
+            (true, false) => warn!(
+                "The directory {} served at {} has gone missing",
+                self.dir.display(),
+                self.route
+            ),
+            (false, true) => info!(
+                "The directory {} served at {} is back",
+                self.dir.display(),
+                self.route
+            ),
+            _ => {}
+        }
+
+        available
+    }
+
+    /// `response` builds the fallback response, asking clients to retry once
+    /// the directory has next been checked.
+    fn response(&self, interval: Duration) -> Response<Body> {
+        let rsp = Response::builder()
+            .status(self.status)
+            .header(RETRY_AFTER, interval.as_secs().max(1));
+
+        match &self.page {
+            Some(page) => rsp
+                .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                .body(Body::from(page.clone()))
+                .unwrap(),
+            None => rsp.body(Body::empty()).unwrap(),
+        }
+    }
+}
+
+/// `is_readable` returns whether a directory exists and can be listed, which
+/// fails for deleted directories and for stale or unmounted network shares.
+fn is_readable(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RouteConfig;
+    use crate::hashmap;
+
+    #[test]
+    fn test_missing_directory_recovers() {
+        let dir = std::env::temp_dir().join(format!("gee-static-health-{}", std::process::id()));
+        fs::create_dir_all(dir.join("www")).unwrap();
+        fs::write(dir.join("down.html"), "<p>Back soon</p>").unwrap();
+
+        let mut config = Config::new_default();
+        config.root_dir = dir.to_string_lossy().into_owned();
+        config.static_routes = Some(hashmap!["/".to_owned() => "www".to_owned()]);
+        config.routes = Some(vec![RouteConfig {
+            route: "/".to_owned(),
+            critical: Some(true),
+            unavailable_page: Some("down.html".to_owned()),
+            ..RouteConfig::default()
+        }]);
+        let health = StaticHealth::new(&config).unwrap();
+        assert!(health.unavailable("/").is_none());
+        assert_eq!(health.readiness_response().status(), StatusCode::OK);
+
+        fs::remove_dir(dir.join("www")).unwrap();
+        let response = health.recheck("/").unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(health.unavailable("/").is_some());
+        assert_eq!(health.missing_critical(), vec!["/"]);
+        assert_eq!(
+            health.readiness_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        fs::create_dir(dir.join("www")).unwrap();
+        assert!(health.recheck("/").is_none());
+        assert!(health.unavailable("/").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_fallbacks() {
+        let mut config = Config::new_default();
+        config.routes = Some(vec![RouteConfig {
+            route: "/static".to_owned(),
+            unavailable_status: Some(200),
+            ..RouteConfig::default()
+        }]);
+        assert!(StaticHealth::new(&config).is_err());
+
+        config.routes = Some(vec![RouteConfig {
+            route: "/static".to_owned(),
+            unavailable_page: Some("/nonexistent/down.html".to_owned()),
+            ..RouteConfig::default()
+        }]);
+        assert!(StaticHealth::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_new_rejects_zero_interval() {
+        let mut config = Config::new_default();
+        config.static_check_interval = Some(0);
+        assert!(StaticHealth::new(&config).is_err());
+
+        config.static_check_interval = Some(1);
+        assert_eq!(
+            StaticHealth::new(&config).unwrap().interval,
+            Duration::from_secs(1)
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{HeaderValue, CONTENT_LENGTH},
+    Body, Response,
+};
+use log::debug;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+use crate::config::RouteConfig;
+
+/// `Throttle` limits the rate at which the response bodies under a route are
+/// sent, shared between every response, so that large downloads cannot starve
+/// other traffic served by the same process.
+pub struct Throttle {
+    /// `route` is the path on the server the responses are throttled under.
+    pub route: String,
+
+    /// `rate` is the number of bytes per second sent on average.
+    rate: u64,
+
+    /// `burst` is the number of bytes that may be sent at once after the
+    /// route has been idle.
+    burst: u64,
+
+    /// `bucket` is the number of bytes that may be sent now and when it was
+    /// last refilled.
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    /// `new` creates the throttle of a route, failing if its rate or burst is
+    /// zero.
+    pub fn new(route: &RouteConfig) -> Result<Option<Self>, String> {
+        let rate = match route.max_bandwidth {
+            Some(rate) => rate,
+            None => return Ok(None),
+        };
+        let burst = route.bandwidth_burst.unwrap_or(rate);
+        if rate == 0 || burst == 0 {
+            return Err(format!(
+                "The max_bandwidth and bandwidth_burst of {} must be greater than 0",
+                route.route
+            ));
+        }
+
+        Ok(Some(Self {
+            route: route.route.clone(),
+            rate,
+            burst,
+            bucket: Mutex::new((burst as f64, Instant::now())),
+        }))
+    }
+
+    /// `apply` sends the body of a response no faster than the throttle
+    /// allows. A body of known length keeps its `Content-Length`.
+    pub fn apply(self: Arc<Self>, response: Response<Body>) -> Response<Body> {
+        let (mut parts, mut body) = response.into_parts();
+        if let Some(length) = body.size_hint().exact() {
+            if length == 0 {
+                return Response::from_parts(parts, body);
+            }
+            parts
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from(length));
+        }
+
+        let (mut sender, throttled) = Body::channel();
+        tokio::spawn(async move {
+            while let Some(chunk) = body.data().await {
+                let mut chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        debug!("Stopped sending a throttled response: {}", e);
+                        sender.abort();
+                        return;
+                    }
+                };
+
+                while !chunk.is_empty() {
+                    let piece: Bytes = chunk.split_to(chunk.len().min(self.burst as usize));
+                    tokio::time::sleep(self.reserve(piece.len() as u64, Instant::now())).await;
+                    if sender.send_data(piece).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Response::from_parts(parts, throttled)
+    }
+
+    /// `reserve` takes `bytes` from the bucket at `now`, returning how long
+    /// to wait before they may be sent. The bucket may go into debt, so that
+    /// later reservations wait their turn.
+    fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, refilled) = &mut *bucket;
+
+        let elapsed = now.saturating_duration_since(*refilled).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.rate as f64).min(self.burst as f64);
+        *refilled = now.max(*refilled);
+        *tokens -= bytes as f64;
+
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.rate as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn throttle(rate: u64, burst: Option<u64>) -> Throttle {
+        Throttle::new(&RouteConfig {
+            route: "/media".to_owned(),
+            max_bandwidth: Some(rate),
+            bandwidth_burst: burst,
+            ..RouteConfig::default()
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_new() {
+        let route = |rate, burst| RouteConfig {
+            route: "/media".to_owned(),
+            max_bandwidth: rate,
+            bandwidth_burst: burst,
+            ..RouteConfig::default()
+        };
+
+        assert!(Throttle::new(&route(None, None)).unwrap().is_none());
+        assert!(Throttle::new(&route(Some(0), None)).is_err());
+        assert!(Throttle::new(&route(Some(100), Some(0))).is_err());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let throttle = throttle(1000, Some(500));
+        let start = Instant::now();
+
+        assert_eq!(throttle.reserve(500, start), Duration::ZERO);
+        assert_eq!(throttle.reserve(500, start), Duration::from_millis(500));
+        assert_eq!(throttle.reserve(500, start), Duration::from_secs(1));
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(throttle.reserve(500, later), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        let throttle = Arc::new(throttle(100_000, Some(10_000)));
+        let response = Response::new(Body::from(vec![b'a'; 30_000]));
+
+        let start = Instant::now();
+        let response = throttle.apply(response);
+        assert_eq!(response.headers()[CONTENT_LENGTH], "30000");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body.len(), 30_000);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+}
@@ -0,0 +1,295 @@
+use hyper::{body::HttpBody, Body, Request, Response};
+use log::debug;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use crate::context::RequestContext;
+use crate::metrics::Counter;
+
+/// `MAX_KEYS` is the number of routes or hosts counted separately. Further
+/// ones, e.g. made up by clients in their `Host` headers, are counted
+/// together under `OTHER`.
+const MAX_KEYS: usize = 1000;
+
+/// `OTHER` names the routes or hosts counted together past `MAX_KEYS`.
+const OTHER: &str = "other";
+
+/// `NONE` names requests under no route, or naming no host.
+const NONE: &str = "-";
+
+/// `Family` is a metric rendered for each route or host: the end of its
+/// name, its help text, and how its value is read from the counts.
+type Family = (&'static str, &'static str, fn(&Usage) -> u64);
+
+/// `FAMILIES` are the metrics rendered for each route and for each host.
+const FAMILIES: [Family; 3] = [
+    ("requests_total", "Requests received", |usage| {
+        usage.requests.get()
+    }),
+    (
+        "received_bytes_total",
+        "Bytes of request bodies received",
+        |usage| usage.received.get(),
+    ),
+    (
+        "sent_bytes_total",
+        "Bytes of response bodies sent",
+        |usage| usage.sent.get(),
+    ),
+];
+
+/// `Traffic` counts the requests and the bytes of their bodies received and
+/// sent under each route and for each virtual host, so that bandwidth can be
+/// attributed to endpoints and tenants.
+#[derive(Default)]
+pub struct Traffic {
+    /// `routes` are the counts for each route.
+    routes: Mutex<HashMap<String, Arc<Usage>>>,
+
+    /// `hosts` are the counts for each host.
+    hosts: Mutex<HashMap<String, Arc<Usage>>>,
+}
+
+/// `Usage` is what was received and sent under a route or for a host.
+#[derive(Default)]
+pub struct Usage {
+    /// `requests` counts the requests.
+    pub requests: Counter,
+
+    /// `received` counts the bytes of the request bodies.
+    pub received: Counter,
+
+    /// `sent` counts the bytes of the response bodies.
+    pub sent: Counter,
+}
+
+/// `Tracked` is a request being counted, whose response is yet to be.
+pub struct Tracked {
+    /// `usages` are the counts of its route and of its host.
+    usages: [Arc<Usage>; 2],
+}
+
+impl Traffic {
+    /// `track` counts a request under its route and host, passing its body
+    /// through a counter.
+    pub fn track(&self, req: Request<Body>, context: &RequestContext) -> (Request<Body>, Tracked) {
+        let route = usage(&self.routes, context.route.as_deref());
+        let host = usage(&self.hosts, context.host.as_deref());
+        route.requests.inc();
+        host.requests.inc();
+
+        let usages = [route, host];
+        let (parts, body) = req.into_parts();
+        let counted = usages.clone();
+        let body = count_body(body, move |bytes| {
+            for usage in counted.iter() {
+                usage.received.add(bytes);
+            }
+        });
+
+        (Request::from_parts(parts, body), Tracked { usages })
+    }
+
+    /// `render` writes the counts in the Prometheus text exposition format.
+    pub fn render(&self, out: &mut String) {
+        for (label, usages) in [("route", &self.routes), ("host", &self.hosts)] {
+            let usages = sorted(usages);
+            for (suffix, help, value) in FAMILIES {
+                let name = format!("gee_{}_{}", label, suffix);
+                writeln!(out, "# HELP {} {} for each {}.", name, help, label).unwrap();
+                writeln!(out, "# TYPE {} counter", name).unwrap();
+                for (key, usage) in usages.iter() {
+                    writeln!(
+                        out,
+                        "{}{{{}=\"{}\"}} {}",
+                        name,
+                        label,
+                        escape_label(key),
+                        value(usage)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+
+    /// `top` lists the `count` routes and hosts which sent the most bytes,
+    /// with their counts, as a table.
+    pub fn top(&self, count: usize) -> String {
+        let mut out = String::new();
+        for (label, usages) in [("ROUTE", &self.routes), ("HOST", &self.hosts)] {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            writeln!(
+                out,
+                "{:<32} {:>10} {:>14} {:>14}",
+                label, "REQUESTS", "RECEIVED", "SENT"
+            )
+            .unwrap();
+            for (key, usage) in sorted(usages).iter().take(count) {
+                writeln!(
+                    out,
+                    "{:<32} {:>10} {:>14} {:>14}",
+                    key,
+                    usage.requests.get(),
+                    usage.received.get(),
+                    usage.sent.get()
+                )
+                .unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+impl Tracked {
+    /// `response` passes the body of the response through a counter.
+    pub fn response(self, response: Response<Body>) -> Response<Body> {
+        let (parts, body) = response.into_parts();
+        let usages = self.usages;
+        let body = count_body(body, move |bytes| {
+            for usage in usages.iter() {
+                usage.sent.add(bytes);
+            }
+        });
+
+        Response::from_parts(parts, body)
+    }
+}
+
+/// `usage` returns the counts for `key`, creating them if there is room.
+fn usage(usages: &Mutex<HashMap<String, Arc<Usage>>>, key: Option<&str>) -> Arc<Usage> {
+    let key = key.unwrap_or(NONE);
+    let mut usages = usages.lock().unwrap();
+    if let Some(usage) = usages.get(key) {
+        return usage.clone();
+    }
+
+    let key = if usages.len() < MAX_KEYS { key } else { OTHER };
+    usages.entry(key.to_owned()).or_default().clone()
+}
+
+/// `sorted` lists the counts, those which sent the most bytes first.
+fn sorted(usages: &Mutex<HashMap<String, Arc<Usage>>>) -> Vec<(String, Arc<Usage>)> {
+    let mut usages: Vec<_> = usages
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, usage)| (key.clone(), usage.clone()))
+        .collect();
+    usages.sort_by(|(a_key, a), (b_key, b)| {
+        b.sent
+            .get()
+            .cmp(&a.sent.get())
+            .then_with(|| a_key.cmp(b_key))
+    });
+
+    usages
+}
+
+/// `count_body` passes the size of a body to `count`. A body of known size is
+/// counted straight away; otherwise it is forwarded chunk by chunk, each
+/// counted as it passes.
+fn count_body(mut body: Body, count: impl Fn(u64) + Send + 'static) -> Body {
+    if let Some(size) = body.size_hint().exact() {
+        count(size);
+        return body;
+    }
+
+    let (mut sender, counted) = Body::channel();
+    tokio::spawn(async move {
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    debug!("Stopped counting a body: {}", e);
+                    sender.abort();
+                    return;
+                }
+            };
+
+            count(chunk.len() as u64);
+            if sender.send_data(chunk).await.is_err() {
+                return;
+            }
+        }
+
+        if let Ok(Some(trailers)) = body.trailers().await {
+            let _ = sender.send_trailers(trailers).await;
+        }
+    });
+
+    counted
+}
+
+/// `escape_label` escapes a Prometheus label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{ClientInfo, TrustedProxies};
+
+    fn context(route: Option<&str>, host: Option<&str>) -> RequestContext {
+        let req = Request::new(Body::empty());
+        let client = ClientInfo::from_request(&req, None, &TrustedProxies::default());
+        let mut context = RequestContext::new(&req, client, route.map(str::to_owned));
+        context.host = host.map(str::to_owned);
+        context
+    }
+
+    #[tokio::test]
+    async fn test_track() {
+        let traffic = Traffic::default();
+
+        let req = Request::new(Body::from("hello"));
+        let (req, tracked) = traffic.track(req, &context(Some("/api"), Some("example.com")));
+        hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let (mut sender, body) = Body::channel();
+        let response = tracked.response(Response::new(body));
+        sender.send_data("hello world".into()).await.unwrap();
+        drop(sender);
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        traffic.track(Request::new(Body::empty()), &context(None, None));
+
+        let mut out = String::new();
+        traffic.render(&mut out);
+        assert!(out.contains("gee_route_requests_total{route=\"/api\"} 1\n"));
+        assert!(out.contains("gee_route_received_bytes_total{route=\"/api\"} 5\n"));
+        assert!(out.contains("gee_route_sent_bytes_total{route=\"/api\"} 11\n"));
+        assert!(out.contains("gee_host_sent_bytes_total{host=\"example.com\"} 11\n"));
+        assert!(out.contains("gee_route_requests_total{route=\"-\"} 1\n"));
+
+        let top = traffic.top(1);
+        assert_eq!(top.lines().count(), 5);
+        assert!(top.lines().nth(1).unwrap().starts_with("/api "));
+    }
+
+    #[test]
+    fn test_usage_caps_keys() {
+        let usages = Mutex::new(HashMap::new());
+        for i in 0..MAX_KEYS + 5 {
+            usage(&usages, Some(&format!("host-{}", i))).requests.inc();
+        }
+
+        let usages = usages.lock().unwrap();
+        assert_eq!(usages.len(), MAX_KEYS + 1);
+        assert_eq!(usages[OTHER].requests.get(), 5);
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+    }
+}
@@ -0,0 +1,318 @@
+use hyper::{
+    body::HttpBody,
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Response, StatusCode,
+};
+use log::debug;
+use regex::Regex;
+
+use crate::config::RouteConfig;
+use crate::handlers::{CONTENT_DIGEST, DIGEST};
+
+/// `DEFAULT_MAX_SIZE` is the size of the largest response transformed if the
+/// route does not set `max_transform_size`.
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// `Transform` rewrites the bodies of the HTML responses under a route.
+pub struct Transform {
+    /// `route` is the path on the server the responses are transformed under.
+    pub route: String,
+
+    /// `rules` are the patterns replaced, in order, and their replacements.
+    rules: Vec<(Regex, String)>,
+
+    /// `max_size` is the size of the largest response that is transformed.
+    max_size: u64,
+}
+
+impl Transform {
+    /// `new` compiles the transforms of a route, failing if a rule does not
+    /// have exactly one of a valid `pattern` or a `token`.
+    pub fn new(route: &RouteConfig) -> Result<Self, String> {
+        let rules = route
+            .transforms
+            .iter()
+            .flatten()
+            .map(|transform| match (&transform.pattern, &transform.token) {
+                (Some(pattern), None) => Regex::new(pattern)
+                    .map(|pattern| (pattern, transform.replacement.clone()))
+                    .map_err(|e| format!("Invalid transform pattern {}: {}", pattern, e)),
+                (None, Some(token)) => Ok((
+                    Regex::new(&regex::escape(token)).unwrap(),
+                    transform.replacement.replace('$', "$$"),
+                )),
+                _ => Err(format!(
+                    "The transforms of {} must each have exactly one of `pattern` or `token`",
+                    route.route
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            route: route.route.clone(),
+            rules,
+            max_size: route.max_transform_size.unwrap_or(DEFAULT_MAX_SIZE),
+        })
+    }
+
+    /// `apply` transforms the body of a successful, uncompressed HTML response
+    /// as it streams to the client. Responses declaring a `Content-Length`
+    /// over the `max_size` are returned untouched, and a response found to be
+    /// larger while streaming is passed through unchanged from then on.
+    pub fn apply(&self, response: Response<Body>) -> Response<Body> {
+        let headers = response.headers();
+        let is_html = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim_start().starts_with("text/html"));
+        let is_small = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .is_none_or(|length| length <= self.max_size);
+
+        if response.status() != StatusCode::OK
+            || !is_html
+            || !is_small
+            || headers.contains_key(CONTENT_ENCODING)
+            || self.rules.is_empty()
+        {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.remove(DIGEST);
+        parts.headers.remove(CONTENT_DIGEST);
+
+        let (mut sender, transformed) = Body::channel();
+        let mut lines = Lines::new(self.rules.clone(), self.max_size);
+        tokio::spawn(async move {
+            let mut body = body;
+            while let Some(chunk) = body.data().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        debug!("Stopped transforming a response: {}", e);
+                        sender.abort();
+                        return;
+                    }
+                };
+                let output = lines.push(&chunk);
+                if !output.is_empty() && sender.send_data(output.into()).await.is_err() {
+                    return;
+                }
+            }
+
+            let output = lines.finish();
+            if !output.is_empty() {
+                let _ = sender.send_data(output.into()).await;
+            }
+        });
+
+        Response::from_parts(parts, transformed)
+    }
+}
+
+/// `Lines` transforms a body one complete line at a time as its chunks
+/// arrive, holding back a partial line until the rest of it is received.
+struct Lines {
+    /// `rules` are the patterns replaced and their replacements.
+    rules: Vec<(Regex, String)>,
+
+    /// `max_size` is the number of bytes after which the rest of the body is
+    /// passed through unchanged.
+    max_size: u64,
+
+    /// `received` is the number of bytes of the body received so far.
+    received: u64,
+
+    /// `pending` is the partial line held back from the last chunk.
+    pending: Vec<u8>,
+}
+
+impl Lines {
+    /// `new` creates the line buffer of a response about to stream.
+    fn new(rules: Vec<(Regex, String)>, max_size: u64) -> Self {
+        Self {
+            rules,
+            max_size,
+            received: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// `push` receives a chunk of the body and returns the output ready to be
+    /// sent: every complete line received so far, transformed.
+    fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if self.received > self.max_size {
+            return chunk.to_vec();
+        }
+
+        self.received += chunk.len() as u64;
+        self.pending.extend_from_slice(chunk);
+        if self.received > self.max_size {
+            debug!(
+                "Response is larger than {} bytes, not transforming the rest",
+                self.max_size
+            );
+            return std::mem::take(&mut self.pending);
+        }
+
+        match self.pending.iter().rposition(|byte| *byte == b'\n') {
+            Some(end) => {
+                let rest = self.pending.split_off(end + 1);
+                let lines = std::mem::replace(&mut self.pending, rest);
+                self.transform(lines)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// `finish` returns the transformed last line of the body.
+    fn finish(&mut self) -> Vec<u8> {
+        let pending = std::mem::take(&mut self.pending);
+        if self.received > self.max_size {
+            return pending;
+        }
+
+        self.transform(pending)
+    }
+
+    /// `transform` applies the rules to each line of the text. Text that is
+    /// not valid UTF-8 is passed through unchanged.
+    fn transform(&self, text: Vec<u8>) -> Vec<u8> {
+        let text = match String::from_utf8(text) {
+            Ok(text) => text,
+            Err(e) => return e.into_bytes(),
+        };
+
+        text.split_inclusive('\n')
+            .map(|line| {
+                self.rules
+                    .iter()
+                    .fold(line.to_owned(), |line, (pattern, replacement)| {
+                        pattern
+                            .replace_all(&line, replacement.as_str())
+                            .into_owned()
+                    })
+            })
+            .collect::<String>()
+            .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::TransformConfig;
+
+    fn transform(transforms: Vec<TransformConfig>, max_size: Option<u64>) -> Transform {
+        Transform::new(&RouteConfig {
+            route: "/".to_owned(),
+            transforms: Some(transforms),
+            max_transform_size: max_size,
+            ..RouteConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn token(token: &str, replacement: &str) -> TransformConfig {
+        TransformConfig {
+            token: Some(token.to_owned()),
+            replacement: replacement.to_owned(),
+            ..TransformConfig::default()
+        }
+    }
+
+    fn html(chunks: Vec<&'static str>) -> Response<Body> {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in chunks {
+                if sender.send_data(chunk.into()).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(body)
+            .unwrap()
+    }
+
+    async fn body(response: Response<Body>) -> String {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_transforms() {
+        let route = |transform| RouteConfig {
+            route: "/".to_owned(),
+            transforms: Some(vec![transform]),
+            ..RouteConfig::default()
+        };
+
+        assert!(Transform::new(&route(TransformConfig {
+            pattern: Some("(".to_owned()),
+            ..TransformConfig::default()
+        }))
+        .is_err());
+        assert!(Transform::new(&route(TransformConfig::default())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_across_chunks() {
+        let transform = transform(
+            vec![
+                token("</head>", "<script src=\"/a.js\"></script></head>"),
+                TransformConfig {
+                    pattern: Some("<body( [^>]*)?>".to_owned()),
+                    replacement: "<body$1><div>staging</div>".to_owned(),
+                    ..TransformConfig::default()
+                },
+            ],
+            None,
+        );
+
+        let response = transform.apply(html(vec!["<head></he", "ad>\n<body class=\"x\">", "\n"]));
+        assert_eq!(
+            body(response).await,
+            "<head><script src=\"/a.js\"></script></head>\n<body class=\"x\"><div>staging</div>\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_token_replacement_is_literal() {
+        let transform = transform(vec![token("{{ENV}}", "$1 staging")], None);
+
+        let response = transform.apply(html(vec!["<p>{{ENV}}</p>"]));
+        assert_eq!(body(response).await, "<p>$1 staging</p>");
+    }
+
+    #[tokio::test]
+    async fn test_apply_passes_through_large_responses() {
+        let transform = transform(vec![token("a", "b")], Some(8));
+
+        let response = transform.apply(html(vec!["aaaa\n", "aaaa\n", "aaaa\n"]));
+        assert_eq!(body(response).await, "bbbb\naaaa\naaaa\n");
+    }
+
+    #[tokio::test]
+    async fn test_apply_skips_other_responses() {
+        let transform = transform(vec![token("a", "b")], None);
+
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "text/plain")
+            .body(Body::from("a"))
+            .unwrap();
+        assert_eq!(body(transform.apply(response)).await, "a");
+
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "text/html")
+            .header(CONTENT_ENCODING, "gzip")
+            .body(Body::from("a"))
+            .unwrap();
+        assert_eq!(body(transform.apply(response)).await, "a");
+    }
+}
@@ -0,0 +1,238 @@
+use hyper::{Body, Request, StatusCode};
+use regex::{Regex, RegexSet};
+
+use crate::config::{WafConfig, WafMode};
+use crate::percent::percent_decode_once;
+
+/// `DEFAULT_PATH_RULES` match paths no site should serve: version control
+/// metadata, environment files, server configuration, and traversal out of
+/// the served directories.
+const DEFAULT_PATH_RULES: [&str; 5] = [
+    r"(^|/)\.(git|svn|hg)(/|$)",
+    r"(^|/)\.env(\.[^/]*)?$",
+    r"(?i)(^|/)\.(htaccess|htpasswd|ds_store)$",
+    r"(^|/)\.\.(/|$)",
+    r"\x00",
+];
+
+/// `DEFAULT_QUERY_RULES` match common SQL injection signatures.
+const DEFAULT_QUERY_RULES: [&str; 5] = [
+    r"(?i)\bunion\b.*\bselect\b",
+    r#"(?i)['"]\s*(or|and)\s+['"]?\w+['"]?\s*=\s*['"]?\w+"#,
+    r"(?i);\s*(drop|delete|insert|update|alter)\s+\w",
+    r"(?i)\b(sleep|benchmark|pg_sleep|waitfor\s+delay)\s*\(",
+    r"(?i)\binformation_schema\b",
+];
+
+/// `Waf` filters requests against rules matching their decoded paths and
+/// query strings, and against a limit on their number of headers, before
+/// they are routed.
+pub struct Waf {
+    /// `mode` is whether matching requests are refused or only logged.
+    pub mode: WafMode,
+
+    /// `status` is the status refused requests are answered with.
+    pub status: StatusCode,
+
+    /// `paths` are the rules matching decoded paths.
+    paths: Rules,
+
+    /// `queries` are the rules matching decoded query strings.
+    queries: Rules,
+
+    /// `max_headers` is the number of headers a request may have.
+    max_headers: Option<usize>,
+}
+
+/// `Rules` are regular expressions, kept individually to name the one that
+/// matched.
+struct Rules {
+    /// `set` matches every rule in a single pass.
+    set: RegexSet,
+
+    /// `patterns` are the rules, in the order of the set.
+    patterns: Vec<String>,
+}
+
+impl Waf {
+    /// `new` compiles the rules of the WAF, failing if one is not a valid
+    /// regular expression or the status is not an error.
+    pub fn new(config: &WafConfig) -> Result<Self, String> {
+        let defaults = config.default_rules != Some(false);
+        let rules = |default: &[&str], configured: &Option<Vec<String>>, name: &str| {
+            let patterns = default
+                .iter()
+                .filter(|_| defaults)
+                .map(|pattern| pattern.to_string())
+                .chain(configured.iter().flatten().cloned())
+                .collect();
+            Rules::new(patterns).map_err(|e| format!("Invalid WAF {} rule: {}", name, e))
+        };
+
+        let status = config.status.unwrap_or(403);
+        let status = StatusCode::from_u16(status)
+            .ok()
+            .filter(|status| status.is_client_error() || status.is_server_error())
+            .ok_or_else(|| format!("The WAF status {} must be an error", status))?;
+
+        Ok(Self {
+            mode: config.mode.unwrap_or_default(),
+            status,
+            paths: rules(&DEFAULT_PATH_RULES, &config.blocked_paths, "path")?,
+            queries: rules(&DEFAULT_QUERY_RULES, &config.blocked_queries, "query")?,
+            max_headers: config.max_headers,
+        })
+    }
+
+    /// `check` returns a description of the first rule a request matches, if
+    /// any.
+    pub fn check(&self, req: &Request<Body>) -> Option<String> {
+        if let Some(max_headers) = self.max_headers {
+            let count = req.headers().len();
+            if count > max_headers {
+                return Some(format!("{} headers, more than {}", count, max_headers));
+            }
+        }
+
+        let path = percent_decode(req.uri().path(), false);
+        if let Some(pattern) = self.paths.first_match(&path) {
+            return Some(format!("path matches {}", pattern));
+        }
+
+        let query = percent_decode(req.uri().query().unwrap_or_default(), true);
+        if let Some(pattern) = self.queries.first_match(&query) {
+            return Some(format!("query matches {}", pattern));
+        }
+
+        None
+    }
+}
+
+impl Rules {
+    /// `new` compiles the rules.
+    fn new(patterns: Vec<String>) -> Result<Self, regex::Error> {
+        for pattern in &patterns {
+            Regex::new(pattern)?;
+        }
+
+        Ok(Self {
+            set: RegexSet::new(&patterns)?,
+            patterns,
+        })
+    }
+
+    /// `first_match` returns the first rule matching `text`, if any.
+    fn first_match(&self, text: &str) -> Option<&str> {
+        self.set
+            .matches(text)
+            .iter()
+            .next()
+            .map(|index| self.patterns[index].as_str())
+    }
+}
+
+/// `percent_decode` decodes the `%XX` escapes of a path or query string,
+/// repeatedly, so that doubly encoded attacks are seen as they would be by
+/// an application that decodes twice. With `plus_as_space`, `+` is decoded
+/// as a space, as in form-encoded query strings.
+fn percent_decode(text: &str, plus_as_space: bool) -> String {
+    let mut decoded = if plus_as_space {
+        text.replace('+', " ")
+    } else {
+        text.to_owned()
+    };
+
+    for _ in 0..3 {
+        let next = percent_decode_once(&decoded, false);
+        if next == decoded {
+            break;
+        }
+        decoded = next;
+    }
+
+    decoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_rules() {
+        let config = WafConfig {
+            blocked_paths: Some(vec!["(".to_owned()]),
+            ..WafConfig::default()
+        };
+        assert!(Waf::new(&config).is_err());
+
+        let config = WafConfig {
+            status: Some(200),
+            ..WafConfig::default()
+        };
+        assert!(Waf::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_check_default_rules() {
+        let waf = Waf::new(&WafConfig::default()).unwrap();
+
+        assert!(waf.check(&request("/.git/config")).is_some());
+        assert!(waf.check(&request("/app/.env")).is_some());
+        assert!(waf.check(&request("/static/%2e%2e/secrets")).is_some());
+        assert!(waf
+            .check(&request("/items?id=1%20UNION%20SELECT%20password"))
+            .is_some());
+        assert!(waf
+            .check(&request("/login?user=admin'+or+'1'='1"))
+            .is_some());
+        assert!(waf
+            .check(&request("/items?id=%25271;%2520DROP%2520TABLE%2520users"))
+            .is_some());
+
+        assert!(waf.check(&request("/.well-known/security.txt")).is_none());
+        assert!(waf.check(&request("/static/app.git.js")).is_none());
+        assert!(waf
+            .check(&request("/search?q=select+a+union+rep"))
+            .is_none());
+        assert!(waf
+            .check(&request("/search?q=gardening+and+sleep"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_check_configured_rules() {
+        let waf = Waf::new(&WafConfig {
+            default_rules: Some(false),
+            blocked_paths: Some(vec!["^/wp-admin".to_owned()]),
+            max_headers: Some(1),
+            ..WafConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            waf.check(&request("/wp-admin/setup.php")),
+            Some("path matches ^/wp-admin".to_owned())
+        );
+        assert!(waf.check(&request("/.git/config")).is_none());
+
+        let req = Request::builder()
+            .uri("/")
+            .header("a", "1")
+            .header("b", "2")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(waf.check(&req), Some("2 headers, more than 1".to_owned()));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("/a%20b", false), "/a b");
+        assert_eq!(percent_decode("a+b%2Bc", true), "a b+c");
+        assert_eq!(percent_decode("%252e%252e", false), "..");
+        assert_eq!(percent_decode("100%", false), "100%");
+    }
+}
@@ -0,0 +1,119 @@
+use hyper::{
+    header::{
+        HeaderMap, HeaderName, ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, AUTHORIZATION,
+        CACHE_CONTROL, COOKIE, HOST, SET_COOKIE, VARY,
+    },
+    Method, StatusCode, Uri,
+};
+
+/// `KEYED_HEADERS` are the request headers which, along with the URI, tell
+/// requests getting different responses apart.
+const KEYED_HEADERS: [HeaderName; 4] = [HOST, ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE];
+
+/// `shared_key` returns the key of the response to a request which may be
+/// shared with other clients, or `None` if it may not be: only GET requests
+/// without credentials are. Requests differing in their host, path, query, or
+/// content negotiation headers get different keys.
+pub fn shared_key(method: &Method, uri: &Uri, headers: &HeaderMap) -> Option<String> {
+    if method != Method::GET || headers.contains_key(AUTHORIZATION) || headers.contains_key(COOKIE)
+    {
+        return None;
+    }
+
+    let mut key = uri.to_string();
+    for name in KEYED_HEADERS {
+        key.push('\n');
+        key.push_str(
+            &headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+
+    Some(key)
+}
+
+/// `is_shareable` returns whether a response may be given to clients other
+/// than the one it was generated for: it must be successful, neither set a
+/// cookie nor be marked `private` or `no-store`, and only vary on headers its
+/// `shared_key` is made of.
+pub fn is_shareable<N: AsRef<str>, V: AsRef<str>>(
+    status: StatusCode,
+    headers: impl IntoIterator<Item = (N, V)>,
+) -> bool {
+    status == StatusCode::OK
+        && headers.into_iter().all(|(name, value)| {
+            let (name, value) = (name.as_ref(), value.as_ref().to_ascii_lowercase());
+            if name.eq_ignore_ascii_case(SET_COOKIE.as_str()) {
+                false
+            } else if name.eq_ignore_ascii_case(CACHE_CONTROL.as_str()) {
+                !(value.contains("private") || value.contains("no-store"))
+            } else if name.eq_ignore_ascii_case(VARY.as_str()) {
+                value.split(',').all(|varied| {
+                    KEYED_HEADERS
+                        .iter()
+                        .any(|keyed| keyed.as_str() == varied.trim())
+                })
+            } else {
+                true
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shared_key() {
+        let key = |method: Method, headers: Vec<(HeaderName, &str)>| {
+            let mut map = HeaderMap::new();
+            for (name, value) in headers {
+                map.append(name, value.parse().unwrap());
+            }
+            shared_key(&method, &Uri::from_static("/page?q=1"), &map)
+        };
+
+        assert_eq!(
+            key(Method::GET, vec![(HOST, "example.com")]),
+            Some("/page?q=1\nexample.com\n\n\n".to_owned())
+        );
+        assert_ne!(
+            key(Method::GET, vec![(HOST, "blog.example.com")]),
+            key(Method::GET, vec![(HOST, "shop.example.com")])
+        );
+        assert_ne!(
+            key(Method::GET, vec![(ACCEPT_LANGUAGE, "fr")]),
+            key(Method::GET, vec![])
+        );
+        assert_eq!(key(Method::POST, vec![]), None);
+        assert_eq!(key(Method::GET, vec![(COOKIE, "session=1")]), None);
+        assert_eq!(key(Method::GET, vec![(AUTHORIZATION, "Basic YQ==")]), None);
+    }
+
+    #[test]
+    fn test_is_shareable() {
+        assert!(is_shareable(
+            StatusCode::OK,
+            [("Content-Type", "text/html")]
+        ));
+        assert!(!is_shareable(
+            StatusCode::NOT_FOUND,
+            [("Content-Type", "text/html")]
+        ));
+        assert!(!is_shareable(StatusCode::OK, [("Set-Cookie", "a=b")]));
+        assert!(!is_shareable(
+            StatusCode::OK,
+            [("Cache-Control", "Private, max-age=60")]
+        ));
+        assert!(is_shareable(
+            StatusCode::OK,
+            [("Vary", "Accept-Encoding, Accept-Language")]
+        ));
+        assert!(!is_shareable(StatusCode::OK, [("Vary", "User-Agent")]));
+        assert!(!is_shareable(StatusCode::OK, [("Vary", "*")]));
+    }
+}
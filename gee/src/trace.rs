@@ -0,0 +1,168 @@
+use hyper::header::{HeaderMap, HeaderValue};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// `TRACEPARENT` carries the W3C trace context of a request.
+const TRACEPARENT: &str = "traceparent";
+
+/// `TRACE_HEADERS` are the W3C and Zipkin B3 headers that carry the trace
+/// context of a request, and the environ keys they are passed to the
+/// applications under.
+const TRACE_HEADERS: [(&str, &str); 8] = [
+    (TRACEPARENT, "gee.traceparent"),
+    ("tracestate", "gee.tracestate"),
+    ("b3", "gee.b3"),
+    ("x-b3-traceid", "gee.b3.trace_id"),
+    ("x-b3-spanid", "gee.b3.span_id"),
+    ("x-b3-parentspanid", "gee.b3.parent_span_id"),
+    ("x-b3-sampled", "gee.b3.sampled"),
+    ("x-b3-flags", "gee.b3.flags"),
+];
+
+/// `COUNTER` makes the identifiers generated within the same instant differ.
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// `ensure_trace_context` starts a new, sampled W3C trace for a request that
+/// does not carry a trace context, so that the applications and upstreams it
+/// is passed to can join it. Requests that carry one are left unchanged.
+pub fn ensure_trace_context(headers: &mut HeaderMap) {
+    let has_context = [TRACEPARENT, "b3", "x-b3-traceid"]
+        .iter()
+        .any(|name| headers.contains_key(*name));
+    if has_context {
+        return;
+    }
+
+    let traceparent = format!(
+        "00-{:016x}{:016x}-{:016x}-01",
+        random_id(),
+        random_id(),
+        random_id()
+    );
+    headers.insert(TRACEPARENT, HeaderValue::from_str(&traceparent).unwrap());
+}
+
+/// `trace_extensions` returns the trace context headers of a request by the
+/// environ keys they are passed to the applications under.
+pub fn trace_extensions(headers: &HeaderMap) -> impl Iterator<Item = (String, String)> + '_ {
+    TRACE_HEADERS.iter().filter_map(|(header, key)| {
+        let value = headers.get(*header)?.to_str().ok()?;
+        Some((key.to_string(), value.to_owned()))
+    })
+}
+
+/// `trace_id` returns the ID of the trace a request belongs to, from its W3C
+/// or B3 trace context, if it carries one.
+pub fn trace_id(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+    header(TRACEPARENT)
+        .and_then(|traceparent| traceparent.split('-').nth(1))
+        .or_else(|| header("x-b3-traceid"))
+        .or_else(|| header("b3").and_then(|b3| b3.split('-').next()))
+        .filter(|trace_id| !trace_id.is_empty())
+        .map(str::to_owned)
+}
+
+/// `random_id` returns a random, non-zero 64-bit identifier. The standard
+/// library's randomly seeded hasher is enough, as trace identifiers need to
+/// be unique rather than unpredictable.
+pub fn random_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default(),
+    );
+
+    hasher.finish().max(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ensure_trace_context_starts_trace() {
+        let mut headers = HeaderMap::new();
+        ensure_trace_context(&mut headers);
+
+        let traceparent = headers[TRACEPARENT].to_str().unwrap();
+        let parts: Vec<_> = traceparent.split('-').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "00");
+        assert_eq!(parts[1].len(), 32);
+        assert_eq!(parts[2].len(), 16);
+        assert_eq!(parts[3], "01");
+
+        let mut other = HeaderMap::new();
+        ensure_trace_context(&mut other);
+        assert_ne!(headers[TRACEPARENT], other[TRACEPARENT]);
+    }
+
+    #[test]
+    fn test_ensure_trace_context_keeps_existing() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-b3-traceid",
+            "80f198ee56343ba864fe8b2a57d3eff7".parse().unwrap(),
+        );
+        ensure_trace_context(&mut headers);
+
+        assert!(!headers.contains_key(TRACEPARENT));
+    }
+
+    #[test]
+    fn test_trace_extensions() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            TRACEPARENT,
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert("tracestate", "congo=t61rcWkgMzE".parse().unwrap());
+        headers.insert("x-b3-sampled", "1".parse().unwrap());
+
+        let mut extensions: Vec<_> = trace_extensions(&headers).collect();
+        extensions.sort();
+        assert_eq!(
+            extensions,
+            vec![
+                ("gee.b3.sampled".to_owned(), "1".to_owned()),
+                (
+                    "gee.traceparent".to_owned(),
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_owned()
+                ),
+                ("gee.tracestate".to_owned(), "congo=t61rcWkgMzE".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_id() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(trace_id(&headers), None);
+
+        headers.insert(
+            "b3",
+            HeaderValue::from_static("80f198ee56343ba8-e457b5a2e4d86bd1-1"),
+        );
+        assert_eq!(trace_id(&headers).as_deref(), Some("80f198ee56343ba8"));
+
+        headers.insert(
+            TRACEPARENT,
+            HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        );
+        assert_eq!(
+            trace_id(&headers).as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+    }
+}
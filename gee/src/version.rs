@@ -0,0 +1,92 @@
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// `PROTOCOLS` are the protocols the Gee server accepts requests over.
+const PROTOCOLS: [&str; 3] = ["HTTP/1.0", "HTTP/1.1", "HTTP/2 (prior knowledge)"];
+
+/// `VersionInfo` describes the build of Gee that is running.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub build_date: &'static str,
+    pub features: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub python_version: Option<String>,
+    pub protocols: Vec<&'static str>,
+}
+
+impl VersionInfo {
+    /// `current` describes this build of Gee, and the Python it is linked
+    /// against if `with_python` is set. Finding the version of Python starts
+    /// the interpreter, so it is left out when no applications will use it.
+    pub fn current(with_python: bool) -> Self {
+        let python_version = with_python.then(|| {
+            pyo3::prepare_freethreaded_python();
+            pyo3::Python::with_gil(|py| {
+                let version = py.version_info();
+                format!("{}.{}.{}", version.major, version.minor, version.patch)
+            })
+        });
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("GEE_GIT_COMMIT"),
+            build_date: env!("GEE_BUILD_DATE"),
+            features: env!("GEE_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .collect(),
+            python_version,
+            protocols: PROTOCOLS.to_vec(),
+        }
+    }
+
+    /// `to_json` serializes the version info as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "gee {} ({} built {})",
+            self.version, self.git_commit, self.build_date
+        )?;
+        if let Some(python_version) = &self.python_version {
+            writeln!(f, "python {}", python_version)?;
+        }
+        writeln!(f, "protocols: {}", self.protocols.join(", "))?;
+        write!(
+            f,
+            "features: {}",
+            if self.features.is_empty() {
+                "none".to_owned()
+            } else {
+                self.features.join(", ")
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_current() {
+        let info = VersionInfo::current(true);
+
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.build_date.len(), "2022-01-01".len());
+        assert!(info.python_version.as_ref().unwrap().starts_with('3'));
+        assert!(info.to_json().starts_with("{\"version\":"));
+
+        let info = VersionInfo::current(false);
+        assert_eq!(info.python_version, None);
+        assert!(!info.to_json().contains("python_version"));
+        assert!(!info.to_string().contains("python"));
+    }
+}